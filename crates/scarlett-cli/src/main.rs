@@ -0,0 +1,248 @@
+//! Scarlett CLI - headless device control for scripts
+
+use clap::{Parser, Subcommand};
+use scarlett_core::{Device, DeviceInfo, Error, Result};
+use scarlett_usb::{DeviceDetector, KernelDriverPolicy, UsbDevice};
+use serde_json::json;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(name = "scarlett-cli", version, about = "Control Focusrite Scarlett interfaces from the command line")]
+struct Cli {
+    /// Print machine-readable JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Detach the kernel driver if it owns the control interface (stops audio)
+    #[arg(long, global = true)]
+    detach_kernel_driver: bool,
+
+    /// Record every USB control transfer to this file, one JSON object per line
+    #[arg(long, global = true, value_name = "FILE")]
+    trace_usb: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List connected devices
+    List,
+    /// Read or change an output's volume
+    Volume {
+        #[command(flatten)]
+        target: OutputTarget,
+        /// Set the volume, in dB
+        #[arg(long, allow_negative_numbers = true, conflicts_with = "adjust")]
+        set: Option<f32>,
+        /// Change the volume by this many dB
+        #[arg(long, allow_negative_numbers = true)]
+        adjust: Option<f32>,
+    },
+    /// Read or change an output's mute
+    Mute {
+        #[command(flatten)]
+        target: OutputTarget,
+        /// Mute the output
+        #[arg(long, conflicts_with_all = ["off", "toggle"])]
+        on: bool,
+        /// Unmute the output
+        #[arg(long, conflicts_with = "toggle")]
+        off: bool,
+        /// Flip the current mute state
+        #[arg(long)]
+        toggle: bool,
+    },
+    /// Route a source to a destination
+    Route {
+        /// Serial number of the device (defaults to the first found)
+        #[arg(long)]
+        serial: Option<String>,
+        /// Destination index
+        #[arg(long)]
+        dest: usize,
+        /// Source index
+        #[arg(long)]
+        source: usize,
+    },
+}
+
+#[derive(clap::Args)]
+struct OutputTarget {
+    /// Serial number of the device (defaults to the first found)
+    #[arg(long)]
+    serial: Option<String>,
+    /// Output index
+    #[arg(long, default_value_t = 0)]
+    output: u8,
+}
+
+fn main() -> ExitCode {
+    // Logs go to stderr so they don't mix with the command output
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("warn")),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = Cli::parse();
+
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if cli.json {
+                println!("{}", json!({ "error": e.to_string(), "hint": e.hint() }));
+            } else {
+                eprintln!("Error: {}", e);
+                if let Some(hint) = e.hint() {
+                    eprintln!("Hint: {}", hint);
+                }
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: &Cli) -> Result<()> {
+    let mut detector = DeviceDetector::default();
+    if cli.detach_kernel_driver {
+        detector.set_kernel_driver_policy(KernelDriverPolicy::Detach);
+    }
+    detector.set_usb_trace(cli.trace_usb.clone());
+
+    match &cli.command {
+        Command::List => {
+            let devices = detector.scan_devices()?;
+            if cli.json {
+                println!("{}", json!(devices));
+            } else {
+                for device in &devices {
+                    println!("{}\t{}\t{}", device.serial_number, device.model.name(), device.usb_path);
+                }
+            }
+        }
+        Command::Volume { target, set, adjust } => {
+            let mut device = open(&detector, target.serial.as_deref())?;
+
+            let volume_db = match (set, adjust) {
+                (Some(db), _) => {
+                    let db = device.volume_scale().clamp(*db);
+                    device.set_volume(target.output, db)?;
+                    db
+                }
+                (None, Some(delta)) => device.adjust_volume(target.output, *delta)?,
+                (None, None) => device.get_volume(target.output)?,
+            };
+
+            if cli.json {
+                println!("{}", json!({ "output": target.output, "volume_db": volume_db }));
+            } else {
+                println!("{:.1}", volume_db);
+            }
+        }
+        Command::Mute { target, on, off, toggle } => {
+            let mut device = open(&detector, target.serial.as_deref())?;
+
+            let muted = if *toggle {
+                device.toggle_mute(target.output)?
+            } else if *on || *off {
+                device.set_mute(target.output, *on)?;
+                *on
+            } else {
+                device.get_mute(target.output)?
+            };
+
+            if cli.json {
+                println!("{}", json!({ "output": target.output, "muted": muted }));
+            } else {
+                println!("{}", if muted { "muted" } else { "unmuted" });
+            }
+        }
+        Command::Route { serial, dest, source } => {
+            let mut device = open(&detector, serial.as_deref())?;
+            let (dest_name, source_name) = route(&mut device, *dest, *source)?;
+
+            if cli.json {
+                println!(
+                    "{}",
+                    json!({ "dest": dest, "dest_name": dest_name, "source": source, "source_name": source_name })
+                );
+            } else {
+                println!("{} <- {}", dest_name, source_name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Route `source` to `dest`, returning their port names
+///
+/// The routing table is read back first, so the indexes are checked
+/// against the device's own ports and the other routes are kept.
+fn route(device: &mut UsbDevice, dest: usize, source: usize) -> Result<(String, String)> {
+    if !device.capabilities().mux {
+        return Err(Error::NotSupported(format!(
+            "{} has no routing matrix",
+            device.info().model.name()
+        )));
+    }
+
+    let protocol = device.protocol();
+    let mut matrix = protocol.get_routing()?;
+    matrix.set_route(dest, Some(source))?;
+    protocol.set_routing(&matrix)?;
+
+    Ok((matrix.destinations[dest].name.clone(), matrix.sources[source].name.clone()))
+}
+
+/// Open the device with `serial`, or the first one found
+fn open(detector: &DeviceDetector, serial: Option<&str>) -> Result<UsbDevice> {
+    let info: DeviceInfo = match serial {
+        Some(serial) => detector.find_device_by_serial(serial)?,
+        None => detector.scan_devices()?.into_iter().next(),
+    }
+    .ok_or(Error::DeviceNotFound)?;
+
+    detector.open_device(&info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scarlett_usb::virtual_device::parse_virtual_devices;
+
+    #[test]
+    fn test_route_virtual_device() {
+        let mut device = UsbDevice::open_virtual(parse_virtual_devices("18i20g4").remove(0));
+        let before = device.protocol().get_routing().unwrap();
+
+        let (dest_name, source_name) = route(&mut device, 2, 5).unwrap();
+        let after = device.protocol().get_routing().unwrap();
+        assert_eq!(after.get_route(2), Some(5));
+        assert_eq!(dest_name, after.destinations[2].name);
+        assert_eq!(source_name, after.sources[5].name);
+
+        // Only the one destination changes
+        let changes = before.diff(&after);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].dest_idx, 2);
+
+        // Out of range ports are rejected without touching the device
+        let dests = after.destinations.len();
+        let sources = after.sources.len();
+        assert!(matches!(route(&mut device, dests, 0), Err(Error::InvalidParameter(_))));
+        assert!(matches!(route(&mut device, 0, sources), Err(Error::InvalidParameter(_))));
+        assert!(device.protocol().get_routing().unwrap().diff(&after).is_empty());
+    }
+
+    #[test]
+    fn test_route_without_mux() {
+        let mut device = UsbDevice::open_virtual(parse_virtual_devices("solog4").remove(0));
+        assert!(matches!(route(&mut device, 0, 0), Err(Error::NotSupported(_))));
+    }
+}
@@ -1,10 +1,15 @@
 //! Configuration management
 
 use directories::ProjectDirs;
-use scarlett_core::{Error, Result};
+use scarlett_core::{DeviceModel, Error, Result};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use tracing::{debug, info};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// Schema version of exported device configs
+pub const EXPORT_SCHEMA_VERSION: u32 = 1;
 
 /// Application preferences
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +22,10 @@ pub struct Preferences {
     pub last_device_serial: Option<String>,
     /// Window positions and sizes
     pub window_geometry: WindowGeometry,
+    /// Detach the kernel driver (snd-usb-audio on Linux) if it owns the
+    /// control interface; audio stops while the device is open
+    #[serde(default)]
+    pub detach_kernel_driver: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +48,7 @@ impl Default for Preferences {
                 main_width: 800,
                 main_height: 600,
             },
+            detach_kernel_driver: false,
         }
     }
 }
@@ -69,14 +79,10 @@ impl ConfigManager {
     pub fn load_preferences(&self) -> Result<Preferences> {
         let path = self.config_dir.join("preferences.ron");
 
-        if !path.exists() {
+        let Some(prefs) = read_ron(&path, "preferences")? else {
             debug!("No preferences file found, using defaults");
             return Ok(Preferences::default());
-        }
-
-        let contents = std::fs::read_to_string(&path)?;
-        let prefs = ron::from_str(&contents)
-            .map_err(|e| Error::Config(format!("Failed to parse preferences: {}", e)))?;
+        };
 
         info!("Loaded preferences from {:?}", path);
         Ok(prefs)
@@ -89,7 +95,7 @@ impl ConfigManager {
         let contents = ron::ser::to_string_pretty(prefs, Default::default())
             .map_err(|e| Error::Config(format!("Failed to serialize preferences: {}", e)))?;
 
-        std::fs::write(&path, contents)?;
+        write_atomic(&path, &contents)?;
         info!("Saved preferences to {:?}", path);
         Ok(())
     }
@@ -103,14 +109,10 @@ impl ConfigManager {
     pub fn load_device_config(&self, serial: &str) -> Result<DeviceConfig> {
         let path = self.device_config_path(serial);
 
-        if !path.exists() {
+        let Some(config) = read_ron(&path, "device config")? else {
             debug!("No device config found for {}, using defaults", serial);
             return Ok(DeviceConfig::default());
-        }
-
-        let contents = std::fs::read_to_string(&path)?;
-        let config = ron::from_str(&contents)
-            .map_err(|e| Error::Config(format!("Failed to parse device config: {}", e)))?;
+        };
 
         info!("Loaded device config for {} from {:?}", serial, path);
         Ok(config)
@@ -123,10 +125,191 @@ impl ConfigManager {
         let contents = ron::ser::to_string_pretty(config, Default::default())
             .map_err(|e| Error::Config(format!("Failed to serialize device config: {}", e)))?;
 
-        std::fs::write(&path, contents)?;
+        write_atomic(&path, &contents)?;
         info!("Saved device config for {} to {:?}", serial, path);
         Ok(())
     }
+
+    /// Get the path of a named preset
+    ///
+    /// Serials and preset names may only use letters, digits, spaces, `-`,
+    /// `_` and `.`, and can't start with `.`, so they can't escape the
+    /// config directory.
+    pub fn preset_path(&self, serial: &str, name: &str) -> Result<PathBuf> {
+        check_file_name_part("device serial", serial)?;
+        check_file_name_part("preset name", name)?;
+
+        Ok(self.config_dir.join(format!("device-{}-preset-{}.ron", serial, name)))
+    }
+
+    /// Save a device configuration as a named preset
+    pub fn save_preset(&self, serial: &str, name: &str, config: &DeviceConfig) -> Result<()> {
+        let path = self.preset_path(serial, name)?;
+
+        let contents = ron::ser::to_string_pretty(config, Default::default())
+            .map_err(|e| Error::Config(format!("Failed to serialize preset: {}", e)))?;
+
+        write_atomic(&path, &contents)?;
+        info!("Saved preset {:?} for {} to {:?}", name, serial, path);
+        Ok(())
+    }
+
+    /// Load a named preset
+    pub fn load_preset(&self, serial: &str, name: &str) -> Result<DeviceConfig> {
+        let path = self.preset_path(serial, name)?;
+
+        let config = read_ron(&path, "preset")?
+            .ok_or_else(|| Error::Config(format!("No preset {:?} for {}", name, serial)))?;
+
+        info!("Loaded preset {:?} for {} from {:?}", name, serial, path);
+        Ok(config)
+    }
+
+    /// List the names of a device's presets, sorted
+    pub fn list_presets(&self, serial: &str) -> Result<Vec<String>> {
+        check_file_name_part("device serial", serial)?;
+        let prefix = format!("device-{}-preset-", serial);
+
+        let mut names: Vec<String> = std::fs::read_dir(&self.config_dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name().into_string().ok()?;
+                let name = file_name.strip_prefix(&prefix)?.strip_suffix(".ron")?;
+                Some(name.to_string())
+            })
+            .collect();
+
+        names.sort();
+        Ok(names)
+    }
+
+    /// Delete a named preset
+    pub fn delete_preset(&self, serial: &str, name: &str) -> Result<()> {
+        let path = self.preset_path(serial, name)?;
+
+        std::fs::remove_file(&path)?;
+        let _ = std::fs::remove_file(backup_path(&path));
+        info!("Deleted preset {:?} for {}", name, serial);
+        Ok(())
+    }
+
+    /// Export a device's configuration as portable JSON
+    pub fn export_device_config(&self, serial: &str, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let config = self.load_device_config(serial)?;
+
+        let exported = ExportedDeviceConfig {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            model: config.model.map(|m| m.name().to_string()),
+            config,
+        };
+        let contents = serde_json::to_string_pretty(&exported)
+            .map_err(|e| Error::Config(format!("Failed to serialize device config: {}", e)))?;
+
+        std::fs::write(path, contents)?;
+        info!("Exported device config for {} to {:?}", serial, path);
+        Ok(())
+    }
+
+    /// Import a device configuration exported with `export_device_config`
+    ///
+    /// Check the result against the target device with
+    /// `DeviceConfig::check_model` before applying it.
+    pub fn import_device_config(&self, path: impl AsRef<Path>) -> Result<DeviceConfig> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        let exported: ExportedDeviceConfig = serde_json::from_str(&contents)
+            .map_err(|e| Error::Config(format!("Failed to parse exported device config: {}", e)))?;
+
+        if exported.schema_version > EXPORT_SCHEMA_VERSION {
+            return Err(Error::Config(format!(
+                "Exported device config has schema version {}, newest supported is {}",
+                exported.schema_version, EXPORT_SCHEMA_VERSION
+            )));
+        }
+
+        info!("Imported device config from {:?}", path);
+        Ok(exported.config)
+    }
+}
+
+/// Device configuration as written by `export_device_config`
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedDeviceConfig {
+    schema_version: u32,
+    /// Model name, for people reading the file
+    model: Option<String>,
+    config: DeviceConfig,
+}
+
+/// Path of the backup kept next to a config file
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Replace a config file without ever leaving it half-written
+///
+/// The new contents go to a temporary file in the same directory, which is
+/// then renamed over the target. The previous version is kept as a `.bak`
+/// copy for `read_ron` to fall back on.
+fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    {
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+
+    if path.exists() {
+        std::fs::copy(path, backup_path(path))?;
+    }
+
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Check that `value` is safe to use as part of a file name in the config
+/// directory
+fn check_file_name_part(what: &str, value: &str) -> Result<()> {
+    let valid = !value.is_empty()
+        && !value.starts_with('.')
+        && value.chars().all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.'));
+    if !valid {
+        return Err(Error::InvalidParameter(format!("Invalid {}: {:?}", what, value)));
+    }
+    Ok(())
+}
+
+/// Read a RON config file, falling back to its backup if it's corrupt
+///
+/// Returns `None` if neither file exists.
+fn read_ron<T: DeserializeOwned>(path: &Path, what: &str) -> Result<Option<T>> {
+    let backup = backup_path(path);
+
+    let error = match std::fs::read_to_string(path) {
+        Ok(contents) => match ron::from_str(&contents) {
+            Ok(value) => return Ok(Some(value)),
+            Err(e) => Error::Config(format!("Failed to parse {}: {}", what, e)),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !backup.exists() => return Ok(None),
+        Err(e) => e.into(),
+    };
+
+    let Ok(contents) = std::fs::read_to_string(&backup) else {
+        return Err(error);
+    };
+    match ron::from_str(&contents) {
+        Ok(value) => {
+            warn!("{} ({:?}), using backup {:?}", error, path, backup);
+            Ok(Some(value))
+        }
+        Err(_) => Err(error),
+    }
 }
 
 impl Default for ConfigManager {
@@ -140,6 +323,40 @@ impl Default for ConfigManager {
 pub struct DeviceConfig {
     pub routing: scarlett_core::routing::RoutingMatrix,
     pub mixer: scarlett_core::mixer::MixerState,
+    /// Keep routing active with no host connected
+    #[serde(default)]
+    pub standalone: bool,
+    /// Restore the 48V phantom power state at power-on
+    #[serde(default)]
+    pub phantom_persistence: bool,
+    /// Model the configuration was made on
+    #[serde(default)]
+    pub model: Option<DeviceModel>,
+    /// Output pairs whose volume and mute are set together
+    #[serde(default)]
+    pub output_links: Vec<(u8, u8)>,
+}
+
+impl DeviceConfig {
+    /// Check that the configuration fits a device's channel layout
+    ///
+    /// Configs from another model are rejected, since their routing and
+    /// mixer channels won't line up. Configs that don't record a model are
+    /// allowed with a warning.
+    pub fn check_model(&self, model: DeviceModel) -> Result<()> {
+        match self.model {
+            Some(config_model) if config_model != model => Err(Error::Config(format!(
+                "Device config is for {}, not {}",
+                config_model.name(),
+                model.name()
+            ))),
+            Some(_) => Ok(()),
+            None => {
+                warn!("Device config has no model, assuming it fits {}", model.name());
+                Ok(())
+            }
+        }
+    }
 }
 
 impl Default for DeviceConfig {
@@ -147,6 +364,117 @@ impl Default for DeviceConfig {
         Self {
             routing: scarlett_core::routing::RoutingMatrix::new(),
             mixer: scarlett_core::mixer::MixerState::new(),
+            standalone: false,
+            phantom_persistence: false,
+            model: None,
+            output_links: Vec::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_manager(test: &str) -> (ConfigManager, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("scarlett-config-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        (ConfigManager { config_dir: dir.clone() }, dir)
+    }
+
+    #[test]
+    fn test_presets() {
+        let (manager, dir) = temp_manager("presets");
+
+        let mut config = DeviceConfig::default();
+        manager.save_preset("ABC123", "Show", &config).unwrap();
+        config.standalone = true;
+        manager.save_preset("ABC123", "Sound check", &config).unwrap();
+        manager.save_preset("XYZ789", "Recording", &config).unwrap();
+
+        assert_eq!(manager.list_presets("ABC123").unwrap(), vec!["Show", "Sound check"]);
+        assert!(manager.load_preset("ABC123", "Sound check").unwrap().standalone);
+        assert!(manager.load_preset("ABC123", "Recording").is_err());
+
+        for name in ["", "../evil", "a/b", ".hidden"] {
+            assert!(manager.save_preset("ABC123", name, &config).is_err());
+            assert!(manager.save_preset(name, "Show", &config).is_err());
+            assert!(manager.list_presets(name).is_err());
+        }
+
+        manager.delete_preset("ABC123", "Show").unwrap();
+        assert_eq!(manager.list_presets("ABC123").unwrap(), vec!["Sound check"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_preferences_without_detach_kernel_driver() {
+        let (manager, dir) = temp_manager("prefs-compat");
+
+        // Saved before the option existed
+        std::fs::write(
+            dir.join("preferences.ron"),
+            "(enable_hotkeys: true, volume_step_db: 1.5, last_device_serial: None, \
+             window_geometry: (main_x: 0, main_y: 0, main_width: 800, main_height: 600))",
+        )
+        .unwrap();
+        let prefs = manager.load_preferences().unwrap();
+        assert_eq!(prefs.volume_step_db, 1.5);
+        assert!(!prefs.detach_kernel_driver);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_config_falls_back_to_backup() {
+        let (manager, dir) = temp_manager("backup");
+
+        let mut prefs = Preferences { volume_step_db: 2.0, ..Default::default() };
+        manager.save_preferences(&prefs).unwrap();
+        prefs.volume_step_db = 3.0;
+        manager.save_preferences(&prefs).unwrap();
+        assert_eq!(manager.load_preferences().unwrap().volume_step_db, 3.0);
+
+        // Truncated by a crash: the previous save is used instead
+        std::fs::write(dir.join("preferences.ron"), "(enable_hotkeys: tr").unwrap();
+        assert_eq!(manager.load_preferences().unwrap().volume_step_db, 2.0);
+
+        std::fs::write(dir.join("preferences.ron.bak"), "").unwrap();
+        assert!(manager.load_preferences().is_err());
+        assert!(!dir.join("preferences.ron.tmp").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_import() {
+        let (manager, dir) = temp_manager("export");
+
+        let mut config = DeviceConfig {
+            standalone: true,
+            phantom_persistence: true,
+            model: Some(DeviceModel::Scarlett18i20Gen4),
+            ..DeviceConfig::default()
+        };
+        config.mixer.master_volume_db = -6.0;
+        manager.save_device_config("ABC123", &config).unwrap();
+
+        let path = dir.join("preset.json");
+        manager.export_device_config("ABC123", &path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("\"schema_version\": 1"));
+        assert!(contents.contains(DeviceModel::Scarlett18i20Gen4.name()));
+
+        let imported = manager.import_device_config(&path).unwrap();
+        assert!(imported.standalone && imported.phantom_persistence);
+        assert_eq!(imported.mixer.master_volume_db, -6.0);
+        assert!(imported.check_model(DeviceModel::Scarlett18i20Gen4).is_ok());
+        assert!(imported.check_model(DeviceModel::Scarlett2i2Gen4).is_err());
+
+        std::fs::write(&path, contents.replace("\"schema_version\": 1", "\"schema_version\": 99")).unwrap();
+        assert!(manager.import_device_config(&path).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
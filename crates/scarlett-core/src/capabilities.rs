@@ -0,0 +1,117 @@
+//! Device capabilities
+
+use crate::device::{DeviceGeneration, DeviceModel};
+use serde::{Deserialize, Serialize};
+
+/// What a device can do, and how many channels it has
+///
+/// Gen 4 FCP devices report most of this over CapRead; other devices use
+/// the static per-model tables in `for_model`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceCapabilities {
+    /// Level meters available
+    pub meter: bool,
+    /// Mixer available
+    pub mix: bool,
+    /// Routing (mux) available
+    pub mux: bool,
+    /// Flash access available
+    pub flash: bool,
+    /// Clock sync status available
+    pub sync: bool,
+    /// ESP32 firmware update available
+    pub esp_dfu: bool,
+    /// Software-switchable 48V phantom power
+    pub phantom: bool,
+    /// Software-switchable Air
+    pub air: bool,
+    /// Built-in talkback microphone
+    pub talkback: bool,
+    /// On-board input DSP (compressor, EQ)
+    pub dsp: bool,
+    /// Number of input pairs that can be stereo-linked
+    #[serde(default)]
+    pub input_link_pairs: u8,
+    /// Number of analogue and digital inputs
+    pub num_inputs: u8,
+    /// Number of analogue and digital outputs
+    pub num_outputs: u8,
+    /// Number of meter slots
+    pub num_meters: u8,
+    /// Number of mixer outputs (mixes)
+    pub num_mixer_outputs: u8,
+    /// Number of mixer inputs
+    pub num_mixer_inputs: u8,
+    /// Mux table size for each sample rate band (1x, 2x, 4x)
+    pub mux_sizes: [u16; 3],
+}
+
+impl DeviceCapabilities {
+    /// Static capabilities of a model, for devices without CapRead
+    pub fn for_model(model: DeviceModel) -> Self {
+        use DeviceModel::*;
+
+        let (num_inputs, num_outputs) = match model {
+            Scarlett2i2Gen3 | Scarlett2i2Gen4 => (2, 2),
+            Scarlett4i4Gen3 | Scarlett4i4Gen4 => (4, 4),
+            Scarlett6i6Gen2 => (6, 6),
+            Scarlett8i6Gen3 => (8, 6),
+            Scarlett18i8Gen2 | Scarlett18i8Gen3 => (18, 8),
+            Scarlett18i20Gen2 | Scarlett18i20Gen3 | Scarlett18i20Gen4 => (18, 20),
+            Scarlett16i16Gen4 => (16, 16),
+            Scarlett18i16Gen4 => (18, 16),
+            _ => (0, 0),
+        };
+
+        let num_mixer_inputs = match model {
+            Scarlett6i6Gen2 | Scarlett18i8Gen2 | Scarlett18i20Gen2 => 18,
+            Scarlett18i20Gen3 | Scarlett18i20Gen4 => 25,
+            Scarlett4i4Gen3 | Scarlett4i4Gen4 => 8,
+            Scarlett8i6Gen3 => 18,
+            Scarlett18i8Gen3 => 20,
+            Scarlett16i16Gen4 => 18,
+            Scarlett18i16Gen4 => 20,
+            _ => 0,
+        };
+
+        // Solo and 2i2 don't have mixers or routing
+        let mix = !matches!(model, ScarlettSoloGen3 | Scarlett2i2Gen3 | ScarlettSoloGen4 | Scarlett2i2Gen4);
+
+        Self {
+            meter: model.generation() != DeviceGeneration::Gen1,
+            mix,
+            mux: mix,
+            phantom: model.phantom_count() > 0,
+            air: !model.air_inputs().is_empty(),
+            talkback: model.has_talkback(),
+            dsp: model.generation() == DeviceGeneration::Vocaster,
+            input_link_pairs: model.input_link_pairs(),
+            esp_dfu: matches!(model, Scarlett16i16Gen4 | Scarlett18i16Gen4 | Scarlett18i20Gen4),
+            num_inputs,
+            num_outputs,
+            num_mixer_inputs: if mix { num_mixer_inputs } else { 0 },
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_model() {
+        let caps = DeviceCapabilities::for_model(DeviceModel::Scarlett18i20Gen3);
+        assert!(caps.mix && caps.mux && caps.talkback && caps.phantom);
+        assert_eq!((caps.num_inputs, caps.num_outputs, caps.num_mixer_inputs), (18, 20, 25));
+
+        // Gen 2 mixes all have 18 inputs
+        for model in [DeviceModel::Scarlett6i6Gen2, DeviceModel::Scarlett18i8Gen2, DeviceModel::Scarlett18i20Gen2] {
+            assert_eq!(DeviceCapabilities::for_model(model).num_mixer_inputs, 18);
+        }
+
+        let caps = DeviceCapabilities::for_model(DeviceModel::Scarlett2i2Gen4);
+        assert!(!caps.mix && !caps.mux && caps.air);
+        assert_eq!(caps.num_mixer_inputs, 0);
+    }
+}
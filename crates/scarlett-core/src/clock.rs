@@ -0,0 +1,196 @@
+//! Clock and sync types
+
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Interface sample rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum SampleRate {
+    /// 44.1 kHz
+    Hz44100,
+    /// 48 kHz
+    Hz48000,
+    /// 88.2 kHz
+    Hz88200,
+    /// 96 kHz
+    Hz96000,
+    /// 176.4 kHz
+    Hz176400,
+    /// 192 kHz
+    Hz192000,
+}
+
+impl SampleRate {
+    /// All sample rates, lowest first
+    pub const ALL: [SampleRate; 6] = [
+        Self::Hz44100,
+        Self::Hz48000,
+        Self::Hz88200,
+        Self::Hz96000,
+        Self::Hz176400,
+        Self::Hz192000,
+    ];
+
+    /// Rate in Hz
+    pub fn hz(&self) -> u32 {
+        match self {
+            Self::Hz44100 => 44100,
+            Self::Hz48000 => 48000,
+            Self::Hz88200 => 88200,
+            Self::Hz96000 => 96000,
+            Self::Hz176400 => 176400,
+            Self::Hz192000 => 192000,
+        }
+    }
+
+    /// Convert from a rate in Hz
+    pub fn from_hz(hz: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|rate| rate.hz() == hz)
+    }
+}
+
+impl fmt::Display for SampleRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hz = self.hz();
+        if hz.is_multiple_of(1000) {
+            write!(f, "{} kHz", hz / 1000)
+        } else {
+            write!(f, "{:.1} kHz", hz as f64 / 1000.0)
+        }
+    }
+}
+
+/// Source the device takes its sample clock from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClockSource {
+    /// Internal clock
+    Internal,
+    /// S/PDIF input
+    Spdif,
+    /// ADAT input
+    Adat,
+    /// Word clock input
+    Wordclock,
+}
+
+impl ClockSource {
+    /// Convert from the device's clock source value
+    pub fn from_raw(raw: i32) -> Option<Self> {
+        match raw {
+            0 => Some(Self::Internal),
+            1 => Some(Self::Spdif),
+            2 => Some(Self::Adat),
+            3 => Some(Self::Wordclock),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ClockSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Internal => write!(f, "Internal"),
+            Self::Spdif => write!(f, "S/PDIF"),
+            Self::Adat => write!(f, "ADAT"),
+            Self::Wordclock => write!(f, "Word Clock"),
+        }
+    }
+}
+
+/// Clock sync status as reported by the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncStatus {
+    /// Whether the device is locked to its clock source
+    pub locked: bool,
+    /// Current sample rate in Hz, if known
+    pub sample_rate: Option<u32>,
+    /// Current clock source, if known
+    #[serde(default)]
+    pub clock_source: Option<ClockSource>,
+}
+
+impl SyncStatus {
+    /// Parse a GET_SYNC response (le32, nonzero when locked)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 4 {
+            return Err(Error::Protocol(format!(
+                "Sync status response too short: {} bytes",
+                bytes.len()
+            )));
+        }
+
+        let raw = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+        Ok(Self {
+            locked: raw != 0,
+            sample_rate: None,
+            clock_source: None,
+        })
+    }
+
+    /// Attach a known sample rate
+    pub fn with_sample_rate(mut self, rate: u32) -> Self {
+        self.sample_rate = Some(rate);
+        self
+    }
+
+    /// Attach a known clock source
+    pub fn with_clock_source(mut self, source: ClockSource) -> Self {
+        self.clock_source = Some(source);
+        self
+    }
+}
+
+impl fmt::Display for SyncStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.locked { "Locked" } else { "Unlocked" };
+        if let Some(source) = self.clock_source {
+            write!(f, "{}: ", source)?;
+        }
+        match self.sample_rate {
+            Some(rate) if rate % 1000 == 0 => write!(f, "{} kHz, {}", rate / 1000, state),
+            Some(rate) => write!(f, "{:.1} kHz, {}", rate as f64 / 1000.0, state),
+            None => write!(f, "{}", state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sync_status() {
+        let locked = SyncStatus::from_bytes(&1u32.to_le_bytes()).unwrap();
+        assert!(locked.locked);
+        assert_eq!(locked.sample_rate, None);
+
+        let unlocked = SyncStatus::from_bytes(&[0, 0, 0, 0]).unwrap();
+        assert!(!unlocked.locked);
+
+        assert!(SyncStatus::from_bytes(&[1]).is_err());
+
+        assert_eq!(ClockSource::from_raw(3), Some(ClockSource::Wordclock));
+        assert_eq!(ClockSource::from_raw(4), None);
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        assert_eq!(SampleRate::from_hz(88200), Some(SampleRate::Hz88200));
+        assert_eq!(SampleRate::from_hz(32000), None);
+        assert_eq!(SampleRate::Hz176400.to_string(), "176.4 kHz");
+        assert_eq!(SampleRate::Hz96000.to_string(), "96 kHz");
+    }
+
+    #[test]
+    fn test_sync_status_display() {
+        let status = SyncStatus::from_bytes(&1u32.to_le_bytes()).unwrap();
+        assert_eq!(status.to_string(), "Locked");
+        assert_eq!(status.with_sample_rate(48000).to_string(), "48 kHz, Locked");
+        let status = SyncStatus { locked: false, sample_rate: Some(44100), clock_source: None };
+        assert_eq!(status.to_string(), "44.1 kHz, Unlocked");
+        let status = status.with_clock_source(ClockSource::Adat);
+        assert_eq!(status.to_string(), "ADAT: 44.1 kHz, Unlocked");
+    }
+}
@@ -1,5 +1,7 @@
 //! Device models and information
 
+use crate::clock::SampleRate;
+use crate::monitor::DirectMonitorMode;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -62,6 +64,48 @@ pub enum DeviceModel {
 }
 
 impl DeviceModel {
+    /// Every supported model
+    pub const ALL: [DeviceModel; 28] = [
+        Self::Scarlett6i6Gen1,
+        Self::Scarlett8i6Gen1,
+        Self::Scarlett18i6Gen1,
+        Self::Scarlett18i8Gen1,
+        Self::Scarlett18i20Gen1,
+        Self::Scarlett6i6Gen2,
+        Self::Scarlett18i8Gen2,
+        Self::Scarlett18i20Gen2,
+        Self::ScarlettSoloGen3,
+        Self::Scarlett2i2Gen3,
+        Self::Scarlett4i4Gen3,
+        Self::Scarlett8i6Gen3,
+        Self::Scarlett18i8Gen3,
+        Self::Scarlett18i20Gen3,
+        Self::ScarlettSoloGen4,
+        Self::Scarlett2i2Gen4,
+        Self::Scarlett4i4Gen4,
+        Self::Scarlett16i16Gen4,
+        Self::Scarlett18i16Gen4,
+        Self::Scarlett18i20Gen4,
+        Self::Clarett2PreUsb,
+        Self::Clarett4PreUsb,
+        Self::Clarett8PreUsb,
+        Self::Clarett2PrePlus,
+        Self::Clarett4PrePlus,
+        Self::Clarett8PrePlus,
+        Self::VocasterOne,
+        Self::VocasterTwo,
+    ];
+
+    /// Every supported model, for enumerating in order
+    pub fn all() -> &'static [DeviceModel] {
+        &Self::ALL
+    }
+
+    /// Get the sources and destinations the device can route between
+    pub fn port_layout(&self) -> crate::routing::PortLayout {
+        crate::routing::PortLayout::for_model(*self)
+    }
+
     /// Get the device generation
     pub fn generation(&self) -> DeviceGeneration {
         match self {
@@ -89,6 +133,17 @@ impl DeviceModel {
         }
     }
 
+    /// Check if the device is controlled with FCP rather than Scarlett2
+    ///
+    /// Only the big Gen 4 interfaces speak FCP; the Solo, 2i2 and 4i4 Gen 4
+    /// use the Scarlett2 protocol like Gen 3.
+    pub fn uses_fcp(&self) -> bool {
+        matches!(
+            self,
+            Self::Scarlett16i16Gen4 | Self::Scarlett18i16Gen4 | Self::Scarlett18i20Gen4
+        )
+    }
+
     /// Get the USB Product ID for this device
     pub fn product_id(&self) -> u16 {
         match self {
@@ -176,6 +231,162 @@ impl DeviceModel {
         }
     }
 
+    /// Get the number of inputs with software-controllable preamp gain
+    pub fn gain_input_count(&self) -> u8 {
+        match self {
+            Self::Scarlett2i2Gen4 | Self::Scarlett4i4Gen4 | Self::Scarlett16i16Gen4 => 2,
+            Self::Scarlett18i16Gen4 => 4,
+            Self::Scarlett18i20Gen4 => 8,
+            Self::VocasterOne => 1,
+            Self::VocasterTwo => 2,
+            _ => 0,
+        }
+    }
+
+    /// Get the number of input pairs (1+2, 3+4, ...) that can be stereo-linked
+    ///
+    /// Linked inputs share gain, Air and autogain settings.
+    pub fn input_link_pairs(&self) -> u8 {
+        match self {
+            Self::Scarlett2i2Gen4 | Self::Scarlett4i4Gen4 => 1,
+            _ => 0,
+        }
+    }
+
+    /// Get the preamp gain range in dB, if the device has software gain control
+    pub fn input_gain_range(&self) -> Option<(u8, u8)> {
+        match self.generation() {
+            _ if self.gain_input_count() == 0 => None,
+            DeviceGeneration::Vocaster => Some((0, 70)),
+            _ => Some((0, 69)),
+        }
+    }
+
+    /// Get the number of software-switchable 48V phantom power switches
+    pub fn phantom_count(&self) -> u8 {
+        match self {
+            Self::ScarlettSoloGen3 | Self::Scarlett2i2Gen3 | Self::Scarlett4i4Gen3
+            | Self::Scarlett8i6Gen3 => 1,
+            Self::Scarlett18i8Gen3 | Self::Scarlett18i20Gen3 => 2,
+            Self::ScarlettSoloGen4 | Self::Scarlett2i2Gen4 => 1,
+            Self::Scarlett4i4Gen4 | Self::Scarlett16i16Gen4 => 2,
+            Self::Scarlett18i16Gen4 => 4,
+            Self::Scarlett18i20Gen4 => 8,
+            Self::VocasterOne => 1,
+            Self::VocasterTwo => 2,
+            _ => 0,
+        }
+    }
+
+    /// Get the inputs (0-based) controlled by a phantom power switch
+    ///
+    /// Some devices gang 48V across several inputs with a single switch,
+    /// e.g. inputs 1-4 and 5-8 on the 18i20 Gen 3.
+    pub fn phantom_group_inputs(&self, group: u8) -> Option<std::ops::Range<u8>> {
+        if group >= self.phantom_count() {
+            return None;
+        }
+
+        let (first, per_switch) = match self {
+            Self::Scarlett2i2Gen3 | Self::Scarlett4i4Gen3 | Self::Scarlett8i6Gen3
+            | Self::Scarlett18i8Gen3 | Self::Scarlett2i2Gen4 => (0, 2),
+            Self::Scarlett18i20Gen3 => (0, 4),
+            // The Solo Gen 4 mic preamp is on input 2
+            Self::ScarlettSoloGen4 => (1, 1),
+            _ => (0, 1),
+        };
+
+        let start = first + group * per_switch;
+        Some(start..start + per_switch)
+    }
+
+    /// Get the phantom power switch controlling an input (0-based)
+    pub fn phantom_group_for_input(&self, input: u8) -> Option<u8> {
+        (0..self.phantom_count())
+            .find(|&group| self.phantom_group_inputs(group).is_some_and(|r| r.contains(&input)))
+    }
+
+    /// Get the inputs (0-based) with a software-switchable Air control
+    pub fn air_inputs(&self) -> std::ops::Range<u8> {
+        match self {
+            Self::ScarlettSoloGen3 => 0..1,
+            Self::Scarlett2i2Gen3 | Self::Scarlett4i4Gen3 | Self::Scarlett8i6Gen3 => 0..2,
+            Self::Scarlett18i8Gen3 => 0..4,
+            Self::Scarlett18i20Gen3 => 0..8,
+            // The Solo Gen 4 mic preamp is on input 2
+            Self::ScarlettSoloGen4 => 1..2,
+            Self::Scarlett2i2Gen4 | Self::Scarlett4i4Gen4 | Self::Scarlett16i16Gen4 => 0..2,
+            Self::Scarlett18i16Gen4 => 0..4,
+            Self::Scarlett18i20Gen4 => 0..8,
+            Self::Clarett2PreUsb | Self::Clarett2PrePlus => 0..2,
+            Self::Clarett4PreUsb | Self::Clarett4PrePlus => 0..4,
+            Self::Clarett8PreUsb | Self::Clarett8PrePlus => 0..8,
+            _ => 0..0,
+        }
+    }
+
+    /// Inputs (0-based) that can be switched between Line and Inst level
+    pub fn level_inputs(&self) -> std::ops::Range<u8> {
+        match self {
+            Self::Scarlett6i6Gen2 | Self::Scarlett18i8Gen2 => 0..2,
+            // The Solo Gen 3 instrument input is input 2
+            Self::ScarlettSoloGen3 => 1..2,
+            Self::Scarlett2i2Gen3 | Self::Scarlett4i4Gen3 | Self::Scarlett8i6Gen3
+            | Self::Scarlett18i8Gen3 | Self::Scarlett18i20Gen3 => 0..2,
+            Self::ScarlettSoloGen4 => 0..1,
+            Self::Scarlett2i2Gen4 | Self::Scarlett4i4Gen4 => 0..2,
+            Self::Clarett2PreUsb | Self::Clarett4PreUsb | Self::Clarett8PreUsb
+            | Self::Clarett2PrePlus | Self::Clarett4PrePlus | Self::Clarett8PrePlus => 0..2,
+            _ => 0..0,
+        }
+    }
+
+    /// Inputs (0-based) that have a switchable -10 dB pad
+    pub fn pad_inputs(&self) -> std::ops::Range<u8> {
+        match self {
+            Self::Scarlett6i6Gen2 => 0..2,
+            Self::Scarlett18i8Gen2 => 0..4,
+            Self::Scarlett4i4Gen3 | Self::Scarlett8i6Gen3 => 0..2,
+            Self::Scarlett18i8Gen3 => 0..4,
+            Self::Scarlett18i20Gen3 => 0..8,
+            _ => 0..0,
+        }
+    }
+
+    /// Check if the Air control supports Presence + Drive (Gen 4)
+    pub fn has_air_drive(&self) -> bool {
+        self.generation() == DeviceGeneration::Gen4 && !self.air_inputs().is_empty()
+    }
+
+    /// Check if the device has a built-in talkback microphone
+    pub fn has_talkback(&self) -> bool {
+        matches!(self, Self::Scarlett18i20Gen3 | Self::Scarlett18i20Gen4)
+    }
+
+    /// Sample rates supported by the device
+    pub fn sample_rates(&self) -> &'static [SampleRate] {
+        match self.generation() {
+            // Gen 1 interfaces top out at 96 kHz
+            DeviceGeneration::Gen1 => &SampleRate::ALL[..4],
+            DeviceGeneration::Vocaster => &[SampleRate::Hz48000],
+            _ => &SampleRate::ALL,
+        }
+    }
+
+    /// Direct Monitor settings supported by the device
+    ///
+    /// The raw device value of a mode is its position in this list. Empty if
+    /// the device has no software Direct Monitor control.
+    pub fn direct_monitor_modes(&self) -> &'static [DirectMonitorMode] {
+        use DirectMonitorMode::*;
+        match self {
+            Self::ScarlettSoloGen3 | Self::ScarlettSoloGen4 => &[Off, On],
+            Self::Scarlett2i2Gen3 | Self::Scarlett2i2Gen4 => &[Off, Mono, Stereo],
+            // The 4i4 uses the mixer for monitoring instead
+            _ => &[],
+        }
+    }
+
     /// Try to identify a device model from USB Product ID
     pub fn from_product_id(pid: u16) -> Option<Self> {
         match pid {
@@ -241,6 +452,10 @@ pub struct DeviceInfo {
     pub serial_number: String,
     pub firmware_version: Option<String>,
     pub usb_path: String,
+    /// Device is in MSD ("Easy Start") mode; most controls are unavailable
+    /// until it is disabled
+    #[serde(default)]
+    pub msd_mode: bool,
 }
 
 impl DeviceInfo {
@@ -254,6 +469,7 @@ impl DeviceInfo {
             serial_number,
             firmware_version: None,
             usb_path,
+            msd_mode: false,
         }
     }
 }
@@ -281,3 +497,51 @@ pub trait Device: Send + Sync {
     /// Has routing matrix
     fn has_routing(&self) -> bool;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_product_ids() {
+        for &model in DeviceModel::all() {
+            assert_eq!(DeviceModel::from_product_id(model.product_id()), Some(model), "{}", model);
+        }
+
+        // Each model in all() reaches a distinct name() arm
+        let mut names: Vec<&str> = DeviceModel::all().iter().map(|m| m.name()).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), DeviceModel::all().len());
+
+        let mut pids: Vec<u16> = DeviceModel::all().iter().map(|m| m.product_id()).collect();
+        pids.sort_unstable();
+        pids.dedup();
+        assert_eq!(pids.len(), DeviceModel::all().len());
+
+        // From the kernel driver's device table
+        assert_eq!(DeviceModel::from_product_id(0x8217), Some(DeviceModel::VocasterTwo));
+        assert_eq!(DeviceModel::from_product_id(0x821a), Some(DeviceModel::Scarlett4i4Gen4));
+        assert_eq!(DeviceModel::from_product_id(0x820c), Some(DeviceModel::Clarett8PrePlus));
+        assert_eq!(DeviceModel::from_product_id(0x8201), Some(DeviceModel::Scarlett18i20Gen2));
+    }
+
+    #[test]
+    fn test_uses_fcp() {
+        let gen4 = [
+            (DeviceModel::ScarlettSoloGen4, false),
+            (DeviceModel::Scarlett2i2Gen4, false),
+            (DeviceModel::Scarlett4i4Gen4, false),
+            (DeviceModel::Scarlett16i16Gen4, true),
+            (DeviceModel::Scarlett18i16Gen4, true),
+            (DeviceModel::Scarlett18i20Gen4, true),
+        ];
+        for (model, fcp) in gen4 {
+            assert_eq!(model.generation(), DeviceGeneration::Gen4);
+            assert_eq!(model.uses_fcp(), fcp, "{}", model.name());
+        }
+
+        assert!(!DeviceModel::Scarlett18i20Gen3.uses_fcp());
+        assert!(!DeviceModel::Scarlett6i6Gen2.uses_fcp());
+    }
+}
@@ -1,5 +1,6 @@
 //! Error types for Scarlett operations
 
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -13,6 +14,17 @@ pub enum Error {
     #[error("Device not found")]
     DeviceNotFound,
 
+    #[error("Device disconnected")]
+    Disconnected,
+
+    /// Another driver has claimed the interface we need
+    #[error("Device busy: {0}")]
+    DeviceBusy(String),
+
+    /// A transfer got no answer in time; the device may just be busy
+    #[error("{0} timed out")]
+    Timeout(String),
+
     #[error("Invalid parameter: {0}")]
     InvalidParameter(String),
 
@@ -24,6 +36,101 @@ pub enum Error {
 
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
+
+    #[error("Device error: {code} ({context})")]
+    Device { code: FcpErrorCode, context: String },
+}
+
+impl Error {
+    /// Get the error code reported by the device, if any
+    pub fn device_code(&self) -> Option<FcpErrorCode> {
+        match self {
+            Self::Device { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Suggest what the user can do about the error, if there is something
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::Timeout(_) => return Some("The device didn't answer in time; try again"),
+            Self::Disconnected => return Some("Try again once the device is back"),
+            Self::DeviceBusy(_) => {
+                return Some("Allow detaching the kernel driver; audio stops while the device is open")
+            }
+            _ => {}
+        }
+
+        match self.device_code()? {
+            FcpErrorCode::InvalidState => Some("Reboot the device and try again"),
+            FcpErrorCode::Timeout => Some("The device is busy; try again"),
+            FcpErrorCode::InvalidUsbId | FcpErrorCode::InvalidHash => {
+                Some("The firmware file doesn't match this device")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// FCP Error codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i16)]
+pub enum FcpErrorCode {
+    InvalidMagic = 1,
+    InvalidCommand = 2,
+    InvalidLength = 3,
+    InvalidHash = 4,
+    InvalidUsbId = 5,
+    Config = 6,
+    Fcp = 7,
+    Timeout = 8,
+    Read = 9,
+    Write = 10,
+    NotLeapfrog = 11,
+    InvalidState = 12,
+}
+
+impl FcpErrorCode {
+    pub fn from_i16(val: i16) -> Option<Self> {
+        match val {
+            1 => Some(Self::InvalidMagic),
+            2 => Some(Self::InvalidCommand),
+            3 => Some(Self::InvalidLength),
+            4 => Some(Self::InvalidHash),
+            5 => Some(Self::InvalidUsbId),
+            6 => Some(Self::Config),
+            7 => Some(Self::Fcp),
+            8 => Some(Self::Timeout),
+            9 => Some(Self::Read),
+            10 => Some(Self::Write),
+            11 => Some(Self::NotLeapfrog),
+            12 => Some(Self::InvalidState),
+            _ => None,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::InvalidMagic => "Invalid magic byte",
+            Self::InvalidCommand => "Invalid command",
+            Self::InvalidLength => "Invalid length",
+            Self::InvalidHash => "Invalid hash",
+            Self::InvalidUsbId => "Invalid USB ID",
+            Self::Config => "Configuration error",
+            Self::Fcp => "FCP error",
+            Self::Timeout => "Timeout",
+            Self::Read => "Read error",
+            Self::Write => "Write error",
+            Self::NotLeapfrog => "Not leapfrog",
+            Self::InvalidState => "Invalid state",
+        }
+    }
+}
+
+impl fmt::Display for FcpErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,189 @@
+//! Input channel control types
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Preamp "Air" mode
+///
+/// Gen 3 and Clarett devices have a simple Air on/off switch; Gen 4 devices
+/// add a Presence + Drive setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AirMode {
+    /// Air off
+    Off,
+    /// Air presence (the only "on" setting on Gen 3)
+    Presence,
+    /// Air presence with harmonic drive (Gen 4 only)
+    PresenceDrive,
+}
+
+impl AirMode {
+    /// Decode a raw device value
+    pub fn from_raw(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Off),
+            1 => Some(Self::Presence),
+            2 => Some(Self::PresenceDrive),
+            _ => None,
+        }
+    }
+
+    /// Encode as a raw device value
+    pub fn to_raw(self) -> i32 {
+        match self {
+            Self::Off => 0,
+            Self::Presence => 1,
+            Self::PresenceDrive => 2,
+        }
+    }
+
+    /// Check if Air is enabled in any mode
+    pub fn is_on(self) -> bool {
+        self != Self::Off
+    }
+}
+
+impl fmt::Display for AirMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "Off"),
+            Self::Presence => write!(f, "Presence"),
+            Self::PresenceDrive => write!(f, "Presence + Drive"),
+        }
+    }
+}
+
+/// Input level setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputLevel {
+    /// Line level
+    Line,
+    /// Instrument (Hi-Z) level
+    Inst,
+}
+
+impl InputLevel {
+    /// Decode a raw device value
+    pub fn from_raw(value: i32) -> Self {
+        if value != 0 {
+            Self::Inst
+        } else {
+            Self::Line
+        }
+    }
+
+    /// Encode as a raw device value
+    pub fn to_raw(self) -> i32 {
+        match self {
+            Self::Line => 0,
+            Self::Inst => 1,
+        }
+    }
+}
+
+impl fmt::Display for InputLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Line => write!(f, "Line"),
+            Self::Inst => write!(f, "Inst"),
+        }
+    }
+}
+
+/// Hardware autogain status
+///
+/// Matches the states reported by 4th Gen devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AutogainStatus {
+    /// Autogain is in progress
+    Running,
+    /// Gain set successfully
+    Success,
+    /// Succeeded, but the signal's dynamic range was over the target
+    SuccessDrOver,
+    /// Succeeded, but gain was limited by the minimum gain setting
+    WarnMinGainLimit,
+    /// Failed: dynamic range under the target (signal too low)
+    FailDrUnder,
+    /// Failed: maximum gain reached without hitting the target
+    FailMaxGainLimit,
+    /// Failed: the input clipped
+    FailClipped,
+    /// Autogain was cancelled
+    Cancelled,
+    /// The device reported a status outside the known range
+    Invalid,
+}
+
+impl AutogainStatus {
+    /// Decode from the autogain switch and raw status values of a Gen 4 device
+    ///
+    /// While the switch is set autogain is running; afterwards the raw status
+    /// holds the result.
+    pub fn from_gen4_raw(switch: bool, raw: i32) -> Self {
+        if switch {
+            return Self::Running;
+        }
+
+        match raw {
+            0 => Self::Success,
+            1 => Self::SuccessDrOver,
+            2 => Self::WarnMinGainLimit,
+            3 => Self::FailDrUnder,
+            4 => Self::FailMaxGainLimit,
+            5 => Self::FailClipped,
+            6 => Self::Cancelled,
+            _ => Self::Invalid,
+        }
+    }
+
+    /// Check if autogain has finished (successfully or not)
+    pub fn is_finished(self) -> bool {
+        self != Self::Running
+    }
+
+    /// Check if autogain set the gain
+    pub fn is_success(self) -> bool {
+        matches!(self, Self::Success | Self::SuccessDrOver | Self::WarnMinGainLimit)
+    }
+}
+
+impl fmt::Display for AutogainStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Self::Running => "Running",
+            Self::Success => "Success",
+            Self::SuccessDrOver => "Success (DR over)",
+            Self::WarnMinGainLimit => "Minimum gain limit",
+            Self::FailDrUnder => "Failed (DR under)",
+            Self::FailMaxGainLimit => "Failed (maximum gain limit)",
+            Self::FailClipped => "Failed (clipped)",
+            Self::Cancelled => "Cancelled",
+            Self::Invalid => "Invalid",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_air_mode_raw_roundtrip() {
+        for mode in [AirMode::Off, AirMode::Presence, AirMode::PresenceDrive] {
+            assert_eq!(AirMode::from_raw(mode.to_raw()), Some(mode));
+        }
+        assert_eq!(AirMode::from_raw(3), None);
+    }
+
+    #[test]
+    fn test_autogain_status_decode() {
+        assert_eq!(AutogainStatus::from_gen4_raw(true, 5), AutogainStatus::Running);
+        assert_eq!(AutogainStatus::from_gen4_raw(false, 0), AutogainStatus::Success);
+        assert_eq!(AutogainStatus::from_gen4_raw(false, 5), AutogainStatus::FailClipped);
+        assert_eq!(AutogainStatus::from_gen4_raw(false, 42), AutogainStatus::Invalid);
+        assert!(AutogainStatus::WarnMinGainLimit.is_success());
+        assert!(!AutogainStatus::Running.is_finished());
+    }
+}
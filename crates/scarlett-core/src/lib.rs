@@ -7,9 +7,19 @@ pub mod protocol;
 pub mod routing;
 pub mod mixer;
 pub mod error;
+pub mod input;
+pub mod monitor;
+pub mod clock;
+pub mod capabilities;
+pub mod power;
 
+pub use capabilities::DeviceCapabilities;
 pub use device::{Device, DeviceInfo, DeviceGeneration, DeviceModel};
-pub use error::{Error, Result};
+pub use error::{Error, FcpErrorCode, Result};
+pub use input::{AirMode, AutogainStatus, InputLevel};
+pub use monitor::DirectMonitorMode;
+pub use clock::{ClockSource, SampleRate, SyncStatus};
+pub use power::PowerStatus;
 
 /// Focusrite USB Vendor ID
 pub const FOCUSRITE_VENDOR_ID: u16 = 0x1235;
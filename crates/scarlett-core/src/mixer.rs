@@ -1,5 +1,7 @@
 //! Mixer data structures
 
+use crate::routing::Port;
+use crate::{Error, Result};
 use serde::{Deserialize, Serialize};
 
 /// Mixer channel
@@ -43,6 +45,16 @@ impl MixerChannel {
     pub fn set_volume_linear(&mut self, gain: f32) {
         self.volume_db = linear_to_db(gain);
     }
+
+    /// Raw mixer gain to write to the device
+    pub fn mixer_gain(&self) -> i16 {
+        db_to_mixer_gain(self.volume_db)
+    }
+
+    /// Set volume from a raw mixer gain read from the device
+    pub fn set_mixer_gain(&mut self, raw: i16) {
+        self.volume_db = mixer_gain_to_db(raw);
+    }
 }
 
 /// Mixer state
@@ -54,6 +66,9 @@ pub struct MixerState {
     pub master_volume_db: f32,
     /// Master mute
     pub master_muted: bool,
+    /// Monitor dim
+    #[serde(default)]
+    pub dim: bool,
 }
 
 impl MixerState {
@@ -62,7 +77,109 @@ impl MixerState {
             channels: Vec::new(),
             master_volume_db: 0.0,
             master_muted: false,
+            dim: false,
+        }
+    }
+
+    fn channel_mut(&mut self, idx: usize) -> Result<&mut MixerChannel> {
+        let count = self.channels.len();
+        self.channels.get_mut(idx).ok_or_else(|| {
+            Error::InvalidParameter(format!("Mixer channel {} out of range ({} channels)", idx, count))
+        })
+    }
+
+    /// Link two channels as a stereo pair
+    ///
+    /// The second channel takes the first one's mute state.
+    pub fn link_stereo(&mut self, a: usize, b: usize) -> Result<()> {
+        if a == b {
+            return Err(Error::InvalidParameter(format!("Cannot link mixer channel {} to itself", a)));
+        }
+
+        for (idx, partner) in [(a, b), (b, a)] {
+            if let Some(existing) = self.channel_mut(idx)?.stereo_pair {
+                if existing != partner {
+                    return Err(Error::InvalidParameter(format!(
+                        "Mixer channel {} is already linked to channel {}",
+                        idx, existing
+                    )));
+                }
+            }
         }
+
+        let muted = self.channels[a].muted;
+        self.channels[a].stereo_pair = Some(b);
+        self.channels[b].stereo_pair = Some(a);
+        self.channels[b].muted = muted;
+        Ok(())
+    }
+
+    /// Unlink a channel from its stereo pair, if it has one
+    pub fn unlink_stereo(&mut self, idx: usize) -> Result<()> {
+        if let Some(partner) = self.channel_mut(idx)?.stereo_pair.take() {
+            if let Some(channel) = self.channels.get_mut(partner) {
+                channel.stereo_pair = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the volume of a channel and its stereo partner
+    ///
+    /// Pan is left alone, so the pair keeps its stereo image.
+    pub fn set_linked_volume(&mut self, idx: usize, db: f32) -> Result<()> {
+        let channel = self.channel_mut(idx)?;
+        channel.volume_db = db;
+
+        if let Some(partner) = channel.stereo_pair {
+            self.channel_mut(partner)?.volume_db = db;
+        }
+        Ok(())
+    }
+
+    /// Mute or unmute a channel, along with its stereo partner
+    pub fn set_mute(&mut self, idx: usize, muted: bool) -> Result<()> {
+        let channel = self.channel_mut(idx)?;
+        channel.muted = muted;
+
+        if let Some(partner) = channel.stereo_pair {
+            self.channel_mut(partner)?.muted = muted;
+        }
+        Ok(())
+    }
+
+    /// Solo a channel, along with its stereo partner
+    ///
+    /// Other solos are kept, so several channels can be soloed at once.
+    pub fn solo_channel(&mut self, idx: usize) -> Result<()> {
+        let channel = self.channel_mut(idx)?;
+        channel.solo = true;
+
+        if let Some(partner) = channel.stereo_pair {
+            self.channel_mut(partner)?.solo = true;
+        }
+        Ok(())
+    }
+
+    /// Clear every solo
+    pub fn clear_solos(&mut self) {
+        for channel in &mut self.channels {
+            channel.solo = false;
+        }
+    }
+
+    /// Check if any channel is soloed
+    pub fn any_solo(&self) -> bool {
+        self.channels.iter().any(|channel| channel.solo)
+    }
+
+    /// Check if a channel is silent, either muted or left out of a solo
+    ///
+    /// The stored mute flags are not changed by soloing.
+    pub fn effective_mute(&self, idx: usize) -> bool {
+        self.channels
+            .get(idx)
+            .is_some_and(|channel| channel.muted || (!channel.solo && self.any_solo()))
     }
 }
 
@@ -109,6 +226,26 @@ impl Default for LevelMeter {
     }
 }
 
+/// Level read from a device's meter, labelled with the port it measures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortLevel {
+    /// Mux destination the meter sits on
+    pub port: Port,
+    /// Level in dB (-127.0 to 0.0)
+    pub level_db: f32,
+}
+
+/// What one meter slot measures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeterLabel {
+    /// Position in the meter readings
+    pub index: usize,
+    /// Display name, e.g. "Analogue 1" or "Mix A"
+    pub name: String,
+    /// Port the meter sits on, if known
+    pub port: Option<Port>,
+}
+
 /// Convert dB to linear gain
 pub fn db_to_linear(db: f32) -> f32 {
     10.0_f32.powf(db / 20.0)
@@ -123,6 +260,49 @@ pub fn linear_to_db(gain: f32) -> f32 {
     }
 }
 
+/// Raw mixer gain of 0 dB
+pub const MIXER_GAIN_UNITY: i16 = 8192;
+
+/// Lowest mixer gain in dB; anything quieter is silence
+pub const MIXER_MIN_DB: f32 = -80.0;
+
+/// Highest mixer gain in dB
+pub const MIXER_MAX_DB: f32 = 6.0;
+
+/// Convert a raw mixer gain to dB
+///
+/// Gains scale linearly from 8192 at 0 dB, as in the kernel driver's
+/// `scarlett2_mixer_values` table, and are clamped to -80..+6 dB.
+pub fn mixer_gain_to_db(raw: i16) -> f32 {
+    if raw <= 0 {
+        return MIXER_MIN_DB;
+    }
+    let db = 20.0 * (raw as f32 / MIXER_GAIN_UNITY as f32).log10();
+    db.clamp(MIXER_MIN_DB, MIXER_MAX_DB)
+}
+
+/// Convert dB to a raw mixer gain
+///
+/// -80 dB and below is 0 (silence); +6 dB is the device's maximum of 16345.
+pub fn db_to_mixer_gain(db: f32) -> i16 {
+    if db.is_nan() || db <= MIXER_MIN_DB {
+        return 0;
+    }
+    let gain = MIXER_GAIN_UNITY as f32 * db_to_linear(db.min(MIXER_MAX_DB));
+    gain.round() as i16
+}
+
+/// Left and right linear gains for a pan position
+///
+/// Uses the constant-power (sine/cosine) law: with `theta = (pan + 1) * pi/4`,
+/// left is `cos(theta)` and right is `sin(theta)`, so `left² + right² = 1`
+/// and a centred source sits at -3 dB in each leg. `pan` is clamped to
+/// -1.0 (left) ..= 1.0 (right).
+pub fn pan_gains(pan: f32) -> (f32, f32) {
+    let theta = (pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+    (theta.cos(), theta.sin())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -134,6 +314,114 @@ mod tests {
         assert!((db_to_linear(6.0) - 1.995).abs() < 0.01);
     }
 
+    fn mixer(count: usize) -> MixerState {
+        let mut mixer = MixerState::new();
+        mixer.channels = (0..count).map(|i| MixerChannel::new(i, format!("Mix {}", i + 1))).collect();
+        mixer
+    }
+
+    #[test]
+    fn test_stereo_link() {
+        let mut mixer = mixer(4);
+        mixer.channels[0].pan = -1.0;
+        mixer.channels[1].pan = 1.0;
+
+        mixer.link_stereo(0, 1).unwrap();
+        assert_eq!(mixer.channels[1].stereo_pair, Some(0));
+
+        // Linking again is fine; linking into another pair isn't
+        mixer.link_stereo(1, 0).unwrap();
+        assert!(mixer.link_stereo(1, 2).is_err());
+        assert!(mixer.link_stereo(2, 2).is_err());
+        assert!(mixer.link_stereo(2, 9).is_err());
+
+        mixer.set_linked_volume(1, -12.0).unwrap();
+        assert_eq!(mixer.channels[0].volume_db, -12.0);
+        assert_eq!((mixer.channels[0].pan, mixer.channels[1].pan), (-1.0, 1.0));
+
+        mixer.set_mute(0, true).unwrap();
+        assert!(mixer.channels[1].muted);
+
+        mixer.unlink_stereo(1).unwrap();
+        assert_eq!(mixer.channels[0].stereo_pair, None);
+        mixer.set_mute(0, false).unwrap();
+        assert!(mixer.channels[1].muted);
+        mixer.link_stereo(1, 2).unwrap();
+    }
+
+    #[test]
+    fn test_solo() {
+        let mut mixer = mixer(4);
+        mixer.set_mute(3, true).unwrap();
+        mixer.link_stereo(0, 1).unwrap();
+
+        assert!(!mixer.effective_mute(2));
+        mixer.solo_channel(0).unwrap();
+        assert!(mixer.channels[1].solo);
+        assert!(!mixer.effective_mute(1));
+        assert!(mixer.effective_mute(2));
+        assert!(!mixer.channels[2].muted);
+
+        mixer.solo_channel(3).unwrap();
+        assert!(mixer.effective_mute(3));
+        assert!(mixer.solo_channel(9).is_err());
+
+        mixer.clear_solos();
+        assert!(!mixer.effective_mute(2));
+        assert!(mixer.effective_mute(3));
+    }
+
+    #[test]
+    fn test_mixer_gain() {
+        assert_eq!(db_to_mixer_gain(0.0), MIXER_GAIN_UNITY);
+        assert_eq!(db_to_mixer_gain(MIXER_MAX_DB), 16345);
+        assert_eq!(db_to_mixer_gain(12.0), 16345);
+        assert_eq!(db_to_mixer_gain(MIXER_MIN_DB), 0);
+        assert_eq!(db_to_mixer_gain(-127.0), 0);
+        assert_eq!(db_to_mixer_gain(f32::NAN), 0);
+
+        assert_eq!(mixer_gain_to_db(0), MIXER_MIN_DB);
+        assert_eq!(mixer_gain_to_db(-1), MIXER_MIN_DB);
+        assert_eq!(mixer_gain_to_db(i16::MAX), MIXER_MAX_DB);
+
+        // Points from the kernel driver's table (-80 dB + index / 2)
+        for (index, raw) in [(100, 259), (148, 4105), (160, 8192), (172, 16345)] {
+            let db = MIXER_MIN_DB + index as f32 / 2.0;
+            assert!((mixer_gain_to_db(raw) - db).abs() < 0.2, "{} dB", db);
+        }
+    }
+
+    #[test]
+    fn test_mixer_gain_roundtrip() {
+        let mut db = -40.0;
+        while db <= MIXER_MAX_DB {
+            let converted = mixer_gain_to_db(db_to_mixer_gain(db));
+            assert!((converted - db).abs() < 0.2, "{} dB came back as {} dB", db, converted);
+            db += 0.1;
+        }
+
+        let mut channel = MixerChannel::new(0, "Analogue 1".to_string());
+        channel.volume_db = -12.3;
+        let raw = channel.mixer_gain();
+        channel.set_mixer_gain(raw);
+        assert!((channel.volume_db + 12.3).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_pan_gains() {
+        let (left, right) = pan_gains(0.0);
+        assert_eq!(left, right);
+        assert!((linear_to_db(left) + 3.01).abs() < 0.01);
+
+        let (left, right) = pan_gains(-1.0);
+        assert!((left - 1.0).abs() < 1e-6 && right.abs() < 1e-6);
+        assert_eq!(pan_gains(2.0), pan_gains(1.0));
+
+        let (left, right) = pan_gains(0.3);
+        assert!((left * left + right * right - 1.0).abs() < 1e-6);
+        assert!(right > left);
+    }
+
     #[test]
     fn test_linear_conversion() {
         assert!((linear_to_db(1.0) - 0.0).abs() < 0.001);
@@ -0,0 +1,31 @@
+//! Monitoring control types
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Direct Monitor setting
+///
+/// The Solo has a simple on/off switch; the 2i2 chooses between mono and
+/// stereo. Use `DeviceModel::direct_monitor_modes` for the legal values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DirectMonitorMode {
+    /// Direct Monitor off
+    Off,
+    /// Direct Monitor on (Solo)
+    On,
+    /// Inputs monitored in mono (2i2)
+    Mono,
+    /// Inputs monitored in stereo (2i2)
+    Stereo,
+}
+
+impl fmt::Display for DirectMonitorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "Off"),
+            Self::On => write!(f, "On"),
+            Self::Mono => write!(f, "Mono"),
+            Self::Stereo => write!(f, "Stereo"),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Power source types
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Where a bus-powerable device is drawing its power from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerStatus {
+    /// External power supply connected
+    External,
+    /// Powered over USB with enough current
+    BusFull,
+    /// Powered over USB without enough current; phantom power is disabled
+    BusInsufficient,
+}
+
+impl PowerStatus {
+    /// Convert from the device's external-power and low-power values
+    ///
+    /// Low power takes precedence, since it is reported even when an
+    /// external supply is connected but not sufficient.
+    pub fn from_raw(external: i32, low: i32) -> Self {
+        if low != 0 {
+            Self::BusInsufficient
+        } else if external != 0 {
+            Self::External
+        } else {
+            Self::BusFull
+        }
+    }
+
+    /// Whether the device is short of power
+    pub fn is_insufficient(&self) -> bool {
+        *self == Self::BusInsufficient
+    }
+}
+
+impl fmt::Display for PowerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::External => write!(f, "External"),
+            Self::BusFull => write!(f, "Bus"),
+            Self::BusInsufficient => write!(f, "Bus (insufficient)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power_status_from_raw() {
+        assert_eq!(PowerStatus::from_raw(1, 0), PowerStatus::External);
+        assert_eq!(PowerStatus::from_raw(0, 0), PowerStatus::BusFull);
+        assert_eq!(PowerStatus::from_raw(0, 1), PowerStatus::BusInsufficient);
+        assert_eq!(PowerStatus::from_raw(1, 1), PowerStatus::BusInsufficient);
+        assert!(!PowerStatus::External.is_insufficient());
+    }
+}
@@ -8,16 +8,49 @@ pub mod gen1 {
 
 pub mod gen2 {
     // Scarlett2 USB Protocol constants
-    // Based on Linux kernel driver: sound/usb/mixer_scarlett_gen2.c
+    // Based on Linux kernel driver: sound/usb/mixer_scarlett2.c
+
+    // Class requests to the vendor-specific interface
+    pub const USB_CMD_INIT: u8 = 0;
+    pub const USB_CMD_REQ: u8 = 2;
+    pub const USB_CMD_RESP: u8 = 3;
+
+    // Commands
+    pub const CMD_INIT_1: u32 = 0x0000_0000;
+    pub const CMD_INIT_2: u32 = 0x0000_0002;
+    pub const CMD_REBOOT: u32 = 0x0000_0003;
+    pub const CMD_GET_METER: u32 = 0x0000_1001;
+    pub const CMD_GET_MIX: u32 = 0x0000_2001;
+    pub const CMD_SET_MIX: u32 = 0x0000_2002;
+    pub const CMD_GET_MUX: u32 = 0x0000_3001;
+    pub const CMD_SET_MUX: u32 = 0x0000_3002;
+    pub const CMD_GET_SYNC: u32 = 0x0000_6004;
+    pub const CMD_GET_DATA: u32 = 0x0080_0000;
+    pub const CMD_SET_DATA: u32 = 0x0080_0001;
+    pub const CMD_DATA_CMD: u32 = 0x0080_0002;
 
-    pub const CMD_OFFSET: u16 = 0x0000;
     pub const FLASH_SEGMENT_ID_SETTINGS: u8 = 0;
     pub const FLASH_SEGMENT_ID_FIRMWARE: u8 = 1;
+
+    // Config offsets of the 6i6, 18i8 and 18i20 Gen 2
+    // (scarlett2_config_set_gen2a/gen2b)
+    pub const CONFIG_DIM_MUTE: u32 = 0x31;  // 18i20 only
+    pub const CONFIG_LINE_OUT_VOLUME: u32 = 0x34;
+    pub const CONFIG_MUTE_SWITCH: u32 = 0x5c;
+    pub const CONFIG_SW_HW_SWITCH: u32 = 0x66;  // 18i20 only
+    pub const CONFIG_MASTER_VOLUME: u32 = 0x76;  // 18i20 only
+    pub const CONFIG_LEVEL_SWITCH: u32 = 0x7c;
+    pub const CONFIG_PAD_SWITCH: u32 = 0x84;
+    pub const CONFIG_STANDALONE_SWITCH: u32 = 0x8d;
 }
 
 pub mod gen3 {
-    // Gen 3 uses similar protocol to Gen 2
-    pub use super::gen2::*;
+    // Gen 3 uses the Gen 2 commands, but most config offsets moved
+    pub use super::gen2::{
+        CMD_DATA_CMD, CMD_GET_DATA, CMD_GET_METER, CMD_GET_MIX, CMD_GET_MUX, CMD_GET_SYNC, CMD_INIT_1,
+        CMD_INIT_2, CMD_REBOOT, CMD_SET_DATA, CMD_SET_MIX, CMD_SET_MUX, FLASH_SEGMENT_ID_FIRMWARE,
+        FLASH_SEGMENT_ID_SETTINGS, USB_CMD_INIT, USB_CMD_REQ, USB_CMD_RESP,
+    };
 }
 
 pub mod gen4 {
@@ -1,5 +1,7 @@
 //! Audio routing data structures
 
+use crate::device::DeviceModel;
+use crate::{Error, Result};
 use serde::{Deserialize, Serialize};
 
 /// Audio port type
@@ -17,6 +19,8 @@ pub enum PortType {
     AdatIn,
     /// ADAT output
     AdatOut,
+    /// Mixer input
+    MixerIn,
     /// Mixer output
     MixerOut,
     /// PCM (DAW) input
@@ -29,6 +33,17 @@ pub enum PortType {
     DspOut,
 }
 
+impl PortType {
+    /// Whether ports of this type feed a route (device inputs, mixer and
+    /// DSP outputs, and DAW playback) rather than take one
+    pub fn is_source(&self) -> bool {
+        matches!(
+            self,
+            Self::AnalogIn | Self::SpdifIn | Self::AdatIn | Self::MixerOut | Self::PcmIn | Self::DspOut
+        )
+    }
+}
+
 /// Audio port
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Port {
@@ -37,6 +52,277 @@ pub struct Port {
     pub name: String,
 }
 
+impl Port {
+    /// Create a port
+    pub fn new(port_type: PortType, index: usize, name: impl Into<String>) -> Self {
+        Self {
+            port_type,
+            index,
+            name: name.into(),
+        }
+    }
+}
+
+/// Sources and destinations a device can route between
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PortLayout {
+    /// Ports that can feed a destination
+    pub sources: Vec<Port>,
+    /// Ports that take a source
+    pub destinations: Vec<Port>,
+}
+
+/// Number of ports of each type, as (sources, destinations)
+///
+/// From the `port_count` tables in mixer_scarlett2.c.
+struct PortCounts {
+    analogue: (usize, usize),
+    spdif: (usize, usize),
+    adat: (usize, usize),
+    mix: (usize, usize),
+    pcm: (usize, usize),
+    /// Names of the analogue outputs that have one
+    line_outs: &'static [Option<&'static str>],
+}
+
+const MONITOR_HEADPHONES: &[Option<&str>] = &[
+    Some("Monitor L"),
+    Some("Monitor R"),
+    Some("Headphone L"),
+    Some("Headphone R"),
+];
+
+const TWO_HEADPHONES: &[Option<&str>] = &[
+    Some("Headphone 1 L"),
+    Some("Headphone 1 R"),
+    Some("Headphone 2 L"),
+    Some("Headphone 2 R"),
+];
+
+const MONITOR_TWO_HEADPHONES: &[Option<&str>] = &[
+    Some("Monitor L"),
+    Some("Monitor R"),
+    Some("Headphone 1 L"),
+    Some("Headphone 1 R"),
+    Some("Headphone 2 L"),
+    Some("Headphone 2 R"),
+];
+
+const RACK_LINE_OUTS: &[Option<&str>] = &[
+    Some("Monitor L"),
+    Some("Monitor R"),
+    None,
+    None,
+    None,
+    None,
+    Some("Headphone 1 L"),
+    Some("Headphone 1 R"),
+    Some("Headphone 2 L"),
+    Some("Headphone 2 R"),
+];
+
+const GEN4_LINE_OUTS: &[Option<&str>] = &[
+    Some("Monitor 1"),
+    Some("Monitor 2"),
+    Some("Monitor 3"),
+    Some("Monitor 4"),
+    Some("Headphone 1 L"),
+    Some("Headphone 1 R"),
+    Some("Headphone 2 L"),
+    Some("Headphone 2 R"),
+];
+
+fn port_counts(model: DeviceModel) -> Option<PortCounts> {
+    use DeviceModel::*;
+
+    let counts = |analogue, spdif, adat, mix, pcm, line_outs| PortCounts {
+        analogue,
+        spdif,
+        adat,
+        mix,
+        pcm,
+        line_outs,
+    };
+
+    let counts = match model {
+        Scarlett6i6Gen2 => counts((4, 4), (2, 2), (0, 0), (10, 18), (6, 6), TWO_HEADPHONES),
+        Scarlett18i8Gen2 => counts((8, 6), (2, 2), (8, 0), (10, 18), (8, 18), MONITOR_TWO_HEADPHONES),
+        Scarlett18i20Gen2 => counts((8, 10), (2, 2), (8, 8), (10, 18), (20, 18), RACK_LINE_OUTS),
+
+        // No mux; these only have their fixed analogue I/O
+        ScarlettSoloGen3 | Scarlett2i2Gen3 => counts((2, 2), (0, 0), (0, 0), (0, 0), (0, 0), &[]),
+        Scarlett4i4Gen3 => counts((4, 4), (0, 0), (0, 0), (6, 8), (4, 6), MONITOR_HEADPHONES),
+        Scarlett8i6Gen3 => counts((6, 4), (2, 2), (0, 0), (8, 8), (6, 10), TWO_HEADPHONES),
+        Scarlett18i8Gen3 => counts(
+            (8, 8),
+            (2, 2),
+            (8, 0),
+            (10, 20),
+            (8, 20),
+            &[
+                Some("Monitor L"),
+                Some("Monitor R"),
+                Some("Alt Monitor L"),
+                Some("Alt Monitor R"),
+                Some("Headphone 1 L"),
+                Some("Headphone 1 R"),
+                Some("Headphone 2 L"),
+                Some("Headphone 2 R"),
+            ],
+        ),
+        Scarlett18i20Gen3 => counts(
+            (9, 10),
+            (2, 2),
+            (8, 8),
+            (12, 25),
+            (20, 20),
+            &[
+                Some("Monitor 1 L"),
+                Some("Monitor 1 R"),
+                Some("Monitor 2 L"),
+                Some("Monitor 2 R"),
+                None,
+                None,
+                Some("Headphone 1 L"),
+                Some("Headphone 1 R"),
+                Some("Headphone 2 L"),
+                Some("Headphone 2 R"),
+            ],
+        ),
+
+        ScarlettSoloGen4 => counts((2, 2), (0, 0), (0, 0), (8, 6), (2, 4), &[]),
+        Scarlett2i2Gen4 => counts((2, 2), (0, 0), (0, 0), (6, 6), (2, 4), &[]),
+        Scarlett4i4Gen4 => counts((4, 6), (0, 0), (0, 0), (8, 12), (6, 6), &[]),
+
+        // From the sources and sinks in fcp-support/data/fcp-alsa-map-*.json
+        Scarlett16i16Gen4 => counts((6, 8), (2, 2), (8, 8), (12, 34), (24, 18), GEN4_LINE_OUTS),
+        Scarlett18i16Gen4 => counts((8, 8), (2, 2), (8, 8), (12, 36), (24, 20), GEN4_LINE_OUTS),
+        Scarlett18i20Gen4 => counts(
+            (9, 14),
+            (2, 2),
+            (8, 8),
+            (12, 43),
+            (24, 20),
+            &[
+                Some("Monitor 1"),
+                Some("Monitor 2"),
+                Some("Monitor 3"),
+                Some("Monitor 4"),
+                Some("Monitor 5"),
+                Some("Monitor 6"),
+                Some("Monitor 7"),
+                Some("Monitor 8"),
+                Some("Monitor 9"),
+                Some("Monitor 10"),
+                Some("Headphone 1 L"),
+                Some("Headphone 1 R"),
+                Some("Headphone 2 L"),
+                Some("Headphone 2 R"),
+            ],
+        ),
+
+        Clarett2PreUsb | Clarett2PrePlus => counts((2, 4), (2, 0), (8, 0), (10, 18), (4, 12), MONITOR_HEADPHONES),
+        Clarett4PreUsb | Clarett4PrePlus => counts((8, 6), (2, 2), (8, 0), (10, 18), (8, 18), MONITOR_TWO_HEADPHONES),
+        Clarett8PreUsb | Clarett8PrePlus => counts((8, 10), (2, 2), (8, 8), (10, 18), (20, 18), RACK_LINE_OUTS),
+
+        VocasterOne => counts((2, 4), (0, 0), (0, 0), (9, 9), (4, 10), &[]),
+        VocasterTwo => counts((6, 6), (0, 0), (0, 0), (12, 14), (4, 14), &[]),
+
+        // Gen 1 isn't covered by the scarlett2 tables
+        _ => return None,
+    };
+
+    Some(counts)
+}
+
+/// Name of a mix, e.g. "Mix A"
+fn mix_name(index: usize) -> String {
+    format!("Mix {}", (b'A' + index as u8) as char)
+}
+
+/// Name of an S/PDIF port, e.g. "SPDIF L"
+fn spdif_name(index: usize) -> String {
+    match index {
+        0 => "SPDIF L".to_string(),
+        1 => "SPDIF R".to_string(),
+        _ => format!("SPDIF {}", index + 1),
+    }
+}
+
+impl PortLayout {
+    /// Port layout of a model
+    ///
+    /// Models whose layout isn't known (Gen 1) get an empty layout.
+    pub fn for_model(model: DeviceModel) -> Self {
+        let Some(counts) = port_counts(model) else {
+            return Self::default();
+        };
+
+        let mut layout = Self::default();
+
+        for i in 0..counts.analogue.0 {
+            layout.sources.push(Port::new(PortType::AnalogIn, i, format!("Analogue {}", i + 1)));
+        }
+        for i in 0..counts.spdif.0 {
+            layout.sources.push(Port::new(PortType::SpdifIn, i, spdif_name(i)));
+        }
+        for i in 0..counts.adat.0 {
+            layout.sources.push(Port::new(PortType::AdatIn, i, format!("ADAT {}", i + 1)));
+        }
+        for i in 0..counts.mix.0 {
+            layout.sources.push(Port::new(PortType::MixerOut, i, mix_name(i)));
+        }
+        for i in 0..counts.pcm.0 {
+            layout.sources.push(Port::new(PortType::PcmIn, i, format!("PCM {}", i + 1)));
+        }
+
+        for i in 0..counts.analogue.1 {
+            let name = match counts.line_outs.get(i).copied().flatten() {
+                Some(name) => name.to_string(),
+                None => format!("Analogue {}", i + 1),
+            };
+            layout.destinations.push(Port::new(PortType::AnalogOut, i, name));
+        }
+        for i in 0..counts.spdif.1 {
+            layout.destinations.push(Port::new(PortType::SpdifOut, i, spdif_name(i)));
+        }
+        for i in 0..counts.adat.1 {
+            layout.destinations.push(Port::new(PortType::AdatOut, i, format!("ADAT {}", i + 1)));
+        }
+        for i in 0..counts.mix.1 {
+            layout.destinations.push(Port::new(PortType::MixerIn, i, format!("Mixer In {}", i + 1)));
+        }
+        for i in 0..counts.pcm.1 {
+            layout.destinations.push(Port::new(PortType::PcmOut, i, format!("PCM {}", i + 1)));
+        }
+
+        layout
+    }
+
+    /// Indexes of the analogue outputs that feed monitors
+    ///
+    /// These are the line outputs named "Monitor ..." or "Alt Monitor ...",
+    /// which the monitor knob, Dim and Mute act on.
+    pub fn monitor_outputs(&self) -> Vec<usize> {
+        self.destinations
+            .iter()
+            .filter(|port| port.port_type == PortType::AnalogOut && port.name.contains("Monitor"))
+            .map(|port| port.index)
+            .collect()
+    }
+}
+
+/// A destination whose source differs between two matrices
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteChange {
+    /// Destination index
+    pub dest_idx: usize,
+    /// Source in the old matrix
+    pub from: Option<usize>,
+    /// Source in the new matrix
+    pub to: Option<usize>,
+}
+
 /// Routing matrix - maps sources to destinations
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoutingMatrix {
@@ -57,11 +343,93 @@ impl RoutingMatrix {
         }
     }
 
-    /// Set a route from source to destination
-    pub fn set_route(&mut self, dest_idx: usize, source_idx: Option<usize>) {
-        if dest_idx < self.routes.len() {
-            self.routes[dest_idx] = source_idx;
+    /// Create an unrouted matrix over a device's ports
+    pub fn from_layout(layout: PortLayout) -> Self {
+        Self {
+            routes: vec![None; layout.destinations.len()],
+            sources: layout.sources,
+            destinations: layout.destinations,
+        }
+    }
+
+    /// Create an unrouted matrix over a model's ports
+    pub fn for_model(model: DeviceModel) -> Self {
+        Self::from_layout(model.port_layout())
+    }
+
+    /// Set a route from source to destination, or clear it with `None`
+    pub fn set_route(&mut self, dest_idx: usize, source_idx: Option<usize>) -> Result<()> {
+        if dest_idx >= self.routes.len() {
+            return Err(Error::InvalidParameter(format!(
+                "Destination {} out of range ({} destinations)",
+                dest_idx,
+                self.routes.len()
+            )));
+        }
+        if let Some(source_idx) = source_idx.filter(|&idx| idx >= self.sources.len()) {
+            return Err(Error::InvalidParameter(format!(
+                "Source {} out of range ({} sources)",
+                source_idx,
+                self.sources.len()
+            )));
         }
+
+        self.routes[dest_idx] = source_idx;
+        Ok(())
+    }
+
+    /// Check that the matrix can be written to a device
+    ///
+    /// Every destination must have a route entry, routed sources must exist,
+    /// and ports must be on the right side of the matrix.
+    pub fn validate(&self) -> Result<()> {
+        if self.routes.len() != self.destinations.len() {
+            return Err(Error::InvalidParameter(format!(
+                "Routing has {} routes for {} destinations",
+                self.routes.len(),
+                self.destinations.len()
+            )));
+        }
+
+        if let Some(port) = self.sources.iter().find(|port| !port.port_type.is_source()) {
+            return Err(Error::InvalidParameter(format!(
+                "{} ({:?}) can't be used as a source",
+                port.name, port.port_type
+            )));
+        }
+        if let Some(port) = self.destinations.iter().find(|port| port.port_type.is_source()) {
+            return Err(Error::InvalidParameter(format!(
+                "{} ({:?}) can't be used as a destination",
+                port.name, port.port_type
+            )));
+        }
+
+        for (dest_idx, source_idx) in self.routes.iter().enumerate() {
+            if let Some(source_idx) = source_idx.filter(|&idx| idx >= self.sources.len()) {
+                return Err(Error::InvalidParameter(format!(
+                    "{} is routed from source {}, but there are only {} sources",
+                    self.destinations[dest_idx].name,
+                    source_idx,
+                    self.sources.len()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the destinations whose source differs in `other`
+    ///
+    /// Destinations only present in one of the matrices count as unrouted
+    /// in the other.
+    pub fn diff(&self, other: &RoutingMatrix) -> Vec<RouteChange> {
+        (0..self.routes.len().max(other.routes.len()))
+            .filter_map(|dest_idx| {
+                let from = self.get_route(dest_idx);
+                let to = other.get_route(dest_idx);
+                (from != to).then_some(RouteChange { dest_idx, from, to })
+            })
+            .collect()
     }
 
     /// Get the source for a destination
@@ -75,3 +443,117 @@ impl Default for RoutingMatrix {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(ports: &[Port]) -> Vec<&str> {
+        ports.iter().map(|port| port.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_port_layout() {
+        let layout = DeviceModel::Scarlett18i20Gen3.port_layout();
+        assert_eq!(layout.sources.len(), 9 + 2 + 8 + 12 + 20);
+        assert_eq!(layout.destinations.len(), 10 + 2 + 8 + 25 + 20);
+
+        let sources = names(&layout.sources);
+        assert_eq!(sources[0], "Analogue 1");
+        assert_eq!(sources[9..11], ["SPDIF L", "SPDIF R"]);
+        assert_eq!(sources[13], "ADAT 3");
+        assert_eq!(sources[19], "Mix A");
+        assert_eq!(layout.sources[31].port_type, PortType::PcmIn);
+
+        let destinations = names(&layout.destinations);
+        assert_eq!(destinations[..2], ["Monitor 1 L", "Monitor 1 R"]);
+        assert_eq!(destinations[4], "Analogue 5");
+        assert_eq!(destinations[6], "Headphone 1 L");
+
+        let layout = DeviceModel::Scarlett18i20Gen4.port_layout();
+        assert_eq!(layout.sources.len(), 9 + 2 + 8 + 12 + 24);
+        assert_eq!(layout.destinations.len(), 14 + 2 + 8 + 43 + 20);
+
+        assert!(DeviceModel::Scarlett18i8Gen1.port_layout().sources.is_empty());
+    }
+
+    #[test]
+    fn test_monitor_outputs() {
+        assert_eq!(DeviceModel::Scarlett18i8Gen3.port_layout().monitor_outputs(), [0, 1, 2, 3]);
+        assert_eq!(DeviceModel::Scarlett18i20Gen4.port_layout().monitor_outputs(), (0..10).collect::<Vec<_>>());
+        assert!(DeviceModel::Scarlett2i2Gen4.port_layout().monitor_outputs().is_empty());
+    }
+
+    #[test]
+    fn test_matrix_for_model() {
+        let mut matrix = RoutingMatrix::for_model(DeviceModel::Scarlett4i4Gen3);
+        assert_eq!(matrix.routes.len(), matrix.destinations.len());
+        assert!(matrix.routes.iter().all(Option::is_none));
+        assert_eq!(matrix.destinations[2].name, "Headphone L");
+
+        matrix.set_route(0, Some(4)).unwrap();
+        assert_eq!(matrix.get_route(0), Some(4));
+        assert_eq!(matrix.sources[4].name, "Mix A");
+
+        matrix.set_route(0, None).unwrap();
+        assert_eq!(matrix.get_route(0), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut matrix = RoutingMatrix::for_model(DeviceModel::Scarlett18i8Gen3);
+        matrix.set_route(0, Some(0)).unwrap();
+        matrix.validate().unwrap();
+        RoutingMatrix::new().validate().unwrap();
+
+        let mut bad = matrix.clone();
+        bad.routes[1] = Some(bad.sources.len());
+        assert!(matches!(bad.validate(), Err(Error::InvalidParameter(_))));
+
+        let mut bad = matrix.clone();
+        bad.routes.pop();
+        assert!(matches!(bad.validate(), Err(Error::InvalidParameter(_))));
+
+        // An output port can't feed a route
+        let mut bad = matrix.clone();
+        bad.sources[0] = Port::new(PortType::AnalogOut, 0, "Monitor L");
+        assert!(matches!(bad.validate(), Err(Error::InvalidParameter(msg)) if msg.contains("Monitor L")));
+
+        let mut bad = matrix;
+        bad.destinations[0] = Port::new(PortType::PcmIn, 0, "PCM 1");
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = RoutingMatrix::for_model(DeviceModel::Scarlett4i4Gen3);
+        assert!(old.diff(&old).is_empty());
+
+        let mut new = old.clone();
+        new.set_route(1, Some(2)).unwrap();
+        new.set_route(3, Some(0)).unwrap();
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                RouteChange { dest_idx: 1, from: None, to: Some(2) },
+                RouteChange { dest_idx: 3, from: None, to: Some(0) },
+            ]
+        );
+
+        let mut newer = new.clone();
+        newer.set_route(3, None).unwrap();
+        assert_eq!(new.diff(&newer), vec![RouteChange { dest_idx: 3, from: Some(0), to: None }]);
+    }
+
+    #[test]
+    fn test_set_route_bounds() {
+        let mut matrix = RoutingMatrix::for_model(DeviceModel::Scarlett4i4Gen3);
+        let (sources, destinations) = (matrix.sources.len(), matrix.destinations.len());
+
+        assert!(matches!(matrix.set_route(0, Some(sources)), Err(Error::InvalidParameter(_))));
+        assert!(matches!(matrix.set_route(destinations, Some(0)), Err(Error::InvalidParameter(_))));
+        assert!(matrix.routes.iter().all(Option::is_none));
+
+        assert!(RoutingMatrix::new().set_route(0, None).is_err());
+    }
+}
@@ -1,15 +1,181 @@
 //! Scarlett GUI - Main Application
 
 use scarlett_config::ConfigManager;
+use scarlett_core::{Device, DeviceInfo, Error};
+use slint::Model;
 use scarlett_hotkeys::{HotkeyManager, VolumeCommand};
-use scarlett_usb::{DeviceDetector, HotplugEvent, UsbDevice};
+use scarlett_usb::{virtual_device, DeviceDetector, HotplugEvent, KernelDriverPolicy, Notification, UsbDevice, DEFAULT_COMMIT_DELAY};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
-use tracing::{error, info, warn};
-use tracing_subscriber;
+use tracing::{debug, error, info, warn};
 
 slint::include_modules!();
 
+/// Output controlled by the keyboard volume keys
+const MONITOR_OUTPUT: u8 = 0;
+
+/// How often to check for settled changes to save to flash
+const AUTO_COMMIT_POLL: Duration = Duration::from_millis(500);
+
+/// How often to check for front panel changes on Scarlett2 devices
+const NOTIFY_POLL: Duration = Duration::from_millis(100);
+
+/// Device list row for a device
+fn device_item(info: &DeviceInfo) -> DeviceItem {
+    DeviceItem {
+        name: info.model.name().into(),
+        serial: info.serial_number.clone().into(),
+        firmware: info.firmware_version.clone().unwrap_or_default().into(),
+        status: if virtual_device::is_virtual(info) { "Virtual" } else { "Connected" }.into(),
+    }
+}
+
+/// Carry firmware versions read when opening devices over to a new scan
+///
+/// Scanning doesn't open devices, so it can't read their firmware version.
+fn keep_firmware_versions(devices: &mut [DeviceInfo], known: &[DeviceInfo]) {
+    for device in devices.iter_mut().filter(|d| d.firmware_version.is_none()) {
+        device.firmware_version = known
+            .iter()
+            .find(|k| k.serial_number == device.serial_number)
+            .and_then(|k| k.firmware_version.clone());
+    }
+}
+
+/// Link the output pairs saved in the device's config
+fn apply_output_links(device: &mut UsbDevice, serial: &str) {
+    let links = match ConfigManager::new().and_then(|config| config.load_device_config(serial)) {
+        Ok(config) => config.output_links,
+        Err(e) => {
+            warn!("Failed to load device config for {}: {}", serial, e);
+            return;
+        }
+    };
+
+    if let Some(fcp) = device.fcp_protocol() {
+        if let Err(e) = fcp.set_output_links(&links) {
+            warn!("Ignoring output links for {}: {}", serial, e);
+        }
+    }
+}
+
+/// Set the monitor volume, on all monitor outputs if the device has several
+fn set_monitor_volume(device: &mut UsbDevice, volume_db: f32) -> Result<f32, Error> {
+    if let Some(fcp) = device.fcp_protocol() {
+        match fcp.set_master_volume(volume_db) {
+            Err(Error::NotSupported(_)) => {}
+            result => return result.and_then(|_| fcp.get_master_volume()),
+        }
+    }
+
+    device.set_volume(MONITOR_OUTPUT, volume_db)?;
+    device.get_volume(MONITOR_OUTPUT)
+}
+
+/// Toggle the monitor mute, on all monitor outputs if the device has several
+fn toggle_monitor_mute(device: &mut UsbDevice) -> Result<bool, Error> {
+    if let Some(fcp) = device.fcp_protocol() {
+        match fcp.get_monitor_mute() {
+            Err(Error::NotSupported(_)) => {}
+            result => {
+                let muted = !result?;
+                fcp.set_monitor_mute(muted)?;
+                return Ok(muted);
+            }
+        }
+    }
+
+    device.toggle_mute(MONITOR_OUTPUT)
+}
+
+/// Re-read the controls a front panel change affected
+///
+/// Returns the new monitor volume and mute, where they changed.
+fn refresh_notified(device: &mut UsbDevice) -> (Option<f32>, Option<bool>) {
+    let Some(protocol) = device.scarlett2_protocol() else {
+        return (None, None);
+    };
+
+    let notifications = match protocol.read_notifications() {
+        Ok(notifications) => notifications,
+        Err(Error::NotSupported(_)) => return (None, None),
+        Err(e) => {
+            debug!("Could not read notifications: {}", e);
+            return (None, None);
+        }
+    };
+
+    let mut volume_db = None;
+    let mut muted = None;
+    for notification in notifications {
+        match notification {
+            Notification::Monitor => volume_db = device.get_volume(MONITOR_OUTPUT).ok(),
+            Notification::DimMute => muted = device.get_mute(MONITOR_OUTPUT).ok(),
+            _ => {}
+        }
+    }
+
+    (volume_db, muted)
+}
+
+/// Read the controls shown for an opened device
+fn device_controls(device: &mut UsbDevice) -> DeviceControls {
+    let mut controls = DeviceControls {
+        name: device.info().model.name().into(),
+        num_inputs: device.num_inputs() as i32,
+        num_outputs: device.num_outputs() as i32,
+        ..Default::default()
+    };
+
+    let scale = device.volume_scale();
+    match (device.get_volume(MONITOR_OUTPUT), device.get_mute(MONITOR_OUTPUT)) {
+        (Ok(volume_db), Ok(muted)) => {
+            controls.has_volume = true;
+            controls.volume_db = volume_db;
+            controls.volume_min = scale.min_db;
+            controls.volume_max = scale.max_db;
+            controls.muted = muted;
+        }
+        (Err(Error::NotSupported(_)), _) => {}
+        (Err(e), _) | (_, Err(e)) => warn!("Could not read monitor volume: {}", e),
+    }
+
+    if let Some(fcp) = device.fcp_protocol() {
+        if let (Ok(volume_db), Ok(muted)) = (fcp.get_master_volume(), fcp.get_monitor_mute()) {
+            controls.volume_db = volume_db;
+            controls.muted = muted;
+        }
+    }
+
+    match device.protocol().power_status() {
+        Ok(status) => controls.power_low = status.is_insufficient(),
+        Err(Error::NotSupported(_)) => {}
+        Err(e) => warn!("Could not read power status: {}", e),
+    }
+
+    controls
+}
+
+/// Run device I/O on the open device from a blocking thread
+///
+/// Transfers wait for the device to answer, so they are kept off the UI
+/// thread and the async workers. Returns `None` if no device is open.
+async fn with_device<T, F>(selected: &Arc<Mutex<Option<UsbDevice>>>, f: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut UsbDevice) -> T + Send + 'static,
+{
+    let mut selected = selected.clone().lock_owned().await;
+    match tokio::task::spawn_blocking(move || selected.as_mut().map(f)).await {
+        Ok(result) => result,
+        Err(e) => {
+            error!("Device I/O failed: {}", e);
+            None
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize logging
@@ -31,7 +197,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("Loaded preferences");
 
     // Create device detector
-    let (detector, mut hotplug_rx) = DeviceDetector::new();
+    let (mut detector, mut hotplug_rx) = DeviceDetector::new();
+    if prefs.detach_kernel_driver {
+        detector.set_kernel_driver_policy(KernelDriverPolicy::Detach);
+    }
 
     // Create hotkey manager
     let (hotkey_mgr, mut volume_rx) = HotkeyManager::new();
@@ -42,6 +211,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Store current devices
     let current_devices = Arc::new(Mutex::new(Vec::new()));
 
+    // Device opened from the list, if any
+    let selected_device: Arc<Mutex<Option<UsbDevice>>> = Arc::new(Mutex::new(None));
+
     // Initial device scan
     {
         let devices = detector.scan_devices()?;
@@ -51,11 +223,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Update UI with devices
         let device_items: Vec<DeviceItem> = devices
             .iter()
-            .map(|d| DeviceItem {
-                name: d.model.name().into(),
-                serial: d.serial_number.clone().into(),
-                status: "Connected".into(),
-            })
+            .map(device_item)
             .collect();
 
         ui.set_devices(std::rc::Rc::new(slint::VecModel::from(device_items)).into());
@@ -81,7 +249,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Handle scan button
     let ui_handle = ui.as_weak();
-    let detector_clone = Arc::new(detector);
+    let detector = Arc::new(detector);
+    let detector_clone = detector.clone();
+    let hotplug_detector = detector.clone();
     let current_devices_clone = current_devices.clone();
     ui.on_scan_devices(move || {
         let ui = ui_handle.unwrap();
@@ -90,17 +260,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         slint::spawn_local(async move {
             match detector.scan_devices() {
-                Ok(devices) => {
+                Ok(mut devices) => {
                     let mut current = current_devices.lock().await;
+                    keep_firmware_versions(&mut devices, &current);
                     *current = devices.clone();
 
                     let device_items: Vec<DeviceItem> = devices
                         .iter()
-                        .map(|d| DeviceItem {
-                            name: d.model.name().into(),
-                            serial: d.serial_number.clone().into(),
-                            status: "Connected".into(),
-                        })
+                        .map(device_item)
                         .collect();
 
                     ui.set_devices(std::rc::Rc::new(slint::VecModel::from(device_items)).into());
@@ -113,7 +280,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
                 Err(e) => {
                     error!("Failed to scan devices: {}", e);
-                    ui.set_status_text(format!("Error: {}", e).into());
+                    let status = match e.hint() {
+                        Some(hint) => format!("Error: {} - {}", e, hint),
+                        None => format!("Error: {}", e),
+                    };
+                    ui.set_status_text(status.into());
                 }
             }
         })
@@ -122,16 +293,119 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Handle device selection
     let ui_handle = ui.as_weak();
+    let current_devices_clone = current_devices.clone();
+    let selected_device_clone = selected_device.clone();
     ui.on_select_device(move |index| {
         let ui = ui_handle.unwrap();
         info!("Selected device at index {}", index);
-        // TODO: Open device control window
+
+        let detector = detector.clone();
+        let current_devices = current_devices_clone.clone();
+        let selected_device = selected_device_clone.clone();
+
+        slint::spawn_local(async move {
+            let Some(info) = current_devices.lock().await.get(index as usize).cloned() else {
+                return;
+            };
+
+            ui.set_device_open(false);
+            let name = info.model.name();
+
+            // Dropping the previous device releases its interface, so it
+            // is closed before the next one is opened
+            let mut selected = selected_device.lock_owned().await;
+            let opened = tokio::task::spawn_blocking(move || {
+                *selected = None;
+                let mut device = detector.open_device(&info)?;
+                apply_output_links(&mut device, &info.serial_number);
+                device.set_auto_commit(Some(DEFAULT_COMMIT_DELAY));
+                let controls = device_controls(&mut device);
+                let opened = device.info().clone();
+                *selected = Some(device);
+                Ok::<_, Error>((opened, controls))
+            })
+            .await;
+
+            match opened {
+                Ok(Ok((opened, controls))) => {
+                    // The firmware version is only known once the device is open
+                    if let Some(entry) = current_devices.lock().await.get_mut(index as usize) {
+                        entry.firmware_version = opened.firmware_version.clone();
+                    }
+                    ui.get_devices().set_row_data(index as usize, device_item(&opened));
+
+                    ui.set_controls(controls);
+                    ui.set_device_open(true);
+                    ui.set_status_text(format!("Opened {}", name).into());
+                }
+                Ok(Err(e)) => {
+                    error!("Failed to open {}: {}", name, e);
+                    ui.set_status_text(format!("Error: {}", e).into());
+                }
+                Err(e) => error!("Failed to open {}: {}", name, e),
+            }
+        })
+        .unwrap();
+    });
+
+    // Handle the monitor volume slider
+    let ui_handle = ui.as_weak();
+    let selected_device_clone = selected_device.clone();
+    ui.on_set_volume(move |volume_db| {
+        let ui = ui_handle.unwrap();
+        let selected_device = selected_device_clone.clone();
+
+        slint::spawn_local(async move {
+            let Some(result) = with_device(&selected_device, move |device| set_monitor_volume(device, volume_db)).await else {
+                return;
+            };
+
+            match result {
+                Ok(volume_db) => {
+                    let mut controls = ui.get_controls();
+                    controls.volume_db = volume_db;
+                    ui.set_controls(controls);
+                }
+                Err(e) => {
+                    error!("Failed to set volume: {}", e);
+                    ui.set_status_text(format!("Error: {}", e).into());
+                }
+            }
+        })
+        .unwrap();
+    });
+
+    // Handle the monitor mute button
+    let ui_handle = ui.as_weak();
+    let selected_device_clone = selected_device.clone();
+    ui.on_toggle_mute(move || {
+        let ui = ui_handle.unwrap();
+        let selected_device = selected_device_clone.clone();
+
+        slint::spawn_local(async move {
+            let Some(result) = with_device(&selected_device, toggle_monitor_mute).await else {
+                return;
+            };
+
+            match result {
+                Ok(muted) => {
+                    let mut controls = ui.get_controls();
+                    controls.muted = muted;
+                    ui.set_controls(controls);
+                }
+                Err(e) => {
+                    error!("Failed to toggle mute: {}", e);
+                    ui.set_status_text(format!("Error: {}", e).into());
+                }
+            }
+        })
+        .unwrap();
     });
 
     // Handle routing button
     let ui_handle = ui.as_weak();
     ui.on_open_routing(move || {
-        let ui = ui_handle.unwrap();
+        let _ui = ui_handle.unwrap();
         info!("Opening routing window");
         // TODO: Open routing window
     });
@@ -139,7 +413,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Handle mixer button
     let ui_handle = ui.as_weak();
     ui.on_open_mixer(move || {
-        let ui = ui_handle.unwrap();
+        let _ui = ui_handle.unwrap();
         info!("Opening mixer window");
         // TODO: Open mixer window
     });
@@ -147,44 +421,176 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Handle levels button
     let ui_handle = ui.as_weak();
     ui.on_open_levels(move || {
-        let ui = ui_handle.unwrap();
+        let _ui = ui_handle.unwrap();
         info!("Opening levels window");
         // TODO: Open levels window
     });
 
     // Spawn task to handle hotplug events
     let ui_weak = ui.as_weak();
+    let current_devices_clone = current_devices.clone();
+    let selected_device_clone = selected_device.clone();
     tokio::spawn(async move {
         while let Some(event) = hotplug_rx.recv().await {
             match event {
                 HotplugEvent::Connected(device_info) => {
                     info!("Device connected: {}", device_info.model);
-                    // TODO: Update UI
+
+                    // Carry on with the open device if this is it coming back
+                    let detector = hotplug_detector.clone();
+                    let reconnected = with_device(&selected_device_clone, move |device| {
+                        match detector.reconnect_device(device, &device_info) {
+                            Ok(true) => {}
+                            Ok(false) => return None,
+                            Err(e) => {
+                                warn!("Failed to reconnect {}: {}", device_info.model, e);
+                                return None;
+                            }
+                        }
+
+                        let reopened = device.info().clone();
+                        info!("{} reconnected", reopened.model.name());
+                        apply_output_links(device, &reopened.serial_number);
+                        Some((reopened, device_controls(device)))
+                    })
+                    .await;
+                    let Some((reopened, controls)) = reconnected.flatten() else {
+                        continue;
+                    };
+
+                    let index = {
+                        let mut current = current_devices_clone.lock().await;
+                        let index = current.iter().position(|d| d.serial_number == reopened.serial_number);
+                        if let Some(index) = index {
+                            current[index] = reopened.clone();
+                        }
+                        index
+                    };
+                    let _ = ui_weak.upgrade_in_event_loop(move |ui| {
+                        if let Some(index) = index {
+                            ui.get_devices().set_row_data(index, device_item(&reopened));
+                        }
+                        ui.set_controls(controls);
+                        ui.set_device_open(true);
+                        ui.set_status_text(format!("{} reconnected", reopened.model.name()).into());
+                    });
                 }
                 HotplugEvent::Disconnected(path) => {
                     info!("Device disconnected: {}", path);
-                    // TODO: Update UI
+
+                    // The notify task closes the device once it sees this
+                    if let Some(device) = selected_device_clone.lock().await.as_mut() {
+                        if device.info().usb_path == path {
+                            device.mark_disconnected();
+                        }
+                    }
+
+                    let index = current_devices_clone.lock().await.iter().position(|d| d.usb_path == path);
+                    if let Some(index) = index {
+                        let _ = ui_weak.upgrade_in_event_loop(move |ui| {
+                            let devices = ui.get_devices();
+                            if let Some(mut item) = devices.row_data(index) {
+                                item.status = "Disconnected".into();
+                                devices.set_row_data(index, item);
+                            }
+                        });
+                    }
                 }
             }
         }
     });
 
-    // Spawn task to handle volume commands
+    // Spawn task to save settled changes to flash
+    let selected_device_clone = selected_device.clone();
     tokio::spawn(async move {
-        while let Some(cmd) = volume_rx.recv().await {
-            match cmd {
-                VolumeCommand::VolumeUp => {
-                    info!("Volume up");
-                    // TODO: Increase device volume
+        let mut ticker = tokio::time::interval(AUTO_COMMIT_POLL);
+        loop {
+            ticker.tick().await;
+            with_device(&selected_device_clone, |device| {
+                if let Err(e) = device.poll_auto_commit() {
+                    warn!("Failed to save settings to flash: {}", e);
+                }
+            })
+            .await;
+        }
+    });
+
+    // Spawn task to follow the monitor knob and buttons
+    let ui_weak = ui.as_weak();
+    let selected_device_clone = selected_device.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(NOTIFY_POLL);
+        let mut offline = false;
+        loop {
+            ticker.tick().await;
+
+            // Stop talking to a device that has gone away; it is kept open
+            // so the hotplug task can reconnect it if it comes back
+            let polled = with_device(&selected_device_clone, |device| {
+                if device.is_connected() {
+                    Ok(refresh_notified(device))
+                } else {
+                    Err(device.info().model.name())
                 }
-                VolumeCommand::VolumeDown => {
-                    info!("Volume down");
-                    // TODO: Decrease device volume
+            })
+            .await;
+            let (volume_db, muted) = match polled {
+                None => continue,
+                Some(Ok(changes)) => changes,
+                Some(Err(name)) => {
+                    if !offline {
+                        warn!("{} is no longer connected", name);
+                        let _ = ui_weak.upgrade_in_event_loop(move |ui| {
+                            ui.set_device_open(false);
+                            ui.set_status_text(format!("{} disconnected", name).into());
+                        });
+                    }
+                    offline = true;
+                    continue;
                 }
-                VolumeCommand::Mute => {
-                    info!("Mute toggle");
-                    // TODO: Toggle device mute
+            };
+            offline = false;
+
+            if volume_db.is_none() && muted.is_none() {
+                continue;
+            }
+
+            let _ = ui_weak.upgrade_in_event_loop(move |ui| {
+                let mut controls = ui.get_controls();
+                if let Some(volume_db) = volume_db {
+                    controls.volume_db = volume_db;
                 }
+                if let Some(muted) = muted {
+                    controls.muted = muted;
+                }
+                ui.set_controls(controls);
+            });
+        }
+    });
+
+    // Spawn task to handle volume commands
+    let volume_step_db = prefs.volume_step_db;
+    let selected_device_clone = selected_device.clone();
+    tokio::spawn(async move {
+        let selected_device = selected_device_clone;
+        while let Some(cmd) = volume_rx.recv().await {
+            let result = with_device(&selected_device, move |device| match cmd {
+                VolumeCommand::VolumeUp => device
+                    .adjust_volume(MONITOR_OUTPUT, volume_step_db)
+                    .map(|db| info!("Volume up: {} dB", db)),
+                VolumeCommand::VolumeDown => device
+                    .adjust_volume(MONITOR_OUTPUT, -volume_step_db)
+                    .map(|db| info!("Volume down: {} dB", db)),
+                VolumeCommand::Mute => device
+                    .toggle_mute(MONITOR_OUTPUT)
+                    .map(|muted| info!("Mute: {}", muted)),
+            })
+            .await;
+
+            match result {
+                None => debug!("Ignoring {:?}: no device selected", cmd),
+                Some(Err(e)) => warn!("Failed to apply {:?}: {}", cmd, e),
+                Some(Ok(())) => {}
             }
         }
     });
@@ -192,6 +598,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Run UI event loop
     ui.run()?;
 
+    // Don't lose changes made within the commit delay
+    with_device(&selected_device, |device| {
+        if device.has_unsaved_changes() {
+            if let Err(e) = device.commit_to_flash() {
+                warn!("Failed to save settings to flash: {}", e);
+            }
+        }
+    })
+    .await;
+
     // Save preferences on exit
     config.save_preferences(&prefs)?;
     info!("Scarlett GUI exiting");
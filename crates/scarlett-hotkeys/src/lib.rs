@@ -1,13 +1,16 @@
 //! System keyboard volume control integration
 
-use scarlett_core::{Error, Result};
-use tokio::sync::mpsc;
+use scarlett_core::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::{mpsc, watch};
 use tracing::{debug, info};
 
 #[cfg(target_os = "macos")]
 mod macos;
 #[cfg(target_os = "linux")]
 mod linux;
+#[cfg(target_os = "windows")]
+mod windows;
 
 /// Volume control command
 #[derive(Debug, Clone, Copy)]
@@ -23,41 +26,89 @@ pub enum VolumeCommand {
 /// Hotkey manager
 pub struct HotkeyManager {
     command_tx: mpsc::UnboundedSender<VolumeCommand>,
+    /// Set to `true` to tell the capture tasks to shut down
+    stop_tx: watch::Sender<bool>,
+    running: AtomicBool,
 }
 
 impl HotkeyManager {
     /// Create a new hotkey manager
     pub fn new() -> (Self, mpsc::UnboundedReceiver<VolumeCommand>) {
         let (command_tx, command_rx) = mpsc::unbounded_channel();
-        (Self { command_tx }, command_rx)
+        let (stop_tx, _) = watch::channel(false);
+        let manager = Self {
+            command_tx,
+            stop_tx,
+            running: AtomicBool::new(false),
+        };
+        (manager, command_rx)
+    }
+
+    /// Check if keyboard capture is running
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
     }
 
     /// Start capturing keyboard events
+    ///
+    /// Does nothing if capture is already running.
     pub async fn start(&self) -> Result<()> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            debug!("Keyboard hotkey capture already running");
+            return Ok(());
+        }
+
         info!("Starting keyboard hotkey capture");
+        self.stop_tx.send_replace(false);
+
+        let result = self.start_platform().await;
+        if result.is_err() {
+            self.running.store(false, Ordering::SeqCst);
+        }
+        result
+    }
 
+    async fn start_platform(&self) -> Result<()> {
         #[cfg(target_os = "macos")]
         {
-            macos::start_capture(self.command_tx.clone()).await
+            macos::start_capture(self.command_tx.clone(), self.stop_tx.subscribe()).await
         }
 
         #[cfg(target_os = "linux")]
         {
-            linux::start_capture(self.command_tx.clone()).await
+            linux::start_capture(self.command_tx.clone(), self.stop_tx.subscribe()).await
+        }
+
+        #[cfg(target_os = "windows")]
+        {
+            windows::start_capture(self.command_tx.clone(), self.stop_tx.subscribe()).await
         }
 
-        #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+        #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
         {
-            Err(Error::NotSupported(
+            Err(scarlett_core::Error::NotSupported(
                 "Keyboard hotkeys not supported on this platform".to_string()
             ))
         }
     }
 
     /// Stop capturing keyboard events
+    ///
+    /// Capture tasks release their input devices as they exit. Does nothing
+    /// if capture is not running.
     pub fn stop(&self) {
+        if !self.running.swap(false, Ordering::SeqCst) {
+            return;
+        }
+
         info!("Stopping keyboard hotkey capture");
-        // TODO: Implement stop logic
+        self.stop_tx.send_replace(true);
+    }
+}
+
+impl Drop for HotkeyManager {
+    fn drop(&mut self) {
+        self.stop();
     }
 }
 
@@ -66,3 +117,18 @@ impl Default for HotkeyManager {
         Self::new().0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_is_idempotent() {
+        let (manager, _rx) = HotkeyManager::new();
+        assert!(!manager.is_running());
+
+        manager.stop();
+        manager.stop();
+        assert!(!manager.is_running());
+    }
+}
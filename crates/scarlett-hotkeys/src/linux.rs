@@ -1,34 +1,180 @@
 //! Linux keyboard event capture using evdev
 
 use super::VolumeCommand;
-use scarlett_core::Result;
-use tokio::sync::mpsc;
+use evdev::{Device, InputEventKind, Key};
+use scarlett_core::{Error, Result};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::sync::{mpsc, watch};
 use tracing::{debug, info, warn};
 
-// TODO: Implement Linux keyboard capture using evdev
-// This requires:
-// 1. Find keyboard device in /dev/input/event*
-// 2. Open device and read events
-// 3. Filter for KEY_VOLUMEUP, KEY_VOLUMEDOWN, KEY_MUTE
-// 4. Send VolumeCommand events when keys are pressed
+const INPUT_DIR: &str = "/dev/input";
 
-pub async fn start_capture(command_tx: mpsc::UnboundedSender<VolumeCommand>) -> Result<()> {
-    info!("Starting Linux keyboard event capture");
+/// Key value reported on press (0 is release, 2 is autorepeat)
+const KEY_PRESSED: i32 = 1;
+
+/// Map a key to the volume command it triggers
+fn volume_command(key: Key) -> Option<VolumeCommand> {
+    match key {
+        Key::KEY_VOLUMEUP => Some(VolumeCommand::VolumeUp),
+        Key::KEY_VOLUMEDOWN => Some(VolumeCommand::VolumeDown),
+        Key::KEY_MUTE => Some(VolumeCommand::Mute),
+        _ => None,
+    }
+}
+
+/// Check if a device advertises any of the volume keys
+fn has_volume_keys(device: &Device) -> bool {
+    device.supported_keys().is_some_and(|keys| {
+        keys.contains(Key::KEY_VOLUMEUP)
+            || keys.contains(Key::KEY_VOLUMEDOWN)
+            || keys.contains(Key::KEY_MUTE)
+    })
+}
+
+/// Open all event devices that have volume keys
+///
+/// Fails with `NotSupported` if no device could be opened because of
+/// missing permissions.
+fn find_volume_devices() -> Result<Vec<(PathBuf, Device)>> {
+    let mut devices = Vec::new();
+    let mut permission_denied = false;
+
+    for entry in std::fs::read_dir(INPUT_DIR)? {
+        let path = entry?.path();
+        if !is_event_node(&path) {
+            continue;
+        }
 
-    tokio::spawn(async move {
-        warn!("Linux keyboard capture not yet implemented");
+        match Device::open(&path) {
+            Ok(device) if has_volume_keys(&device) => {
+                debug!(
+                    "Found volume keys on {} ({})",
+                    path.display(),
+                    device.name().unwrap_or("unnamed")
+                );
+                devices.push((path, device));
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                permission_denied = true;
+            }
+            Err(e) => debug!("Skipping {}: {}", path.display(), e),
+        }
+    }
+
+    if devices.is_empty() && permission_denied {
+        return Err(Error::NotSupported(format!(
+            "Permission denied opening {}/event*; add your user to the 'input' group",
+            INPUT_DIR
+        )));
+    }
+
+    Ok(devices)
+}
 
-        // TODO: Implementation will:
-        // 1. Use evdev crate to enumerate input devices
-        // 2. Find device with volume key capabilities
-        // 3. Listen for key events
-        // 4. Send commands via command_tx
+fn is_event_node(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("event"))
+}
+
+/// Forward volume key presses from one device until it goes away or
+/// capture is stopped
+///
+/// The device is grabbed while it is watched, so its volume keys reach
+/// only us rather than also changing the system volume.
+async fn watch_device(
+    path: PathBuf,
+    mut device: Device,
+    command_tx: mpsc::UnboundedSender<VolumeCommand>,
+    mut stop_rx: watch::Receiver<bool>,
+) {
+    if let Err(e) = device.grab() {
+        warn!("Failed to grab {}: {}", path.display(), e);
+        return;
+    }
 
-        // For now, just keep task alive
-        loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    let mut events = match device.into_event_stream() {
+        Ok(events) => events,
+        Err(e) => {
+            warn!("Failed to read events from {}: {}", path.display(), e);
+            return;
         }
-    });
+    };
+
+    loop {
+        let event = tokio::select! {
+            result = events.next_event() => match result {
+                Ok(event) => event,
+                Err(e) => {
+                    info!("Stopped reading {}: {}", path.display(), e);
+                    break;
+                }
+            },
+            _ = stop_rx.wait_for(|stopped| *stopped) => break,
+        };
+
+        let InputEventKind::Key(key) = event.kind() else {
+            continue;
+        };
+
+        if event.value() != KEY_PRESSED {
+            continue;
+        }
+
+        if let Some(command) = volume_command(key) {
+            debug!("Volume key {:?} on {}", key, path.display());
+            if command_tx.send(command).is_err() {
+                // Receiver dropped, nobody is listening any more
+                break;
+            }
+        }
+    }
+
+    // Hand the keys back to the rest of the system; a device that went
+    // away has nothing left to release
+    debug!("Releasing {}", path.display());
+    if let Err(e) = events.device_mut().ungrab() {
+        debug!("Failed to ungrab {}: {}", path.display(), e);
+    }
+}
+
+pub async fn start_capture(
+    command_tx: mpsc::UnboundedSender<VolumeCommand>,
+    stop_rx: watch::Receiver<bool>,
+) -> Result<()> {
+    info!("Starting Linux keyboard event capture");
+
+    let devices = find_volume_devices()?;
+    if devices.is_empty() {
+        warn!("No input devices with volume keys found");
+        return Ok(());
+    }
+
+    info!("Monitoring {} input device(s) for volume keys", devices.len());
+
+    for (path, device) in devices {
+        tokio::spawn(watch_device(path, device, command_tx.clone(), stop_rx.clone()));
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_command_mapping() {
+        assert!(matches!(volume_command(Key::KEY_VOLUMEUP), Some(VolumeCommand::VolumeUp)));
+        assert!(matches!(volume_command(Key::KEY_MUTE), Some(VolumeCommand::Mute)));
+        assert!(volume_command(Key::KEY_A).is_none());
+    }
+
+    #[test]
+    fn test_event_node_filter() {
+        assert!(is_event_node(Path::new("/dev/input/event3")));
+        assert!(!is_event_node(Path::new("/dev/input/mice")));
+    }
+}
@@ -2,7 +2,7 @@
 
 use super::VolumeCommand;
 use scarlett_core::Result;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tracing::{debug, info, warn};
 
 // TODO: Implement macOS keyboard capture using CGEventTap
@@ -12,7 +12,10 @@ use tracing::{debug, info, warn};
 // 3. Send VolumeCommand events when keys are pressed
 // 4. Run event tap on a separate thread/task
 
-pub async fn start_capture(command_tx: mpsc::UnboundedSender<VolumeCommand>) -> Result<()> {
+pub async fn start_capture(
+    command_tx: mpsc::UnboundedSender<VolumeCommand>,
+    stop_rx: watch::Receiver<bool>,
+) -> Result<()> {
     info!("Starting macOS keyboard event capture");
 
     // Spawn a thread for the event tap (CFRunLoop must run on a dedicated thread)
@@ -28,10 +31,13 @@ pub async fn start_capture(command_tx: mpsc::UnboundedSender<VolumeCommand>) ->
         // 3. Add tap to run loop
         // 4. In callback: detect volume keys and send commands via command_tx
 
-        // Keep thread alive
-        loop {
-            std::thread::sleep(std::time::Duration::from_secs(1));
+        // Keep thread alive until capture is stopped; the event tap will be
+        // removed from the run loop here
+        while !*stop_rx.borrow() {
+            std::thread::sleep(std::time::Duration::from_millis(100));
         }
+
+        debug!("macOS keyboard capture stopped");
     });
 
     Ok(())
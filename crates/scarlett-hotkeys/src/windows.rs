@@ -0,0 +1,124 @@
+//! Windows keyboard event capture using a low-level keyboard hook
+//!
+//! A `WH_KEYBOARD_LL` hook is used rather than `RegisterHotKey` so that the
+//! media keys keep controlling the system volume as well.
+
+use super::VolumeCommand;
+use scarlett_core::{Error, Result};
+use std::ptr;
+use std::sync::Mutex;
+use tokio::sync::{mpsc, oneshot, watch};
+use tracing::{debug, info, warn};
+use windows_sys::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::System::Threading::GetCurrentThreadId;
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+    VK_VOLUME_DOWN, VK_VOLUME_MUTE, VK_VOLUME_UP,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    CallNextHookEx, GetMessageW, PostThreadMessageW, SetWindowsHookExW, UnhookWindowsHookEx,
+    HC_ACTION, KBDLLHOOKSTRUCT, MSG, WH_KEYBOARD_LL, WM_KEYDOWN, WM_QUIT, WM_SYSKEYDOWN,
+};
+
+/// Command sender used by the hook procedure, which has no user data pointer
+static COMMAND_TX: Mutex<Option<mpsc::UnboundedSender<VolumeCommand>>> = Mutex::new(None);
+
+/// Map a virtual key code to the volume command it triggers
+fn volume_command(vk_code: u32) -> Option<VolumeCommand> {
+    match u16::try_from(vk_code).ok()? {
+        VK_VOLUME_UP => Some(VolumeCommand::VolumeUp),
+        VK_VOLUME_DOWN => Some(VolumeCommand::VolumeDown),
+        VK_VOLUME_MUTE => Some(VolumeCommand::Mute),
+        _ => None,
+    }
+}
+
+unsafe extern "system" fn keyboard_hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    let key_down = wparam == WM_KEYDOWN as WPARAM || wparam == WM_SYSKEYDOWN as WPARAM;
+
+    if code == HC_ACTION as i32 && key_down {
+        // SAFETY: for WH_KEYBOARD_LL, lparam points to a KBDLLHOOKSTRUCT
+        let event = &*(lparam as *const KBDLLHOOKSTRUCT);
+
+        if let Some(command) = volume_command(event.vkCode) {
+            if let Ok(guard) = COMMAND_TX.lock() {
+                if let Some(tx) = guard.as_ref() {
+                    let _ = tx.send(command);
+                }
+            }
+        }
+    }
+
+    CallNextHookEx(ptr::null_mut(), code, wparam, lparam)
+}
+
+/// Install the hook and pump messages until `WM_QUIT` is posted
+fn run_hook_thread(ready_tx: oneshot::Sender<Result<u32>>) {
+    // SAFETY: plain Win32 calls on this thread; the hook is removed before
+    // the thread exits
+    unsafe {
+        let hook = SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_hook), ptr::null_mut(), 0);
+        if hook.is_null() {
+            let _ = ready_tx.send(Err(Error::NotSupported(format!(
+                "Failed to install keyboard hook: {}",
+                std::io::Error::last_os_error()
+            ))));
+            return;
+        }
+
+        let _ = ready_tx.send(Ok(GetCurrentThreadId()));
+
+        // Low-level hooks are called from this thread's message loop
+        let mut msg: MSG = std::mem::zeroed();
+        while GetMessageW(&mut msg, ptr::null_mut(), 0, 0) > 0 {}
+
+        UnhookWindowsHookEx(hook);
+    }
+
+    debug!("Windows keyboard hook removed");
+}
+
+pub async fn start_capture(
+    command_tx: mpsc::UnboundedSender<VolumeCommand>,
+    mut stop_rx: watch::Receiver<bool>,
+) -> Result<()> {
+    info!("Starting Windows keyboard event capture");
+
+    *COMMAND_TX.lock().map_err(|_| Error::Protocol("Hotkey state poisoned".to_string()))? =
+        Some(command_tx);
+
+    let (ready_tx, ready_rx) = oneshot::channel();
+    std::thread::Builder::new()
+        .name("scarlett-hotkeys".to_string())
+        .spawn(move || run_hook_thread(ready_tx))?;
+
+    let thread_id = ready_rx
+        .await
+        .map_err(|_| Error::Protocol("Keyboard hook thread exited".to_string()))??;
+
+    tokio::spawn(async move {
+        let _ = stop_rx.wait_for(|stopped| *stopped).await;
+
+        // SAFETY: posting to a thread ID is harmless if the thread has exited
+        if unsafe { PostThreadMessageW(thread_id, WM_QUIT, 0, 0) } == 0 {
+            warn!("Failed to stop keyboard hook thread");
+        }
+
+        if let Ok(mut tx) = COMMAND_TX.lock() {
+            *tx = None;
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_command_mapping() {
+        assert!(matches!(volume_command(VK_VOLUME_UP as u32), Some(VolumeCommand::VolumeUp)));
+        assert!(matches!(volume_command(VK_VOLUME_MUTE as u32), Some(VolumeCommand::Mute)));
+        assert!(volume_command(0x41).is_none());
+    }
+}
@@ -36,11 +36,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     entries.sort_by_key(|e| e.path());
 
+    let mut covered = Vec::new();
     for entry in entries {
         let path = entry.path();
 
         match FirmwareFile::from_file(&path) {
             Ok(firmware) => {
+                covered.push(firmware.header.usb_pid);
                 let model = pid_to_model(firmware.header.usb_pid);
                 println!(
                     "{:<40} 0x{:04X} {:10} {:>7} KB",
@@ -56,6 +58,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    let missing: Vec<_> = DeviceModel::all()
+        .iter()
+        .filter(|m| !covered.contains(&m.product_id()))
+        .collect();
+    if !missing.is_empty() {
+        println!("\nSupported devices without a firmware file:");
+        for model in missing {
+            println!("  {:<38} 0x{:04X}", model.name(), model.product_id());
+        }
+    }
+
     println!();
     Ok(())
 }
@@ -49,7 +49,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         match model.generation() {
             scarlett_core::DeviceGeneration::Gen4 => {
                 println!("🎛️  Attempting Gen 4 FCP communication...");
-                test_gen4_fcp(&device_info)?;
+                test_gen4_fcp(device_info, *model)?;
             }
             scarlett_core::DeviceGeneration::Gen3 => {
                 println!("🎛️  Gen 3 Scarlett2 protocol");
@@ -69,7 +69,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn test_gen4_fcp(device_info: &nusb::DeviceInfo) -> Result<(), Box<dyn std::error::Error>> {
+fn test_gen4_fcp(device_info: &nusb::DeviceInfo, model: DeviceModel) -> Result<(), Box<dyn std::error::Error>> {
     println!("  → Opening USB device...");
 
     // Open the nusb device
@@ -83,7 +83,7 @@ fn test_gen4_fcp(device_info: &nusb::DeviceInfo) -> Result<(), Box<dyn std::erro
 
     println!("  → Creating FCP protocol handler...");
     // Create FCP protocol with the interface number
-    let mut fcp = FcpProtocol::new_with_interface(Box::new(transport), interface_num);
+    let mut fcp = FcpProtocol::new_with_interface(Box::new(transport), interface_num).with_model(model);
 
     println!("  → Sending INIT command...");
     // Try initialization
@@ -106,9 +106,23 @@ fn test_gen4_fcp(device_info: &nusb::DeviceInfo) -> Result<(), Box<dyn std::erro
                 Err(e) => println!("  ⚠️  Failed to read mute: {}", e),
             }
 
+            // Read phantom power status
+            match fcp.get_phantom(0) {
+                Ok(enabled) => println!("  ⚡ Phantom power (48V): {}", if enabled { "ON" } else { "OFF" }),
+                Err(e) => println!("  ⚠️  Failed to read phantom power: {}", e),
+            }
+
+            // Read input levels
+            for input in model.level_inputs() {
+                match fcp.get_input_level(input) {
+                    Ok(level) => println!("  🎸 Input {} level: {}", input + 1, level),
+                    Err(e) => println!("  ⚠️  Failed to read input {} level: {}", input + 1, e),
+                }
+            }
+
             // Optionally test volume change (commented out for safety)
             // println!("\n  → Testing volume adjustment (+1 dB)...");
-            // match fcp.adjust_volume(0, 1) {
+            // match fcp.adjust_volume(0, 1.0) {
             //     Ok(new_vol) => println!("  ✅ New volume: {} dB", new_vol),
             //     Err(e) => println!("  ❌ Failed to adjust volume: {}", e),
             // }
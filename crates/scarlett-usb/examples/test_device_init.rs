@@ -1,6 +1,5 @@
 // Test opening and initializing a Scarlett device
-use scarlett_usb::{DeviceDetector, UsbDevice};
-use scarlett_core::DeviceModel;
+use scarlett_usb::DeviceDetector;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize logging
@@ -38,34 +37,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Try to open the first device
     println!("Attempting to open device: {}\n", devices[0].model.name());
 
-    // Get the nusb device handle
-    let nusb_info = nusb::list_devices()?
-        .find(|d| {
-            d.vendor_id() == devices[0].vendor_id &&
-            d.product_id() == devices[0].product_id
-        })
-        .ok_or("Device disappeared")?;
-
-    let nusb_device = nusb_info.open()?;
-
-    println!("✅ USB device opened\n");
-
-    // Create our device wrapper
-    println!("Creating device wrapper...");
-    let mut device = UsbDevice::open(devices[0].clone(), nusb_device)?;
-    println!("✅ Device wrapper created\n");
-
-    // Try to initialize
+    // Open the exact device found by the scan and send the INIT commands
     println!("Initializing device (sending INIT commands)...");
-    match device.initialize() {
-        Ok(()) => {
+    match detector.open_device(&devices[0]) {
+        Ok(mut device) => {
             println!("✅ Device initialized successfully!\n");
 
             // If it's a Gen 4 device, try some FCP commands
             if devices[0].model.generation() == scarlett_core::DeviceGeneration::Gen4 {
                 println!("Testing Gen 4 FCP protocol:");
 
-                if let Some(fcp) = device.fcp_protocol() {
+                if let Some(_fcp) = device.fcp_protocol() {
                     println!("  ✅ FCP protocol accessible");
 
                     // Try to read device info
@@ -42,7 +42,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    // Test with Vocaster Two (PID 0x821A)
+    // Test with 4i4 Gen 4 (PID 0x821A)
     let firmware_path = Path::new("scarlett2-firmware/firmware/scarlett2-1235-821a-2108.bin");
     println!("Testing: {}", firmware_path.display());
 
@@ -50,7 +50,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         Ok(firmware) => {
             println!("✅ Firmware file parsed successfully!");
             println!("   VID: 0x{:04X} (Focusrite)", firmware.header.usb_vid);
-            println!("   PID: 0x{:04X} (Vocaster Two)", firmware.header.usb_pid);
+            println!("   PID: 0x{:04X} (4i4 Gen 4)", firmware.header.usb_pid);
             println!("   Version: {}", firmware.header.firmware_version);
             println!("   Data size: {} bytes", firmware.data.len());
             println!("   SHA-256 verified: ✅\n");
@@ -0,0 +1,114 @@
+//! Debounced saving of the configuration to flash
+//!
+//! Every flash save wears the device's flash, so a burst of writes (e.g.
+//! dragging a fader) is coalesced into one save once the writes have
+//! settled.
+
+use std::time::{Duration, Instant};
+
+/// Settle delay after the last write, as used by the kernel driver
+pub const DEFAULT_COMMIT_DELAY: Duration = Duration::from_secs(2);
+
+/// Tracks configuration writes and decides when to save them to flash
+///
+/// Driven by the protocol handler's write counter and the caller's clock,
+/// so it does no I/O itself.
+#[derive(Debug, Clone)]
+pub struct AutoCommit {
+    delay: Duration,
+    seen_writes: u64,
+    last_write: Option<Instant>,
+}
+
+impl AutoCommit {
+    /// Save `delay` after the last write
+    ///
+    /// `writes` is the handler's current write count, so writes made before
+    /// auto-commit was enabled are not saved.
+    pub fn new(delay: Duration, writes: u64) -> Self {
+        Self {
+            delay,
+            seen_writes: writes,
+            last_write: None,
+        }
+    }
+
+    /// Settle delay after the last write
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Check if there are writes not yet saved
+    pub fn is_pending(&self) -> bool {
+        self.last_write.is_some()
+    }
+
+    /// Note the write count at `now`, returning true when a save is due
+    ///
+    /// A changed count restarts the delay. Once this returns true the
+    /// writes count as saved; call [`AutoCommit::retry`] if the save fails.
+    pub fn poll(&mut self, writes: u64, now: Instant) -> bool {
+        if writes != self.seen_writes {
+            self.seen_writes = writes;
+            self.last_write = Some(now);
+            return false;
+        }
+
+        match self.last_write {
+            Some(last) if now.saturating_duration_since(last) >= self.delay => {
+                self.last_write = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Schedule another attempt after a failed save
+    pub fn retry(&mut self, now: Instant) {
+        self.last_write = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debounce() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut commit = AutoCommit::new(Duration::from_millis(2000), 5);
+
+        // Nothing written yet
+        assert!(!commit.poll(5, at(0)));
+        assert!(!commit.poll(5, at(5000)));
+        assert!(!commit.is_pending());
+
+        // A fader drag: each write restarts the delay
+        for (i, ms) in (0..20).map(|i| (i, 5000 + i * 100)) {
+            assert!(!commit.poll(6 + i, at(ms)));
+        }
+        assert!(commit.is_pending());
+        assert!(!commit.poll(25, at(6900 + 1999)));
+
+        // One save once the writes settle
+        assert!(commit.poll(25, at(6900 + 2000)));
+        assert!(!commit.is_pending());
+        assert!(!commit.poll(25, at(20000)));
+    }
+
+    #[test]
+    fn test_retry() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut commit = AutoCommit::new(Duration::from_millis(500), 0);
+
+        assert!(!commit.poll(1, at(0)));
+        assert!(commit.poll(1, at(500)));
+
+        commit.retry(at(600));
+        assert!(commit.is_pending());
+        assert!(!commit.poll(1, at(1000)));
+        assert!(commit.poll(1, at(1100)));
+    }
+}
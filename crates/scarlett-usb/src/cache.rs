@@ -0,0 +1,98 @@
+//! Config-space read cache
+//!
+//! Keeps values read from the device's data space so the GUI can poll
+//! settings that rarely change without a USB round trip each time. Entries
+//! are dropped by byte range, so a change to one block of controls leaves
+//! the others cached.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Largest value size in bytes
+const MAX_VALUE_SIZE: u32 = 4;
+
+/// Cached data-space values
+#[derive(Debug, Clone, Default)]
+pub struct ConfigCache {
+    /// Size in bytes and value of each cached entry, by offset
+    values: BTreeMap<u32, (u32, i32)>,
+}
+
+impl ConfigCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a cached value read with the same size
+    pub fn get(&self, offset: u32, size: u32) -> Option<i32> {
+        match self.values.get(&offset) {
+            Some(&(cached_size, value)) if cached_size == size => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Store a value read from the device
+    pub fn insert(&mut self, offset: u32, size: u32, value: i32) {
+        self.values.insert(offset, (size, value));
+    }
+
+    /// Drop every entry overlapping `range`
+    pub fn invalidate(&mut self, range: Range<u32>) {
+        let stale: Vec<u32> = self
+            .values
+            .range(range.start.saturating_sub(MAX_VALUE_SIZE - 1)..range.end)
+            .filter(|(&offset, &(size, _))| offset + size > range.start)
+            .map(|(&offset, _)| offset)
+            .collect();
+
+        for offset in stale {
+            self.values.remove(&offset);
+        }
+    }
+
+    /// Drop every entry
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Offset and size of every cached entry
+    pub fn entries(&self) -> Vec<(u32, u32)> {
+        self.values.iter().map(|(&offset, &(size, _))| (offset, size)).collect()
+    }
+
+    /// Number of cached entries
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Check if nothing is cached
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalidate_range() {
+        let mut cache = ConfigCache::new();
+        cache.insert(10, 2, -5);
+        cache.insert(12, 1, 1);
+        cache.insert(20, 4, 7);
+
+        assert_eq!(cache.get(10, 2), Some(-5));
+        assert_eq!(cache.get(10, 1), None);
+
+        // Overlaps the second byte of the entry at 10 only
+        cache.invalidate(11..12);
+        assert_eq!(cache.get(10, 2), None);
+        assert_eq!(cache.get(12, 1), Some(1));
+
+        cache.invalidate(23..30);
+        assert!(cache.get(20, 4).is_none());
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,835 @@
+//! Configuration item tables
+//!
+//! Location, size, and activation command for the configuration parameters
+//! that can be read and written on each device. Based on the
+//! `scarlett2_config_set_*` tables in mixer_scarlett2.c.
+
+use crate::notify::Notification;
+use scarlett_core::{AutogainStatus, DeviceModel, DirectMonitorMode, Error, PowerStatus, Result};
+
+/// Configuration parameters that can be read and written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfigParam {
+    /// Preamp input gain (Gen 4, Vocaster)
+    InputGain,
+    /// 48V phantom power switch, one per phantom group
+    PhantomSwitch,
+    /// Keep phantom power state across power cycles (Gen 3)
+    PhantomPersistence,
+    /// Air switch (Gen 3, Clarett) or Air mode (Gen 4)
+    AirSwitch,
+    /// Line/Inst input level switch
+    LevelSwitch,
+    /// Direct Monitor switch (Solo, 2i2)
+    DirectMonitor,
+    /// Monitor Mute and Dim buttons; see `DIM_MUTE_INDEX_*`
+    DimMute,
+    /// Speaker switching (bit 0) and talkback (bit 1) enables
+    MonitorOtherEnable,
+    /// Alt speakers (bit 0) and talkback (bit 1) active switches
+    MonitorOtherSwitch,
+    /// Bitmap of mixes that talkback is routed to
+    TalkbackMap,
+    /// -10 dB input pad switch (Gen 2, Gen 3)
+    PadSwitch,
+    /// Keep routing active with no host connected (rack devices)
+    StandaloneSwitch,
+    /// Mass storage ("Easy Start") mode switch
+    MsdSwitch,
+    /// Autogain start/running switch (Gen 4)
+    AutogainSwitch,
+    /// Autogain result status (Gen 4)
+    AutogainStatus,
+    /// Line output volume in signed dB (Gen 2, Gen 3, Clarett)
+    LineOutVolume,
+    /// Line output mute switch (Gen 2, Gen 3, Clarett)
+    MuteSwitch,
+    /// Hardware monitor knob position in dB (read-only)
+    MasterVolume,
+    /// Per-output volume source: 0 = software, 1 = monitor knob
+    SwHwSwitch,
+    /// External power supply connected (read-only)
+    PowerExt,
+    /// Not enough power to run phantom power (read-only)
+    PowerLow,
+    /// Stereo link of an input pair (Gen 4)
+    InputLinkSwitch,
+}
+
+/// Location and activation details of a configuration parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigItem {
+    /// Offset into the device's data space
+    pub offset: u32,
+    /// Size in bits (1, 8, 16, or 32)
+    pub size: u8,
+    /// Activate command sent after the value is written
+    pub activate: u32,
+    /// Written through the parameter buffer rather than directly
+    pub pbuf: bool,
+    /// Written as 0x02/0x03 while the device mutes the channel (Gen 4)
+    pub mute: bool,
+}
+
+impl ConfigItem {
+    const fn new(offset: u32, size: u8, activate: u32) -> Self {
+        Self {
+            offset,
+            size,
+            activate,
+            pbuf: false,
+            mute: false,
+        }
+    }
+
+    const fn pbuf(mut self) -> Self {
+        self.pbuf = true;
+        self
+    }
+
+    const fn mute(mut self) -> Self {
+        self.mute = true;
+        self
+    }
+
+    /// Size of a single value in bytes (bit-sized items occupy one byte)
+    pub fn size_bytes(&self) -> u32 {
+        if self.size >= 8 {
+            self.size as u32 / 8
+        } else {
+            1
+        }
+    }
+}
+
+/// Configuration set used by a group of devices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigSet {
+    /// Gen 2 without SW/HW volume switch: 6i6, 18i8
+    Gen2a,
+    /// Gen 2 with SW/HW volume switch: 18i20
+    Gen2b,
+    /// Gen 3 without a mixer: Solo, 2i2
+    Gen3a,
+    /// Gen 3 without SW/HW volume switch: 4i4, 8i6
+    Gen3b,
+    /// Gen 3 with SW/HW volume switch: 18i8, 18i20
+    Gen3c,
+    /// Clarett USB and Clarett+
+    Clarett,
+    Vocaster,
+    Gen4Solo,
+    Gen4_2i2,
+    Gen4_4i4,
+}
+
+fn config_set(model: DeviceModel) -> Option<ConfigSet> {
+    use DeviceModel::*;
+    match model {
+        Scarlett6i6Gen2 | Scarlett18i8Gen2 => Some(ConfigSet::Gen2a),
+        Scarlett18i20Gen2 => Some(ConfigSet::Gen2b),
+        ScarlettSoloGen3 | Scarlett2i2Gen3 => Some(ConfigSet::Gen3a),
+        Scarlett4i4Gen3 | Scarlett8i6Gen3 => Some(ConfigSet::Gen3b),
+        Scarlett18i8Gen3 | Scarlett18i20Gen3 => Some(ConfigSet::Gen3c),
+        Clarett2PreUsb | Clarett4PreUsb | Clarett8PreUsb
+        | Clarett2PrePlus | Clarett4PrePlus | Clarett8PrePlus => Some(ConfigSet::Clarett),
+        VocasterOne | VocasterTwo => Some(ConfigSet::Vocaster),
+        ScarlettSoloGen4 => Some(ConfigSet::Gen4Solo),
+        Scarlett2i2Gen4 => Some(ConfigSet::Gen4_2i2),
+        Scarlett4i4Gen4 => Some(ConfigSet::Gen4_4i4),
+        _ => None,
+    }
+}
+
+/// Notification bits sent by Gen 2/3 and Clarett devices
+const SCARLETT2_NOTIFICATIONS: &[(u32, Notification)] = &[
+    (0x0000_0008, Notification::Sync),
+    (0x0020_0000, Notification::DimMute),
+    (0x0040_0000, Notification::Monitor),
+    (0x0080_0000, Notification::InputOther),
+    (0x0100_0000, Notification::MonitorOther),
+];
+
+/// Notification bits sent by the Solo and 2i2 Gen 3
+const GEN3A_NOTIFICATIONS: &[(u32, Notification)] = &[
+    (0x0080_0000, Notification::InputOther),
+    (0x0100_0000, Notification::DirectMonitor),
+];
+
+/// Notification bits a device sends, and what each one means
+///
+/// From the `*_notifications` tables in mixer_scarlett2.c. The command
+/// acknowledgement bit (bit 0) isn't listed. Vocaster and Gen 4 devices
+/// aren't covered yet and get an empty table.
+pub(crate) fn notifications(model: DeviceModel) -> &'static [(u32, Notification)] {
+    match config_set(model) {
+        Some(ConfigSet::Gen2a | ConfigSet::Gen2b | ConfigSet::Gen3b | ConfigSet::Gen3c | ConfigSet::Clarett) => {
+            SCARLETT2_NOTIFICATIONS
+        }
+        Some(ConfigSet::Gen3a) => GEN3A_NOTIFICATIONS,
+        _ => &[],
+    }
+}
+
+/// `DimMute` index of the monitor Mute button
+pub const DIM_MUTE_INDEX_MUTE: u8 = 0;
+
+/// `DimMute` index of the monitor Dim button
+pub const DIM_MUTE_INDEX_DIM: u8 = 1;
+
+/// Get the parameter buffer address for a device, if it has one
+///
+/// Vocaster and 4th Gen devices write some parameters by placing the
+/// channel and value in the parameter buffer and sending the activate
+/// command, rather than writing to the parameter's offset.
+pub fn param_buf_addr(model: DeviceModel) -> Option<u32> {
+    match config_set(model)? {
+        ConfigSet::Vocaster => Some(0x1bc),
+        ConfigSet::Gen4Solo => Some(0xd8),
+        ConfigSet::Gen4_2i2 => Some(0xfc),
+        ConfigSet::Gen4_4i4 => Some(0x130),
+        _ => None,
+    }
+}
+
+/// Look up a configuration parameter for a device
+///
+/// Returns `None` if the parameter is not present on this device.
+pub fn config_item(model: DeviceModel, param: ConfigParam) -> Option<ConfigItem> {
+    use ConfigParam::*;
+    use ConfigSet::*;
+
+    let item = match (config_set(model)?, param) {
+        (Vocaster, InputGain) => ConfigItem::new(0x9f, 8, 21).pbuf(),
+        (Gen4_2i2, InputGain) => ConfigItem::new(0x4b, 8, 12).pbuf(),
+        (Gen4_4i4, InputGain) => ConfigItem::new(0x5e, 8, 12).pbuf(),
+
+        (Gen3a, PhantomSwitch) => ConfigItem::new(0x06, 8, 3),
+        (Gen3b | Gen3c, PhantomSwitch) => ConfigItem::new(0x9c, 1, 8),
+        (Vocaster, PhantomSwitch) => ConfigItem::new(0x9c, 1, 20).pbuf(),
+        (Gen4Solo, PhantomSwitch) => ConfigItem::new(0x46, 8, 9).pbuf().mute(),
+        (Gen4_2i2, PhantomSwitch) => ConfigItem::new(0x48, 8, 11).pbuf().mute(),
+        (Gen4_4i4, PhantomSwitch) => ConfigItem::new(0x5a, 8, 11).pbuf().mute(),
+
+        (Gen3a, PhantomPersistence) => ConfigItem::new(0x05, 8, 6),
+        (Gen3b | Gen3c, PhantomPersistence) => ConfigItem::new(0x9e, 8, 6),
+
+        (Gen3a, AirSwitch) => ConfigItem::new(0x09, 1, 8),
+        (Gen3b | Gen3c, AirSwitch) => ConfigItem::new(0x8c, 8, 8),
+        (Clarett, AirSwitch) => ConfigItem::new(0x95, 8, 8),
+        (Gen4Solo, AirSwitch) => ConfigItem::new(0x3e, 8, 11).pbuf(),
+        (Gen4_2i2, AirSwitch) => ConfigItem::new(0x3e, 8, 15).pbuf(),
+        (Gen4_4i4, AirSwitch) => ConfigItem::new(0x50, 8, 15).pbuf(),
+
+        (Gen2a | Gen2b | Gen3b | Gen3c | Clarett, LineOutVolume) => ConfigItem::new(0x34, 16, 1),
+        (Gen2a | Gen2b | Gen3b | Gen3c | Clarett, MuteSwitch) => ConfigItem::new(0x5c, 8, 1),
+
+        (Gen2b | Gen3c | Clarett, DimMute) => ConfigItem::new(0x31, 8, 2),
+        (Gen2b | Gen3c | Clarett, MasterVolume) => ConfigItem::new(0x76, 16, 0),
+        (Gen2b | Gen3c | Clarett, SwHwSwitch) => ConfigItem::new(0x66, 8, 3),
+
+        (Gen3c, MonitorOtherSwitch) => ConfigItem::new(0x9f, 1, 10),
+        (Gen3c, MonitorOtherEnable) => ConfigItem::new(0xa0, 1, 10),
+        (Gen3c, TalkbackMap) => ConfigItem::new(0xb0, 16, 10),
+
+        (Gen3a, DirectMonitor) => ConfigItem::new(0x07, 8, 4),
+        (Gen4Solo, DirectMonitor) => ConfigItem::new(0x108, 8, 12).pbuf(),
+        (Gen4_2i2, DirectMonitor) => ConfigItem::new(0x14a, 8, 16).pbuf(),
+
+        (Gen2a | Gen2b | Gen3b | Gen3c | Clarett, LevelSwitch) => ConfigItem::new(0x7c, 8, 7),
+        (Gen3a, LevelSwitch) => ConfigItem::new(0x08, 1, 7),
+        (Gen4Solo, LevelSwitch) => ConfigItem::new(0x3d, 8, 10).pbuf().mute(),
+        (Gen4_2i2, LevelSwitch) => ConfigItem::new(0x3c, 8, 13).pbuf().mute(),
+        (Gen4_4i4, LevelSwitch) => ConfigItem::new(0x4e, 8, 13).pbuf().mute(),
+
+        (Gen2a | Gen2b | Gen3b | Gen3c, PadSwitch) => ConfigItem::new(0x84, 8, 8),
+
+        (Gen2a | Gen2b | Clarett, StandaloneSwitch) => ConfigItem::new(0x8d, 8, 6),
+        (Gen3b | Gen3c, StandaloneSwitch) => ConfigItem::new(0x95, 8, 6),
+
+        (Gen3a, MsdSwitch) => ConfigItem::new(0x04, 8, 6),
+        (Gen3b | Gen3c | Vocaster, MsdSwitch) => ConfigItem::new(0x9d, 8, 6),
+        (Gen4Solo, MsdSwitch) => ConfigItem::new(0x47, 8, 4),
+        (Gen4_2i2, MsdSwitch) => ConfigItem::new(0x49, 8, 4),
+        (Gen4_4i4, MsdSwitch) => ConfigItem::new(0x5c, 8, 4),
+
+        (Gen4_2i2, AutogainSwitch) => ConfigItem::new(0x135, 8, 10).pbuf(),
+        (Gen4_4i4, AutogainSwitch) => ConfigItem::new(0x13e, 8, 10).pbuf(),
+        (Gen4_2i2, AutogainStatus) => ConfigItem::new(0x137, 8, 0),
+        (Gen4_4i4, AutogainStatus) => ConfigItem::new(0x140, 8, 0),
+
+        (Gen4_2i2, InputLinkSwitch) => ConfigItem::new(0x14e, 8, 18).pbuf(),
+        (Gen4_4i4, InputLinkSwitch) => ConfigItem::new(0x156, 8, 17).pbuf(),
+
+        (Gen4_4i4, PowerExt) => ConfigItem::new(0x168, 8, 0),
+        (Gen4_4i4, PowerLow) => ConfigItem::new(0x16d, 8, 0),
+        _ => return None,
+    };
+
+    Some(item)
+}
+
+/// Get the configuration index of a phantom power switch
+///
+/// Switch indexes start at the first input with 48V, so on the Solo Gen 4
+/// (mic preamp on input 2) the only switch is index 1.
+pub fn phantom_config_index(model: DeviceModel, group: u8) -> Option<u8> {
+    model.phantom_group_inputs(group)?;
+    let first = model.phantom_group_inputs(0)?.start;
+    Some(group + first)
+}
+
+/// Check that an input has a Line/Inst level switch
+///
+/// Level switches are indexed by input number, so no mapping is needed.
+pub fn check_level_input(model: Option<DeviceModel>, input: u8) -> Result<()> {
+    let model = model.ok_or_else(|| {
+        Error::NotSupported("Input level: device model unknown".to_string())
+    })?;
+
+    let inputs = model.level_inputs();
+    if inputs.is_empty() || config_item(model, ConfigParam::LevelSwitch).is_none() {
+        return Err(Error::NotSupported(format!("Input level switching on {}", model)));
+    }
+    if !inputs.contains(&input) {
+        return Err(Error::InvalidParameter(format!("Input {} has no Inst mode", input)));
+    }
+    Ok(())
+}
+
+/// Encode a Direct Monitor mode for a device
+pub fn direct_monitor_to_raw(model: Option<DeviceModel>, mode: DirectMonitorMode) -> Result<i32> {
+    let modes = direct_monitor_modes(model)?;
+    modes
+        .iter()
+        .position(|&m| m == mode)
+        .map(|raw| raw as i32)
+        .ok_or_else(|| Error::InvalidParameter(format!("Direct Monitor mode {} not supported", mode)))
+}
+
+/// Decode a raw Direct Monitor value for a device
+pub fn direct_monitor_from_raw(model: Option<DeviceModel>, raw: i32) -> Result<DirectMonitorMode> {
+    let modes = direct_monitor_modes(model)?;
+    usize::try_from(raw)
+        .ok()
+        .and_then(|raw| modes.get(raw).copied())
+        .ok_or_else(|| Error::Protocol(format!("Invalid Direct Monitor value: {}", raw)))
+}
+
+fn direct_monitor_modes(model: Option<DeviceModel>) -> Result<&'static [DirectMonitorMode]> {
+    let model = model.ok_or_else(|| {
+        Error::NotSupported("Direct Monitor: device model unknown".to_string())
+    })?;
+
+    let modes = model.direct_monitor_modes();
+    if modes.is_empty() {
+        return Err(Error::NotSupported(format!("Direct Monitor on {}", model)));
+    }
+    Ok(modes)
+}
+
+/// Get the configuration index of an input's Air control
+///
+/// Air controls are numbered from the first input that has one.
+pub fn air_config_index(model: DeviceModel, input: u8) -> Option<u8> {
+    let inputs = model.air_inputs();
+    inputs.contains(&input).then(|| input - inputs.start)
+}
+
+/// `MonitorOtherEnable`/`MonitorOtherSwitch` index of talkback
+const MONITOR_OTHER_INDEX_TALKBACK: u8 = 1;
+
+/// Number of mixes talkback can be routed to
+pub fn talkback_mix_count(model: DeviceModel) -> u8 {
+    match model {
+        DeviceModel::Scarlett18i20Gen3 => 12,
+        _ => 0,
+    }
+}
+
+fn check_talkback(config: &impl ConfigAccess) -> Result<DeviceModel> {
+    match config.config_model() {
+        Some(model) if model.has_talkback() => Ok(model),
+        Some(model) => Err(Error::NotSupported(format!("Talkback on {}", model))),
+        None => Err(Error::NotSupported("Talkback: device model unknown".to_string())),
+    }
+}
+
+/// Get whether talkback is enabled and active
+pub fn get_talkback(config: &mut impl ConfigAccess) -> Result<bool> {
+    check_talkback(config)?;
+    let enabled = config.get_config(ConfigParam::MonitorOtherEnable, MONITOR_OTHER_INDEX_TALKBACK)?;
+    let active = config.get_config(ConfigParam::MonitorOtherSwitch, MONITOR_OTHER_INDEX_TALKBACK)?;
+    Ok(enabled != 0 && active != 0)
+}
+
+/// Enable and activate talkback, or disable it
+pub fn set_talkback(config: &mut impl ConfigAccess, enabled: bool) -> Result<()> {
+    check_talkback(config)?;
+    config.set_config(ConfigParam::MonitorOtherEnable, MONITOR_OTHER_INDEX_TALKBACK, enabled as i32)?;
+    config.set_config(ConfigParam::MonitorOtherSwitch, MONITOR_OTHER_INDEX_TALKBACK, enabled as i32)
+}
+
+/// Get the bitmap of mixes talkback is routed to (bit 0 = Mix A)
+pub fn get_talkback_map(config: &mut impl ConfigAccess) -> Result<u16> {
+    check_talkback(config)?;
+    Ok(config.get_config(ConfigParam::TalkbackMap, 0)? as u16)
+}
+
+/// Route talkback to a mix (0 = Mix A), or remove it
+pub fn set_talkback_mix(config: &mut impl ConfigAccess, mix: u8, enabled: bool) -> Result<()> {
+    let model = check_talkback(config)?;
+    config.lookup_config(ConfigParam::TalkbackMap)?;
+
+    if mix >= talkback_mix_count(model) {
+        return Err(Error::InvalidParameter(format!("No mix {} for talkback", mix)));
+    }
+
+    let mut bitmap = get_talkback_map(config)?;
+    if enabled {
+        bitmap |= 1 << mix;
+    } else {
+        bitmap &= !(1 << mix);
+    }
+
+    config.set_config(ConfigParam::TalkbackMap, 0, bitmap as i32)
+}
+
+/// Number of analogue outputs the monitor knob can be assigned to
+pub fn knob_output_count(model: DeviceModel) -> u8 {
+    use DeviceModel::*;
+    match model {
+        Clarett2PreUsb | Clarett2PrePlus => 4,
+        Scarlett18i8Gen3 | Clarett4PreUsb | Clarett4PrePlus => 8,
+        Scarlett18i20Gen2 | Scarlett18i20Gen3 | Clarett8PreUsb | Clarett8PrePlus => 10,
+        _ => 0,
+    }
+}
+
+/// Get the monitor knob position in dB
+pub fn get_knob_position(config: &mut impl ConfigAccess) -> Result<i32> {
+    // Stored as signed dB, like the line output volumes in the kernel driver
+    let raw = config.get_config(ConfigParam::MasterVolume, 0)?;
+    Ok((raw as i16 as i32).clamp(-127, 0))
+}
+
+fn knob_outputs(config: &impl ConfigAccess) -> Result<u8> {
+    let (model, _) = config.lookup_config(ConfigParam::SwHwSwitch)?;
+    Ok(knob_output_count(model))
+}
+
+/// Get the bitmap of outputs controlled by the monitor knob (bit 0 = output 1)
+pub fn get_knob_assignment(config: &mut impl ConfigAccess) -> Result<u32> {
+    let count = knob_outputs(config)?;
+
+    let mut mask = 0;
+    for output in 0..count {
+        if config.get_config(ConfigParam::SwHwSwitch, output)? != 0 {
+            mask |= 1 << output;
+        }
+    }
+    Ok(mask)
+}
+
+/// Choose which outputs the monitor knob controls (bit 0 = output 1)
+pub fn set_knob_assignment(config: &mut impl ConfigAccess, mask: u32) -> Result<()> {
+    let count = knob_outputs(config)?;
+    if mask >> count != 0 {
+        return Err(Error::InvalidParameter(format!(
+            "Knob assignment {:#x} includes outputs beyond {}",
+            mask, count
+        )));
+    }
+
+    for output in 0..count {
+        config.set_config(ConfigParam::SwHwSwitch, output, ((mask >> output) & 1) as i32)?;
+    }
+    Ok(())
+}
+
+/// Check that an input pair can be linked, returning its first input
+///
+/// Link switches are indexed by input, so a pair is addressed through the
+/// switch of its first input.
+fn check_input_link(config: &impl ConfigAccess, pair: u8) -> Result<u8> {
+    let (model, _) = config.lookup_config(ConfigParam::InputLinkSwitch)?;
+    if pair >= model.input_link_pairs() {
+        return Err(Error::InvalidParameter(format!("No linkable input pair {} on {}", pair, model)));
+    }
+    Ok(pair * 2)
+}
+
+/// Get whether an input pair (0 = inputs 1+2) is stereo-linked
+pub fn get_input_link(config: &mut impl ConfigAccess, pair: u8) -> Result<bool> {
+    let index = check_input_link(config, pair)?;
+    Ok(config.get_config(ConfigParam::InputLinkSwitch, index)? != 0)
+}
+
+/// Link or unlink an input pair (0 = inputs 1+2)
+pub fn set_input_link(config: &mut impl ConfigAccess, pair: u8, linked: bool) -> Result<()> {
+    let index = check_input_link(config, pair)?;
+    config.set_config(ConfigParam::InputLinkSwitch, index, linked as i32)
+}
+
+/// Check that an input has a software-controllable preamp
+fn check_gain_input(config: &impl ConfigAccess, input: u8) -> Result<DeviceModel> {
+    let (model, _) = config.lookup_config(ConfigParam::InputGain)?;
+    let count = model.gain_input_count();
+    if input >= count {
+        return Err(Error::InvalidParameter(format!(
+            "Input {} has no gain control ({} gain inputs)",
+            input, count
+        )));
+    }
+    Ok(model)
+}
+
+/// The input stereo-linked to `input`, if its pair is linked
+pub fn linked_input(config: &mut impl ConfigAccess, input: u8) -> Result<Option<u8>> {
+    let pair = input / 2;
+    if pair >= config.config_model().map(|m| m.input_link_pairs()).unwrap_or(0) {
+        return Ok(None);
+    }
+
+    Ok(get_input_link(config, pair)?.then_some(input ^ 1))
+}
+
+/// Get preamp gain for an input (0-based index), in dB
+pub fn get_input_gain(config: &mut impl ConfigAccess, input: u8) -> Result<u8> {
+    check_gain_input(config, input)?;
+    Ok(config.get_config(ConfigParam::InputGain, input)? as u8)
+}
+
+/// Set preamp gain for an input (0-based index), in dB, and for the input
+/// linked to it
+///
+/// Values outside the device's gain range are clamped; the value written
+/// is returned.
+pub fn set_input_gain(config: &mut impl ConfigAccess, input: u8, gain_db: u8) -> Result<u8> {
+    let model = check_gain_input(config, input)?;
+    let (min, max) = model
+        .input_gain_range()
+        .ok_or_else(|| Error::NotSupported("Input gain control".to_string()))?;
+
+    let clamped = gain_db.clamp(min, max);
+    if clamped != gain_db {
+        tracing::warn!(
+            "Input {} gain {} dB out of range ({}..={}), clamping to {} dB",
+            input, gain_db, min, max, clamped
+        );
+    }
+
+    config.set_config(ConfigParam::InputGain, input, clamped as i32)?;
+
+    if let Some(partner) = linked_input(config, input)? {
+        tracing::debug!("Input {} is linked, also setting input {}", input, partner);
+        config.set_config(ConfigParam::InputGain, partner, clamped as i32)?;
+    }
+
+    Ok(clamped)
+}
+
+/// Check that an input supports hardware autogain
+fn check_autogain_input(config: &impl ConfigAccess, input: u8) -> Result<()> {
+    let supported = config
+        .lookup_config(ConfigParam::AutogainSwitch)
+        .is_ok_and(|(model, _)| input < model.gain_input_count());
+
+    if !supported {
+        return Err(Error::NotSupported(format!("Autogain on input {}", input)));
+    }
+    Ok(())
+}
+
+/// Start hardware autogain on an input (0-based)
+pub fn start_autogain(config: &mut impl ConfigAccess, input: u8) -> Result<()> {
+    check_autogain_input(config, input)?;
+    config.set_config(ConfigParam::AutogainSwitch, input, 1)
+}
+
+/// Get the autogain status of an input (0-based)
+pub fn get_autogain_status(config: &mut impl ConfigAccess, input: u8) -> Result<AutogainStatus> {
+    check_autogain_input(config, input)?;
+    let running = config.get_config(ConfigParam::AutogainSwitch, input)? != 0;
+    let raw = config.get_config(ConfigParam::AutogainStatus, input)?;
+    Ok(AutogainStatus::from_gen4_raw(running, raw))
+}
+
+/// Get where the device is drawing its power from
+pub fn get_power_status(config: &mut impl ConfigAccess) -> Result<PowerStatus> {
+    let external = config.get_config(ConfigParam::PowerExt, 0)?;
+    let low = config.get_config(ConfigParam::PowerLow, 0)?;
+    Ok(PowerStatus::from_raw(external, low))
+}
+
+/// Activate value that saves the configuration to flash
+pub const CONFIG_SAVE: u32 = 6;
+
+/// Save the configuration to flash so it survives a power cycle
+pub fn commit_to_flash(config: &mut impl ConfigAccess) -> Result<()> {
+    config.activate_config(CONFIG_SAVE)
+}
+
+/// Parameter-level access to a device's configuration space
+///
+/// Implemented by the protocol handlers on top of their raw data reads and
+/// writes, so per-parameter encoding lives in one place.
+pub trait ConfigAccess {
+    /// Device model used to look up configuration items
+    fn config_model(&self) -> Option<DeviceModel>;
+
+    /// Read a 1, 2, or 4 byte value from the data space
+    fn read_data(&mut self, offset: u32, size: u32) -> Result<i32>;
+
+    /// Write a 1, 2, or 4 byte value to the data space
+    fn write_data(&mut self, offset: u32, size: u32, value: i32) -> Result<()>;
+
+    /// Activate a change previously written to the data space
+    fn activate_config(&mut self, activate: u32) -> Result<()>;
+
+    /// Look up a configuration item for this device
+    fn lookup_config(&self, param: ConfigParam) -> Result<(DeviceModel, ConfigItem)> {
+        let model = self.config_model().ok_or_else(|| {
+            Error::NotSupported(format!("{:?}: device model unknown", param))
+        })?;
+
+        let item = config_item(model, param)
+            .ok_or_else(|| Error::NotSupported(format!("{:?} on {}", param, model)))?;
+
+        Ok((model, item))
+    }
+
+    /// Read a configuration parameter value for the given index
+    fn get_config(&mut self, param: ConfigParam, index: u8) -> Result<i32> {
+        let (_, item) = self.lookup_config(param)?;
+
+        if item.size < 8 {
+            // Bit-sized parameters are packed into a single byte
+            let bits = self.read_data(item.offset, 1)?;
+            return Ok((bits >> index) & 1);
+        }
+
+        let size = item.size_bytes();
+        let value = self.read_data(item.offset + index as u32 * size, size)?;
+
+        Ok(match size {
+            // 0x02/0x03 are transitional values for 1/0 while muted
+            1 if item.mute && value & 0x02 != 0 => (value & 0x01 == 0) as i32,
+            1 => value & 0xff,
+            _ => value,
+        })
+    }
+
+    /// Write a configuration parameter value for the given index
+    ///
+    /// Parameters marked `pbuf` are written through the parameter buffer;
+    /// others are written directly. Either way the change is then activated.
+    fn set_config(&mut self, param: ConfigParam, index: u8, value: i32) -> Result<()> {
+        let (model, item) = self.lookup_config(param)?;
+
+        // Muteable Gen 4 controls set bit 1 while the device applies the change
+        let value = if item.mute {
+            (if value != 0 { 0 } else { 1 }) | 0x02
+        } else {
+            value
+        };
+
+        if item.pbuf {
+            let pbuf = param_buf_addr(model)
+                .ok_or_else(|| Error::Protocol(format!("{} has no parameter buffer", model)))?;
+
+            self.write_data(pbuf + 1, 1, index as i32)?;
+            self.write_data(pbuf, 1, value)?;
+        } else if item.size < 8 {
+            let mut bits = self.read_data(item.offset, 1)?;
+            if value != 0 {
+                bits |= 1 << index;
+            } else {
+                bits &= !(1 << index);
+            }
+            self.write_data(item.offset, 1, bits)?;
+        } else {
+            let size = item.size_bytes();
+            self.write_data(item.offset + index as u32 * size, size, value)?;
+        }
+
+        self.activate_config(item.activate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_gain_items() {
+        let item = config_item(DeviceModel::Scarlett2i2Gen4, ConfigParam::InputGain).unwrap();
+        assert_eq!(item.offset, 0x4b);
+        assert!(item.pbuf);
+        assert_eq!(param_buf_addr(DeviceModel::Scarlett2i2Gen4), Some(0xfc));
+
+        // Solo has no software-controllable gain
+        assert!(config_item(DeviceModel::ScarlettSoloGen4, ConfigParam::InputGain).is_none());
+    }
+
+    #[test]
+    fn test_phantom_items() {
+        let item = config_item(DeviceModel::Scarlett4i4Gen3, ConfigParam::PhantomSwitch).unwrap();
+        assert_eq!(item.size, 1);
+        assert_eq!(param_buf_addr(DeviceModel::Scarlett4i4Gen3), None);
+
+        let item = config_item(DeviceModel::Scarlett2i2Gen4, ConfigParam::PhantomSwitch).unwrap();
+        assert!(item.pbuf && item.mute);
+
+        // Gen 2 has hardware 48V switches only
+        assert!(config_item(DeviceModel::Scarlett6i6Gen2, ConfigParam::PhantomSwitch).is_none());
+    }
+
+    #[test]
+    fn test_pad_items() {
+        let item = config_item(DeviceModel::Scarlett18i20Gen3, ConfigParam::PadSwitch).unwrap();
+        assert_eq!((item.offset, item.activate), (0x84, 8));
+        assert_eq!(DeviceModel::Scarlett18i20Gen3.pad_inputs(), 0..8);
+
+        // Solo and 2i2 Gen 3 have no pads
+        assert!(config_item(DeviceModel::ScarlettSoloGen3, ConfigParam::PadSwitch).is_none());
+        assert!(DeviceModel::ScarlettSoloGen3.pad_inputs().is_empty());
+    }
+
+    #[test]
+    fn test_level_inputs() {
+        assert!(check_level_input(Some(DeviceModel::ScarlettSoloGen3), 1).is_ok());
+        assert!(matches!(
+            check_level_input(Some(DeviceModel::ScarlettSoloGen3), 0),
+            Err(Error::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            check_level_input(Some(DeviceModel::VocasterOne), 0),
+            Err(Error::NotSupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_dim_mute_items() {
+        let item = config_item(DeviceModel::Scarlett18i20Gen3, ConfigParam::DimMute).unwrap();
+        assert_eq!((item.offset, item.activate), (0x31, 2));
+        assert!(config_item(DeviceModel::Scarlett4i4Gen3, ConfigParam::DimMute).is_none());
+    }
+
+    #[test]
+    fn test_line_out_items() {
+        let item = config_item(DeviceModel::Scarlett4i4Gen3, ConfigParam::LineOutVolume).unwrap();
+        assert_eq!((item.offset, item.size_bytes(), item.activate), (0x34, 2, 1));
+        let item = config_item(DeviceModel::Clarett8PrePlus, ConfigParam::MuteSwitch).unwrap();
+        assert_eq!((item.offset, item.size_bytes(), item.activate), (0x5c, 1, 1));
+
+        // Solo and 2i2 Gen 3 have no software volume
+        assert!(config_item(DeviceModel::Scarlett2i2Gen3, ConfigParam::LineOutVolume).is_none());
+        assert!(config_item(DeviceModel::Scarlett4i4Gen3, ConfigParam::SwHwSwitch).is_none());
+    }
+
+    #[test]
+    fn test_standalone_items() {
+        let item = config_item(DeviceModel::Scarlett8i6Gen3, ConfigParam::StandaloneSwitch).unwrap();
+        assert_eq!((item.offset, item.activate), (0x95, 6));
+        let item = config_item(DeviceModel::Scarlett18i20Gen2, ConfigParam::StandaloneSwitch).unwrap();
+        assert_eq!(item.offset, 0x8d);
+
+        // No routing, no standalone mode
+        assert!(config_item(DeviceModel::Scarlett2i2Gen3, ConfigParam::StandaloneSwitch).is_none());
+    }
+
+    #[test]
+    fn test_gen2_items() {
+        use scarlett_core::protocol::gen2;
+
+        for model in [DeviceModel::Scarlett6i6Gen2, DeviceModel::Scarlett18i8Gen2, DeviceModel::Scarlett18i20Gen2] {
+            let offset = |param| config_item(model, param).map(|item| item.offset);
+            assert_eq!(offset(ConfigParam::LineOutVolume), Some(gen2::CONFIG_LINE_OUT_VOLUME));
+            assert_eq!(offset(ConfigParam::MuteSwitch), Some(gen2::CONFIG_MUTE_SWITCH));
+            assert_eq!(offset(ConfigParam::LevelSwitch), Some(gen2::CONFIG_LEVEL_SWITCH));
+            assert_eq!(offset(ConfigParam::PadSwitch), Some(gen2::CONFIG_PAD_SWITCH));
+            assert_eq!(offset(ConfigParam::StandaloneSwitch), Some(gen2::CONFIG_STANDALONE_SWITCH));
+
+            // No Air, phantom or MSD switches on Gen 2
+            assert_eq!(offset(ConfigParam::AirSwitch), None);
+            assert_eq!(offset(ConfigParam::PhantomSwitch), None);
+            assert_eq!(offset(ConfigParam::MsdSwitch), None);
+
+            let hw_volume = model == DeviceModel::Scarlett18i20Gen2;
+            assert_eq!(offset(ConfigParam::DimMute), hw_volume.then_some(gen2::CONFIG_DIM_MUTE));
+            assert_eq!(offset(ConfigParam::MasterVolume), hw_volume.then_some(gen2::CONFIG_MASTER_VOLUME));
+            assert_eq!(offset(ConfigParam::SwHwSwitch), hw_volume.then_some(gen2::CONFIG_SW_HW_SWITCH));
+        }
+    }
+
+    #[test]
+    fn test_msd_items() {
+        let item = config_item(DeviceModel::Scarlett2i2Gen3, ConfigParam::MsdSwitch).unwrap();
+        assert_eq!((item.offset, item.activate), (0x04, 6));
+        let item = config_item(DeviceModel::Scarlett4i4Gen4, ConfigParam::MsdSwitch).unwrap();
+        assert_eq!((item.offset, item.activate), (0x5c, 4));
+
+        // Gen 2 predates MSD mode
+        assert!(config_item(DeviceModel::Scarlett18i20Gen2, ConfigParam::MsdSwitch).is_none());
+    }
+
+    /// In-memory configuration space recording activations
+    struct MockConfig {
+        model: DeviceModel,
+        data: std::collections::HashMap<u32, u8>,
+        activations: Vec<u32>,
+    }
+
+    impl MockConfig {
+        fn new(model: DeviceModel) -> Self {
+            Self { model, data: Default::default(), activations: Vec::new() }
+        }
+    }
+
+    impl ConfigAccess for MockConfig {
+        fn config_model(&self) -> Option<DeviceModel> {
+            Some(self.model)
+        }
+
+        fn read_data(&mut self, offset: u32, size: u32) -> Result<i32> {
+            let mut bytes = [0u8; 4];
+            for i in 0..size {
+                bytes[i as usize] = self.data.get(&(offset + i)).copied().unwrap_or(0);
+            }
+            Ok(i32::from_le_bytes(bytes))
+        }
+
+        fn write_data(&mut self, offset: u32, size: u32, value: i32) -> Result<()> {
+            for (i, byte) in value.to_le_bytes().iter().take(size as usize).enumerate() {
+                self.data.insert(offset + i as u32, *byte);
+            }
+            Ok(())
+        }
+
+        fn activate_config(&mut self, activate: u32) -> Result<()> {
+            self.activations.push(activate);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_talkback_mix_bitmap() {
+        let mut config = MockConfig::new(DeviceModel::Scarlett18i20Gen3);
+
+        // Talkback on mixes A and C only
+        set_talkback_mix(&mut config, 0, true).unwrap();
+        set_talkback_mix(&mut config, 2, true).unwrap();
+
+        assert_eq!(config.read_data(0xb0, 2).unwrap(), 0b101);
+        assert_eq!(get_talkback_map(&mut config).unwrap(), 0b101);
+        assert_eq!(config.activations, vec![10, 10]);
+
+        set_talkback(&mut config, true).unwrap();
+        assert_eq!(config.read_data(0xa0, 1).unwrap(), 0b10);
+        assert!(get_talkback(&mut config).unwrap());
+
+        let mut config = MockConfig::new(DeviceModel::Scarlett18i8Gen3);
+        assert!(matches!(set_talkback(&mut config, true), Err(Error::NotSupported(_))));
+    }
+}
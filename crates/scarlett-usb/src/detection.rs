@@ -1,9 +1,16 @@
 //! USB device detection and hotplug
 
-use scarlett_core::{DeviceInfo, DeviceModel, Error, Result, FOCUSRITE_VENDOR_ID};
+use crate::device_impl::{OpenOptions, UsbDevice};
+use crate::direct_usb_transport::KernelDriverPolicy;
+use crate::virtual_device;
+use scarlett_core::{Device, DeviceInfo, DeviceModel, Error, Result, FOCUSRITE_VENDOR_ID};
+use std::path::PathBuf;
 use tokio::sync::mpsc;
 use tracing::{debug, info, warn};
 
+/// Serial number reported when the device doesn't provide one
+const UNKNOWN_SERIAL: &str = "Unknown";
+
 /// Hotplug event
 #[derive(Debug, Clone)]
 pub enum HotplugEvent {
@@ -16,13 +23,35 @@ pub enum HotplugEvent {
 /// Device detector
 pub struct DeviceDetector {
     event_tx: mpsc::UnboundedSender<HotplugEvent>,
+    open_options: OpenOptions,
 }
 
 impl DeviceDetector {
     /// Create a new device detector
     pub fn new() -> (Self, mpsc::UnboundedReceiver<HotplugEvent>) {
         let (event_tx, event_rx) = mpsc::unbounded_channel();
-        (Self { event_tx }, event_rx)
+        let detector = Self {
+            event_tx,
+            open_options: OpenOptions::default(),
+        };
+        (detector, event_rx)
+    }
+
+    /// Set what to do when opening a device whose control interface a
+    /// kernel driver owns
+    ///
+    /// Detaching the driver stops audio streaming while the device is
+    /// open, so it is off by default.
+    pub fn set_kernel_driver_policy(&mut self, policy: KernelDriverPolicy) {
+        self.open_options.kernel_driver_policy = policy;
+    }
+
+    /// Record the USB control transfers of devices opened from now on to
+    /// `path`, or stop recording with `None`
+    ///
+    /// The recording can be played back with `ReplayTransport`.
+    pub fn set_usb_trace(&mut self, path: Option<PathBuf>) {
+        self.open_options.usb_trace = path;
     }
 
     /// Scan for connected Scarlett devices
@@ -63,15 +92,11 @@ impl DeviceDetector {
                     // Get serial number
                     let serial = device_info
                         .serial_number()
-                        .unwrap_or("Unknown")
+                        .unwrap_or(UNKNOWN_SERIAL)
                         .to_string();
 
                     // Create USB path identifier
-                    let usb_path = format!(
-                        "usb-{:03}-{:03}",
-                        device_info.bus_number(),
-                        device_info.device_address()
-                    );
+                    let usb_path = usb_path(&device_info);
 
                     info!("   Serial: {}, Path: {}", serial, usb_path);
 
@@ -95,10 +120,89 @@ impl DeviceDetector {
             info!("🎵 Found {} Focusrite device(s)", focusrite_count);
         }
 
+        let virtual_devices = virtual_device::virtual_devices();
+        if !virtual_devices.is_empty() {
+            info!("🧪 Adding {} virtual device(s) from {}", virtual_devices.len(), virtual_device::VIRTUAL_ENV);
+            devices.extend(virtual_devices);
+        }
+
         info!("✨ Scan complete: {} Scarlett device(s) ready", devices.len());
         Ok(devices)
     }
 
+    /// Find a connected device by serial number
+    ///
+    /// Useful for reselecting a device after it re-enumerates, since its
+    /// USB path may change (e.g. after a hub reset).
+    pub fn find_device_by_serial(&self, serial: &str) -> Result<Option<DeviceInfo>> {
+        if serial == UNKNOWN_SERIAL {
+            return Ok(None);
+        }
+
+        let devices = scan_devices_internal()?;
+        Ok(devices.into_iter().find(|d| d.serial_number == serial))
+    }
+
+    /// Open and initialize a device found by a scan
+    ///
+    /// The device is matched by serial number, or by USB path if it has
+    /// none, so the same physical device is opened even if it has been
+    /// reconnected since the scan.
+    pub fn open_device(&self, info: &DeviceInfo) -> Result<UsbDevice> {
+        if virtual_device::is_virtual(info) {
+            let mut device = UsbDevice::open_virtual(info.clone());
+            device.initialize()?;
+            return Ok(device);
+        }
+
+        let mut device = UsbDevice::open_with_options(info.clone(), open_nusb_device(info)?, self.open_options.clone())?;
+        device.initialize()?;
+        Ok(device)
+    }
+
+    /// Reopen an open device that has re-enumerated
+    ///
+    /// Call this with each `HotplugEvent::Connected` device. If it is the
+    /// same physical device as `device` (by serial number) and that has
+    /// dropped off the bus or moved to a new address, the interface is
+    /// claimed again and the device re-initialized. Returns whether it was.
+    /// Devices without a serial number can't be recognised once their
+    /// address changes, so they have to be opened again instead.
+    pub fn reconnect_device(&self, device: &mut UsbDevice, info: &DeviceInfo) -> Result<bool> {
+        let current = device.info();
+        if virtual_device::is_virtual(current) || current.serial_number == UNKNOWN_SERIAL || !same_device(current, info) {
+            return Ok(false);
+        }
+        if device.is_connected() && current.usb_path == info.usb_path {
+            return Ok(false);
+        }
+
+        device.reconnect(info, open_nusb_device(info)?)?;
+        Ok(true)
+    }
+
+    /// Wait for a device to come back after a reboot
+    ///
+    /// Waits for the device to drop off the bus and then polls by serial
+    /// number until it reappears or the timeout expires. The running hotplug
+    /// monitor reports the same disconnect and reconnect.
+    pub async fn wait_for_device(&self, serial: &str, timeout: std::time::Duration) -> Result<Option<DeviceInfo>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let poll = tokio::time::Duration::from_millis(250);
+
+        let mut gone = false;
+        while tokio::time::Instant::now() < deadline {
+            match self.find_device_by_serial(serial)? {
+                Some(device) if gone => return Ok(Some(device)),
+                Some(_) => {}
+                None => gone = true,
+            }
+            tokio::time::sleep(poll).await;
+        }
+
+        Ok(None)
+    }
+
     /// Start hotplug monitoring
     pub async fn start_monitoring(&self) -> Result<()> {
         info!("Starting hotplug monitoring");
@@ -127,15 +231,26 @@ impl DeviceDetector {
 
                 // Check for new devices
                 for device in &devices {
-                    if !current_devices.iter().any(|d| d.usb_path == device.usb_path) {
-                        info!("Device connected: {}", device.model);
-                        let _ = event_tx.send(HotplugEvent::Connected(device.clone()));
+                    match current_devices.iter().find(|d| same_device(d, device)) {
+                        None => {
+                            info!("Device connected: {}", device.model);
+                            let _ = event_tx.send(HotplugEvent::Connected(device.clone()));
+                        }
+                        Some(previous) if previous.usb_path != device.usb_path => {
+                            // Same serial, new path: report it so handles can be reopened
+                            info!(
+                                "Device re-enumerated: {} ({} -> {})",
+                                device.model, previous.usb_path, device.usb_path
+                            );
+                            let _ = event_tx.send(HotplugEvent::Connected(device.clone()));
+                        }
+                        Some(_) => {}
                     }
                 }
 
                 // Check for removed devices
                 for device in &current_devices {
-                    if !devices.iter().any(|d| d.usb_path == device.usb_path) {
+                    if !devices.iter().any(|d| same_device(d, device)) {
                         info!("Device disconnected: {}", device.model);
                         let _ = event_tx.send(HotplugEvent::Disconnected(device.usb_path.clone()));
                     }
@@ -155,6 +270,35 @@ impl Default for DeviceDetector {
     }
 }
 
+/// Check if two scans refer to the same physical device
+///
+/// Serial numbers survive re-enumeration while USB paths don't, so prefer
+/// them when both devices report one.
+fn same_device(a: &DeviceInfo, b: &DeviceInfo) -> bool {
+    if a.serial_number != UNKNOWN_SERIAL && b.serial_number != UNKNOWN_SERIAL {
+        a.serial_number == b.serial_number
+    } else {
+        a.usb_path == b.usb_path
+    }
+}
+
+/// Open the USB device that a scan found
+///
+/// The device is matched by serial number, or by USB path if it has none.
+fn open_nusb_device(info: &DeviceInfo) -> Result<nusb::Device> {
+    nusb::list_devices()
+        .map_err(|e| Error::Usb(format!("Failed to list USB devices: {}", e)))?
+        .find(|d| scarlett_info(d).is_some_and(|found| found.model == info.model && same_device(&found, info)))
+        .ok_or(Error::DeviceNotFound)?
+        .open()
+        .map_err(|e| Error::Usb(format!("Failed to open {}: {}", info.model.name(), e)))
+}
+
+/// Path identifying a device by bus and address
+fn usb_path(device_info: &nusb::DeviceInfo) -> String {
+    format!("usb-{:03}-{:03}", device_info.bus_number(), device_info.device_address())
+}
+
 /// Internal function to scan for devices
 fn scan_devices_internal() -> Result<Vec<DeviceInfo>> {
     let mut devices = Vec::new();
@@ -163,24 +307,68 @@ fn scan_devices_internal() -> Result<Vec<DeviceInfo>> {
         .map_err(|e| Error::Usb(format!("Failed to list USB devices: {}", e)))?;
 
     for device_info in device_list {
-        if device_info.vendor_id() == FOCUSRITE_VENDOR_ID {
-            if let Some(model) = DeviceModel::from_product_id(device_info.product_id()) {
-                let serial = device_info
-                    .serial_number()
-                    .unwrap_or("Unknown")
-                    .to_string();
-
-                let usb_path = format!(
-                    "usb-{:03}-{:03}",
-                    device_info.bus_number(),
-                    device_info.device_address()
-                );
-
-                let device = DeviceInfo::new(model, serial, usb_path);
-                devices.push(device);
-            }
+        if let Some(device) = scarlett_info(&device_info) {
+            devices.push(device);
         }
     }
+    devices.extend(virtual_device::virtual_devices());
 
     Ok(devices)
 }
+
+/// Describe a USB device, if it is a supported Scarlett
+fn scarlett_info(device_info: &nusb::DeviceInfo) -> Option<DeviceInfo> {
+    if device_info.vendor_id() != FOCUSRITE_VENDOR_ID {
+        return None;
+    }
+    let model = DeviceModel::from_product_id(device_info.product_id())?;
+
+    let serial = device_info
+        .serial_number()
+        .unwrap_or(UNKNOWN_SERIAL)
+        .to_string();
+    Some(DeviceInfo::new(model, serial, usb_path(device_info)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(serial: &str, usb_path: &str) -> DeviceInfo {
+        DeviceInfo::new(DeviceModel::Scarlett2i2Gen4, serial.to_string(), usb_path.to_string())
+    }
+
+    #[test]
+    fn test_same_device_prefers_serial() {
+        // Path changed after a reconnect, serial still matches
+        assert!(same_device(&device("S123", "usb-001-004"), &device("S123", "usb-001-007")));
+        assert!(!same_device(&device("S123", "usb-001-004"), &device("S456", "usb-001-004")));
+    }
+
+    #[test]
+    fn test_reconnect_ignores_other_devices() {
+        let (detector, _events) = DeviceDetector::new();
+        let mut devices = virtual_device::parse_virtual_devices("2i2g4,4i4g4");
+        let mut device = UsbDevice::open_virtual(devices.remove(0));
+        device.mark_disconnected();
+
+        // Another device coming back leaves this one alone, and virtual
+        // devices are never reopened
+        let info = device.info().clone();
+        assert!(!detector.reconnect_device(&mut device, &devices[0]).unwrap());
+        assert!(!detector.reconnect_device(&mut device, &info).unwrap());
+        assert!(!device.is_connected());
+    }
+
+    #[test]
+    fn test_same_device_falls_back_to_path() {
+        assert!(same_device(
+            &device(UNKNOWN_SERIAL, "usb-001-004"),
+            &device(UNKNOWN_SERIAL, "usb-001-004")
+        ));
+        assert!(!same_device(
+            &device(UNKNOWN_SERIAL, "usb-001-004"),
+            &device("S123", "usb-001-007")
+        ));
+    }
+}
@@ -2,58 +2,132 @@
 //!
 //! Wires together device detection, USB transport, and protocol layers
 
-use scarlett_core::{Device, DeviceInfo, DeviceGeneration, Result};
-use crate::direct_usb_transport::DirectUsbTransport;
-use crate::gen4_fcp::FcpProtocol;
+use scarlett_core::{Device, DeviceCapabilities, DeviceInfo, DeviceGeneration, Error, FcpErrorCode, Result};
+use crate::autocommit::AutoCommit;
+use crate::direct_usb_transport::{DirectUsbTransport, KernelDriverPolicy};
+use crate::firmware::{EspFirmware, FirmwareUpdateOptions};
+use crate::gen4_fcp::{FcpProtocol, VolumeScale};
 use crate::gen3_protocol::Scarlett2Protocol;
+use crate::meters::{MeterBroadcast, MeterSource};
+use crate::protocol::Protocol;
+use crate::recorder::SessionRecorder;
+use crate::transport::UsbTransport;
+use crate::virtual_device::VirtualDevice;
+use futures::future::BoxFuture;
 use nusb::Device as NusbDevice;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 
 /// USB device wrapper that combines transport + protocol
 pub struct UsbDevice {
     info: DeviceInfo,
     device_type: DeviceType,
-    connected: bool,
+    /// Capabilities reported by the device (Gen 4 FCP only)
+    capabilities: DeviceCapabilities,
+    /// Saves configuration writes to flash once they settle, if enabled
+    auto_commit: Option<AutoCommit>,
+    /// Set when hotplug reports the device gone
+    unplugged: bool,
+    /// How the device was opened, for reconnecting
+    options: OpenOptions,
+}
+
+/// How to open a device
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    /// What to do if a kernel driver owns the control interface
+    pub kernel_driver_policy: KernelDriverPolicy,
+    /// Record every control transfer to this file, for debugging (see
+    /// [`SessionRecorder`])
+    pub usb_trace: Option<PathBuf>,
+}
+
+impl OpenOptions {
+    /// Claim the control interface, recording its transfers if asked to
+    ///
+    /// A reconnected device carries on the recording of its first open.
+    fn control_transport(&self, transport: DirectUsbTransport, reconnect: bool) -> Result<Box<dyn UsbTransport>> {
+        let Some(path) = &self.usb_trace else {
+            return Ok(Box::new(transport));
+        };
+
+        tracing::info!("Recording USB transfers to {}", path.display());
+        let recorder = if reconnect {
+            SessionRecorder::append(transport, path)?
+        } else {
+            SessionRecorder::create(transport, path)?
+        };
+        Ok(Box::new(recorder))
+    }
 }
 
 /// Device type with protocol-specific state
+///
+/// There is one per open device, so the larger FCP state isn't boxed.
+#[allow(clippy::large_enum_variant)]
 enum DeviceType {
     /// Gen 4 "big" devices using FCP protocol
     Gen4Fcp {
         protocol: FcpProtocol,
     },
-    /// Gen 2/3 devices using Scarlett2 protocol
-    Gen2Or3 {
+    /// Gen 2/3 and small Gen 4 devices using Scarlett2 protocol
+    Scarlett2 {
         protocol: Scarlett2Protocol,
     },
+    /// Simulated device with in-memory state
+    Virtual {
+        device: VirtualDevice,
+    },
 }
 
 impl UsbDevice {
     /// Open and initialize a device
     pub fn open(info: DeviceInfo, nusb_device: NusbDevice) -> Result<Self> {
+        Self::open_with_options(info, nusb_device, OpenOptions::default())
+    }
+
+    /// Open a device as `options` say
+    pub fn open_with_options(info: DeviceInfo, nusb_device: NusbDevice, options: OpenOptions) -> Result<Self> {
+        let policy = options.kernel_driver_policy;
         tracing::info!("Opening device: {} ({})", info.model.name(), info.serial_number);
 
         let generation = info.model.generation();
 
         let device_type = match generation {
-            DeviceGeneration::Gen4 => {
-                // Gen 4 "big" devices use FCP
+            DeviceGeneration::Gen4 if info.model.uses_fcp() => {
+                // Big Gen 4 devices use FCP
                 tracing::info!("Initializing Gen 4 FCP protocol");
 
-                // Create USB transport
-                let transport = DirectUsbTransport::new(nusb_device, 0)?;
+                // Create USB transport on the vendor-specific control
+                // interface; interface 0 is audio streaming on some models
+                let transport = DirectUsbTransport::vendor_interface_with_policy(nusb_device, policy)?;
+                let interface_num = transport.interface_number();
 
                 // Create FCP protocol handler (boxing the transport)
-                let protocol = FcpProtocol::new(Box::new(transport));
+                let protocol = FcpProtocol::new_with_interface(options.control_transport(transport, false)?, interface_num)
+                    .with_model(info.model);
 
                 DeviceType::Gen4Fcp { protocol }
             }
-            DeviceGeneration::Gen2 | DeviceGeneration::Gen3 => {
-                // Gen 2/3 use Scarlett2 protocol
-                tracing::info!("Initializing Gen 2/3 Scarlett2 protocol");
+            DeviceGeneration::Gen2 | DeviceGeneration::Gen3 | DeviceGeneration::Gen4 => {
+                // Gen 2/3 and the small Gen 4 devices use Scarlett2
+                tracing::info!("Initializing Scarlett2 protocol");
+
+                let transport = DirectUsbTransport::vendor_interface_with_policy(nusb_device, policy)?;
+                let interface_num = transport.interface_number();
+                let notify_endpoint = transport.interrupt_endpoint();
 
-                let protocol = Scarlett2Protocol::new(nusb_device);
+                let mut protocol = Scarlett2Protocol::new(options.control_transport(transport, false)?)
+                    .with_interface(interface_num)
+                    .with_model(info.model);
+                match notify_endpoint {
+                    Some(endpoint) => protocol = protocol.with_notify_endpoint(endpoint),
+                    None => tracing::warn!("No notification endpoint; front panel changes won't be seen"),
+                }
 
-                DeviceType::Gen2Or3 { protocol }
+                DeviceType::Scarlett2 { protocol }
             }
             _ => {
                 return Err(scarlett_core::Error::Protocol(
@@ -63,12 +137,32 @@ impl UsbDevice {
         };
 
         Ok(Self {
+            capabilities: DeviceCapabilities::for_model(info.model),
             info,
             device_type,
-            connected: true,
+            auto_commit: None,
+            unplugged: false,
+            options,
         })
     }
 
+    /// Open a simulated device, for working without hardware
+    ///
+    /// See [`crate::virtual_device`].
+    pub fn open_virtual(info: DeviceInfo) -> Self {
+        tracing::info!("Opening virtual device: {} ({})", info.model.name(), info.serial_number);
+
+        let device = VirtualDevice::new(info.clone());
+        Self {
+            capabilities: device.capabilities().clone(),
+            info,
+            device_type: DeviceType::Virtual { device },
+            auto_commit: None,
+            unplugged: false,
+            options: OpenOptions::default(),
+        }
+    }
+
     /// Initialize device (send INIT commands, etc.)
     pub fn initialize(&mut self) -> Result<()> {
         tracing::info!("Initializing device: {}", self.info.model.name());
@@ -82,18 +176,286 @@ impl UsbDevice {
                 tracing::debug!("INIT_1 response: {} bytes", resp1.len());
                 tracing::debug!("INIT_2 response: {} bytes", resp2.len());
 
+                if let Some(versions) = protocol.versions() {
+                    self.info.firmware_version = Some(versions.firmware.to_string());
+                }
+
+                // Without a device map, volume and mute use fixed offsets
+                if let Err(e) = protocol.read_devmap() {
+                    tracing::warn!("Failed to read device map: {}", e);
+                }
+
+                // Keep the per-model tables if CapRead fails
+                match protocol.read_capabilities() {
+                    Ok(caps) => self.capabilities = caps,
+                    Err(e) => tracing::warn!("Failed to read device capabilities: {}", e),
+                }
+
                 tracing::info!("Gen 4 device initialized successfully");
             }
-            DeviceType::Gen2Or3 { .. } => {
-                // Gen 2/3 initialization (TODO)
-                tracing::info!("Gen 2/3 initialization not yet implemented");
+            DeviceType::Scarlett2 { protocol } => {
+                tracing::debug!("Sending Scarlett2 INIT commands");
+                protocol.init()?;
+
+                if let Some(versions) = protocol.versions() {
+                    self.info.firmware_version = Some(versions.firmware.to_string());
+                }
+
+                tracing::info!("Scarlett2 device initialized successfully");
             }
+            DeviceType::Virtual { device } => {
+                self.info.firmware_version = device.info().firmware_version.clone();
+                tracing::info!("Virtual device initialized successfully");
+            }
+        }
+
+        // Devices without an MSD switch (Gen 2, big Gen 4) are never in MSD mode
+        let msd_mode = match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.get_msd_mode(),
+            DeviceType::Scarlett2 { protocol } => protocol.get_msd_mode(),
+            DeviceType::Virtual { device } => device.get_msd_mode(),
+        };
+        self.info.msd_mode = msd_mode.unwrap_or(false);
+        if self.info.msd_mode {
+            tracing::warn!("{} is in MSD (Easy Start) mode", self.info.model.name());
         }
 
         Ok(())
     }
 
-    /// Get access to Gen 4 FCP protocol
+    /// Turn off MSD mode and reboot the device
+    ///
+    /// The device drops off the bus, so this handle is unusable afterwards.
+    /// Wait for it to come back (e.g. `DeviceDetector::wait_for_device`) and
+    /// pass it to `DeviceDetector::reconnect_device`.
+    pub fn disable_msd_mode(&mut self) -> Result<()> {
+        match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.disable_msd_mode()?,
+            DeviceType::Scarlett2 { protocol } => protocol.disable_msd_mode()?,
+            DeviceType::Virtual { device } => device.disable_msd_mode()?,
+        }
+        self.info.msd_mode = false;
+        Ok(())
+    }
+
+    /// Flash the ESP (Wi-Fi module) firmware
+    ///
+    /// Refused unless `options.update_esp` is set, so a normal firmware
+    /// update never touches the ESP by accident.
+    pub async fn update_esp_firmware(
+        &mut self,
+        firmware: &EspFirmware,
+        options: &FirmwareUpdateOptions,
+        mut progress: impl FnMut(u8),
+    ) -> Result<()> {
+        if !options.update_esp {
+            return Err(Error::InvalidParameter(
+                "ESP firmware update not enabled in the update options".to_string(),
+            ));
+        }
+
+        firmware.validate_for_device(self.info.vendor_id, self.info.product_id)?;
+
+        if !self.capabilities.esp_dfu {
+            return Err(Error::NotSupported("Device has no ESP to update".to_string()));
+        }
+
+        let DeviceType::Gen4Fcp { protocol } = &mut self.device_type else {
+            return Err(Error::NotSupported(
+                "ESP firmware update requires a Gen 4 device".to_string(),
+            ));
+        };
+
+        // The update starts over from the first block, so a device timeout
+        // can be retried once; other device errors need the user
+        match protocol.esp_dfu_update(&firmware.data, &mut progress).await {
+            Err(e) if e.device_code() == Some(FcpErrorCode::Timeout) => {
+                tracing::warn!("ESP firmware update timed out, restarting: {}", e);
+                protocol.esp_dfu_update(&firmware.data, &mut progress).await
+            }
+            Err(e) => {
+                if let Some(hint) = e.hint() {
+                    tracing::error!("ESP firmware update failed: {} ({})", e, hint);
+                }
+                Err(e)
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Mark the device as gone, e.g. on a hotplug disconnect event
+    ///
+    /// `is_connected` reports false from then on, even before a transfer
+    /// has failed.
+    pub fn mark_disconnected(&mut self) {
+        if !self.unplugged {
+            tracing::info!("{} marked disconnected", self.info.model.name());
+        }
+        self.unplugged = true;
+    }
+
+    /// Carry on with a device that has dropped off the bus and come back,
+    /// e.g. after a firmware update, disabling MSD mode or sleep
+    ///
+    /// `nusb_device` is the re-enumerated device and `info` its new scan
+    /// (see `DeviceDetector::reconnect_device`). The interface is claimed
+    /// again and the device re-initialized; until then calls fail with
+    /// `Error::Disconnected` and can be retried once this succeeds.
+    pub fn reconnect(&mut self, info: &DeviceInfo, nusb_device: NusbDevice) -> Result<()> {
+        tracing::info!("Reconnecting {} at {}", self.info.model.name(), info.usb_path);
+
+        if let DeviceType::Virtual { .. } = self.device_type {
+            return Err(Error::NotSupported("Virtual devices never disconnect".to_string()));
+        }
+
+        let transport = DirectUsbTransport::vendor_interface_with_policy(nusb_device, self.options.kernel_driver_policy)?;
+        let transport = self.options.control_transport(transport, true)?;
+        match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.set_transport(transport),
+            DeviceType::Scarlett2 { protocol } => protocol.set_transport(transport),
+            DeviceType::Virtual { .. } => {}
+        }
+
+        self.info.usb_path = info.usb_path.clone();
+        self.unplugged = false;
+        self.initialize()
+    }
+
+    /// Get the device capabilities
+    ///
+    /// Reported by the device on big Gen 4, otherwise from the per-model tables.
+    pub fn capabilities(&self) -> &DeviceCapabilities {
+        &self.capabilities
+    }
+
+    /// Number of configuration writes made through the protocol handler
+    fn config_writes(&self) -> u64 {
+        match &self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.config_writes(),
+            DeviceType::Scarlett2 { protocol } => protocol.config_writes(),
+            DeviceType::Virtual { device } => device.config_writes(),
+        }
+    }
+
+    /// Save the configuration to flash so it survives a power cycle
+    pub fn commit_to_flash(&mut self) -> Result<()> {
+        match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.commit_to_flash(),
+            DeviceType::Scarlett2 { protocol } => protocol.commit_to_flash(),
+            DeviceType::Virtual { device } => device.commit_to_flash(),
+        }
+    }
+
+    /// Save configuration writes to flash `delay` after the last one, or
+    /// turn auto-commit off with `None`
+    ///
+    /// Saves only happen from [`UsbDevice::poll_auto_commit`], which the
+    /// caller runs periodically.
+    pub fn set_auto_commit(&mut self, delay: Option<Duration>) {
+        let writes = self.config_writes();
+        self.auto_commit = delay.map(|delay| AutoCommit::new(delay, writes));
+    }
+
+    /// Check if there are configuration writes waiting to be saved
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.auto_commit.as_ref().is_some_and(|commit| commit.is_pending())
+    }
+
+    /// Save to flash if auto-commit is on and the writes have settled
+    ///
+    /// Returns true if a save was made.
+    pub fn poll_auto_commit(&mut self) -> Result<bool> {
+        let writes = self.config_writes();
+        let now = Instant::now();
+        let Some(commit) = &mut self.auto_commit else {
+            return Ok(false);
+        };
+        if !commit.poll(writes, now) {
+            return Ok(false);
+        }
+
+        if let Err(e) = self.commit_to_flash() {
+            if let Some(commit) = &mut self.auto_commit {
+                commit.retry(now);
+            }
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Get the line output volume scale
+    pub fn volume_scale(&self) -> VolumeScale {
+        match &self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.volume_scale(),
+            DeviceType::Scarlett2 { protocol } => protocol.volume_scale(),
+            DeviceType::Virtual { device } => device.volume_scale(),
+        }
+    }
+
+    /// Get the volume of a line output, in dB
+    pub fn get_volume(&mut self, output_index: u8) -> Result<f32> {
+        match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.get_volume(output_index),
+            DeviceType::Scarlett2 { protocol } => protocol.get_volume(output_index),
+            DeviceType::Virtual { device } => device.get_volume(output_index),
+        }
+    }
+
+    /// Set the volume of a line output, in dB
+    pub fn set_volume(&mut self, output_index: u8, volume_db: f32) -> Result<()> {
+        match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.set_volume(output_index, volume_db),
+            DeviceType::Scarlett2 { protocol } => protocol.set_volume(output_index, volume_db),
+            DeviceType::Virtual { device } => device.set_volume(output_index, volume_db),
+        }
+    }
+
+    /// Adjust the volume of a line output, returning the new volume in dB
+    pub fn adjust_volume(&mut self, output_index: u8, delta_db: f32) -> Result<f32> {
+        match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.adjust_volume(output_index, delta_db),
+            DeviceType::Scarlett2 { protocol } => protocol.adjust_volume(output_index, delta_db),
+            DeviceType::Virtual { device } => device.adjust_volume(output_index, delta_db),
+        }
+    }
+
+    /// Get the mute state of a line output
+    pub fn get_mute(&mut self, output_index: u8) -> Result<bool> {
+        match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.get_mute(output_index),
+            DeviceType::Scarlett2 { protocol } => protocol.get_mute(output_index),
+            DeviceType::Virtual { device } => device.get_mute(output_index),
+        }
+    }
+
+    /// Set the mute state of a line output
+    pub fn set_mute(&mut self, output_index: u8, muted: bool) -> Result<()> {
+        match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.set_mute(output_index, muted),
+            DeviceType::Scarlett2 { protocol } => protocol.set_mute(output_index, muted),
+            DeviceType::Virtual { device } => device.set_mute(output_index, muted),
+        }
+    }
+
+    /// Toggle the mute state of a line output, returning the new state
+    pub fn toggle_mute(&mut self, output_index: u8) -> Result<bool> {
+        match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.toggle_mute(output_index),
+            DeviceType::Scarlett2 { protocol } => protocol.toggle_mute(output_index),
+            DeviceType::Virtual { device } => device.toggle_mute(output_index),
+        }
+    }
+
+    /// Get access to the device through the generic protocol interface
+    pub fn protocol(&mut self) -> &mut dyn Protocol {
+        match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol,
+            DeviceType::Scarlett2 { protocol } => protocol,
+            DeviceType::Virtual { device } => device,
+        }
+    }
+
+    /// Get access to the FCP protocol (big Gen 4)
     pub fn fcp_protocol(&mut self) -> Option<&mut FcpProtocol> {
         match &mut self.device_type {
             DeviceType::Gen4Fcp { protocol } => Some(protocol),
@@ -101,13 +463,39 @@ impl UsbDevice {
         }
     }
 
-    /// Get access to Gen 2/3 Scarlett2 protocol
+    /// Get access to the Scarlett2 protocol (Gen 2/3 and small Gen 4)
     pub fn scarlett2_protocol(&mut self) -> Option<&mut Scarlett2Protocol> {
         match &mut self.device_type {
-            DeviceType::Gen2Or3 { protocol } => Some(protocol),
+            DeviceType::Scarlett2 { protocol } => Some(protocol),
             _ => None,
         }
     }
+
+    /// Start polling the level meters in the background
+    ///
+    /// The task stops once the device is dropped or disconnected; see
+    /// [`MeterBroadcast`].
+    pub fn spawn_meter_broadcast(device: &Arc<Mutex<Self>>, interval: Duration) -> MeterBroadcast {
+        MeterBroadcast::spawn(Arc::downgrade(device), interval)
+    }
+}
+
+impl MeterSource for UsbDevice {
+    fn meter_count(&mut self) -> Result<u16> {
+        match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => MeterSource::meter_count(protocol),
+            DeviceType::Scarlett2 { protocol } => MeterSource::meter_count(protocol),
+            DeviceType::Virtual { device } => device.meter_count(),
+        }
+    }
+
+    fn read_levels(&mut self, count: u16) -> BoxFuture<'_, Result<Vec<f32>>> {
+        match &mut self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.read_levels(count),
+            DeviceType::Scarlett2 { protocol } => protocol.read_levels(count),
+            DeviceType::Virtual { device } => device.read_levels(count),
+        }
+    }
 }
 
 impl Device for UsbDevice {
@@ -116,65 +504,49 @@ impl Device for UsbDevice {
     }
 
     fn is_connected(&self) -> bool {
-        self.connected
+        if self.unplugged {
+            return false;
+        }
+
+        match &self.device_type {
+            DeviceType::Gen4Fcp { protocol } => protocol.is_connected(),
+            DeviceType::Scarlett2 { protocol } => protocol.is_connected(),
+            DeviceType::Virtual { device } => device.is_connected(),
+        }
     }
 
     fn num_inputs(&self) -> usize {
-        use scarlett_core::DeviceModel::*;
-        match self.info.model {
-            Scarlett2i2Gen3 | Scarlett2i2Gen4 => 2,
-            Scarlett4i4Gen3 | Scarlett4i4Gen4 => 4,
-            Scarlett6i6Gen2 => 6,
-            Scarlett8i6Gen3 => 8,
-            Scarlett18i8Gen2 | Scarlett18i8Gen3 => 18,
-            Scarlett18i20Gen2 | Scarlett18i20Gen3 | Scarlett18i20Gen4 => 18,
-            Scarlett16i16Gen4 => 16,
-            Scarlett18i16Gen4 => 18,
-            _ => 0,
-        }
+        self.capabilities.num_inputs as usize
     }
 
     fn num_outputs(&self) -> usize {
-        use scarlett_core::DeviceModel::*;
-        match self.info.model {
-            Scarlett2i2Gen3 | Scarlett2i2Gen4 => 2,
-            Scarlett4i4Gen3 | Scarlett4i4Gen4 => 4,
-            Scarlett6i6Gen2 => 6,
-            Scarlett8i6Gen3 => 6,
-            Scarlett18i8Gen2 | Scarlett18i8Gen3 => 8,
-            Scarlett18i20Gen2 | Scarlett18i20Gen3 | Scarlett18i20Gen4 => 20,
-            Scarlett16i16Gen4 => 16,
-            Scarlett18i16Gen4 => 16,
-            _ => 0,
-        }
+        self.capabilities.num_outputs as usize
     }
 
     fn num_mixer_inputs(&self) -> usize {
-        use scarlett_core::DeviceModel::*;
-        match self.info.model {
-            Scarlett18i20Gen2 | Scarlett18i20Gen3 | Scarlett18i20Gen4 => 25,
-            Scarlett4i4Gen3 | Scarlett4i4Gen4 => 8,
-            Scarlett8i6Gen3 => 18,
-            Scarlett18i8Gen3 => 20,
-            Scarlett16i16Gen4 => 18,
-            Scarlett18i16Gen4 => 20,
-            _ => 0,
-        }
+        if self.capabilities.mix { self.capabilities.num_mixer_inputs as usize } else { 0 }
     }
 
     fn has_mixer(&self) -> bool {
-        // Solo and 2i2 don't have mixers
-        !matches!(
-            self.info.model,
-            scarlett_core::DeviceModel::ScarlettSoloGen3
-                | scarlett_core::DeviceModel::Scarlett2i2Gen3
-                | scarlett_core::DeviceModel::ScarlettSoloGen4
-                | scarlett_core::DeviceModel::Scarlett2i2Gen4
-        )
+        self.capabilities.mix
     }
 
     fn has_routing(&self) -> bool {
-        // Most devices have routing except Solo and 2i2
-        self.has_mixer()
+        self.capabilities.mux
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_device::parse_virtual_devices;
+
+    #[test]
+    fn test_mark_disconnected() {
+        let mut device = UsbDevice::open_virtual(parse_virtual_devices("2i2g4").remove(0));
+        assert!(device.is_connected());
+
+        device.mark_disconnected();
+        assert!(!device.is_connected());
     }
 }
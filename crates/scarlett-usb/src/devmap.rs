@@ -0,0 +1,438 @@
+//! Gen 4 device map
+//!
+//! Gen 4 firmware describes its parameter layout in a base64-encoded,
+//! zlib-compressed JSON document read with DevmapInfo/DevmapRead. Based on
+//! fcp-devmap.c and output-controls.c in fcp-support.
+
+use base64::Engine;
+use flate2::read::ZlibDecoder;
+use crate::config_items::{ConfigItem, ConfigParam};
+use scarlett_core::{Error, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::ops::Range;
+
+/// Size of each DevmapRead block
+pub const DEVMAP_BLOCK_SIZE: usize = 1024;
+
+/// Logical parameters located through the device map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DevMapParam {
+    /// Line output volume, one per physical output
+    LineOutVolume,
+    /// Line output mute switch, one per physical output
+    MuteSwitch,
+}
+
+impl DevMapParam {
+    /// Name of the per-output control in the device specification
+    fn control_name(&self) -> &'static str {
+        match self {
+            Self::LineOutVolume => "level",
+            Self::MuteSwitch => "mute",
+        }
+    }
+}
+
+/// Name of the per-input control holding a configuration parameter
+///
+/// These are the `input-controls` of the fcp-support ALSA maps.
+fn input_control_name(param: ConfigParam) -> Option<&'static str> {
+    match param {
+        ConfigParam::InputGain => Some("preamp-gain"),
+        ConfigParam::AirSwitch => Some("air"),
+        ConfigParam::PhantomSwitch => Some("phantom-power"),
+        ConfigParam::LevelSwitch => Some("instrument"),
+        ConfigParam::AutogainSwitch => Some("auto-gain"),
+        ConfigParam::AutogainStatus => Some("auto-gain-exit-status"),
+        ConfigParam::InputLinkSwitch => Some("channel-link"),
+        _ => None,
+    }
+}
+
+/// A member of a device map struct
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DevMapMember {
+    /// Offset into the device's data space
+    pub offset: u32,
+    /// Element type (bool, uint8, int16, ...); empty for nested structs
+    #[serde(rename = "type", default)]
+    pub data_type: String,
+    /// Notification sent to the device after a write
+    #[serde(rename = "notify-device", default)]
+    pub notify_device: Option<u32>,
+    /// Notification bits the device sends when the value changes
+    #[serde(rename = "notify-client", default)]
+    pub notify_client: Option<u32>,
+}
+
+impl DevMapMember {
+    /// Size of one element in bytes
+    pub fn width(&self) -> Option<u32> {
+        match self.data_type.as_str() {
+            "bool" | "uint8" | "int8" => Some(1),
+            "uint16" | "int16" => Some(2),
+            "uint32" | "int32" => Some(4),
+            _ => None,
+        }
+    }
+}
+
+/// Location of a single parameter value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DevMapLocation {
+    /// Offset into the device's data space
+    pub offset: u32,
+    /// Size in bytes
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ControlRef {
+    member: String,
+    #[serde(default)]
+    index: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PhysicalPort {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    controls: HashMap<String, ControlRef>,
+}
+
+/// A source or destination in the device specification
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DevMapPort {
+    /// Device's name for the port, e.g. "USB 1" or "Mixer 3"
+    #[serde(default)]
+    pub name: String,
+    /// Meter slot measuring the port, if it has one
+    #[serde(rename = "peak-index", default)]
+    pub peak_index: Option<u16>,
+}
+
+#[derive(Deserialize)]
+struct RawDevMap {
+    #[serde(rename = "device-specification")]
+    spec: RawSpec,
+    structs: HashMap<String, RawStruct>,
+    #[serde(default)]
+    enums: HashMap<String, RawEnum>,
+}
+
+#[derive(Deserialize)]
+struct RawSpec {
+    #[serde(rename = "physical-outputs", default)]
+    outputs: Vec<PhysicalPort>,
+    #[serde(rename = "physical-inputs", default)]
+    inputs: Vec<PhysicalPort>,
+    #[serde(default)]
+    sources: Vec<DevMapPort>,
+    #[serde(default)]
+    destinations: Vec<DevMapPort>,
+}
+
+#[derive(Deserialize)]
+struct RawStruct {
+    members: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct RawEnum {
+    enumerators: HashMap<String, i64>,
+}
+
+/// Name of the struct describing the device's data space
+const APP_SPACE: &str = "APP_SPACE";
+
+/// Parsed device map
+#[derive(Debug, Clone, Default)]
+pub struct DevMap {
+    /// Members of each struct, by struct name
+    structs: HashMap<String, HashMap<String, DevMapMember>>,
+    /// Enumerator values of each enum, by enum name
+    enums: HashMap<String, HashMap<String, i64>>,
+    outputs: Vec<PhysicalPort>,
+    inputs: Vec<PhysicalPort>,
+    sources: Vec<DevMapPort>,
+    destinations: Vec<DevMapPort>,
+}
+
+impl DevMap {
+    /// Decode a device map as read from the device
+    pub fn decode(encoded: &[u8]) -> Result<Self> {
+        // The transfer is padded with NULs and may contain line breaks
+        let encoded: Vec<u8> = encoded
+            .iter()
+            .copied()
+            .filter(|&b| b != 0 && !b.is_ascii_whitespace())
+            .collect();
+
+        let compressed = base64::engine::general_purpose::STANDARD
+            .decode(&encoded)
+            .map_err(|e| Error::Protocol(format!("Invalid device map encoding: {}", e)))?;
+
+        let mut json = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut json)
+            .map_err(|e| Error::Protocol(format!("Invalid device map compression: {}", e)))?;
+
+        Self::from_json(&json)
+    }
+
+    /// Parse a decompressed device map
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        let raw: RawDevMap = serde_json::from_slice(json)
+            .map_err(|e| Error::Protocol(format!("Invalid device map: {}", e)))?;
+
+        if !raw.structs.contains_key(APP_SPACE) {
+            return Err(Error::Protocol("Device map has no APP_SPACE struct".to_string()));
+        }
+
+        // Members without an offset are skipped
+        let structs = raw
+            .structs
+            .into_iter()
+            .map(|(struct_name, raw_struct)| {
+                let members = raw_struct
+                    .members
+                    .into_iter()
+                    .filter_map(|(name, value)| {
+                        serde_json::from_value::<DevMapMember>(value).ok().map(|m| (name, m))
+                    })
+                    .collect();
+                (struct_name, members)
+            })
+            .collect();
+
+        let enums = raw
+            .enums
+            .into_iter()
+            .map(|(name, raw_enum)| (name, raw_enum.enumerators))
+            .collect();
+
+        Ok(Self {
+            structs,
+            enums,
+            outputs: raw.spec.outputs,
+            inputs: raw.spec.inputs,
+            sources: raw.spec.sources,
+            destinations: raw.spec.destinations,
+        })
+    }
+
+    /// Look up an APP_SPACE member by name
+    pub fn member(&self, name: &str) -> Option<&DevMapMember> {
+        self.struct_member(APP_SPACE, name)
+    }
+
+    /// Byte ranges of the APP_SPACE members matching `filter`
+    ///
+    /// A member is taken to extend up to the next member, which covers
+    /// arrays.
+    pub fn member_ranges(&self, filter: impl Fn(&DevMapMember) -> bool) -> Vec<Range<u32>> {
+        let Some(members) = self.structs.get(APP_SPACE) else {
+            return Vec::new();
+        };
+
+        let mut sorted: Vec<&DevMapMember> = members.values().collect();
+        sorted.sort_by_key(|m| m.offset);
+
+        sorted
+            .iter()
+            .enumerate()
+            .filter(|(_, member)| filter(member))
+            .map(|(i, member)| {
+                let end = sorted[i + 1..]
+                    .iter()
+                    .map(|next| next.offset)
+                    .find(|&offset| offset > member.offset)
+                    .unwrap_or(member.offset + member.width().unwrap_or(1));
+                member.offset..end
+            })
+            .collect()
+    }
+
+    /// Look up a member of any struct; offsets are relative to the struct
+    pub fn struct_member(&self, struct_name: &str, name: &str) -> Option<&DevMapMember> {
+        self.structs.get(struct_name)?.get(name)
+    }
+
+    /// Look up the value of an enumerator
+    pub fn enum_value(&self, enum_name: &str, name: &str) -> Option<i64> {
+        self.enums.get(enum_name)?.get(name).copied()
+    }
+
+    /// Number of physical outputs
+    pub fn num_outputs(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Name of a physical output
+    pub fn output_name(&self, output_index: u8) -> Option<&str> {
+        self.outputs.get(output_index as usize).map(|o| o.name.as_str())
+    }
+
+    /// Routing sources, in device order
+    pub fn sources(&self) -> &[DevMapPort] {
+        &self.sources
+    }
+
+    /// Routing destinations, in device order
+    pub fn destinations(&self) -> &[DevMapPort] {
+        &self.destinations
+    }
+
+    /// Locate a per-output parameter
+    pub fn lookup(&self, param: DevMapParam, output_index: u8) -> Option<DevMapLocation> {
+        let control = self
+            .outputs
+            .get(output_index as usize)?
+            .controls
+            .get(param.control_name())?;
+        let member = self.member(&control.member)?;
+        let size = member.width()?;
+
+        Some(DevMapLocation {
+            offset: member.offset + control.index * size,
+            size,
+        })
+    }
+
+    /// Locate a per-input configuration parameter
+    ///
+    /// The item addresses the control of the first input that has one, as
+    /// configuration indexes count from there. Values are written directly
+    /// and activated with the member's device notification.
+    pub fn input_config_item(&self, param: ConfigParam) -> Option<ConfigItem> {
+        let name = input_control_name(param)?;
+        let control = self.inputs.iter().find_map(|input| input.controls.get(name))?;
+        let member = self.member(&control.member)?;
+        let size = member.width()?;
+
+        Some(ConfigItem {
+            offset: member.offset + control.index * size,
+            size: size as u8 * 8,
+            activate: member.notify_device.unwrap_or(0),
+            pbuf: false,
+            mute: false,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    pub(crate) const TEST_JSON: &str = r#"{
+        "device-specification": {
+            "physical-outputs": [
+                { "name": "Monitor L",
+                  "controls": { "level": { "member": "lineOutVolume", "index": 0 },
+                                "mute": { "member": "muteSwitch", "index": 0 } } },
+                { "name": "Monitor R",
+                  "controls": { "level": { "member": "lineOutVolume", "index": 1 } } }
+            ],
+            "physical-inputs": [
+                { "name": "Analogue 1",
+                  "controls": { "preamp-gain": { "member": "preampGain", "index": 0 },
+                                "channel-link": { "member": "channelLink", "index": 0 } } },
+                { "name": "Analogue 2",
+                  "controls": { "preamp-gain": { "member": "preampGain", "index": 1 },
+                                "channel-link": { "member": "channelLink", "index": 1 } } }
+            ],
+            "sources": [
+                { "name": "Analogue 1", "peak-index": 0 },
+                { "name": "USB 1", "peak-index": 3 },
+                { "name": "Mixer 1" }
+            ],
+            "destinations": [
+                { "name": "Monitor L", "peak-index": 1 },
+                { "name": "Loopback 1", "peak-index": 2 }
+            ]
+        },
+        "structs": {
+            "APP_SPACE": {
+                "members": {
+                    "lineOutVolume": { "offset": 80, "type": "int16", "notify-device": 1, "notify-client": 4 },
+                    "muteSwitch": { "offset": 120, "type": "bool", "notify-client": 8 },
+                    "clockSource": { "offset": 16, "type": "uint8" },
+                    "preampGain": { "offset": 300, "type": "uint8", "notify-device": 30 },
+                    "channelLink": { "offset": 310, "type": "bool", "notify-device": 31 },
+                    "mixer": { "struct": "MIXER" },
+                    "espSpace": { "offset": 512, "struct": "ESP_SPACE" },
+                    "ESPBootMode": { "offset": 200, "type": "uint8", "notify-device": 24 }
+                }
+            },
+            "ESP_SPACE": {
+                "members": {
+                    "SuperState": { "offset": 4, "type": "uint8" },
+                    "DFU_NOTIFY": { "offset": 9, "type": "uint8" }
+                }
+            }
+        },
+        "enums": {
+            "eSuperState": { "enumerators": { "eSuperOff": 1, "eSuperDFU": 2, "eSuperNormal": 3 } },
+            "eDFU_NOTIFICATION": {
+                "enumerators": { "eClear": 0, "eNextblock": 1, "eFinish": 2, "eError": 3 }
+            }
+        }
+    }"#;
+
+    /// Compress and encode a JSON device map as the device would
+    pub(crate) fn encode(json: &str) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(json.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        base64::engine::general_purpose::STANDARD.encode(compressed).into_bytes()
+    }
+
+    #[test]
+    fn test_decode_and_lookup() {
+        let mut encoded = encode(TEST_JSON);
+        encoded.extend_from_slice(&[0, 0]);
+        let devmap = DevMap::decode(&encoded).unwrap();
+
+        assert_eq!(devmap.num_outputs(), 2);
+        assert_eq!(devmap.output_name(1), Some("Monitor R"));
+        assert_eq!(
+            devmap.lookup(DevMapParam::LineOutVolume, 1),
+            Some(DevMapLocation { offset: 82, size: 2 })
+        );
+        assert_eq!(
+            devmap.lookup(DevMapParam::MuteSwitch, 0),
+            Some(DevMapLocation { offset: 120, size: 1 })
+        );
+        assert_eq!(devmap.lookup(DevMapParam::MuteSwitch, 1), None);
+        assert_eq!(devmap.member("lineOutVolume").unwrap().notify_device, Some(1));
+        assert!(devmap.member("mixer").is_none());
+        assert_eq!(devmap.member("espSpace").unwrap().width(), None);
+        assert_eq!(devmap.member_ranges(|m| m.notify_client == Some(8)), vec![120..200]);
+
+        assert_eq!(devmap.sources().len(), 3);
+        assert_eq!(devmap.sources()[1], DevMapPort { name: "USB 1".to_string(), peak_index: Some(3) });
+        assert_eq!(devmap.sources()[2].peak_index, None);
+        assert_eq!(devmap.destinations()[1].name, "Loopback 1");
+
+        let gain = devmap.input_config_item(ConfigParam::InputGain).unwrap();
+        assert_eq!((gain.offset, gain.size, gain.activate, gain.pbuf), (300, 8, 30, false));
+        assert_eq!(devmap.input_config_item(ConfigParam::InputLinkSwitch).unwrap().offset, 310);
+        assert_eq!(devmap.input_config_item(ConfigParam::AirSwitch), None);
+        assert_eq!(devmap.input_config_item(ConfigParam::MuteSwitch), None);
+
+        assert_eq!(devmap.struct_member("ESP_SPACE", "SuperState").unwrap().offset, 4);
+        assert_eq!(devmap.enum_value("eSuperState", "eSuperNormal"), Some(3));
+        assert_eq!(devmap.enum_value("eSuperState", "eSuperBoot"), None);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert!(DevMap::decode(b"not base64!").is_err());
+        assert!(DevMap::from_json(b"{}").is_err());
+    }
+}
@@ -2,65 +2,106 @@
 //!
 //! Local USB device communication using the nusb library.
 
-use crate::transport::{BulkTransfer, ControlTransfer, UsbTransport};
+use crate::transport::{AsyncUsbTransport, BulkTransfer, ControlTransfer, UsbTransport};
 use scarlett_core::{Error, Result};
+use nusb::descriptors::Configuration;
 use nusb::{Device, Interface};
-use std::sync::Arc;
-use tracing::{debug, trace};
+use futures::future::{self, BoxFuture, Either};
+use nusb::transfer::{Completion, RequestBuffer, TransferError};
+use std::future::Future;
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tracing::{debug, info, trace, warn};
+
+/// USB interface class of the Focusrite Control interface
+const USB_CLASS_VENDOR_SPECIFIC: u8 = 0xff;
+
+/// How often the interrupt reader thread checks whether to stop
+const INTERRUPT_READER_POLL: Duration = Duration::from_millis(100);
+
+/// What to do if a kernel driver has claimed the interface
+///
+/// On Linux, snd-usb-audio binds to the control interface of most
+/// Scarletts, so claiming it fails until the driver lets go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KernelDriverPolicy {
+    /// Leave the driver alone and fail with `Error::DeviceBusy`
+    #[default]
+    Keep,
+    /// Detach the driver while the transport is open and re-attach it
+    /// afterwards; audio through that driver stops in between
+    Detach,
+}
 
 /// Direct USB transport implementation using nusb
 pub struct DirectUsbTransport {
     device: Arc<Device>,
+    /// Reader for the interrupt endpoint, started by the first `interrupt_in`
+    interrupt: Mutex<Option<InterruptReader>>,
     interface: Interface,
     interface_number: u8,
+    /// Cleared once a transfer reports the device is gone
+    connected: AtomicBool,
+    /// Hands the interface back to the kernel driver we detached, once the
+    /// interface above has been released
+    _reattach: Option<KernelDriverReattach>,
 }
 
 impl DirectUsbTransport {
     /// Create a new direct USB transport
     pub fn new(device: Device, interface_number: u8) -> Result<Self> {
+        Self::with_kernel_driver_policy(device, interface_number, KernelDriverPolicy::Keep)
+    }
+
+    /// Create a new direct USB transport, handling a busy interface as
+    /// `policy` says
+    pub fn with_kernel_driver_policy(device: Device, interface_number: u8, policy: KernelDriverPolicy) -> Result<Self> {
         debug!("Claiming USB interface {}", interface_number);
+        let device = Arc::new(device);
 
         // Claim the interface for exclusive access
-        let interface = device
-            .claim_interface(interface_number)
-            .map_err(|e| Error::Usb(format!("Failed to claim interface: {:?}", e)))?;
+        let (interface, reattach) = match device.claim_interface(interface_number) {
+            Ok(interface) => (interface, None),
+            Err(e) if e.kind() == ErrorKind::ResourceBusy && policy == KernelDriverPolicy::Detach => {
+                info!("Detaching kernel driver from interface {}", interface_number);
+                let interface = device
+                    .detach_and_claim_interface(interface_number)
+                    .map_err(|e| claim_error(interface_number, e, policy))?;
+                let reattach = KernelDriverReattach {
+                    device: device.clone(),
+                    interface_number,
+                };
+                (interface, Some(reattach))
+            }
+            Err(e) => return Err(claim_error(interface_number, e, policy)),
+        };
 
         Ok(Self {
-            device: Arc::new(device),
+            device,
+            interrupt: Mutex::new(None),
             interface,
             interface_number,
+            connected: AtomicBool::new(true),
+            _reattach: reattach,
         })
     }
 
     /// Find and create transport for vendor-specific interface (class 255)
-    /// This is the Focusrite Control interface used for mixer/routing commands
+    /// This is the Focusrite Control interface used for mixer/routing commands.
+    /// The claimed interface number is available from `interface_number()`.
     pub fn new_vendor_interface(device: Device) -> Result<Self> {
-        debug!("Searching for vendor-specific interface (class 255)");
-
-        // Get active configuration
-        let config = device.active_configuration()
-            .map_err(|e| Error::Usb(format!("Failed to get configuration: {:?}", e)))?;
-
-        // Look for vendor-specific interface (class 255)
-        let mut vendor_interface_num = None;
-        for interface_info in config.interfaces() {
-            for alt_setting in interface_info.alt_settings() {
-                if alt_setting.class() == 255 {  // Vendor-specific class
-                    vendor_interface_num = Some(interface_info.interface_number());
-                    break;
-                }
-            }
-            if vendor_interface_num.is_some() {
-                break;
-            }
-        }
+        Self::vendor_interface_with_policy(device, KernelDriverPolicy::Keep)
+    }
 
-        if let Some(interface_num) = vendor_interface_num {
-            debug!("Found vendor-specific interface: {}", interface_num);
-            Self::new(device, interface_num)
-        } else {
-            Err(Error::Usb("No vendor-specific interface found (class 255)".to_string()))
-        }
+    /// Create a transport for the vendor-specific interface, handling a
+    /// busy interface as `policy` says
+    pub fn vendor_interface_with_policy(device: Device, policy: KernelDriverPolicy) -> Result<Self> {
+        let interface_num = find_vendor_interface(&device)?;
+        Self::with_kernel_driver_policy(device, interface_num, policy)
     }
 
     /// Get the interface number this transport is using
@@ -68,6 +109,203 @@ impl DirectUsbTransport {
         self.interface_number
     }
 
+    /// Get the interrupt IN endpoint of the claimed interface, if it has one
+    pub fn interrupt_endpoint(&self) -> Option<u8> {
+        find_interrupt_endpoint(&self.device, self.interface_number)
+    }
+
+    /// Record a transfer failure, noting if the device has gone away
+    fn transfer_error(&self, kind: &str, error: TransferError) -> Error {
+        transfer_error(&self.connected, kind, error)
+    }
+
+    /// Await a transfer, cancelling it if it takes longer than `timeout`
+    ///
+    /// Dropping an nusb transfer future cancels the transfer.
+    async fn wait<T>(direction: &str, transfer: impl Future<Output = T>, timeout: Duration) -> Result<T> {
+        let transfer = std::pin::pin!(transfer);
+        match future::select(transfer, async_io::Timer::after(timeout)).await {
+            Either::Left((completion, _)) => Ok(completion),
+            Either::Right(_) => {
+                debug!("Control {} timed out after {:?}", direction, timeout);
+                Err(Error::Timeout(format!("Control {}", direction)))
+            }
+        }
+    }
+
+}
+
+/// SETUP packet fields of a control transfer
+fn control_setup(transfer: &ControlTransfer) -> Result<nusb::transfer::Control> {
+    // Parse request_type to determine control transfer parameters
+    let control_type = match (transfer.request_type >> 5) & 0x03 {
+        0 => nusb::transfer::ControlType::Standard,
+        1 => nusb::transfer::ControlType::Class,
+        2 => nusb::transfer::ControlType::Vendor,
+        _ => return Err(Error::Usb("Invalid control type".to_string())),
+    };
+
+    let recipient = match transfer.request_type & 0x1F {
+        0 => nusb::transfer::Recipient::Device,
+        1 => nusb::transfer::Recipient::Interface,
+        2 => nusb::transfer::Recipient::Endpoint,
+        3 => nusb::transfer::Recipient::Other,
+        _ => return Err(Error::Usb("Invalid recipient".to_string())),
+    };
+
+    Ok(nusb::transfer::Control {
+        control_type,
+        recipient,
+        request: transfer.request,
+        value: transfer.value,
+        index: transfer.index,
+    })
+}
+
+/// Convert a transfer failure, clearing `connected` if the device has gone
+fn transfer_error(connected: &AtomicBool, kind: &str, error: TransferError) -> Error {
+    match error {
+        TransferError::Disconnected => {
+            debug!("Device disconnected during {}", kind);
+            connected.store(false, Ordering::SeqCst);
+            Error::Disconnected
+        }
+        // Only transfers dropped after their timeout are cancelled
+        TransferError::Cancelled => Error::Timeout(kind.to_string()),
+        _ => Error::Usb(format!("{} failed: {:?}", kind, error)),
+    }
+}
+
+/// Find the interrupt IN endpoint of an interface, if it has one
+///
+/// Scarlett2 devices send notifications on this endpoint of the control
+/// interface.
+pub fn find_interrupt_endpoint(device: &Device, interface_number: u8) -> Option<u8> {
+    let config = device.active_configuration().ok()?;
+    interrupt_endpoint(&config, interface_number)
+}
+
+/// Find the vendor-specific (class 255) interface used for control
+///
+/// Both FCP and Scarlett2 devices take their commands on this interface.
+pub fn find_vendor_interface(device: &Device) -> Result<u8> {
+    debug!("Searching for vendor-specific interface (class 255)");
+
+    // Get active configuration
+    let config = device.active_configuration()
+        .map_err(|e| Error::Usb(format!("Failed to get configuration: {:?}", e)))?;
+
+    let interface_number = vendor_interface(&config)
+        .ok_or_else(|| Error::Usb("No vendor-specific interface found (class 255)".to_string()))?;
+    debug!("Found vendor-specific interface: {}", interface_number);
+    Ok(interface_number)
+}
+
+/// First interface of a configuration with a vendor-specific alt setting
+fn vendor_interface(config: &Configuration) -> Option<u8> {
+    config
+        .interfaces()
+        .find(|interface| interface.alt_settings().any(|alt| alt.class() == USB_CLASS_VENDOR_SPECIFIC))
+        .map(|interface| interface.interface_number())
+}
+
+/// First interrupt IN endpoint of an interface in a configuration
+fn interrupt_endpoint(config: &Configuration, interface_number: u8) -> Option<u8> {
+    for interface_info in config.interfaces() {
+        if interface_info.interface_number() != interface_number {
+            continue;
+        }
+        for alt_setting in interface_info.alt_settings() {
+            for endpoint in alt_setting.endpoints() {
+                if endpoint.transfer_type() == nusb::transfer::EndpointType::Interrupt
+                    && endpoint.direction() == nusb::transfer::Direction::In
+                {
+                    return Some(endpoint.address());
+                }
+            }
+        }
+    }
+
+    None
+}
+
+impl AsyncUsbTransport for DirectUsbTransport {
+    fn control_out_async<'a>(&'a self, transfer: &'a ControlTransfer, data: &'a [u8]) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(async move {
+            trace!(
+                "USB control OUT: type=0x{:02x}, req=0x{:02x}, val=0x{:04x}, idx=0x{:04x}, len={}",
+                transfer.request_type,
+                transfer.request,
+                transfer.value,
+                transfer.index,
+                data.len()
+            );
+
+            #[cfg(feature = "packet-dump")]
+            crate::transport::dump_packet(self.transport_name(), transfer, data);
+
+            let control = control_setup(transfer)?;
+
+            // Perform the control transfer
+            let future = self.interface.control_out(nusb::transfer::ControlOut {
+                control_type: control.control_type,
+                recipient: control.recipient,
+                request: control.request,
+                value: control.value,
+                index: control.index,
+                data,
+            });
+
+            let completion = Self::wait("OUT", future, transfer.timeout).await?;
+
+            // Check status
+            completion.status
+                .map_err(|e| self.transfer_error("Control OUT", e))?;
+
+            trace!("Control OUT completed: {} bytes transferred", data.len());
+            Ok(data.len())
+        })
+    }
+
+    fn control_in_async<'a>(&'a self, transfer: &'a ControlTransfer, buffer: &'a mut [u8]) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(async move {
+            trace!(
+                "USB control IN: type=0x{:02x}, req=0x{:02x}, val=0x{:04x}, idx=0x{:04x}, len={}",
+                transfer.request_type,
+                transfer.request,
+                transfer.value,
+                transfer.index,
+                buffer.len()
+            );
+
+            let control = control_setup(transfer)?;
+
+            // Perform the control transfer
+            let future = self.interface.control_in(nusb::transfer::ControlIn {
+                control_type: control.control_type,
+                recipient: control.recipient,
+                request: control.request,
+                value: control.value,
+                index: control.index,
+                length: buffer.len() as u16,
+            });
+
+            let completion = Self::wait("IN", future, transfer.timeout).await?;
+
+            // Check status
+            completion.status
+                .map_err(|e| self.transfer_error("Control IN", e))?;
+
+            // Copy data to buffer
+            let actual_len = completion.data.len().min(buffer.len());
+            buffer[..actual_len].copy_from_slice(&completion.data[..actual_len]);
+
+            trace!("Control IN completed: {} bytes received", actual_len);
+            #[cfg(feature = "packet-dump")]
+            crate::transport::dump_packet(self.transport_name(), transfer, &buffer[..actual_len]);
+            Ok(actual_len)
+        })
+    }
 }
 
 impl UsbTransport for DirectUsbTransport {
@@ -81,41 +319,18 @@ impl UsbTransport for DirectUsbTransport {
             data.len()
         );
 
-        // Parse request_type to determine control transfer parameters
-        let control_type = match (transfer.request_type >> 5) & 0x03 {
-            0 => nusb::transfer::ControlType::Standard,
-            1 => nusb::transfer::ControlType::Class,
-            2 => nusb::transfer::ControlType::Vendor,
-            _ => return Err(Error::Usb("Invalid control type".to_string())),
-        };
-
-        let recipient = match transfer.request_type & 0x1F {
-            0 => nusb::transfer::Recipient::Device,
-            1 => nusb::transfer::Recipient::Interface,
-            2 => nusb::transfer::Recipient::Endpoint,
-            3 => nusb::transfer::Recipient::Other,
-            _ => return Err(Error::Usb("Invalid recipient".to_string())),
-        };
+        #[cfg(feature = "packet-dump")]
+        crate::transport::dump_packet(self.transport_name(), transfer, data);
 
-        // Perform the control transfer
-        let future = self.interface.control_out(nusb::transfer::ControlOut {
-            control_type,
-            recipient,
-            request: transfer.request,
-            value: transfer.value,
-            index: transfer.index,
-            data,
-        });
+        // A blocking transfer, so no executor is needed whatever thread
+        // or runtime the caller is on
+        let len = self
+            .interface
+            .control_out_blocking(control_setup(transfer)?, data, transfer.timeout)
+            .map_err(|e| self.transfer_error("Control OUT", e))?;
 
-        // Block on the async operation
-        let completion = futures::executor::block_on(future);
-
-        // Check status
-        completion.status
-            .map_err(|e| Error::Usb(format!("Control OUT failed: {:?}", e)))?;
-
-        trace!("Control OUT completed: {} bytes transferred", data.len());
-        Ok(data.len())
+        trace!("Control OUT completed: {} bytes transferred", len);
+        Ok(len)
     }
 
     fn control_in(&self, transfer: &ControlTransfer, buffer: &mut [u8]) -> Result<usize> {
@@ -128,45 +343,15 @@ impl UsbTransport for DirectUsbTransport {
             buffer.len()
         );
 
-        // Parse request_type to determine control transfer parameters
-        let control_type = match (transfer.request_type >> 5) & 0x03 {
-            0 => nusb::transfer::ControlType::Standard,
-            1 => nusb::transfer::ControlType::Class,
-            2 => nusb::transfer::ControlType::Vendor,
-            _ => return Err(Error::Usb("Invalid control type".to_string())),
-        };
-
-        let recipient = match transfer.request_type & 0x1F {
-            0 => nusb::transfer::Recipient::Device,
-            1 => nusb::transfer::Recipient::Interface,
-            2 => nusb::transfer::Recipient::Endpoint,
-            3 => nusb::transfer::Recipient::Other,
-            _ => return Err(Error::Usb("Invalid recipient".to_string())),
-        };
-
-        // Perform the control transfer
-        let future = self.interface.control_in(nusb::transfer::ControlIn {
-            control_type,
-            recipient,
-            request: transfer.request,
-            value: transfer.value,
-            index: transfer.index,
-            length: buffer.len() as u16,
-        });
-
-        // Block on the async operation
-        let completion = futures::executor::block_on(future);
-
-        // Check status
-        completion.status
-            .map_err(|e| Error::Usb(format!("Control IN failed: {:?}", e)))?;
+        let len = self
+            .interface
+            .control_in_blocking(control_setup(transfer)?, buffer, transfer.timeout)
+            .map_err(|e| self.transfer_error("Control IN", e))?;
 
-        // Copy data to buffer
-        let actual_len = completion.data.len().min(buffer.len());
-        buffer[..actual_len].copy_from_slice(&completion.data[..actual_len]);
-
-        trace!("Control IN completed: {} bytes received", actual_len);
-        Ok(actual_len)
+        trace!("Control IN completed: {} bytes received", len);
+        #[cfg(feature = "packet-dump")]
+        crate::transport::dump_packet(self.transport_name(), transfer, &buffer[..len]);
+        Ok(len)
     }
 
     fn bulk_out(&self, _transfer: &BulkTransfer, _data: &[u8]) -> Result<usize> {
@@ -185,20 +370,157 @@ impl UsbTransport for DirectUsbTransport {
         Err(Error::NotSupported("Bulk transfers not yet implemented".to_string()))
     }
 
+    fn interrupt_in(&self, transfer: &BulkTransfer, buffer: &mut [u8]) -> Result<usize> {
+        let mut reader = self.interrupt.lock().unwrap_or_else(|e| e.into_inner());
+        if reader.as_ref().map(|r| r.endpoint) != Some(transfer.endpoint) {
+            *reader = Some(InterruptReader::spawn(&self.interface, transfer.endpoint, buffer.len()));
+        }
+        let Some(running) = reader.as_ref() else {
+            unreachable!("interrupt reader was just started");
+        };
+
+        // Nothing to report is the usual outcome, not an error
+        let completion = match running.completions.recv_timeout(transfer.timeout) {
+            Ok(completion) => completion,
+            Err(RecvTimeoutError::Timeout) => return Ok(0),
+            Err(RecvTimeoutError::Disconnected) => {
+                *reader = None;
+                return Err(Error::Usb("Interrupt reader stopped".to_string()));
+            }
+        };
+
+        // The reader stops after a failure; the next call starts a new one
+        if let Err(e) = completion.status {
+            *reader = None;
+            return Err(self.transfer_error("Interrupt IN", e));
+        }
+
+        let actual_len = completion.data.len().min(buffer.len());
+        buffer[..actual_len].copy_from_slice(&completion.data[..actual_len]);
+
+        trace!("Interrupt IN completed: {} bytes received", actual_len);
+        Ok(actual_len)
+    }
+
+    /// Whether no transfer has found the device gone
+    ///
+    /// nusb answers descriptor queries such as the active configuration
+    /// from a cache, so they can't tell a removed device apart. Removal
+    /// shows up here once a transfer fails; hotplug events are handled by
+    /// `UsbDevice::mark_disconnected`.
     fn is_connected(&self) -> bool {
-        // TODO: Properly check if device is still connected
-        // For now, assume it's connected
-        true
+        self.connected.load(Ordering::SeqCst)
     }
 
     fn transport_name(&self) -> &'static str {
         "Direct USB"
     }
+
+    fn as_async(&self) -> Option<&dyn AsyncUsbTransport> {
+        Some(self)
+    }
+}
+
+/// Reads an interrupt endpoint on a thread of its own
+///
+/// nusb only offers interrupt transfers as futures. Waiting for them here,
+/// rather than on the caller's thread, keeps an executor out of whatever
+/// runtime `interrupt_in` is called from. A transfer stays queued between
+/// calls, so a notification sent in between isn't lost.
+struct InterruptReader {
+    endpoint: u8,
+    completions: mpsc::Receiver<Completion<Vec<u8>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl InterruptReader {
+    fn spawn(interface: &Interface, endpoint: u8, len: usize) -> Self {
+        debug!("Starting interrupt reader for endpoint 0x{:02x}", endpoint);
+        let (tx, completions) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut queue = interface.interrupt_in_queue(endpoint);
+        let thread = std::thread::Builder::new()
+            .name(format!("usb-interrupt-{:02x}", endpoint))
+            .spawn({
+                let stop = stop.clone();
+                move || {
+                    while !stop.load(Ordering::SeqCst) {
+                        if queue.pending() == 0 {
+                            queue.submit(RequestBuffer::new(len));
+                        }
+
+                        let next = queue.next_complete();
+                        let timer = async_io::Timer::after(INTERRUPT_READER_POLL);
+                        let Either::Left((completion, _)) = futures::executor::block_on(future::select(next, timer)) else {
+                            continue;
+                        };
+
+                        // Dropping the queue cancels the transfer left pending
+                        let failed = completion.status.is_err();
+                        if tx.send(completion).is_err() || failed {
+                            break;
+                        }
+                    }
+                }
+            })
+            .expect("failed to spawn interrupt reader thread");
+
+        Self {
+            endpoint,
+            completions,
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for InterruptReader {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Re-attaches the kernel driver of an interface when dropped
+struct KernelDriverReattach {
+    device: Arc<Device>,
+    interface_number: u8,
+}
+
+impl Drop for KernelDriverReattach {
+    fn drop(&mut self) {
+        debug!("Re-attaching kernel driver to interface {}", self.interface_number);
+        if let Err(e) = self.device.attach_kernel_driver(self.interface_number) {
+            warn!("Failed to re-attach kernel driver to interface {}: {}", self.interface_number, e);
+        }
+    }
+}
+
+/// Describe a failure to claim an interface
+///
+/// A busy interface is `Error::DeviceBusy`, saying whether detaching the
+/// kernel driver was tried.
+fn claim_error(interface_number: u8, error: std::io::Error, policy: KernelDriverPolicy) -> Error {
+    match (error.kind(), policy) {
+        (ErrorKind::ResourceBusy, KernelDriverPolicy::Keep) => Error::DeviceBusy(format!(
+            "interface {} is claimed by another driver",
+            interface_number
+        )),
+        (ErrorKind::ResourceBusy | ErrorKind::PermissionDenied, KernelDriverPolicy::Detach) => Error::DeviceBusy(format!(
+            "interface {} is claimed by another driver, which couldn't be detached: {}",
+            interface_number, error
+        )),
+        _ => Error::Usb(format!("Failed to claim interface {}: {}", interface_number, error)),
+    }
 }
 
 /// Builder for DirectUsbTransport
 pub struct DirectUsbTransportBuilder {
     interface_number: u8,
+    kernel_driver_policy: KernelDriverPolicy,
 }
 
 impl DirectUsbTransportBuilder {
@@ -206,6 +528,7 @@ impl DirectUsbTransportBuilder {
     pub fn new() -> Self {
         Self {
             interface_number: 0,
+            kernel_driver_policy: KernelDriverPolicy::Keep,
         }
     }
 
@@ -215,6 +538,12 @@ impl DirectUsbTransportBuilder {
         self
     }
 
+    /// Set what to do if a kernel driver has claimed the interface
+    pub fn kernel_driver_policy(mut self, policy: KernelDriverPolicy) -> Self {
+        self.kernel_driver_policy = policy;
+        self
+    }
+
     /// Build the transport with a device
     pub fn build(self, device: Device) -> Result<DirectUsbTransport> {
         debug!(
@@ -222,7 +551,7 @@ impl DirectUsbTransportBuilder {
             self.interface_number
         );
 
-        DirectUsbTransport::new(device, self.interface_number)
+        DirectUsbTransport::with_kernel_driver_policy(device, self.interface_number, self.kernel_driver_policy)
     }
 }
 
@@ -240,5 +569,108 @@ mod tests {
     fn test_builder() {
         let builder = DirectUsbTransportBuilder::new().interface(1);
         assert_eq!(builder.interface_number, 1);
+        assert_eq!(builder.kernel_driver_policy, KernelDriverPolicy::Keep);
+
+        let builder = builder.kernel_driver_policy(KernelDriverPolicy::Detach);
+        assert_eq!(builder.kernel_driver_policy, KernelDriverPolicy::Detach);
+    }
+
+    #[test]
+    fn test_claim_error() {
+        let busy = || std::io::Error::from(ErrorKind::ResourceBusy);
+        assert!(matches!(claim_error(3, busy(), KernelDriverPolicy::Keep), Error::DeviceBusy(_)));
+        assert!(matches!(claim_error(3, busy(), KernelDriverPolicy::Detach), Error::DeviceBusy(_)));
+
+        // Not being allowed to detach the driver leaves it busy
+        let denied = std::io::Error::from(ErrorKind::PermissionDenied);
+        assert!(matches!(claim_error(3, denied, KernelDriverPolicy::Detach), Error::DeviceBusy(_)));
+        let denied = std::io::Error::from(ErrorKind::PermissionDenied);
+        assert!(matches!(claim_error(3, denied, KernelDriverPolicy::Keep), Error::Usb(_)));
+
+        let gone = std::io::Error::from(ErrorKind::NotFound);
+        match claim_error(3, gone, KernelDriverPolicy::Detach) {
+            Error::Usb(msg) => assert!(msg.contains("interface 3")),
+            other => panic!("Expected a USB error, got {:?}", other),
+        }
+        assert!(Error::DeviceBusy(String::new()).hint().is_some());
+    }
+
+    #[test]
+    fn test_transfer_error_disconnects() {
+        let connected = AtomicBool::new(true);
+
+        // Stalls and timeouts leave the device connected
+        assert!(matches!(transfer_error(&connected, "Control IN", TransferError::Stall), Error::Usb(_)));
+        assert!(matches!(transfer_error(&connected, "Control IN", TransferError::Cancelled), Error::Timeout(_)));
+        assert!(connected.load(Ordering::SeqCst));
+
+        assert!(matches!(transfer_error(&connected, "Control OUT", TransferError::Disconnected), Error::Disconnected));
+        assert!(!connected.load(Ordering::SeqCst));
+
+        // Nothing sets the flag again; a reconnect opens a new transport
+        assert!(matches!(transfer_error(&connected, "Control IN", TransferError::Fault), Error::Usb(_)));
+        assert!(!connected.load(Ordering::SeqCst));
+    }
+
+    /// Configuration descriptor holding `descriptors`
+    fn configuration(num_interfaces: u8, descriptors: &[&[u8]]) -> Vec<u8> {
+        let mut buf = vec![9, 2, 0, 0, num_interfaces, 1, 0, 0x80, 250];
+        for descriptor in descriptors {
+            buf.extend_from_slice(descriptor);
+        }
+        let total = buf.len() as u16;
+        buf[2..4].copy_from_slice(&total.to_le_bytes());
+        buf
+    }
+
+    fn interface(number: u8, alt: u8, num_endpoints: u8, class: u8) -> [u8; 9] {
+        [9, 4, number, alt, num_endpoints, class, 0, 0, 0]
+    }
+
+    fn endpoint(address: u8, attributes: u8) -> [u8; 7] {
+        [7, 5, address, attributes, 64, 0, 4]
+    }
+
+    #[test]
+    fn test_vendor_interface_after_audio() {
+        // Audio control and streaming first, as on the big Gen 4 devices
+        let buf = configuration(3, &[
+            &interface(0, 0, 0, 1),
+            &interface(1, 0, 0, 1),
+            &interface(1, 1, 1, 1),
+            &endpoint(0x01, 0x05),
+            &interface(3, 0, 1, 0xff),
+            &endpoint(0x83, 0x03),
+        ]);
+        let config = Configuration::new(&buf);
+
+        assert_eq!(vendor_interface(&config), Some(3));
+        assert_eq!(interrupt_endpoint(&config, 3), Some(0x83));
+        // The isochronous OUT endpoint isn't a notification endpoint
+        assert_eq!(interrupt_endpoint(&config, 1), None);
+    }
+
+    #[test]
+    fn test_vendor_interface_in_alt_setting() {
+        let buf = configuration(2, &[
+            &interface(0, 0, 0, 1),
+            &interface(2, 0, 0, 0xfe),
+            &interface(2, 1, 2, 0xff),
+            &endpoint(0x02, 0x02),
+            &endpoint(0x84, 0x03),
+        ]);
+        let config = Configuration::new(&buf);
+
+        assert_eq!(vendor_interface(&config), Some(2));
+        assert_eq!(interrupt_endpoint(&config, 2), Some(0x84));
+    }
+
+    #[test]
+    fn test_no_vendor_interface() {
+        let buf = configuration(1, &[&interface(0, 0, 1, 1), &endpoint(0x81, 0x03)]);
+        let config = Configuration::new(&buf);
+
+        assert_eq!(vendor_interface(&config), None);
+        assert_eq!(interrupt_endpoint(&config, 3), None);
     }
 }
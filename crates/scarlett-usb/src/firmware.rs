@@ -4,14 +4,18 @@
 //! Based on scarlett2-firmware.c from the Linux tools.
 
 use scarlett_core::{Error, Result};
+use md5::Md5;
 use sha2::{Sha256, Digest};
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::path::Path;
 
 /// Magic string at the start of all Scarlett firmware files
 pub const FIRMWARE_MAGIC: &[u8; 8] = b"SCARLETT";
 
+/// Magic string at the start of a Gen 4 ESP (Wi-Fi module) firmware section
+pub const ESP_FIRMWARE_MAGIC: &[u8; 8] = b"SCARLESP";
+
 /// Scarlett firmware file header (52 bytes, packed)
 /// All multi-byte integers are stored in BIG-ENDIAN format
 #[derive(Debug, Clone)]
@@ -76,14 +80,26 @@ impl FirmwareHeader {
         })
     }
 
+    /// Serialize header to raw bytes (big-endian fields)
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.magic);
+        bytes[8..10].copy_from_slice(&self.usb_vid.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.usb_pid.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.firmware_version.to_be_bytes());
+        bytes[16..20].copy_from_slice(&self.firmware_length.to_be_bytes());
+        bytes[20..52].copy_from_slice(&self.sha256);
+        bytes
+    }
+
     /// Read header from a file
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let mut file = File::open(path.as_ref())
-            .map_err(|e| Error::Io(e))?;
+            .map_err(Error::Io)?;
 
         let mut header_bytes = [0u8; Self::SIZE];
         file.read_exact(&mut header_bytes)
-            .map_err(|e| Error::Io(e))?;
+            .map_err(Error::Io)?;
 
         Self::from_bytes(&header_bytes)
     }
@@ -99,6 +115,46 @@ pub struct FirmwareFile {
 }
 
 impl FirmwareFile {
+    /// Create a firmware image, filling in the header length and SHA-256 hash
+    pub fn new(vid: u16, pid: u16, version: u32, data: Vec<u8>) -> Self {
+        let header = FirmwareHeader {
+            magic: *FIRMWARE_MAGIC,
+            usb_vid: vid,
+            usb_pid: pid,
+            firmware_version: version,
+            firmware_length: data.len() as u32,
+            sha256: compute_sha256(&data),
+        };
+
+        Self { header, data }
+    }
+
+    /// Serialize to a complete firmware image (header + data)
+    ///
+    /// The length and SHA-256 hash are recomputed from `self.data`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut header = self.header.clone();
+        header.firmware_length = self.data.len() as u32;
+        header.sha256 = compute_sha256(&self.data);
+
+        let mut bytes = Vec::with_capacity(FirmwareHeader::SIZE + self.data.len());
+        bytes.extend_from_slice(&header.to_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Write the firmware image to a file
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path_ref = path.as_ref();
+
+        tracing::info!("Writing firmware file: {}", path_ref.display());
+
+        let mut file = File::create(path_ref)?;
+        file.write_all(&self.to_bytes())?;
+
+        Ok(())
+    }
+
     /// Read and validate complete firmware file
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path_ref = path.as_ref();
@@ -118,11 +174,11 @@ impl FirmwareFile {
 
         // Read entire file
         let mut file = File::open(path_ref)
-            .map_err(|e| Error::Io(e))?;
+            .map_err(Error::Io)?;
 
         let mut all_data = Vec::new();
         file.read_to_end(&mut all_data)
-            .map_err(|e| Error::Io(e))?;
+            .map_err(Error::Io)?;
 
         // Validate file size
         let expected_size = FirmwareHeader::SIZE + header.firmware_length as usize;
@@ -145,7 +201,7 @@ impl FirmwareFile {
         hasher.update(&data);
         let computed_hash = hasher.finalize();
 
-        if computed_hash.as_slice() != &header.sha256 {
+        if computed_hash.as_slice() != header.sha256 {
             return Err(Error::Protocol(
                 "Firmware SHA-256 hash mismatch! File may be corrupted.".to_string()
             ));
@@ -196,6 +252,158 @@ impl FirmwareFile {
     }
 }
 
+/// Gen 4 ESP (Wi-Fi module) firmware section
+///
+/// Gen 4 firmware releases carry the ESP image as a separate section with
+/// its own header: magic "SCARLESP", VID, PID, a 4-part version, length and
+/// SHA-256, all big-endian. Based on firmware.c in fcp-support.
+#[derive(Debug, Clone)]
+pub struct EspFirmware {
+    /// USB Vendor ID of the device the image is for
+    pub usb_vid: u16,
+    /// USB Product ID of the device the image is for
+    pub usb_pid: u16,
+    /// Firmware version (major, minor, patch, build)
+    pub version: [u32; 4],
+    /// ESP firmware image
+    pub data: Vec<u8>,
+}
+
+impl EspFirmware {
+    /// Size of the section header in bytes
+    pub const HEADER_SIZE: usize = 64;
+
+    /// Parse an ESP section (header + data), verifying its SHA-256 hash
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < Self::HEADER_SIZE {
+            return Err(Error::Protocol(format!(
+                "ESP firmware header too short: {} bytes (expected {})",
+                bytes.len(),
+                Self::HEADER_SIZE
+            )));
+        }
+
+        if &bytes[0..8] != ESP_FIRMWARE_MAGIC {
+            return Err(Error::Protocol(format!(
+                "Invalid ESP firmware magic: expected 'SCARLESP', got '{}'",
+                String::from_utf8_lossy(&bytes[0..8])
+            )));
+        }
+
+        let be32 = |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+        let usb_vid = u16::from_be_bytes([bytes[8], bytes[9]]);
+        let usb_pid = u16::from_be_bytes([bytes[10], bytes[11]]);
+        let version = [be32(12), be32(16), be32(20), be32(24)];
+        let length = be32(28) as usize;
+
+        let data = &bytes[Self::HEADER_SIZE..];
+        if data.len() != length {
+            return Err(Error::Protocol(format!(
+                "ESP firmware size mismatch: got {} bytes, expected {}",
+                data.len(),
+                length
+            )));
+        }
+
+        if compute_sha256(data) != bytes[32..64] {
+            return Err(Error::Protocol(
+                "ESP firmware SHA-256 hash mismatch! File may be corrupted.".to_string()
+            ));
+        }
+
+        Ok(Self {
+            usb_vid,
+            usb_pid,
+            version,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Read and validate an ESP firmware file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path_ref = path.as_ref();
+
+        tracing::info!("Reading ESP firmware file: {}", path_ref.display());
+
+        let bytes = std::fs::read(path_ref)?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Serialize to a complete ESP section (header + data)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::HEADER_SIZE + self.data.len());
+        bytes.extend_from_slice(ESP_FIRMWARE_MAGIC);
+        bytes.extend_from_slice(&self.usb_vid.to_be_bytes());
+        bytes.extend_from_slice(&self.usb_pid.to_be_bytes());
+        for part in self.version {
+            bytes.extend_from_slice(&part.to_be_bytes());
+        }
+        bytes.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&compute_sha256(&self.data));
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Validate that the image is for a specific device
+    pub fn validate_for_device(&self, vid: u16, pid: u16) -> Result<()> {
+        if (self.usb_vid, self.usb_pid) != (vid, pid) {
+            return Err(Error::Protocol(format!(
+                "ESP firmware is for {:04x}:{:04x}, device is {:04x}:{:04x}",
+                self.usb_vid, self.usb_pid, vid, pid
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Get the version as a dotted string
+    pub fn version_string(&self) -> String {
+        let [major, minor, patch, build] = self.version;
+        format!("{}.{}.{}.{}", major, minor, patch, build)
+    }
+
+    /// Get the MD5 hash the device uses to verify the transfer
+    pub fn md5(&self) -> [u8; 16] {
+        compute_md5(&self.data)
+    }
+}
+
+/// Options for a firmware update
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FirmwareUpdateOptions {
+    /// Also flash the ESP (Wi-Fi module) image; off unless asked for
+    pub update_esp: bool,
+}
+
+impl FirmwareUpdateOptions {
+    /// Enable or disable flashing the ESP image
+    pub fn with_esp_update(mut self, update_esp: bool) -> Self {
+        self.update_esp = update_esp;
+        self
+    }
+}
+
+/// Compute the MD5 hash of ESP firmware data
+pub(crate) fn compute_md5(data: &[u8]) -> [u8; 16] {
+    let mut hasher = Md5::new();
+    hasher.update(data);
+
+    let mut hash = [0u8; 16];
+    hash.copy_from_slice(&hasher.finalize());
+    hash
+}
+
+/// Compute the SHA-256 hash of firmware data
+fn compute_sha256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&hasher.finalize());
+    hash
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -234,4 +442,60 @@ mod tests {
         assert_eq!(header.usb_vid, 0x1235);
         assert_eq!(header.usb_pid, 0x821D);
     }
+
+    #[test]
+    fn test_header_roundtrip() {
+        let firmware = FirmwareFile::new(0x1235, 0x821D, 2128, vec![1, 2, 3, 4]);
+        let bytes = firmware.header.to_bytes();
+        let header = FirmwareHeader::from_bytes(&bytes).unwrap();
+
+        assert_eq!(header.usb_pid, 0x821D);
+        assert_eq!(header.firmware_version, 2128);
+        assert_eq!(header.firmware_length, 4);
+        assert_eq!(header.sha256, firmware.header.sha256);
+    }
+
+    #[test]
+    fn test_file_roundtrip() {
+        let path = std::env::temp_dir().join(format!("scarlett-fw-test-{}.bin", std::process::id()));
+        let firmware = FirmwareFile::new(0x1235, 0x8215, 1644, (0..=255).collect());
+
+        firmware.write_to_file(&path).unwrap();
+        let loaded = FirmwareFile::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.version(), 1644);
+        assert_eq!(loaded.data(), firmware.data());
+        loaded.validate_for_device(0x1235, 0x8215).unwrap();
+    }
+
+    #[test]
+    fn test_esp_firmware_roundtrip() {
+        let firmware = EspFirmware {
+            usb_vid: 0x1235,
+            usb_pid: 0x821b,
+            version: [2, 0, 1, 17],
+            data: vec![0xaa; 100],
+        };
+
+        let bytes = firmware.to_bytes();
+        assert_eq!(bytes.len(), EspFirmware::HEADER_SIZE + 100);
+
+        let parsed = EspFirmware::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.version_string(), "2.0.1.17");
+        assert_eq!(parsed.data, firmware.data);
+        parsed.validate_for_device(0x1235, 0x821b).unwrap();
+        assert!(parsed.validate_for_device(0x1235, 0x821c).is_err());
+
+        // MD5 of the empty image
+        assert_eq!(compute_md5(&[])[..4], [0xd4, 0x1d, 0x8c, 0xd9]);
+
+        let mut corrupt = bytes.clone();
+        corrupt[EspFirmware::HEADER_SIZE] ^= 1;
+        assert!(EspFirmware::from_bytes(&corrupt).is_err());
+
+        // Application firmware is not an ESP image
+        let app = FirmwareFile::new(0x1235, 0x821b, 1, vec![0; 100]).to_bytes();
+        assert!(EspFirmware::from_bytes(&app).is_err());
+    }
 }
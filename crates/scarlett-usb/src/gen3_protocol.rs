@@ -1,10 +1,23 @@
 //! Scarlett Gen 2/3 USB Protocol
 //!
-//! Gen 2 and Gen 3 devices use the "Scarlett2" USB protocol which communicates
-//! via USB vendor-specific control transfers
+//! Gen 2, Gen 3 and the small Gen 4 devices use the "Scarlett2" USB protocol,
+//! which sends commands as class-specific control transfers to the
+//! vendor-specific interface
 
-use scarlett_core::{Error, Result};
-use nusb::{Device, transfer::RequestBuffer};
+use crate::config_items::{self, ConfigAccess, ConfigParam};
+use crate::gen4_fcp::{device_error, routing_from_mux, DeviceVersions, VolumeScale};
+use crate::meters::meter_to_db;
+use crate::mux::{self, MuxTables};
+use crate::notify::{decode_notifications, Notification};
+use crate::protocol::{level_meters, mixer_state_from_gains, Protocol, MIXER_STATE_MIX};
+use crate::transport::{BulkTransfer, ControlTransfer, Direction, RetryPolicy, UsbTransport};
+use scarlett_core::mixer::{db_to_mixer_gain, LevelMeter, MixerState, PortLevel};
+use scarlett_core::protocol::gen2;
+use scarlett_core::routing::{PortLayout, PortType, RoutingMatrix};
+use scarlett_core::{
+    AirMode, AutogainStatus, DeviceCapabilities, DeviceModel, DirectMonitorMode, Error, InputLevel, PowerStatus,
+    Result, SyncStatus,
+};
 use std::time::Duration;
 
 /// USB Control transfer parameters for Scarlett2 protocol
@@ -16,199 +29,1165 @@ pub const USB_REQUEST_TYPE_VENDOR_OUT: u8 = 0x40;  // Vendor-specific, Host-to-D
 pub const USB_AUDIO_CONTROL_INTERFACE: u8 = 0;
 
 /// Scarlett2 USB Request codes
-pub const SCARLETT2_USB_CMD_INIT: u8 = 0x00;
-pub const SCARLETT2_USB_CMD_REQ: u8 = 0x02;
-pub const SCARLETT2_USB_CMD_RESP: u8 = 0x03;
+pub const SCARLETT2_USB_CMD_INIT: u8 = gen2::USB_CMD_INIT;
+pub const SCARLETT2_USB_CMD_REQ: u8 = gen2::USB_CMD_REQ;
+pub const SCARLETT2_USB_CMD_RESP: u8 = gen2::USB_CMD_RESP;
+
+/// Size of the header on every request and response packet
+const PACKET_HEADER_SIZE: usize = 16;
+
+/// Size of the step 0 init response
+const INIT_RESPONSE_SIZE: usize = 24;
+
+/// Size of the INIT_2 response
+const INIT_2_RESPONSE_SIZE: usize = 84;
+
+/// Size of a notification on the interrupt endpoint; the first 4 bytes
+/// hold the bitmask
+const NOTIFY_SIZE: usize = 8;
+
+/// How long to wait for a notification before reporting none
+const NOTIFY_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// Magic value sent with meter level requests
+const METER_LEVELS_MAGIC: u32 = 1;
 
 /// Scarlett2 Protocol Commands
+///
+/// Opcodes from mixer_scarlett2.c (`SCARLETT2_USB_*`).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(u16)]
+#[repr(u32)]
 pub enum Scarlett2Command {
+    /// First init step
+    Init1 = gen2::CMD_INIT_1,
+    /// Second init step, answered with the firmware version
+    Init2 = gen2::CMD_INIT_2,
+    /// Reboot the device
+    Reboot = gen2::CMD_REBOOT,
     /// Get meter levels
-    GetMeterLevels = 0x1001,
-    /// Get configuration
-    GetConfig = 0x1002,
-    /// Set configuration
-    SetConfig = 0x1003,
+    GetMeterLevels = gen2::CMD_GET_METER,
     /// Get mixer values
-    GetMixer = 0x3001,
+    GetMixer = gen2::CMD_GET_MIX,
     /// Set mixer values
-    SetMixer = 0x3002,
+    SetMixer = gen2::CMD_SET_MIX,
     /// Get routing
-    GetRouting = 0x3101,
+    GetRouting = gen2::CMD_GET_MUX,
     /// Set routing
-    SetRouting = 0x3102,
+    SetRouting = gen2::CMD_SET_MUX,
+    /// Get clock sync status
+    GetSync = gen2::CMD_GET_SYNC,
+    /// Get configuration
+    GetConfig = gen2::CMD_GET_DATA,
+    /// Set configuration
+    SetConfig = gen2::CMD_SET_DATA,
+    /// Activate a configuration change written with SetConfig
+    ActivateConfig = gen2::CMD_DATA_CMD,
+}
+
+/// GetMeterLevels request: offset (u16), count (u16), magic (u32)
+fn meter_request(count: u16) -> Vec<u8> {
+    let mut request = Vec::new();
+    request.extend_from_slice(&0u16.to_le_bytes());
+    request.extend_from_slice(&count.to_le_bytes());
+    request.extend_from_slice(&METER_LEVELS_MAGIC.to_le_bytes());
+    request
+}
+
+/// Parse meter levels, each a 32-bit unsigned integer
+fn parse_meter_levels(response: &[u8]) -> Vec<u32> {
+    response
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+/// Check the response to command `cmd`, sent with sequence number `seq`,
+/// and extract its payload
+fn command_response(
+    cmd: Scarlett2Command,
+    seq: u16,
+    mut response: Vec<u8>,
+    actual: usize,
+    response_size: usize,
+) -> Result<Vec<u8>> {
+    if actual < PACKET_HEADER_SIZE + response_size {
+        return Err(Error::Protocol(format!(
+            "{:?} response too short: {} bytes",
+            cmd, actual
+        )));
+    }
+
+    let resp_cmd = u32::from_le_bytes([response[0], response[1], response[2], response[3]]);
+    if resp_cmd != cmd as u32 {
+        return Err(Error::Protocol(format!(
+            "Invalid response command: 0x{:08x}",
+            resp_cmd
+        )));
+    }
+
+    // The device answers init with sequence 0
+    let resp_seq = u16::from_le_bytes([response[6], response[7]]);
+    if resp_seq != seq && !(seq == 1 && resp_seq == 0) {
+        return Err(Error::Protocol(format!(
+            "Sequence mismatch: expected {}, got {}",
+            seq, resp_seq
+        )));
+    }
+
+    let error = u32::from_le_bytes([response[8], response[9], response[10], response[11]]);
+    if error != 0 {
+        return Err(device_error(error as i32, &format!("{:?}", cmd)));
+    }
+
+    // Extract payload (skip the header)
+    response.truncate(actual);
+    Ok(response.split_off(PACKET_HEADER_SIZE))
 }
 
 /// Scarlett2 USB Protocol Handler
 pub struct Scarlett2Protocol {
-    device: Device,
-    sequence: u8,
+    transport: Box<dyn UsbTransport>,
+    interface_num: u8,
+    sequence: u16,
+    model: Option<DeviceModel>,
+    timeout: Duration,
+    max_retries: usize,
+    versions: Option<DeviceVersions>,
+    writes: u64,
+    notify_endpoint: Option<u8>,
+    /// Set once the device has been told to reboot and dropped off the bus
+    rebooted: bool,
 }
 
 impl Scarlett2Protocol {
     /// Create a new protocol handler
-    pub fn new(device: Device) -> Self {
+    pub fn new(transport: Box<dyn UsbTransport>) -> Self {
         Self {
-            device,
+            transport,
+            interface_num: USB_AUDIO_CONTROL_INTERFACE,
             sequence: 0,
+            model: None,
+            timeout: crate::transport::DEFAULT_TIMEOUT,
+            max_retries: crate::transport::DEFAULT_MAX_RETRIES,
+            versions: None,
+            writes: 0,
+            notify_endpoint: None,
+            rebooted: false,
         }
     }
 
+    /// Set the interface that control transfers are addressed to
+    ///
+    /// This is the vendor-specific interface (see
+    /// `direct_usb_transport::find_vendor_interface`).
+    pub fn with_interface(mut self, interface_num: u8) -> Self {
+        self.interface_num = interface_num;
+        self
+    }
+
+    /// Get the interface that control transfers are addressed to
+    pub fn interface_number(&self) -> u8 {
+        self.interface_num
+    }
+
+    /// Set the interrupt endpoint that the device sends notifications on
+    ///
+    /// This is the interrupt IN endpoint of the vendor-specific interface
+    /// (see `DirectUsbTransport::interrupt_endpoint`).
+    pub fn with_notify_endpoint(mut self, endpoint: u8) -> Self {
+        self.notify_endpoint = Some(endpoint);
+        self
+    }
+
+    /// Set the control transfer timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the control transfer timeout
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Get the control transfer timeout
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Set how many times a command is resent after a transient USB error
+    ///
+    /// Set to 0 to disable retries.
+    pub fn set_max_retries(&mut self, max_retries: usize) {
+        self.max_retries = max_retries;
+    }
+
+    /// Set the device model, enabling model-specific controls
+    pub fn with_model(mut self, model: DeviceModel) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    /// Get the device model, if known
+    pub fn model(&self) -> Option<DeviceModel> {
+        self.model
+    }
+
+    /// Check if the device is still connected
+    pub fn is_connected(&self) -> bool {
+        !self.rebooted && self.transport.is_connected()
+    }
+
+    /// Talk to the device through a new transport, e.g. after it has
+    /// re-enumerated
+    ///
+    /// Run `init` again before sending commands.
+    pub fn set_transport(&mut self, transport: Box<dyn UsbTransport>) {
+        self.transport = transport;
+        self.sequence = 0;
+        self.rebooted = false;
+    }
+
     /// Initialize the device
+    ///
+    /// Follows scarlett2_usb_init() in the kernel driver.
     pub fn init(&mut self) -> Result<()> {
+        if self.rebooted {
+            return Err(Error::Disconnected);
+        }
         tracing::debug!("Initializing Scarlett2 protocol");
 
-        // Send INIT command
-        let data = vec![SCARLETT2_USB_CMD_INIT, 0x00];
+        // Step 0: read and discard the init response
+        let transfer = ControlTransfer::class_in(SCARLETT2_USB_CMD_INIT, 0, self.interface_num as u16)
+            .with_timeout(self.timeout);
+        let mut buf = [0u8; INIT_RESPONSE_SIZE];
+        self.transport.control_in(&transfer, &mut buf)?;
 
-        self.control_write(0x00, 0x00, &data)?;
+        // Steps 1 and 2 are both sent with sequence number 1
+        self.sequence = 1;
+        self.send_command(Scarlett2Command::Init1, &[], 0)?;
+        self.sequence = 1;
+        let step2_resp = self.send_command(Scarlett2Command::Init2, &[], INIT_2_RESPONSE_SIZE)?;
+
+        self.versions = DeviceVersions::from_init_response(&step2_resp);
+        if let Some(versions) = &self.versions {
+            tracing::info!("Device firmware version: {}", versions.firmware);
+        }
 
         Ok(())
     }
 
-    /// Send a command and receive response
-    pub fn send_command(&mut self, cmd: Scarlett2Command, data: &[u8]) -> Result<Vec<u8>> {
+    /// Get the firmware version read during init
+    pub fn versions(&self) -> Option<DeviceVersions> {
+        self.versions
+    }
+
+    /// Send a command and receive its response payload
+    ///
+    /// The packet goes out as class request 2 and the response is read back
+    /// as class request 3, both addressed to the vendor-specific interface.
+    pub fn send_command(&mut self, cmd: Scarlett2Command, data: &[u8], response_size: usize) -> Result<Vec<u8>> {
+        let (seq, request) = self.build_command(cmd, data)?;
+        self.exchange(cmd, seq, &request, response_size)
+    }
+
+    /// Send a command without blocking the calling task
+    ///
+    /// Transports without non-blocking transfers fall back to the blocking
+    /// exchange of `send_command`.
+    async fn send_command_async(&mut self, cmd: Scarlett2Command, data: &[u8], response_size: usize) -> Result<Vec<u8>> {
+        let (seq, request) = self.build_command(cmd, data)?;
+        let Some(transport) = self.transport.as_async() else {
+            return self.exchange(cmd, seq, &request, response_size);
+        };
+
+        let (transfer_out, transfer_in) = self.command_transfers();
+        RetryPolicy::exponential(self.max_retries)
+            .retry_async(|| transport.control_out_async(&transfer_out, &request))
+            .await?;
+
+        let mut response = vec![0u8; PACKET_HEADER_SIZE + response_size];
+        let actual = transport.control_in_async(&transfer_in, &mut response).await?;
+        command_response(cmd, seq, response, actual, response_size)
+    }
+
+    /// Number the next command and build its packet
+    fn build_command(&mut self, cmd: Scarlett2Command, data: &[u8]) -> Result<(u16, Vec<u8>)> {
+        if self.rebooted {
+            return Err(Error::Disconnected);
+        }
         tracing::debug!("Sending Scarlett2 command: {:?}", cmd);
 
+        let seq = self.sequence;
         self.sequence = self.sequence.wrapping_add(1);
 
-        // Build request packet
-        let mut request = Vec::new();
-        request.push(SCARLETT2_USB_CMD_REQ);
-        request.push(self.sequence);
-        request.extend_from_slice(&(cmd as u16).to_le_bytes());
+        // Header: cmd (u32), size (u16), seq (u16), error (u32), pad (u32)
+        let mut request = Vec::with_capacity(PACKET_HEADER_SIZE + data.len());
+        request.extend_from_slice(&(cmd as u32).to_le_bytes());
         request.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        request.extend_from_slice(&seq.to_le_bytes());
+        request.extend_from_slice(&[0; 8]);
         request.extend_from_slice(data);
 
-        // Send request
-        self.control_write(0x00, 0x00, &request)?;
+        Ok((seq, request))
+    }
+
+    /// Transfers that send a command and read back its response
+    fn command_transfers(&self) -> (ControlTransfer, ControlTransfer) {
+        let index = self.interface_num as u16;
+        (
+            ControlTransfer::class_out(SCARLETT2_USB_CMD_REQ, 0, index).with_timeout(self.timeout),
+            ControlTransfer::class_in(SCARLETT2_USB_CMD_RESP, 0, index).with_timeout(self.timeout),
+        )
+    }
+
+    /// Send a built command packet and read its response payload
+    fn exchange(&self, cmd: Scarlett2Command, seq: u16, request: &[u8], response_size: usize) -> Result<Vec<u8>> {
+        let (transfer_out, transfer_in) = self.command_transfers();
+
+        // Send request; only the write is retried, as a failed read means
+        // the device may already have acted on the command
+        let transport = &self.transport;
+        crate::transport::retry_transient(self.max_retries, || {
+            transport.control_out(&transfer_out, request)
+        })?;
 
         // Receive response
-        let response = self.control_read(0x00, 0x00, 1024)?;
+        let mut response = vec![0u8; PACKET_HEADER_SIZE + response_size];
+        let actual = self.transport.control_in(&transfer_in, &mut response)?;
+        command_response(cmd, seq, response, actual, response_size)
+    }
+
+    /// Wait briefly for a notification of hardware state changes
+    ///
+    /// Returns the groups of controls that changed, or nothing if the
+    /// device had nothing to report. Command acknowledgements are dropped.
+    pub fn read_notifications(&mut self) -> Result<Vec<Notification>> {
+        let model = self.model.ok_or_else(|| {
+            Error::NotSupported("Notifications: device model unknown".to_string())
+        })?;
+        let endpoint = self.notify_endpoint.ok_or_else(|| {
+            Error::NotSupported("Notifications: no interrupt endpoint".to_string())
+        })?;
 
-        // Validate response
-        if response.len() < 4 {
-            return Err(Error::Protocol("Response too short".to_string()));
+        let transfer = BulkTransfer {
+            endpoint,
+            direction: Direction::In,
+            timeout: NOTIFY_TIMEOUT,
+        };
+        let mut buffer = [0u8; NOTIFY_SIZE];
+        let len = self.transport.interrupt_in(&transfer, &mut buffer)?;
+
+        // As in the kernel driver, anything but a full notification is ignored
+        if len != NOTIFY_SIZE {
+            if len != 0 {
+                tracing::debug!("Ignoring {}-byte notification", len);
+            }
+            return Ok(Vec::new());
         }
 
-        if response[0] != SCARLETT2_USB_CMD_RESP {
-            return Err(Error::Protocol(format!(
-                "Invalid response command: 0x{:02x}",
-                response[0]
-            )));
+        let mask = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+        Ok(decode_notifications(model, mask))
+    }
+
+    /// Read clock sync status
+    pub fn sync_status(&mut self) -> Result<SyncStatus> {
+        let response = self.send_command(Scarlett2Command::GetSync, &[], 4)?;
+        SyncStatus::from_bytes(&response)
+    }
+
+    /// Get the raw levels of the first `count` meters
+    ///
+    /// Meters are in the device's order; see [`Self::read_meters`] for the
+    /// port each one measures.
+    pub fn get_meter_levels(&mut self, count: u16) -> Result<Vec<u32>> {
+        let response = self.send_command(Scarlett2Command::GetMeterLevels, &meter_request(count), count as usize * 4)?;
+        Ok(parse_meter_levels(&response))
+    }
+
+    /// Get the raw levels of the first `count` meters without blocking the
+    /// calling task
+    pub async fn get_meter_levels_async(&mut self, count: u16) -> Result<Vec<u32>> {
+        let response = self
+            .send_command_async(Scarlett2Command::GetMeterLevels, &meter_request(count), count as usize * 4)
+            .await?;
+        Ok(parse_meter_levels(&response))
+    }
+
+    /// Number of level meters, one per 1x mux table entry
+    pub fn meter_count(&self) -> Result<u16> {
+        let (_, tables) = self.routing_layout()?;
+        Ok(mux::table_ports(tables[0]).len() as u16)
+    }
+
+    /// Read every level meter, labelled with the port it measures
+    ///
+    /// The device has one meter per mux destination, reported in the order
+    /// of the 1x mux table.
+    pub fn read_meters(&mut self) -> Result<Vec<PortLevel>> {
+        let (layout, tables) = self.routing_layout()?;
+        let ports = mux::table_ports(tables[0]);
+        let levels = self.get_meter_levels(ports.len() as u16)?;
+
+        ports
+            .into_iter()
+            .zip(levels)
+            .map(|((port_type, index), level)| {
+                let port = layout
+                    .destinations
+                    .iter()
+                    .find(|port| port.port_type == port_type && port.index == index)
+                    .cloned()
+                    .ok_or_else(|| Error::Protocol(format!("No {:?} {} for meter", port_type, index)))?;
+                Ok(PortLevel { port, level_db: meter_to_db(level) })
+            })
+            .collect()
+    }
+
+    /// Number of mixer inputs, which is the length of every mix
+    fn mixer_inputs(&self) -> Result<u16> {
+        let model = self.model.ok_or_else(|| {
+            Error::NotSupported("Mixer: device model unknown".to_string())
+        })?;
+
+        let caps = DeviceCapabilities::for_model(model);
+        if !caps.mix || caps.num_mixer_inputs == 0 {
+            return Err(Error::NotSupported(format!("Mixer on {}", model)));
         }
+        Ok(caps.num_mixer_inputs as u16)
+    }
+
+    /// Get the gain of every mixer input to a mix (0 = Mix A)
+    pub fn get_mix(&mut self, mix: u16) -> Result<Vec<u16>> {
+        let count = self.mixer_inputs()?;
+
+        let mut request = Vec::new();
+        request.extend_from_slice(&mix.to_le_bytes());
+        request.extend_from_slice(&count.to_le_bytes());
+
+        let response = self.send_command(Scarlett2Command::GetMixer, &request, count as usize * 2)?;
+
+        Ok(response
+            .chunks_exact(2)
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect())
+    }
 
-        if response[1] != self.sequence {
-            return Err(Error::Protocol(format!(
-                "Sequence mismatch: expected {}, got {}",
-                self.sequence, response[1]
+    /// Set the gain of every mixer input to a mix (0 = Mix A)
+    pub fn set_mix(&mut self, mix: u16, gains: &[u16]) -> Result<()> {
+        let count = self.mixer_inputs()?;
+        if gains.len() != count as usize {
+            return Err(Error::InvalidParameter(format!(
+                "Mix needs {} gains, got {}",
+                count,
+                gains.len()
             )));
         }
 
-        // Extract payload (skip command byte, sequence, and length)
-        let payload_len = u16::from_le_bytes([response[2], response[3]]) as usize;
+        let mut request = Vec::new();
+        request.extend_from_slice(&mix.to_le_bytes());
+        for gain in gains {
+            request.extend_from_slice(&gain.to_le_bytes());
+        }
+
+        self.send_command(Scarlett2Command::SetMixer, &request, 0)?;
+
+        Ok(())
+    }
+
+    /// Port layout and mux tables of the device, failing if it has no mux
+    fn routing_layout(&self) -> Result<(PortLayout, MuxTables)> {
+        let model = self.model.ok_or_else(|| Error::NotSupported("Routing: device model unknown".to_string()))?;
+        let tables = mux::mux_tables(model).ok_or_else(|| Error::NotSupported(format!("Routing on {}", model)))?;
+        Ok((model.port_layout(), tables))
+    }
+
+    /// Read the raw mux table for 44.1/48 kHz
+    ///
+    /// Each entry is `dest_id | source_id << 12`. As in the kernel driver,
+    /// only the 1x table is read; the others hold the same routes.
+    pub fn get_mux(&mut self) -> Result<Vec<u32>> {
+        let count = self.routing_layout()?.0.destinations.len() as u16;
+
+        // Request: table number (u16), count (u16)
+        let mut request = Vec::new();
+        request.extend_from_slice(&0u16.to_le_bytes());
+        request.extend_from_slice(&count.to_le_bytes());
+
+        let response = self.send_command(Scarlett2Command::GetRouting, &request, count as usize * 4)?;
+        Ok(response
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect())
+    }
+
+    /// Write a raw mux table (0 = 1x rates, 1 = 2x, 2 = 4x)
+    pub fn set_mux(&mut self, table: u16, entries: &[u32]) -> Result<()> {
+        // Request: pad (u16), table number (u16), entries
+        let mut request = Vec::new();
+        request.extend_from_slice(&0u16.to_le_bytes());
+        request.extend_from_slice(&table.to_le_bytes());
+        for entry in entries {
+            request.extend_from_slice(&entry.to_le_bytes());
+        }
+
+        self.send_command(Scarlett2Command::SetRouting, &request, 0)?;
+        Ok(())
+    }
+
+    /// Read the routing matrix
+    ///
+    /// Entries for ports outside the model's layout are ignored.
+    pub fn get_routing(&mut self) -> Result<RoutingMatrix> {
+        let (layout, _) = self.routing_layout()?;
+        let entries = self.get_mux()?;
+        routing_from_mux(layout, &entries)
+    }
 
-        if response.len() < 4 + payload_len {
-            return Err(Error::Protocol("Response payload truncated".to_string()));
+    /// Write the routing matrix to every sample rate band's mux table
+    ///
+    /// Destinations that don't exist at the higher rates (e.g. ADAT at 4x)
+    /// are left out of those tables.
+    pub fn set_routing(&mut self, matrix: &RoutingMatrix) -> Result<()> {
+        matrix.validate()?;
+
+        let (_, tables) = self.routing_layout()?;
+        for (table, runs) in tables.iter().enumerate() {
+            let entries = mux::table_entries(runs, matrix);
+            tracing::debug!("Writing {} entries to mux table {}", entries.len(), table);
+            self.set_mux(table as u16, &entries)?;
         }
 
-        Ok(response[4..4 + payload_len].to_vec())
+        Ok(())
     }
 
-    /// Get meter levels
-    pub fn get_meter_levels(&mut self) -> Result<Vec<i32>> {
-        let response = self.send_command(Scarlett2Command::GetMeterLevels, &[])?;
-
-        // Parse meter levels (each is a 32-bit signed integer)
-        let mut levels = Vec::new();
-        for chunk in response.chunks_exact(4) {
-            let level = i32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
-            levels.push(level);
+    /// Get the gain of a mixer input to a mix
+    pub fn get_mixer_volume(&mut self, mix: u16, input_index: u16) -> Result<u16> {
+        self.get_mix(mix)?
+            .get(input_index as usize)
+            .copied()
+            .ok_or_else(|| Error::InvalidParameter(format!("No mixer input {}", input_index)))
+    }
+
+    /// Set the gain of a mixer input to a mix
+    ///
+    /// The device only takes whole mixes, so the mix is read and written back.
+    pub fn set_mixer_volume(&mut self, mix: u16, input_index: u16, volume: u16) -> Result<()> {
+        let mut gains = self.get_mix(mix)?;
+        let gain = gains
+            .get_mut(input_index as usize)
+            .ok_or_else(|| Error::InvalidParameter(format!("No mixer input {}", input_index)))?;
+        *gain = volume;
+
+        self.set_mix(mix, &gains)
+    }
+
+    /// Get the configuration index for a phantom power switch
+    fn phantom_index(&self, channel_group: u8) -> Result<u8> {
+        self.model
+            .and_then(|m| config_items::phantom_config_index(m, channel_group))
+            .ok_or_else(|| Error::InvalidParameter(format!(
+                "No phantom power switch {}",
+                channel_group
+            )))
+    }
+
+    /// Get preamp gain for an input (0-based), in dB (Gen 4)
+    pub fn get_input_gain(&mut self, input: u8) -> Result<u8> {
+        config_items::get_input_gain(self, input)
+    }
+
+    /// Set preamp gain for an input (0-based), in dB (Gen 4)
+    ///
+    /// Values outside the device's gain range are clamped. A stereo-linked
+    /// partner input is set too.
+    pub fn set_input_gain(&mut self, input: u8, gain_db: u8) -> Result<()> {
+        tracing::info!("Setting input {} gain to {} dB", input, gain_db);
+        config_items::set_input_gain(self, input, gain_db)?;
+        Ok(())
+    }
+
+    /// Start hardware autogain on an input (0-based, Gen 4)
+    pub fn start_autogain(&mut self, input: u8) -> Result<()> {
+        tracing::info!("Starting autogain on input {}", input);
+        config_items::start_autogain(self, input)
+    }
+
+    /// Get the autogain status of an input (0-based, Gen 4)
+    pub fn autogain_status(&mut self, input: u8) -> Result<AutogainStatus> {
+        config_items::get_autogain_status(self, input)
+    }
+
+    /// Get whether an input pair (0 = inputs 1+2) is stereo-linked (Gen 4)
+    pub fn get_input_link(&mut self, pair: u8) -> Result<bool> {
+        config_items::get_input_link(self, pair)
+    }
+
+    /// Link or unlink an input pair (0 = inputs 1+2, Gen 4)
+    ///
+    /// Linked inputs share gain, Air and autogain.
+    pub fn set_input_link(&mut self, pair: u8, linked: bool) -> Result<()> {
+        tracing::info!("Setting input pair {} link: {}", pair, linked);
+        config_items::set_input_link(self, pair, linked)
+    }
+
+    /// Get where the device is drawing its power from
+    ///
+    /// Only bus-powerable devices report this (4i4 Gen 4).
+    pub fn power_status(&mut self) -> Result<PowerStatus> {
+        config_items::get_power_status(self)
+    }
+
+    /// Get 48V phantom power state for a switch (0-based)
+    ///
+    /// On most Gen 3 devices one switch controls a pair of inputs; see
+    /// `DeviceModel::phantom_group_inputs`.
+    pub fn get_phantom(&mut self, channel_group: u8) -> Result<bool> {
+        let index = self.phantom_index(channel_group)?;
+        Ok(self.get_config(ConfigParam::PhantomSwitch, index)? != 0)
+    }
+
+    /// Set 48V phantom power state for a switch (0-based)
+    pub fn set_phantom(&mut self, channel_group: u8, enabled: bool) -> Result<()> {
+        let index = self.phantom_index(channel_group)?;
+        tracing::info!("Setting phantom power switch {}: {}", channel_group, enabled);
+        self.set_config(ConfigParam::PhantomSwitch, index, enabled as i32)
+    }
+
+    /// Get whether phantom power state is restored at power-on
+    pub fn get_phantom_persistence(&mut self) -> Result<bool> {
+        Ok(self.get_config(ConfigParam::PhantomPersistence, 0)? != 0)
+    }
+
+    /// Set whether phantom power state is restored at power-on
+    ///
+    /// Save the configuration to flash for it to survive a power cycle.
+    pub fn set_phantom_persistence(&mut self, enabled: bool) -> Result<()> {
+        tracing::info!("Setting phantom power persistence: {}", enabled);
+        self.set_config(ConfigParam::PhantomPersistence, 0, enabled as i32)
+    }
+
+    /// Get the configuration index for an input's Air control
+    fn air_index(&self, input: u8) -> Result<u8> {
+        self.model
+            .and_then(|m| config_items::air_config_index(m, input))
+            .ok_or_else(|| Error::InvalidParameter(format!("Input {} has no Air control", input)))
+    }
+
+    /// Get Air mode for an input (0-based)
+    pub fn get_air(&mut self, input: u8) -> Result<AirMode> {
+        let index = self.air_index(input)?;
+        let value = self.get_config(ConfigParam::AirSwitch, index)?;
+
+        AirMode::from_raw(value)
+            .ok_or_else(|| Error::Protocol(format!("Invalid Air mode value: {}", value)))
+    }
+
+    /// Set Air mode for an input (0-based)
+    ///
+    /// Presence + Drive is only available on 4th Gen devices.
+    pub fn set_air(&mut self, input: u8, mode: AirMode) -> Result<()> {
+        let index = self.air_index(input)?;
+
+        if mode == AirMode::PresenceDrive && !self.model.is_some_and(|m| m.has_air_drive()) {
+            return Err(Error::NotSupported(
+                "Air Presence + Drive requires a 4th Gen device".to_string()
+            ));
         }
 
-        Ok(levels)
+        tracing::info!("Setting input {} Air: {}", input, mode);
+        self.set_config(ConfigParam::AirSwitch, index, mode.to_raw())
+    }
+
+    /// Get Line/Inst level for an input (0-based)
+    pub fn get_input_level(&mut self, input: u8) -> Result<InputLevel> {
+        config_items::check_level_input(self.model, input)?;
+        Ok(InputLevel::from_raw(self.get_config(ConfigParam::LevelSwitch, input)?))
+    }
+
+    /// Set Line/Inst level for an input (0-based)
+    pub fn set_input_level(&mut self, input: u8, level: InputLevel) -> Result<()> {
+        config_items::check_level_input(self.model, input)?;
+        tracing::info!("Setting input {} level: {}", input, level);
+        self.set_config(ConfigParam::LevelSwitch, input, level.to_raw())
+    }
+
+    /// Get Direct Monitor mode
+    pub fn get_direct_monitor(&mut self) -> Result<DirectMonitorMode> {
+        let raw = self.get_config(ConfigParam::DirectMonitor, 0)?;
+        config_items::direct_monitor_from_raw(self.model, raw)
+    }
+
+    /// Set Direct Monitor mode
+    ///
+    /// The Solo accepts Off/On and the 2i2 Off/Mono/Stereo.
+    pub fn set_direct_monitor(&mut self, mode: DirectMonitorMode) -> Result<()> {
+        let raw = config_items::direct_monitor_to_raw(self.model, mode)?;
+
+        tracing::info!("Setting Direct Monitor: {}", mode);
+        self.set_config(ConfigParam::DirectMonitor, 0, raw)
+    }
+
+    /// Get monitor Dim state
+    ///
+    /// Available on devices with hardware Dim/Mute buttons (18i8/18i20
+    /// Gen 3, 18i20 Gen 2, Clarett).
+    pub fn get_dim(&mut self) -> Result<bool> {
+        Ok(self.get_config(ConfigParam::DimMute, config_items::DIM_MUTE_INDEX_DIM)? != 0)
+    }
+
+    /// Set monitor Dim state
+    pub fn set_dim(&mut self, enabled: bool) -> Result<()> {
+        tracing::info!("Setting monitor Dim: {}", enabled);
+        self.set_config(ConfigParam::DimMute, config_items::DIM_MUTE_INDEX_DIM, enabled as i32)
+    }
+
+    /// Get monitor Mute state
+    pub fn get_monitor_mute(&mut self) -> Result<bool> {
+        Ok(self.get_config(ConfigParam::DimMute, config_items::DIM_MUTE_INDEX_MUTE)? != 0)
+    }
+
+    /// Set monitor Mute state
+    pub fn set_monitor_mute(&mut self, muted: bool) -> Result<()> {
+        tracing::info!("Setting monitor Mute: {}", muted);
+        self.set_config(ConfigParam::DimMute, config_items::DIM_MUTE_INDEX_MUTE, muted as i32)
     }
 
-    /// Get mixer volume for a specific input
-    pub fn get_mixer_volume(&mut self, input_index: u16) -> Result<u16> {
-        let data = input_index.to_le_bytes();
-        let response = self.send_command(Scarlett2Command::GetMixer, &data)?;
+    /// Number of analogue line outputs
+    fn num_line_outputs(&self) -> u8 {
+        let Some(model) = self.model else { return 0 };
+        model
+            .port_layout()
+            .destinations
+            .iter()
+            .filter(|port| port.port_type == PortType::AnalogOut)
+            .count() as u8
+    }
 
-        if response.len() < 2 {
-            return Err(Error::Protocol("Mixer response too short".to_string()));
+    fn check_line_output(&self, output_index: u8) -> Result<()> {
+        let (model, _) = self.lookup_config(ConfigParam::LineOutVolume)?;
+        if output_index >= self.num_line_outputs() {
+            return Err(Error::InvalidParameter(format!("No line output {} on {}", output_index, model)));
         }
+        Ok(())
+    }
 
-        Ok(u16::from_le_bytes([response[0], response[1]]))
+    /// Check if an output's volume follows the monitor knob
+    ///
+    /// Only rack units (18i20, 18i8 Gen 3, Clarett) have the SW/HW switch;
+    /// elsewhere every output is under software control.
+    pub fn is_hw_volume(&mut self, output_index: u8) -> Result<bool> {
+        let Ok((model, _)) = self.lookup_config(ConfigParam::SwHwSwitch) else {
+            return Ok(false);
+        };
+        if output_index >= config_items::knob_output_count(model) {
+            return Ok(false);
+        }
+        Ok(self.get_config(ConfigParam::SwHwSwitch, output_index)? != 0)
     }
 
-    /// Set mixer volume for a specific input
-    pub fn set_mixer_volume(&mut self, input_index: u16, volume: u16) -> Result<()> {
-        let mut data = Vec::new();
-        data.extend_from_slice(&input_index.to_le_bytes());
-        data.extend_from_slice(&volume.to_le_bytes());
+    fn check_sw_volume(&mut self, output_index: u8) -> Result<()> {
+        if self.is_hw_volume(output_index)? {
+            return Err(Error::NotSupported(format!(
+                "Output {} is controlled by the monitor knob",
+                output_index
+            )));
+        }
+        Ok(())
+    }
+
+    /// Get the line output volume scale of the device
+    pub fn volume_scale(&self) -> VolumeScale {
+        VolumeScale::default()
+    }
+
+    /// Get volume for a specific output (0-based index), in dB
+    ///
+    /// Outputs assigned to the monitor knob report the knob position.
+    pub fn get_volume(&mut self, output_index: u8) -> Result<f32> {
+        self.check_line_output(output_index)?;
+        if self.is_hw_volume(output_index)? {
+            return Ok(config_items::get_knob_position(self)? as f32);
+        }
+
+        let raw = self.get_config(ConfigParam::LineOutVolume, output_index)?;
+        Ok(self.volume_scale().clamp(raw as f32))
+    }
+
+    /// Set volume for a specific output (0-based index), in dB
+    ///
+    /// Fails with `NotSupported` if the output is assigned to the monitor
+    /// knob, as the device would ignore the write.
+    pub fn set_volume(&mut self, output_index: u8, volume_db: f32) -> Result<()> {
+        self.check_line_output(output_index)?;
+        self.check_sw_volume(output_index)?;
+
+        // Stored as signed whole dB
+        let value = self.volume_scale().clamp(volume_db).round() as i32;
+        tracing::info!("Setting output {} volume to {} dB", output_index, value);
+        self.set_config(ConfigParam::LineOutVolume, output_index, value)
+    }
+
+    /// Adjust volume by a delta, returning the new volume in dB
+    pub fn adjust_volume(&mut self, output_index: u8, delta_db: f32) -> Result<f32> {
+        let scale = self.volume_scale();
+        let current = self.get_volume(output_index)?;
+        let new_volume = scale.clamp(current + delta_db).round();
+        self.set_volume(output_index, new_volume)?;
+        Ok(new_volume)
+    }
+
+    /// Get mute status for a specific output
+    ///
+    /// Outputs assigned to the monitor knob follow the monitor Mute button.
+    pub fn get_mute(&mut self, output_index: u8) -> Result<bool> {
+        self.check_line_output(output_index)?;
+        if self.is_hw_volume(output_index)? {
+            return self.get_monitor_mute();
+        }
+        Ok(self.get_config(ConfigParam::MuteSwitch, output_index)? != 0)
+    }
+
+    /// Set mute status for a specific output
+    pub fn set_mute(&mut self, output_index: u8, muted: bool) -> Result<()> {
+        self.check_line_output(output_index)?;
+        self.check_sw_volume(output_index)?;
+
+        tracing::info!("Setting output {} mute: {}", output_index, muted);
+        self.set_config(ConfigParam::MuteSwitch, output_index, muted as i32)
+    }
 
-        self.send_command(Scarlett2Command::SetMixer, &data)?;
+    /// Toggle mute for a specific output, returning the new state
+    pub fn toggle_mute(&mut self, output_index: u8) -> Result<bool> {
+        let new_state = !self.get_mute(output_index)?;
+        self.set_mute(output_index, new_state)?;
+        Ok(new_state)
+    }
+
+    /// Get whether the device is in MSD ("Easy Start") mode
+    pub fn get_msd_mode(&mut self) -> Result<bool> {
+        Ok(self.get_config(ConfigParam::MsdSwitch, 0)? != 0)
+    }
+
+    /// Turn off MSD mode and reboot the device
+    ///
+    /// The device drops off the bus and re-enumerates with full
+    /// functionality; it must be reopened once hotplug reports it again.
+    pub fn disable_msd_mode(&mut self) -> Result<()> {
+        tracing::info!("Disabling MSD mode");
+        self.set_config(ConfigParam::MsdSwitch, 0, 0)?;
+        self.reboot()
+    }
+
+    /// Reboot the device
+    ///
+    /// The device drops off the bus, so later commands fail with
+    /// `Error::Disconnected` until `set_transport` is called with the
+    /// re-enumerated device.
+    pub fn reboot(&mut self) -> Result<()> {
+        tracing::info!("Rebooting device");
+        match self.send_command(Scarlett2Command::Reboot, &[], 0) {
+            Ok(_) => {}
+            // The device may drop off the bus before it responds
+            Err(e @ (Error::Disconnected | Error::Timeout(_) | Error::Usb(_))) => {
+                tracing::debug!("Reboot response not received: {}", e);
+            }
+            Err(e) => return Err(e),
+        }
 
+        self.rebooted = true;
         Ok(())
     }
 
-    /// Low-level USB control write
-    fn control_write(&self, value: u16, index: u16, data: &[u8]) -> Result<()> {
-        tracing::trace!(
-            "USB control write: value=0x{:04x}, index=0x{:04x}, len={}",
-            value, index, data.len()
-        );
+    /// Number of configuration writes made through this handler
+    pub fn config_writes(&self) -> u64 {
+        self.writes
+    }
+
+    /// Save the configuration to flash so it survives a power cycle
+    ///
+    /// Flash wears with every save, so avoid calling this per change; see
+    /// [`crate::autocommit::AutoCommit`].
+    pub fn commit_to_flash(&mut self) -> Result<()> {
+        tracing::info!("Saving configuration to flash");
+        config_items::commit_to_flash(self)
+    }
+
+    /// Get whether standalone mode is enabled
+    pub fn get_standalone(&mut self) -> Result<bool> {
+        Ok(self.get_config(ConfigParam::StandaloneSwitch, 0)? != 0)
+    }
+
+    /// Enable or disable standalone mode
+    ///
+    /// Save the configuration to flash for it to survive a power cycle.
+    pub fn set_standalone(&mut self, enabled: bool) -> Result<()> {
+        tracing::info!("Setting standalone mode: {}", enabled);
+        self.set_config(ConfigParam::StandaloneSwitch, 0, enabled as i32)
+    }
+
+    /// Get whether talkback is enabled and active (18i20)
+    pub fn get_talkback(&mut self) -> Result<bool> {
+        config_items::get_talkback(self)
+    }
+
+    /// Enable or disable talkback (18i20)
+    pub fn set_talkback(&mut self, enabled: bool) -> Result<()> {
+        tracing::info!("Setting talkback: {}", enabled);
+        config_items::set_talkback(self, enabled)
+    }
+
+    /// Get whether talkback is routed to a mix (0 = Mix A)
+    pub fn get_talkback_mix(&mut self, mix_index: u8) -> Result<bool> {
+        let bitmap = config_items::get_talkback_map(self)?;
+        Ok(mix_index < 16 && bitmap & (1 << mix_index) != 0)
+    }
 
-        // TODO: Implement actual USB control transfer using nusb
-        // For now, this is a placeholder
+    /// Route talkback to a mix (0 = Mix A), or remove it
+    pub fn set_talkback_mix(&mut self, mix_index: u8, enabled: bool) -> Result<()> {
+        tracing::info!("Setting talkback to mix {}: {}", mix_index, enabled);
+        config_items::set_talkback_mix(self, mix_index, enabled)
+    }
 
-        // let result = self.device.control_out(
-        //     USB_REQUEST_TYPE_VENDOR_OUT,
-        //     0x00,  // request
-        //     value,
-        //     index,
-        //     data,
-        //     Duration::from_millis(1000),
-        // )?;
+    /// Check that an input has a pad switch
+    fn check_pad_input(&self, input: u8) -> Result<()> {
+        let model = self.model.ok_or_else(|| {
+            Error::NotSupported("Pad control: device model unknown".to_string())
+        })?;
 
+        let inputs = model.pad_inputs();
+        if inputs.is_empty() {
+            return Err(Error::NotSupported(format!("Pad control on {}", model)));
+        }
+        if !inputs.contains(&input) {
+            return Err(Error::InvalidParameter(format!("Input {} has no pad", input)));
+        }
         Ok(())
     }
 
-    /// Low-level USB control read
-    fn control_read(&self, value: u16, index: u16, length: usize) -> Result<Vec<u8>> {
-        tracing::trace!(
-            "USB control read: value=0x{:04x}, index=0x{:04x}, len={}",
-            value, index, length
-        );
+    /// Get -10 dB pad state for an input (0-based)
+    pub fn get_pad(&mut self, input: u8) -> Result<bool> {
+        self.check_pad_input(input)?;
+        Ok(self.get_config(ConfigParam::PadSwitch, input)? != 0)
+    }
+
+    /// Set -10 dB pad state for an input (0-based)
+    pub fn set_pad(&mut self, input: u8, enabled: bool) -> Result<()> {
+        self.check_pad_input(input)?;
+        tracing::info!("Setting input {} pad: {}", input, enabled);
+        self.set_config(ConfigParam::PadSwitch, input, enabled as i32)
+    }
 
-        // TODO: Implement actual USB control transfer using nusb
-        // For now, return empty vec as placeholder
+    /// Get the pad state of every input that has one, in a single read
+    pub fn get_pads(&mut self) -> Result<Vec<bool>> {
+        let model = self.model.ok_or_else(|| {
+            Error::NotSupported("Pad control: device model unknown".to_string())
+        })?;
 
-        // let mut buffer = vec![0u8; length];
-        // let result = self.device.control_in(
-        //     USB_REQUEST_TYPE_VENDOR_IN,
-        //     0x00,  // request
-        //     value,
-        //     index,
-        //     &mut buffer,
-        //     Duration::from_millis(1000),
-        // )?;
+        let count = model.pad_inputs().len();
+        let (_, item) = self.lookup_config(ConfigParam::PadSwitch)?;
+        let data = self.read_data_block(item.offset, count as u32)?;
 
-        // Ok(buffer[..result].to_vec())
+        Ok(data.iter().map(|&pad| pad != 0).collect())
+    }
+
+    /// Read a block of bytes from the configuration space
+    fn read_data_block(&mut self, offset: u32, size: u32) -> Result<Vec<u8>> {
+        let mut request = Vec::new();
+        request.extend_from_slice(&offset.to_le_bytes());
+        request.extend_from_slice(&size.to_le_bytes());
+
+        let mut response = self.send_command(Scarlett2Command::GetConfig, &request, size as usize)?;
 
-        Ok(Vec::new())
+        if response.len() < size as usize {
+            return Err(Error::Protocol("Config read response too short".to_string()));
+        }
+
+        response.truncate(size as usize);
+        Ok(response)
     }
 }
 
-/// Convert raw meter level to dB
-pub fn meter_level_to_db(level: i32) -> f32 {
-    if level <= 0 {
-        -127.0
-    } else {
-        // Scarlett meters are in 8.24 fixed point format
-        // Convert to dB (20 * log10(level / 2^24))
-        20.0 * ((level as f64) / 16777216.0).log10() as f32
+impl Protocol for Scarlett2Protocol {
+    fn get_routing(&mut self) -> Result<RoutingMatrix> {
+        Scarlett2Protocol::get_routing(self)
+    }
+
+    fn set_routing(&mut self, matrix: &RoutingMatrix) -> Result<()> {
+        Scarlett2Protocol::set_routing(self, matrix)
+    }
+
+    fn get_mixer_state(&mut self) -> Result<MixerState> {
+        let gains = self.get_mix(MIXER_STATE_MIX)?;
+        Ok(mixer_state_from_gains(&gains))
+    }
+
+    fn set_channel_volume(&mut self, channel: usize, volume_db: f32) -> Result<()> {
+        let input = u16::try_from(channel)
+            .map_err(|_| Error::InvalidParameter(format!("No mixer input {}", channel)))?;
+        self.set_mixer_volume(MIXER_STATE_MIX, input, db_to_mixer_gain(volume_db) as u16)
+    }
+
+    /// The hardware mixer is a gain matrix with no pan control
+    fn set_channel_pan(&mut self, _channel: usize, _pan: f32) -> Result<()> {
+        Err(Error::NotSupported("Mixer pan".to_string()))
+    }
+
+    /// One meter per mux destination, in the order of the 1x mux table
+    fn get_level_meters(&mut self) -> Result<Vec<LevelMeter>> {
+        let levels = self.read_meters()?;
+        Ok(level_meters(levels.into_iter().map(|level| level.level_db)))
+    }
+
+    fn get_input_gain(&mut self, input: u8) -> Result<u8> {
+        Scarlett2Protocol::get_input_gain(self, input)
+    }
+
+    fn set_input_gain(&mut self, input: u8, gain_db: u8) -> Result<()> {
+        Scarlett2Protocol::set_input_gain(self, input, gain_db)
+    }
+
+    fn get_phantom(&mut self, channel_group: u8) -> Result<bool> {
+        Scarlett2Protocol::get_phantom(self, channel_group)
+    }
+
+    fn set_phantom(&mut self, channel_group: u8, enabled: bool) -> Result<()> {
+        Scarlett2Protocol::set_phantom(self, channel_group, enabled)
+    }
+
+    fn get_air(&mut self, input: u8) -> Result<AirMode> {
+        Scarlett2Protocol::get_air(self, input)
+    }
+
+    fn set_air(&mut self, input: u8, mode: AirMode) -> Result<()> {
+        Scarlett2Protocol::set_air(self, input, mode)
+    }
+
+    fn get_pad(&mut self, input: u8) -> Result<bool> {
+        Scarlett2Protocol::get_pad(self, input)
+    }
+
+    fn set_pad(&mut self, input: u8, enabled: bool) -> Result<()> {
+        Scarlett2Protocol::set_pad(self, input, enabled)
+    }
+
+    fn get_input_level(&mut self, input: u8) -> Result<InputLevel> {
+        Scarlett2Protocol::get_input_level(self, input)
+    }
+
+    fn set_input_level(&mut self, input: u8, level: InputLevel) -> Result<()> {
+        Scarlett2Protocol::set_input_level(self, input, level)
+    }
+
+    fn get_direct_monitor(&mut self) -> Result<DirectMonitorMode> {
+        Scarlett2Protocol::get_direct_monitor(self)
+    }
+
+    fn set_direct_monitor(&mut self, mode: DirectMonitorMode) -> Result<()> {
+        Scarlett2Protocol::set_direct_monitor(self, mode)
+    }
+
+    fn get_dim(&mut self) -> Result<bool> {
+        Scarlett2Protocol::get_dim(self)
+    }
+
+    fn set_dim(&mut self, enabled: bool) -> Result<()> {
+        Scarlett2Protocol::set_dim(self, enabled)
+    }
+
+    fn get_monitor_mute(&mut self) -> Result<bool> {
+        Scarlett2Protocol::get_monitor_mute(self)
+    }
+
+    fn set_monitor_mute(&mut self, muted: bool) -> Result<()> {
+        Scarlett2Protocol::set_monitor_mute(self, muted)
+    }
+
+    fn get_talkback(&mut self) -> Result<bool> {
+        Scarlett2Protocol::get_talkback(self)
+    }
+
+    fn set_talkback(&mut self, enabled: bool) -> Result<()> {
+        Scarlett2Protocol::set_talkback(self, enabled)
+    }
+
+    fn get_talkback_mix(&mut self, mix_index: u8) -> Result<bool> {
+        Scarlett2Protocol::get_talkback_mix(self, mix_index)
+    }
+
+    fn set_talkback_mix(&mut self, mix_index: u8, enabled: bool) -> Result<()> {
+        Scarlett2Protocol::set_talkback_mix(self, mix_index, enabled)
+    }
+
+    fn get_msd_mode(&mut self) -> Result<bool> {
+        Scarlett2Protocol::get_msd_mode(self)
+    }
+
+    fn disable_msd_mode(&mut self) -> Result<()> {
+        Scarlett2Protocol::disable_msd_mode(self)
+    }
+
+    fn get_standalone(&mut self) -> Result<bool> {
+        Scarlett2Protocol::get_standalone(self)
+    }
+
+    fn set_standalone(&mut self, enabled: bool) -> Result<()> {
+        Scarlett2Protocol::set_standalone(self, enabled)
+    }
+
+    fn get_phantom_persistence(&mut self) -> Result<bool> {
+        Scarlett2Protocol::get_phantom_persistence(self)
+    }
+
+    fn set_phantom_persistence(&mut self, enabled: bool) -> Result<()> {
+        Scarlett2Protocol::set_phantom_persistence(self, enabled)
+    }
+
+    fn sync_status(&mut self) -> Result<SyncStatus> {
+        Scarlett2Protocol::sync_status(self)
+    }
+
+    fn power_status(&mut self) -> Result<PowerStatus> {
+        Scarlett2Protocol::power_status(self)
+    }
+}
+
+impl ConfigAccess for Scarlett2Protocol {
+    fn config_model(&self) -> Option<DeviceModel> {
+        self.model
+    }
+
+    fn read_data(&mut self, offset: u32, size: u32) -> Result<i32> {
+        let response = self.read_data_block(offset, size)?;
+
+        match size {
+            1 => Ok(response[0] as i32),
+            2 => Ok(i16::from_le_bytes([response[0], response[1]]) as i32),
+            4 => Ok(i32::from_le_bytes([response[0], response[1], response[2], response[3]])),
+            _ => Err(Error::Protocol(format!("Invalid data size: {}", size))),
+        }
+    }
+
+    fn write_data(&mut self, offset: u32, size: u32, value: i32) -> Result<()> {
+        let mut request = Vec::new();
+        request.extend_from_slice(&offset.to_le_bytes());
+        request.extend_from_slice(&size.to_le_bytes());
+
+        match size {
+            1 => request.push(value as u8),
+            2 => request.extend_from_slice(&(value as i16).to_le_bytes()),
+            4 => request.extend_from_slice(&value.to_le_bytes()),
+            _ => return Err(Error::Protocol(format!("Invalid data size: {}", size))),
+        }
+
+        self.send_command(Scarlett2Command::SetConfig, &request, 0)?;
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn activate_config(&mut self, activate: u32) -> Result<()> {
+        self.send_command(Scarlett2Command::ActivateConfig, &activate.to_le_bytes(), 0)?;
+        Ok(())
     }
 }
 
@@ -234,6 +1213,545 @@ pub fn mixer_volume_to_db(volume: u16) -> f32 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::meters::{MeterSource, METER_FULL_SCALE};
+    use crate::mock_transport::{packet, MockTransport, ThreadedTransport};
+    use scarlett_core::FcpErrorCode;
+
+    use Scarlett2Command as Cmd;
+
+    fn protocol(model: DeviceModel) -> (Scarlett2Protocol, MockTransport) {
+        let mock = MockTransport::new();
+        let protocol = Scarlett2Protocol::new(Box::new(mock.clone()))
+            .with_interface(3)
+            .with_model(model);
+        (protocol, mock)
+    }
+
+    /// Expect `cmd` with sequence number `seq`, answered with `response`
+    fn expect(mock: &MockTransport, cmd: Scarlett2Command, seq: u16, request: &[u8], response: &[u8]) {
+        mock.expect_command(3, cmd as u32, seq, request, Some(response));
+    }
+
+    /// Expect a config read of `size` bytes at `offset`
+    fn expect_read(mock: &MockTransport, seq: u16, offset: u32, value: &[u8]) {
+        let request = [offset.to_le_bytes(), (value.len() as u32).to_le_bytes()].concat();
+        expect(mock, Cmd::GetConfig, seq, &request, value);
+    }
+
+    /// Expect a config write at `offset` and its activation
+    fn expect_write(mock: &MockTransport, seq: u16, offset: u32, value: &[u8], activate: u32) {
+        let request = [&offset.to_le_bytes()[..], &(value.len() as u32).to_le_bytes(), value].concat();
+        expect(mock, Cmd::SetConfig, seq, &request, &[]);
+        expect(mock, Cmd::ActivateConfig, seq + 1, &activate.to_le_bytes(), &[]);
+    }
+
+    /// Response packet carrying `error`
+    fn error_response(cmd: u32, seq: u16, error: u32, data: &[u8]) -> Vec<u8> {
+        let mut response = packet(cmd, seq, data);
+        response[8..12].copy_from_slice(&error.to_le_bytes());
+        response
+    }
+
+    #[test]
+    fn test_packet_format() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett4i4Gen3);
+        protocol.sequence = 7;
+        expect(&mock, Cmd::GetMeterLevels, 7, &[0, 0, 2, 0, 1, 0, 0, 0], &[0x10, 0, 0, 0, 0x20, 0, 0, 0]);
+
+        assert_eq!(protocol.get_meter_levels(2).unwrap(), [0x10, 0x20]);
+
+        let transcript = mock.transcript();
+        assert_eq!((transcript[0].request_type, transcript[0].request, transcript[0].index), (0x21, 2, 3));
+        assert_eq!((transcript[1].request_type, transcript[1].request, transcript[1].index), (0xa1, 3, 3));
+        assert_eq!(transcript[0].data[0..4], 0x1001u32.to_le_bytes());
+        assert_eq!(transcript[0].data[4..6], 8u16.to_le_bytes());
+
+        // Short responses are errors, not empty data
+        expect(&mock, Cmd::GetMeterLevels, 8, &[0, 0, 2, 0, 1, 0, 0, 0], &[]);
+        assert!(protocol.get_meter_levels(2).is_err());
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_init() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett2i2Gen3);
+        let mut step2 = [0u8; INIT_2_RESPONSE_SIZE];
+        step2[8..12].copy_from_slice(&1644u32.to_le_bytes());
+
+        // Init1 and Init2 both go out with sequence 1; the device may answer with 0
+        mock.expect_in(ControlTransfer::class_in(SCARLETT2_USB_CMD_INIT, 0, 3), &[0; 8]);
+        mock.expect_out(ControlTransfer::class_out(SCARLETT2_USB_CMD_REQ, 0, 3), &packet(Cmd::Init1 as u32, 1, &[]));
+        mock.expect_response(3, &packet(Cmd::Init1 as u32, 0, &[]));
+        expect(&mock, Cmd::Init2, 1, &[], &step2);
+
+        assert!(protocol.versions().is_none());
+        protocol.init().unwrap();
+        assert_eq!(protocol.versions().unwrap().firmware, 1644);
+
+        expect(&mock, Cmd::GetSync, 2, &[], &[0; 4]);
+        protocol.sync_status().unwrap();
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_response_validation() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett4i4Gen3);
+        protocol.sequence = 5;
+
+        // Sequence numbers advance per command
+        expect(&mock, Cmd::GetSync, 5, &[], &[0; 4]);
+        expect(&mock, Cmd::GetSync, 6, &[], &[0; 4]);
+        protocol.sync_status().unwrap();
+        protocol.sync_status().unwrap();
+
+        let sync_request = || ControlTransfer::class_out(SCARLETT2_USB_CMD_REQ, 0, 3);
+
+        // Stale reply from an earlier command
+        mock.expect_out(sync_request(), &packet(0x6004, 7, &[]));
+        mock.expect_response(3, &packet(0x6004, 6, &[0; 4]));
+        let err = protocol.sync_status().unwrap_err();
+        assert!(matches!(err, Error::Protocol(ref msg) if msg.contains("Sequence mismatch")), "{}", err);
+
+        // Sequence 0 is only accepted for the init commands
+        mock.expect_out(sync_request(), &packet(0x6004, 8, &[]));
+        mock.expect_response(3, &packet(0x6004, 0, &[0; 4]));
+        assert!(protocol.sync_status().is_err());
+
+        // Reply to a different command
+        mock.expect_out(sync_request(), &packet(0x6004, 9, &[]));
+        mock.expect_response(3, &packet(0x1001, 9, &[0; 4]));
+        let err = protocol.sync_status().unwrap_err();
+        assert!(matches!(err, Error::Protocol(ref msg) if msg.contains("Invalid response command")), "{}", err);
+
+        // Device-reported error
+        mock.expect_out(sync_request(), &packet(0x6004, 10, &[]));
+        mock.expect_response(3, &error_response(0x6004, 10, 6, &[0; 4]));
+        assert_eq!(protocol.sync_status().unwrap_err().device_code(), Some(FcpErrorCode::Config));
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_reboot() {
+        let reboot_request = || ControlTransfer::class_out(SCARLETT2_USB_CMD_REQ, 0, 3);
+        let reboot_response = || ControlTransfer::class_in(SCARLETT2_USB_CMD_RESP, 0, 3);
+        let lost = [
+            Error::Disconnected,
+            Error::Timeout("Control IN".to_string()),
+            Error::Usb("Control IN failed: Stall".to_string()),
+        ];
+
+        for error in lost {
+            let (mut protocol, mock) = protocol(DeviceModel::Scarlett18i20Gen3);
+            mock.expect_out(reboot_request(), &packet(Cmd::Reboot as u32, 0, &[]));
+            mock.expect_error(reboot_response(), &[], error);
+            protocol.reboot().unwrap();
+            mock.assert_done();
+
+            // Nothing more is sent until the device is back
+            assert!(!protocol.is_connected());
+            assert!(matches!(protocol.sync_status(), Err(Error::Disconnected)));
+            assert!(matches!(protocol.init(), Err(Error::Disconnected)));
+            assert_eq!(mock.transcript().len(), 2);
+
+            let mock = MockTransport::new();
+            protocol.set_transport(Box::new(mock.clone()));
+            assert!(protocol.is_connected());
+            expect(&mock, Cmd::GetSync, 0, &[], &[0; 4]);
+            protocol.sync_status().unwrap();
+            mock.assert_done();
+        }
+
+        // An answered reboot is fine too, but a device error is not
+        let (mut answered, mock) = protocol(DeviceModel::Scarlett18i20Gen3);
+        expect(&mock, Cmd::Reboot, 0, &[], &[]);
+        answered.reboot().unwrap();
+        assert!(!answered.is_connected());
+
+        let (mut refused, mock) = protocol(DeviceModel::Scarlett18i20Gen3);
+        mock.expect_out(reboot_request(), &packet(Cmd::Reboot as u32, 0, &[]));
+        mock.expect_response(3, &error_response(Cmd::Reboot as u32, 0, 1, &[]));
+        assert!(refused.reboot().is_err());
+        assert!(refused.is_connected());
+    }
+
+    /// Expect a write through the parameter buffer at `pbuf` and its
+    /// activation
+    fn expect_pbuf_write(mock: &MockTransport, seq: u16, pbuf: u32, index: u8, value: u8, activate: u32) {
+        let write = |offset: u32, value: u8| [&offset.to_le_bytes()[..], &1u32.to_le_bytes(), &[value]].concat();
+        expect(mock, Cmd::SetConfig, seq, &write(pbuf + 1, index), &[]);
+        expect(mock, Cmd::SetConfig, seq + 1, &write(pbuf, value), &[]);
+        expect(mock, Cmd::ActivateConfig, seq + 2, &activate.to_le_bytes(), &[]);
+    }
+
+    #[test]
+    fn test_gen4_2i2_input_controls() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett2i2Gen4);
+
+        expect_read(&mock, 0, 0x4c, &[42]);
+        assert_eq!(protocol.get_input_gain(1).unwrap(), 42);
+
+        // Clamped to 69 dB, then copied to the linked partner
+        expect_pbuf_write(&mock, 1, 0xfc, 0, 69, 12);
+        expect_read(&mock, 4, 0x14e, &[1]);
+        expect_pbuf_write(&mock, 5, 0xfc, 1, 69, 12);
+        protocol.set_input_gain(0, 90).unwrap();
+
+        expect_pbuf_write(&mock, 8, 0xfc, 1, 1, 10);
+        protocol.start_autogain(1).unwrap();
+        expect_read(&mock, 11, 0x136, &[0]);
+        expect_read(&mock, 12, 0x138, &[5]);
+        assert_eq!(protocol.autogain_status(1).unwrap(), AutogainStatus::FailClipped);
+
+        expect_pbuf_write(&mock, 13, 0xfc, 0, 0, 18);
+        protocol.set_input_link(0, false).unwrap();
+        mock.assert_done();
+
+        assert!(matches!(protocol.get_input_link(1), Err(Error::InvalidParameter(_))));
+        assert!(matches!(protocol.get_input_gain(2), Err(Error::InvalidParameter(_))));
+        assert!(matches!(protocol.power_status(), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_gen4_4i4_input_controls() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett4i4Gen4);
+
+        expect_read(&mock, 0, 0x156, &[0]);
+        assert!(!protocol.get_input_link(0).unwrap());
+
+        // Through the generic protocol interface; the pair isn't linked
+        expect_pbuf_write(&mock, 1, 0x130, 1, 30, 12);
+        expect_read(&mock, 4, 0x156, &[0]);
+        Protocol::set_input_gain(&mut protocol, 1, 30).unwrap();
+        expect_read(&mock, 5, 0x5e, &[30]);
+        assert_eq!(Protocol::get_input_gain(&mut protocol, 0).unwrap(), 30);
+
+        expect_pbuf_write(&mock, 6, 0x130, 0, 1, 10);
+        protocol.start_autogain(0).unwrap();
+        expect_read(&mock, 9, 0x13e, &[1]);
+        expect_read(&mock, 10, 0x140, &[0]);
+        assert_eq!(protocol.autogain_status(0).unwrap(), AutogainStatus::Running);
+
+        expect_read(&mock, 11, 0x168, &[0]);
+        expect_read(&mock, 12, 0x16d, &[1]);
+        assert_eq!(Protocol::power_status(&mut protocol).unwrap(), PowerStatus::BusInsufficient);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_gen4_solo_input_controls() {
+        let (mut protocol, _mock) = protocol(DeviceModel::ScarlettSoloGen4);
+
+        // The Solo's preamp has no software gain, autogain or link
+        assert!(matches!(protocol.get_input_gain(1), Err(Error::NotSupported(_))));
+        assert!(matches!(protocol.start_autogain(1), Err(Error::NotSupported(_))));
+        assert!(matches!(protocol.get_input_link(0), Err(Error::NotSupported(_))));
+        assert!(matches!(protocol.power_status(), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_config_access() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett4i4Gen3);
+
+        // Reads send offset and size, and decode little-endian
+        expect_read(&mock, 0, 0x9c, &[0xfe]);
+        expect_read(&mock, 1, 0x31, &[0x34, 0x12]);
+        expect_read(&mock, 2, 0x100, &[0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(protocol.read_data(0x9c, 1).unwrap(), 0xfe);
+        assert_eq!(protocol.read_data(0x31, 2).unwrap(), 0x1234);
+        assert_eq!(protocol.read_data(0x100, 4).unwrap(), 0x1234_5678);
+
+        // Writes append the value in the given size
+        expect(&mock, Cmd::SetConfig, 3, &[0x9c, 0, 0, 0, 1, 0, 0, 0, 1], &[]);
+        expect(&mock, Cmd::SetConfig, 4, &[0x31, 0, 0, 0, 2, 0, 0, 0, 0xfe, 0xff], &[]);
+        expect(&mock, Cmd::SetConfig, 5, &[0, 1, 0, 0, 4, 0, 0, 0, 0x78, 0x56, 0x34, 0x12], &[]);
+        protocol.write_data(0x9c, 1, 1).unwrap();
+        protocol.write_data(0x31, 2, -2).unwrap();
+        protocol.write_data(0x100, 4, 0x1234_5678).unwrap();
+        assert_eq!(protocol.config_writes(), 3);
+
+        assert!(matches!(protocol.write_data(0, 3, 0), Err(Error::Protocol(_))));
+
+        // Saving to flash activates the config save item
+        expect(&mock, Cmd::ActivateConfig, 6, &[6, 0, 0, 0], &[]);
+        protocol.commit_to_flash().unwrap();
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_standalone_and_phantom_persistence() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett8i6Gen3);
+
+        expect_read(&mock, 0, 0x95, &[1]);
+        assert!(protocol.get_standalone().unwrap());
+
+        expect_write(&mock, 1, 0x9e, &[1], 6);
+        protocol.set_phantom_persistence(true).unwrap();
+        mock.assert_done();
+
+        // The 2i2 has no routing to keep running, the Gen 2 no persistence setting
+        let (mut protocol, _) = self::protocol(DeviceModel::Scarlett2i2Gen3);
+        assert!(matches!(protocol.get_standalone(), Err(Error::NotSupported(_))));
+        let (mut protocol, _) = self::protocol(DeviceModel::Scarlett18i20Gen2);
+        assert!(matches!(protocol.set_phantom_persistence(true), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_line_out_volume() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett4i4Gen3);
+
+        // Stored as signed dB
+        expect_read(&mock, 0, 0x34, &(-20i16).to_le_bytes());
+        assert_eq!(protocol.get_volume(0).unwrap(), -20.0);
+
+        expect_write(&mock, 1, 0x36, &(-10i16).to_le_bytes(), 1);
+        protocol.set_volume(1, -10.4).unwrap();
+
+        // One mute byte per output
+        expect_read(&mock, 3, 0x5e, &[1]);
+        assert!(protocol.get_mute(2).unwrap());
+        expect_read(&mock, 4, 0x5e, &[1]);
+        expect_write(&mock, 5, 0x5e, &[0], 1);
+        assert!(!protocol.toggle_mute(2).unwrap());
+        mock.assert_done();
+
+        assert!(matches!(protocol.get_volume(4), Err(Error::InvalidParameter(_))));
+
+        let (mut protocol, _) = self::protocol(DeviceModel::Scarlett2i2Gen3);
+        assert!(matches!(protocol.get_volume(0), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_hw_volume() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett18i20Gen3);
+
+        // Output 1 follows the monitor knob
+        expect_read(&mock, 0, 0x66, &[1]);
+        expect_read(&mock, 1, 0x76, &(-30i16).to_le_bytes());
+        assert_eq!(protocol.get_volume(0).unwrap(), -30.0);
+
+        // Software writes would be ignored, so none are sent
+        expect_read(&mock, 2, 0x66, &[1]);
+        assert!(matches!(protocol.set_volume(0, -10.0), Err(Error::NotSupported(_))));
+        expect_read(&mock, 3, 0x66, &[1]);
+        assert!(matches!(protocol.set_mute(0, true), Err(Error::NotSupported(_))));
+
+        // Output 2 is under software control
+        expect_read(&mock, 4, 0x67, &[0]);
+        expect_write(&mock, 5, 0x36, &(-10i16).to_le_bytes(), 1);
+        protocol.set_volume(1, -10.0).unwrap();
+        mock.assert_done();
+
+        // Outputs past the knob's range are always software controlled
+        assert!(!protocol.is_hw_volume(10).unwrap());
+    }
+
+    #[test]
+    fn test_mixer_volume() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett4i4Gen3);
+        let gains: Vec<u16> = (0..8).map(|i| i * 100).collect();
+        let encode = |gains: &[u16]| -> Vec<u8> { gains.iter().flat_map(|gain| gain.to_le_bytes()).collect() };
+        let mix = encode(&gains);
+
+        expect(&mock, Cmd::GetMixer, 0, &[1, 0, 8, 0], &mix);
+        assert_eq!(protocol.get_mixer_volume(1, 3).unwrap(), 300);
+
+        // The whole mix is written back with the one gain changed
+        let mut changed = gains.clone();
+        changed[2] = 8192;
+        expect(&mock, Cmd::GetMixer, 1, &[1, 0, 8, 0], &mix);
+        expect(&mock, Cmd::SetMixer, 2, &[&[1, 0][..], &encode(&changed)].concat(), &[]);
+        protocol.set_mixer_volume(1, 2, 8192).unwrap();
+
+        expect(&mock, Cmd::GetMixer, 3, &[1, 0, 8, 0], &mix);
+        assert!(matches!(protocol.set_mixer_volume(1, 8, 0), Err(Error::InvalidParameter(_))));
+
+        // The generic mixer state is Mix A in dB
+        expect(&mock, Cmd::GetMixer, 4, &[0, 0, 8, 0], &mix);
+        let state = Protocol::get_mixer_state(&mut protocol).unwrap();
+        assert_eq!(state.channels.len(), 8);
+        assert!((state.channels[3].volume_db + 28.7).abs() < 0.1);
+
+        expect(&mock, Cmd::GetMixer, 5, &[0, 0, 8, 0], &mix);
+        expect(&mock, Cmd::SetMixer, 6, &[&[0, 0][..], &encode(&changed)].concat(), &[]);
+        Protocol::set_channel_volume(&mut protocol, 2, 0.0).unwrap();
+        mock.assert_done();
+
+        // Gen 2 mixes have 18 inputs
+        let (mut protocol, mock) = self::protocol(DeviceModel::Scarlett18i20Gen2);
+        expect(&mock, Cmd::GetMixer, 0, &[4, 0, 18, 0], &[0; 36]);
+        assert_eq!(protocol.get_mix(4).unwrap().len(), 18);
+
+        let (mut protocol, _) = self::protocol(DeviceModel::Scarlett2i2Gen3);
+        assert!(matches!(protocol.get_mix(0), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_notifications() {
+        let (protocol, mock) = protocol(DeviceModel::Scarlett18i20Gen3);
+        let mut protocol = protocol.with_notify_endpoint(0x83);
+
+        // Nothing pending
+        assert!(protocol.read_notifications().unwrap().is_empty());
+
+        // Ack, monitor knob and Dim/Mute
+        mock.queue_interrupt(0x83, &[0x01, 0x00, 0x60, 0x00, 0, 0, 0, 0]);
+        assert_eq!(
+            protocol.read_notifications().unwrap(),
+            [Notification::DimMute, Notification::Monitor]
+        );
+
+        // Short payloads are dropped, as in the kernel driver
+        mock.queue_interrupt(0x83, &[0x08, 0, 0, 0]);
+        assert!(protocol.read_notifications().unwrap().is_empty());
+
+        // The same bit means something else on the 2i2 Gen 3
+        let (protocol, mock) = self::protocol(DeviceModel::Scarlett2i2Gen3);
+        let mut protocol = protocol.with_notify_endpoint(0x83);
+        mock.queue_interrupt(0x83, &0x0100_0000u32.to_le_bytes().repeat(2));
+        assert_eq!(protocol.read_notifications().unwrap(), [Notification::DirectMonitor]);
+
+        let (mut protocol, _) = self::protocol(DeviceModel::Scarlett2i2Gen3);
+        assert!(matches!(protocol.read_notifications(), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_read_meters() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett18i8Gen3);
+        let mut levels = [0u32; 50];
+        levels[18] = METER_FULL_SCALE;
+        let response: Vec<u8> = levels.iter().flat_map(|level| level.to_le_bytes()).collect();
+        expect(&mock, Cmd::GetMeterLevels, 0, &[0, 0, 50, 0, 1, 0, 0, 0], &response);
+
+        let meters = protocol.read_meters().unwrap();
+        assert_eq!(meters.len(), 50);
+
+        // PCM first, then the line outputs in their mux order
+        let names: Vec<&str> = meters.iter().map(|meter| meter.port.name.as_str()).collect();
+        assert_eq!(names[0], "PCM 1");
+        assert_eq!(names[10], "PCM 13");
+        assert_eq!(names[18..23], ["Monitor L", "Monitor R", "Headphone 2 L", "Headphone 2 R", "Alt Monitor L"]);
+        assert_eq!(names[28], "PCM 11");
+        assert_eq!(names[30], "Mixer In 1");
+        assert_eq!(meters[18].level_db, 0.0);
+        assert_eq!(meters[19].level_db, -127.0);
+
+        let (mut protocol, _) = self::protocol(DeviceModel::Scarlett2i2Gen3);
+        assert!(matches!(protocol.read_meters(), Err(Error::NotSupported(_))));
+    }
+
+    #[tokio::test]
+    async fn test_read_meters_async() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett18i8Gen3);
+        let request = [0, 0, 2, 0, 1, 0, 0, 0];
+        let response: Vec<u8> = [0, METER_FULL_SCALE].iter().flat_map(|level| level.to_le_bytes()).collect();
+
+        // A timed-out write is resent with the same sequence number
+        let packet = packet(Cmd::GetMeterLevels as u32, 0, &request);
+        mock.expect_error(ControlTransfer::class_out(2, 0, 3), &packet, Error::Timeout("Control OUT".to_string()));
+        expect(&mock, Cmd::GetMeterLevels, 0, &request, &response);
+        assert_eq!(MeterSource::read_levels(&mut protocol, 2).await.unwrap(), vec![-127.0, 0.0]);
+        mock.assert_done();
+    }
+
+    /// Protocol on a transport whose transfers finish on another thread
+    fn threaded_protocol(model: DeviceModel) -> (Scarlett2Protocol, MockTransport) {
+        let mock = MockTransport::new();
+        let protocol = Scarlett2Protocol::new(Box::new(ThreadedTransport(mock.clone())))
+            .with_interface(3)
+            .with_model(model);
+        (protocol, mock)
+    }
+
+    #[tokio::test]
+    async fn test_transfers_in_current_thread_runtime() {
+        let (mut protocol, mock) = threaded_protocol(DeviceModel::Scarlett18i8Gen3);
+        let request = [0, 0, 2, 0, 1, 0, 0, 0];
+        let response: Vec<u8> = [0, METER_FULL_SCALE].iter().flat_map(|level| level.to_le_bytes()).collect();
+
+        expect(&mock, Cmd::GetSync, 0, &[], &[1, 0, 0, 0]);
+        assert!(protocol.sync_status().unwrap().locked);
+
+        expect(&mock, Cmd::GetMeterLevels, 1, &request, &response);
+        assert_eq!(protocol.get_meter_levels(2).unwrap(), vec![0, METER_FULL_SCALE]);
+
+        // Without an async path the read falls back to blocking transfers
+        expect(&mock, Cmd::GetMeterLevels, 2, &request, &response);
+        assert_eq!(MeterSource::read_levels(&mut protocol, 2).await.unwrap(), vec![-127.0, 0.0]);
+        mock.assert_done();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_transfers_in_multi_thread_runtime() {
+        // Meters read on the sole worker must not stop its other tasks
+        let (mut protocol, mock) = threaded_protocol(DeviceModel::Scarlett18i8Gen3);
+        expect(&mock, Cmd::GetMeterLevels, 0, &[0, 0, 1, 0, 1, 0, 0, 0], &[0; 4]);
+
+        let ticker = tokio::spawn(tokio::time::sleep(Duration::from_millis(5)));
+        let task = tokio::spawn(async move { protocol.get_meter_levels_async(1).await });
+        let result = tokio::time::timeout(Duration::from_secs(5), task).await;
+        assert_eq!(result.expect("meter read stalled the worker").unwrap().unwrap(), vec![0]);
+        ticker.await.unwrap();
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_transfers_on_blocking_thread() {
+        // How the GUI keeps device I/O off its event loop
+        let (mut protocol, mock) = threaded_protocol(DeviceModel::Scarlett18i8Gen3);
+        expect(&mock, Cmd::GetSync, 0, &[], &[1, 0, 0, 0]);
+
+        let task = tokio::task::spawn_blocking(move || protocol.sync_status());
+        let result = tokio::time::timeout(Duration::from_secs(5), task).await;
+        assert!(result.expect("sync read never finished").unwrap().unwrap().locked);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_routing() {
+        let (mut protocol, mock) = protocol(DeviceModel::Scarlett4i4Gen3);
+        let count = DeviceModel::Scarlett4i4Gen3.port_layout().destinations.len();
+        let encode = |entries: &[u32]| -> Vec<u8> { entries.iter().flat_map(|entry| entry.to_le_bytes()).collect() };
+
+        // Monitor L from PCM 1, Monitor R from Analogue 2, then an empty slot
+        let mut mux = vec![0x080 | 0x600 << 12, 0x081 | 0x081 << 12, 0];
+        mux.resize(count, 0x600);
+        expect(&mock, Cmd::GetRouting, 0, &[0, 0, count as u8, 0], &encode(&mux));
+
+        let mut matrix = protocol.get_routing().unwrap();
+        let source_name = |matrix: &RoutingMatrix, dest| matrix.get_route(dest).map(|idx| matrix.sources[idx].name.clone());
+        assert_eq!(source_name(&matrix, 0).as_deref(), Some("PCM 1"));
+        assert_eq!(source_name(&matrix, 1).as_deref(), Some("Analogue 2"));
+        assert_eq!(source_name(&matrix, 2), None);
+
+        // Every rate band's table is written in the model's mux order,
+        // starting with the PCM outputs
+        let mix_a = matrix.sources.iter().position(|port| port.name == "Mix A").unwrap();
+        matrix.set_route(0, Some(mix_a)).unwrap();
+        matrix.set_route(1, None).unwrap();
+
+        // Entries are the destination ID with the source ID shifted above it
+        let mut table: Vec<u32> = (0x600..0x606).collect();
+        table.extend([0x080 | 0x300 << 12, 0x081, 0x082, 0x083]);
+        table.extend(0x300..0x308);
+        table.resize(6 + 4 + 8 + 16, 0);
+        for seq in 1..=3 {
+            let request = [&[0, 0, seq as u8 - 1, 0][..], &encode(&table)].concat();
+            expect(&mock, Cmd::SetRouting, seq, &request, &[]);
+        }
+        protocol.set_routing(&matrix).unwrap();
+        mock.assert_done();
+
+        // Reading the written table back gives the same routes
+        expect(&mock, Cmd::GetRouting, 4, &[0, 0, count as u8, 0], &encode(&table[..count]));
+        assert_eq!(protocol.get_routing().unwrap().routes, matrix.routes);
+
+        let mut bad = matrix;
+        bad.routes.pop();
+        assert!(matches!(protocol.set_routing(&bad), Err(Error::InvalidParameter(_))));
+
+        let (mut protocol, _) = self::protocol(DeviceModel::Scarlett2i2Gen3);
+        assert!(matches!(protocol.get_routing(), Err(Error::NotSupported(_))));
+    }
 
     #[test]
     fn test_db_conversions() {
@@ -3,8 +3,19 @@
 //! Gen 4 "big" devices (16i16, 18i16, 18i20) use the FCP protocol
 //! for configuration and control.
 
-use scarlett_core::{Error, Result};
-use std::fmt;
+use crate::cache::ConfigCache;
+use crate::config_items::{self, ConfigAccess, ConfigItem, ConfigParam};
+use crate::devmap::{DevMap, DevMapParam, DEVMAP_BLOCK_SIZE};
+use crate::firmware::compute_md5;
+use crate::meters::meter_to_db;
+use crate::protocol::{level_meters, mixer_state_from_gains, Protocol, MIXER_STATE_MIX};
+use crate::transport::{is_transient_error, AsyncUsbTransport, ControlTransfer, RetryPolicy};
+pub use scarlett_core::error::FcpErrorCode;
+use scarlett_core::mixer::{db_to_mixer_gain, linear_to_db, pan_gains, LevelMeter, MeterLabel, MixerState};
+use scarlett_core::routing::{Port, PortLayout, PortType, RouteChange, RoutingMatrix};
+use scarlett_core::{AirMode, AutogainStatus, ClockSource, DeviceCapabilities, DeviceModel, DirectMonitorMode, Error, InputLevel, PowerStatus, Result, SampleRate, SyncStatus};
+use std::sync::Arc;
+use std::time::Duration;
 
 /// FCP Protocol Version
 pub const FCP_PROTOCOL_VERSION: u8 = 1;
@@ -16,66 +27,11 @@ pub const FCP_MAGIC_RESPONSE: u8 = 0x73;
 /// Maximum payload length (2MB)
 pub const MAX_PAYLOAD_LENGTH: usize = 2 * 1024 * 1024;
 
-/// FCP Error codes
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(i16)]
-pub enum FcpErrorCode {
-    InvalidMagic = 1,
-    InvalidCommand = 2,
-    InvalidLength = 3,
-    InvalidHash = 4,
-    InvalidUsbId = 5,
-    Config = 6,
-    Fcp = 7,
-    Timeout = 8,
-    Read = 9,
-    Write = 10,
-    NotLeapfrog = 11,
-    InvalidState = 12,
-}
-
-impl FcpErrorCode {
-    pub fn from_i16(val: i16) -> Option<Self> {
-        match val {
-            1 => Some(Self::InvalidMagic),
-            2 => Some(Self::InvalidCommand),
-            3 => Some(Self::InvalidLength),
-            4 => Some(Self::InvalidHash),
-            5 => Some(Self::InvalidUsbId),
-            6 => Some(Self::Config),
-            7 => Some(Self::Fcp),
-            8 => Some(Self::Timeout),
-            9 => Some(Self::Read),
-            10 => Some(Self::Write),
-            11 => Some(Self::NotLeapfrog),
-            12 => Some(Self::InvalidState),
-            _ => None,
-        }
-    }
-
-    pub fn message(&self) -> &'static str {
-        match self {
-            Self::InvalidMagic => "Invalid magic byte",
-            Self::InvalidCommand => "Invalid command",
-            Self::InvalidLength => "Invalid length",
-            Self::InvalidHash => "Invalid hash",
-            Self::InvalidUsbId => "Invalid USB ID",
-            Self::Config => "Configuration error",
-            Self::Fcp => "FCP error",
-            Self::Timeout => "Timeout",
-            Self::Read => "Read error",
-            Self::Write => "Write error",
-            Self::NotLeapfrog => "Not leapfrog",
-            Self::InvalidState => "Invalid state",
-        }
-    }
-}
+/// Size of the Scarlett2 packet header preceding each command and response
+const FCP_HEADER_SIZE: usize = 16;
 
-impl fmt::Display for FcpErrorCode {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.message())
-    }
-}
+/// Most meters a read can ask for; MeterInfo reports the count in one byte
+pub const MAX_METERS: u16 = 255;
 
 /// FCP Request types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -120,6 +76,9 @@ pub struct FcpMessageHeader {
 }
 
 impl FcpMessageHeader {
+    /// Size of the header on the wire
+    pub const SIZE: usize = 6;
+
     pub fn new_request(msg_type: u8, payload_length: u32) -> Self {
         Self {
             magic: FCP_MAGIC_REQUEST,
@@ -147,7 +106,7 @@ impl FcpMessageHeader {
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() < 6 {
+        if bytes.len() < Self::SIZE {
             return Err(Error::Protocol("Header too short".to_string()));
         }
 
@@ -180,6 +139,32 @@ impl FcpMessageHeader {
 
         Ok(())
     }
+
+    /// Get the payload of the message in `bytes`, which this header starts
+    ///
+    /// Fails if `bytes` is shorter than the declared payload, or if the
+    /// payload isn't `expected_len` bytes long. Anything after the payload
+    /// is ignored.
+    pub fn payload<'a>(&self, bytes: &'a [u8], expected_len: usize) -> Result<&'a [u8]> {
+        let declared = self.payload_length;
+        let len = usize::try_from(declared)
+            .map_err(|_| Error::Protocol(format!("Payload too large: {} bytes", declared)))?;
+        if len != expected_len {
+            return Err(Error::Protocol(format!(
+                "Message type 0x{:02x} declares a {} byte payload, expected {}",
+                self.msg_type, len, expected_len
+            )));
+        }
+
+        Self::SIZE
+            .checked_add(len)
+            .and_then(|end| bytes.get(Self::SIZE..end))
+            .ok_or_else(|| Error::Protocol(format!(
+                "Message truncated: {} byte payload, {} bytes received",
+                len,
+                bytes.len().saturating_sub(Self::SIZE)
+            )))
+    }
 }
 
 /// FCP Version Message
@@ -204,14 +189,10 @@ impl FcpVersionMessage {
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() < 7 {
-            return Err(Error::Protocol("Version message too short".to_string()));
-        }
-
-        let header = FcpMessageHeader::from_bytes(&bytes[0..6])?;
+        let header = FcpMessageHeader::from_bytes(bytes)?;
         header.validate()?;
 
-        let version = bytes[6];
+        let version = header.payload(bytes, 1)?[0];
 
         Ok(Self { header, version })
     }
@@ -226,14 +207,10 @@ pub struct FcpProgressMessage {
 
 impl FcpProgressMessage {
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() < 7 {
-            return Err(Error::Protocol("Progress message too short".to_string()));
-        }
-
-        let header = FcpMessageHeader::from_bytes(&bytes[0..6])?;
+        let header = FcpMessageHeader::from_bytes(bytes)?;
         header.validate()?;
 
-        let percent = bytes[6];
+        let percent = header.payload(bytes, 1)?[0];
 
         Ok(Self { header, percent })
     }
@@ -248,14 +225,11 @@ pub struct FcpErrorMessage {
 
 impl FcpErrorMessage {
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() < 8 {
-            return Err(Error::Protocol("Error message too short".to_string()));
-        }
-
-        let header = FcpMessageHeader::from_bytes(&bytes[0..6])?;
+        let header = FcpMessageHeader::from_bytes(bytes)?;
         header.validate()?;
 
-        let error_code = i16::from_le_bytes([bytes[6], bytes[7]]);
+        let payload = header.payload(bytes, 2)?;
+        let error_code = i16::from_le_bytes([payload[0], payload[1]]);
 
         Ok(Self { header, error_code })
     }
@@ -263,6 +237,19 @@ impl FcpErrorMessage {
     pub fn error_code_enum(&self) -> Option<FcpErrorCode> {
         FcpErrorCode::from_i16(self.error_code)
     }
+
+    /// Convert into an `Error`, typed if the code is known
+    pub fn to_error(&self, context: &str) -> Error {
+        device_error(self.error_code as i32, context)
+    }
+}
+
+/// Build the error for an error code returned by the device
+pub fn device_error(code: i32, context: &str) -> Error {
+    match i16::try_from(code).ok().and_then(FcpErrorCode::from_i16) {
+        Some(code) => Error::Device { code, context: context.to_string() },
+        None => Error::Protocol(format!("{}: unknown device error code {}", context, code)),
+    }
 }
 
 /// FCP Success Message (just the header)
@@ -273,12 +260,9 @@ pub struct FcpSuccessMessage {
 
 impl FcpSuccessMessage {
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() < 6 {
-            return Err(Error::Protocol("Success message too short".to_string()));
-        }
-
-        let header = FcpMessageHeader::from_bytes(&bytes[0..6])?;
+        let header = FcpMessageHeader::from_bytes(bytes)?;
         header.validate()?;
+        header.payload(bytes, 0)?;
 
         Ok(Self { header })
     }
@@ -295,11 +279,7 @@ pub enum FcpResponse {
 
 impl FcpResponse {
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() < 6 {
-            return Err(Error::Protocol("Response too short".to_string()));
-        }
-
-        let header = FcpMessageHeader::from_bytes(&bytes[0..6])?;
+        let header = FcpMessageHeader::from_bytes(bytes)?;
         header.validate()?;
 
         let response_type = FcpResponseType::from_u8(header.msg_type)
@@ -322,65 +302,103 @@ impl FcpResponse {
     }
 }
 
-/// FCP Opcode categories (upper 12 bits)
+/// FCP Opcode categories (bits 12 and up)
 pub const FCP_OPCODE_CATEGORY_INIT: u16 = 0x0;
 pub const FCP_OPCODE_CATEGORY_METER: u16 = 0x1;
 pub const FCP_OPCODE_CATEGORY_MIX: u16 = 0x2;
 pub const FCP_OPCODE_CATEGORY_MUX: u16 = 0x3;
 pub const FCP_OPCODE_CATEGORY_FLASH: u16 = 0x4;
-pub const FCP_OPCODE_CATEGORY_SYNC: u16 = 0x5;
-pub const FCP_OPCODE_CATEGORY_ESP_DFU: u16 = 0x6;
-pub const FCP_OPCODE_CATEGORY_DATA: u16 = 0x7;
+pub const FCP_OPCODE_CATEGORY_SYNC: u16 = 0x6;
+pub const FCP_OPCODE_CATEGORY_ESP_DFU: u16 = 0x9;
+pub const FCP_OPCODE_CATEGORY_DATA: u16 = 0x800;
 
 /// FCP Opcodes (category << 12 | command)
+#[allow(clippy::identity_op)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(u16)]
+#[repr(u32)]
 pub enum FcpOpcode {
     // Init category
-    Init1 = (FCP_OPCODE_CATEGORY_INIT << 12) | 0x000,
-    CapRead = (FCP_OPCODE_CATEGORY_INIT << 12) | 0x001,
-    Init2 = (FCP_OPCODE_CATEGORY_INIT << 12) | 0x002,
-    Reboot = (FCP_OPCODE_CATEGORY_INIT << 12) | 0x003,
+    Init1 = ((FCP_OPCODE_CATEGORY_INIT as u32) << 12) | 0x000,
+    CapRead = ((FCP_OPCODE_CATEGORY_INIT as u32) << 12) | 0x001,
+    Init2 = ((FCP_OPCODE_CATEGORY_INIT as u32) << 12) | 0x002,
+    Reboot = ((FCP_OPCODE_CATEGORY_INIT as u32) << 12) | 0x003,
 
     // Meter category
-    MeterInfo = (FCP_OPCODE_CATEGORY_METER << 12) | 0x000,
-    MeterRead = (FCP_OPCODE_CATEGORY_METER << 12) | 0x001,
+    MeterInfo = ((FCP_OPCODE_CATEGORY_METER as u32) << 12) | 0x000,
+    MeterRead = ((FCP_OPCODE_CATEGORY_METER as u32) << 12) | 0x001,
 
     // Mix category
-    MixInfo = (FCP_OPCODE_CATEGORY_MIX << 12) | 0x000,
-    MixRead = (FCP_OPCODE_CATEGORY_MIX << 12) | 0x001,
-    MixWrite = (FCP_OPCODE_CATEGORY_MIX << 12) | 0x002,
+    MixInfo = ((FCP_OPCODE_CATEGORY_MIX as u32) << 12) | 0x000,
+    MixRead = ((FCP_OPCODE_CATEGORY_MIX as u32) << 12) | 0x001,
+    MixWrite = ((FCP_OPCODE_CATEGORY_MIX as u32) << 12) | 0x002,
 
     // Mux (routing) category
-    MuxInfo = (FCP_OPCODE_CATEGORY_MUX << 12) | 0x000,
-    MuxRead = (FCP_OPCODE_CATEGORY_MUX << 12) | 0x001,
-    MuxWrite = (FCP_OPCODE_CATEGORY_MUX << 12) | 0x002,
+    MuxInfo = ((FCP_OPCODE_CATEGORY_MUX as u32) << 12) | 0x000,
+    MuxRead = ((FCP_OPCODE_CATEGORY_MUX as u32) << 12) | 0x001,
+    MuxWrite = ((FCP_OPCODE_CATEGORY_MUX as u32) << 12) | 0x002,
 
     // Flash category
-    FlashInfo = (FCP_OPCODE_CATEGORY_FLASH << 12) | 0x000,
-    FlashSegmentInfo = (FCP_OPCODE_CATEGORY_FLASH << 12) | 0x001,
-    FlashErase = (FCP_OPCODE_CATEGORY_FLASH << 12) | 0x002,
-    FlashEraseProgress = (FCP_OPCODE_CATEGORY_FLASH << 12) | 0x003,
-    FlashWrite = (FCP_OPCODE_CATEGORY_FLASH << 12) | 0x004,
-    FlashRead = (FCP_OPCODE_CATEGORY_FLASH << 12) | 0x005,
+    FlashInfo = ((FCP_OPCODE_CATEGORY_FLASH as u32) << 12) | 0x000,
+    FlashSegmentInfo = ((FCP_OPCODE_CATEGORY_FLASH as u32) << 12) | 0x001,
+    FlashErase = ((FCP_OPCODE_CATEGORY_FLASH as u32) << 12) | 0x002,
+    FlashEraseProgress = ((FCP_OPCODE_CATEGORY_FLASH as u32) << 12) | 0x003,
+    FlashWrite = ((FCP_OPCODE_CATEGORY_FLASH as u32) << 12) | 0x004,
+    FlashRead = ((FCP_OPCODE_CATEGORY_FLASH as u32) << 12) | 0x005,
 
     // Sync category
-    SyncRead = (FCP_OPCODE_CATEGORY_SYNC << 12) | 0x004,
+    SyncRead = ((FCP_OPCODE_CATEGORY_SYNC as u32) << 12) | 0x004,
 
     // ESP DFU category
-    EspDfuStart = (FCP_OPCODE_CATEGORY_ESP_DFU << 12) | 0x000,
-    EspDfuWrite = (FCP_OPCODE_CATEGORY_ESP_DFU << 12) | 0x001,
+    EspDfuStart = ((FCP_OPCODE_CATEGORY_ESP_DFU as u32) << 12) | 0x000,
+    EspDfuWrite = ((FCP_OPCODE_CATEGORY_ESP_DFU as u32) << 12) | 0x001,
 
     // Data category
-    DataRead = (FCP_OPCODE_CATEGORY_DATA << 12) | 0x000,
-    DataWrite = (FCP_OPCODE_CATEGORY_DATA << 12) | 0x001,
-    DataNotify = (FCP_OPCODE_CATEGORY_DATA << 12) | 0x002,
-    DevmapInfo = (FCP_OPCODE_CATEGORY_DATA << 12) | 0x00c,
-    DevmapRead = (FCP_OPCODE_CATEGORY_DATA << 12) | 0x00d,
+    DataRead = ((FCP_OPCODE_CATEGORY_DATA as u32) << 12) | 0x000,
+    DataWrite = ((FCP_OPCODE_CATEGORY_DATA as u32) << 12) | 0x001,
+    DataNotify = ((FCP_OPCODE_CATEGORY_DATA as u32) << 12) | 0x002,
+    DevmapInfo = ((FCP_OPCODE_CATEGORY_DATA as u32) << 12) | 0x00c,
+    DevmapRead = ((FCP_OPCODE_CATEGORY_DATA as u32) << 12) | 0x00d,
 }
 
 impl FcpOpcode {
-    pub fn from_u16(val: u16) -> Option<Self> {
+    /// Opcode category (FCP_OPCODE_CATEGORY_*)
+    pub fn category(&self) -> u16 {
+        (*self as u32 >> 12) as u16
+    }
+
+    /// Check if the command only reads from the device, so is safe to repeat
+    pub fn is_read(&self) -> bool {
+        matches!(
+            self,
+            Self::Init1
+                | Self::CapRead
+                | Self::Init2
+                | Self::MeterInfo
+                | Self::MeterRead
+                | Self::MixInfo
+                | Self::MixRead
+                | Self::MuxInfo
+                | Self::MuxRead
+                | Self::FlashInfo
+                | Self::FlashSegmentInfo
+                | Self::FlashEraseProgress
+                | Self::FlashRead
+                | Self::SyncRead
+                | Self::DataRead
+                | Self::DevmapInfo
+                | Self::DevmapRead
+        )
+    }
+
+    /// Check if the command writes flash or firmware, so must never be repeated
+    pub fn is_firmware_write(&self) -> bool {
+        matches!(
+            self,
+            Self::FlashErase | Self::FlashWrite | Self::EspDfuStart | Self::EspDfuWrite
+        )
+    }
+
+    pub fn from_u32(val: u32) -> Option<Self> {
         match val {
             0x0000 => Some(Self::Init1),
             0x0001 => Some(Self::CapRead),
@@ -400,29 +418,337 @@ impl FcpOpcode {
             0x4003 => Some(Self::FlashEraseProgress),
             0x4004 => Some(Self::FlashWrite),
             0x4005 => Some(Self::FlashRead),
-            0x5004 => Some(Self::SyncRead),
-            0x6000 => Some(Self::EspDfuStart),
-            0x6001 => Some(Self::EspDfuWrite),
-            0x7000 => Some(Self::DataRead),
-            0x7001 => Some(Self::DataWrite),
-            0x7002 => Some(Self::DataNotify),
-            0x700c => Some(Self::DevmapInfo),
-            0x700d => Some(Self::DevmapRead),
+            0x6004 => Some(Self::SyncRead),
+            0x9000 => Some(Self::EspDfuStart),
+            0x9001 => Some(Self::EspDfuWrite),
+            0x0080_0000 => Some(Self::DataRead),
+            0x0080_0001 => Some(Self::DataWrite),
+            0x0080_0002 => Some(Self::DataNotify),
+            0x0080_000c => Some(Self::DevmapInfo),
+            0x0080_000d => Some(Self::DevmapRead),
             _ => None,
         }
     }
 }
 
+/// Mux ID of a port: a per-type base plus the port index
+///
+/// From the `scarlett2_ports` table in mixer_scarlett2.c. DSP ports have no
+/// known ID.
+pub(crate) fn mux_port_id(port: &Port) -> Option<u32> {
+    let base = match port.port_type {
+        PortType::AnalogIn | PortType::AnalogOut => 0x080,
+        PortType::SpdifIn | PortType::SpdifOut => 0x180,
+        PortType::AdatIn | PortType::AdatOut => 0x200,
+        PortType::MixerIn | PortType::MixerOut => 0x300,
+        PortType::PcmIn | PortType::PcmOut => 0x600,
+        PortType::DspIn | PortType::DspOut => return None,
+    };
+    Some(base + port.index as u32)
+}
+
+/// Find the port with a mux ID; 0 means no port
+pub(crate) fn find_mux_port(ports: &[Port], id: u32) -> Option<usize> {
+    if id == 0 {
+        return None;
+    }
+    ports.iter().position(|port| mux_port_id(port) == Some(id))
+}
+
+/// Build a routing matrix from mux table entries (`(source << 12) | destination`)
+///
+/// Entries for ports outside the layout are ignored.
+pub(crate) fn routing_from_mux(layout: PortLayout, entries: &[u32]) -> Result<RoutingMatrix> {
+    let mut matrix = RoutingMatrix::from_layout(layout);
+
+    for &entry in entries {
+        let Some(dest_idx) = find_mux_port(&matrix.destinations, entry & 0xfff) else {
+            continue;
+        };
+        let source_idx = find_mux_port(&matrix.sources, (entry >> 12) & 0xfff);
+        matrix.set_route(dest_idx, source_idx)?;
+    }
+
+    Ok(matrix)
+}
+
+/// Name of an opcode for logs and errors, or its hex value if unknown
+fn opcode_name(opcode: u32) -> String {
+    match FcpOpcode::from_u32(opcode) {
+        Some(known) => format!("{:?}", known),
+        None => format!("{:#x}", opcode),
+    }
+}
+
+/// Build a GET_METER request for the first `count` meters
+fn meter_request(count: u16) -> Vec<u8> {
+    // offset (u16), count (u16), pad (u32)
+    let mut request = Vec::new();
+    request.extend_from_slice(&0u16.to_le_bytes());  // offset = 0
+    request.extend_from_slice(&count.to_le_bytes());
+    request.extend_from_slice(&0u32.to_le_bytes());  // padding
+    request
+}
+
+/// Parse meter values (32-bit integers)
+fn parse_meters(response: &[u8]) -> Vec<u32> {
+    response
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+/// Port type of a device map source or destination, from its name
+fn devmap_port_type(name: &str, source: bool) -> Option<PortType> {
+    let is = |prefixes: &[&str]| prefixes.iter().any(|prefix| name.starts_with(prefix));
+
+    let (source_type, dest_type) = if is(&["Analogue", "Microphone", "Monitor", "Headphone", "Line"]) {
+        (PortType::AnalogIn, PortType::AnalogOut)
+    } else if is(&["S/PDIF", "SPDIF"]) {
+        (PortType::SpdifIn, PortType::SpdifOut)
+    } else if is(&["ADAT"]) {
+        (PortType::AdatIn, PortType::AdatOut)
+    } else if is(&["USB", "Loopback", "PCM"]) {
+        (PortType::PcmIn, PortType::PcmOut)
+    } else if is(&["Mix"]) {
+        (PortType::MixerOut, PortType::MixerIn)
+    } else {
+        return None;
+    };
+
+    Some(if source { source_type } else { dest_type })
+}
+
+/// Label the meter slots from the device map's peak indices
+///
+/// Device map names ("USB 1", "Mixer 1") differ from the port layout's
+/// ("PCM 1", "Mix A"), and some are numbered with gaps, so ports are
+/// matched by type and position among the ports of that type. Ports the
+/// layout doesn't cover keep the device's name; slots with no port are
+/// named "Meter N".
+fn meter_labels(count: u16, devmap: Option<&DevMap>, layout: &PortLayout) -> Vec<MeterLabel> {
+    let mut labels: Vec<MeterLabel> = (0..count as usize)
+        .map(|index| MeterLabel { index, name: format!("Meter {}", index + 1), port: None })
+        .collect();
+
+    let Some(devmap) = devmap else {
+        return labels;
+    };
+
+    for (devmap_ports, layout_ports, source) in [
+        (devmap.sources(), &layout.sources, true),
+        (devmap.destinations(), &layout.destinations, false),
+    ] {
+        let mut seen: Vec<PortType> = Vec::new();
+
+        for devmap_port in devmap_ports {
+            let port_type = devmap_port_type(&devmap_port.name, source);
+            let position = port_type.map(|t| seen.iter().filter(|&&s| s == t).count());
+            seen.extend(port_type);
+
+            let Some(peak_index) = devmap_port.peak_index else {
+                continue;
+            };
+            let Some(label) = labels.get_mut(peak_index as usize) else {
+                tracing::warn!("Meter slot {} of {} is out of range", peak_index, devmap_port.name);
+                continue;
+            };
+
+            label.port = port_type.zip(position).and_then(|(port_type, position)| {
+                layout_ports.iter().filter(|port| port.port_type == port_type).nth(position).cloned()
+            });
+            label.name = label.port.as_ref().map_or_else(|| devmap_port.name.clone(), |port| port.name.clone());
+        }
+    }
+
+    labels
+}
+
+/// Minimum timeout for flash commands; erase blocks until the sector erase
+/// finishes
+const FLASH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Flash segment holding the saved settings (SCARLETT2_SEGMENT_SETTINGS_NAME)
+const SETTINGS_SEGMENT_NAME: &str = "App_Settings";
+
+/// FlashEraseProgress value once a segment erase has finished
+const FLASH_ERASE_DONE: u8 = 0xff;
+
+/// Maximum timeout for meter reads, which are polled and should fail fast
+const METER_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Notification bit sent when the monitor knob or Dim/Mute buttons change
+/// (SCARLETT2_USB_NOTIFY_MONITOR)
+pub const NOTIFY_MONITOR: u32 = 0x0020_0000;
+
+/// Level change of the monitor outputs while dimmed without a Dim button
+pub const MONITOR_DIM_DB: f32 = -18.0;
+
+/// Device map member holding the selected clock source
+const CLOCK_SOURCE_MEMBER: &str = "clockSource";
+
+/// Size of each EspDfuWrite block
+pub const ESP_DFU_BLOCK_SIZE: usize = 1024;
+
+/// How long to wait for the ESP to change state or acknowledge a block
+const ESP_DFU_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ESP DFU state and notification locations, from the device map
+///
+/// Based on get_esp_dfu_config() in fcp-support esp-dfu.c.
+#[derive(Debug, Clone, Copy)]
+struct EspDfuLayout {
+    /// ESP_SPACE.SuperState: current ESP state
+    state_offset: u32,
+    /// APP_SPACE.ESPBootMode: requested ESP state
+    boot_mode_offset: u32,
+    /// Notification sent to the device after writing the boot mode
+    boot_mode_notify: u32,
+    /// ESP_SPACE.DFU_NOTIFY: last DFU notification from the ESP
+    notify_offset: u32,
+    /// eSuperState values
+    state_off: i32,
+    state_dfu: i32,
+    state_normal: i32,
+    /// eDFU_NOTIFICATION values
+    notify_clear: i32,
+    notify_next_block: i32,
+    notify_finish: i32,
+    notify_error: i32,
+}
+
+impl EspDfuLayout {
+    fn from_devmap(devmap: &DevMap) -> Result<Self> {
+        let missing = |name: &str| Error::NotSupported(format!("ESP DFU: device map has no {}", name));
+        let enum_value = |enum_name: &str, name: &str| {
+            devmap.enum_value(enum_name, name).map(|v| v as i32).ok_or_else(|| missing(name))
+        };
+
+        let esp_base = devmap.member("espSpace").ok_or_else(|| missing("espSpace"))?.offset;
+        let esp_offset = |name: &str| {
+            devmap
+                .struct_member("ESP_SPACE", name)
+                .map(|m| esp_base + m.offset)
+                .ok_or_else(|| missing(name))
+        };
+
+        let boot_mode = devmap.member("ESPBootMode").ok_or_else(|| missing("ESPBootMode"))?;
+
+        Ok(Self {
+            state_offset: esp_offset("SuperState")?,
+            boot_mode_offset: boot_mode.offset,
+            boot_mode_notify: boot_mode.notify_device.ok_or_else(|| missing("ESPBootMode notify-device"))?,
+            notify_offset: esp_offset("DFU_NOTIFY")?,
+            state_off: enum_value("eSuperState", "eSuperOff")?,
+            state_dfu: enum_value("eSuperState", "eSuperDFU")?,
+            state_normal: enum_value("eSuperState", "eSuperNormal")?,
+            notify_clear: enum_value("eDFU_NOTIFICATION", "eClear")?,
+            notify_next_block: enum_value("eDFU_NOTIFICATION", "eNextblock")?,
+            notify_finish: enum_value("eDFU_NOTIFICATION", "eFinish")?,
+            notify_error: enum_value("eDFU_NOTIFICATION", "eError")?,
+        })
+    }
+}
+
+/// Firmware versions reported by a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceVersions {
+    /// Main (app) firmware version
+    pub firmware: u32,
+    /// ESP co-processor firmware version, on devices that have one
+    pub esp: Option<u32>,
+    /// FPGA version, on devices that report one
+    pub fpga: Option<u32>,
+}
+
+impl DeviceVersions {
+    /// Parse the INIT_2 response
+    ///
+    /// INIT_2 only carries the app firmware version, at bytes 8..12; the
+    /// ESP and FPGA versions are left unset.
+    pub fn from_init_response(response: &[u8]) -> Option<Self> {
+        let firmware = u32::from_le_bytes(response.get(8..12)?.try_into().ok()?);
+
+        Some(Self {
+            firmware,
+            esp: None,
+            fpga: None,
+        })
+    }
+}
+
+/// Line output volume range and resolution of a model
+///
+/// The register holds the volume in signed dB, as mixer_scarlett2.c
+/// writes it (`val - SCARLETT2_VOLUME_BIAS`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumeScale {
+    /// Lowest volume the device accepts
+    pub min_db: f32,
+    /// Highest volume the device accepts
+    pub max_db: f32,
+    /// dB per raw step
+    pub step_db: f32,
+}
+
+impl VolumeScale {
+    /// Volume scale of a model
+    ///
+    /// The fcp-support device maps give the big Gen 4 the same line output
+    /// range as the Scarlett2 devices, so every model uses the default.
+    pub fn for_model(_model: DeviceModel) -> Self {
+        Self::default()
+    }
+
+    /// Convert a raw register value to dB
+    pub fn to_db(self, raw: i32) -> f32 {
+        self.clamp(raw as f32 * self.step_db)
+    }
+
+    /// Convert dB to a raw register value, clamping to the valid range
+    pub fn to_raw(self, db: f32) -> i32 {
+        (self.clamp(db) / self.step_db).round() as i32
+    }
+
+    /// Clamp a volume to the valid range
+    pub fn clamp(self, db: f32) -> f32 {
+        db.clamp(self.min_db, self.max_db)
+    }
+}
+
+impl Default for VolumeScale {
+    /// 1 dB steps from -127 to 0 dB
+    fn default() -> Self {
+        Self {
+            min_db: -127.0,
+            max_db: 0.0,
+            step_db: 1.0,
+        }
+    }
+}
+
 /// FCP Protocol Handler
 ///
 /// Communicates with Gen 4 devices using the Focusrite Control Protocol.
 /// On macOS, this bypasses the Linux kernel driver and communicates directly
 /// via USB vendor-specific control transfers.
 pub struct FcpProtocol {
-    transport: Box<dyn crate::transport::UsbTransport>,
+    transport: Arc<dyn crate::transport::UsbTransport>,  // Shared with async transfers
     initialized: bool,
     seq_num: u16,  // Sequence number for Scarlett2 USB packets
     interface_num: u8,  // Interface number for control transfers
+    model: Option<DeviceModel>,  // Used to look up per-model config items
+    timeout: Duration,  // Applied to every control transfer
+    retry_policy: RetryPolicy,  // Which commands to resend after a transient error
+    devmap: Option<DevMap>,  // Parameter layout, once read from the device
+    rebooted: bool,  // Device was rebooted and has dropped off the bus
+    cache: Option<ConfigCache>,  // Values read by the output getters, if caching
+    versions: Option<DeviceVersions>,  // Parsed from the INIT_2 response
+    links: Vec<(u8, u8)>,  // Stereo-linked output pairs
+    writes: u64,  // Data space writes made, for auto-commit
+    undimmed: Option<Vec<(u8, f32)>>,  // Monitor volumes to restore, while dimmed
+    meter_labels: Option<Vec<MeterLabel>>,  // What each meter slot measures, once read
+    meter_count: Option<u16>,  // Number of meter slots, once read
 }
 
 impl FcpProtocol {
@@ -434,51 +760,263 @@ impl FcpProtocol {
     /// Create a new FCP protocol handler with specific interface number
     pub fn new_with_interface(transport: Box<dyn crate::transport::UsbTransport>, interface_num: u8) -> Self {
         Self {
-            transport,
+            transport: transport.into(),
             initialized: false,
             seq_num: 0,  // Start at 0, will increment on first use
             interface_num,
+            model: None,
+            timeout: crate::transport::DEFAULT_TIMEOUT,
+            retry_policy: RetryPolicy::default(),
+            devmap: None,
+            rebooted: false,
+            cache: None,
+            versions: None,
+            links: Vec::new(),
+            writes: 0,
+            undimmed: None,
+            meter_labels: None,
+            meter_count: None,
+        }
+    }
+
+    /// Set the control transfer timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the control transfer timeout
+    ///
+    /// Useful on slow links such as USB/IP.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Get the control transfer timeout
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Set which commands are resent after a transient USB error
+    ///
+    /// Use `RetryPolicy::none()` to disable retries, e.g. for firmware
+    /// updates.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Set which commands are resent after a transient USB error
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Get the retry policy
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Set how many times a command is resent after a transient USB error
+    ///
+    /// Set to 0 to disable retries.
+    pub fn set_max_retries(&mut self, max_retries: usize) {
+        let retry_writes = self.retry_policy.retry_writes;
+        self.retry_policy = RetryPolicy::exponential(max_retries).with_retry_writes(retry_writes);
+    }
+
+    /// Enable or disable caching of output volume and mute reads
+    pub fn with_cache(mut self, enabled: bool) -> Self {
+        self.set_cache_enabled(enabled);
+        self
+    }
+
+    /// Enable or disable caching of output volume and mute reads
+    ///
+    /// Cached values are dropped when written, when the matching device
+    /// notification is sent, and on `handle_notify`.
+    pub fn set_cache_enabled(&mut self, enabled: bool) {
+        self.cache = enabled.then(ConfigCache::new);
+    }
+
+    /// Get the read cache, if enabled
+    pub fn cache(&self) -> Option<&ConfigCache> {
+        self.cache.as_ref()
+    }
+
+    /// Set the device model, enabling model-specific controls
+    pub fn with_model(mut self, model: DeviceModel) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    /// Get the device model, if known
+    pub fn model(&self) -> Option<DeviceModel> {
+        self.model
+    }
+
+    /// Check if the underlying transport is still connected
+    pub fn is_connected(&self) -> bool {
+        !self.rebooted && self.transport.is_connected()
+    }
+
+    /// Talk to the device through a new transport, e.g. after it has
+    /// re-enumerated
+    ///
+    /// The device starts over, so `init` must be run again before sending
+    /// commands, and cached reads are dropped.
+    pub fn set_transport(&mut self, transport: Box<dyn crate::transport::UsbTransport>) {
+        self.transport = transport.into();
+        self.initialized = false;
+        self.rebooted = false;
+        self.undimmed = None;
+        if let Some(cache) = &mut self.cache {
+            cache.clear();
         }
     }
 
+    /// Check that commands can be sent
+    fn ensure_initialized(&self) -> Result<()> {
+        if self.rebooted {
+            return Err(Error::Disconnected);
+        }
+        if !self.initialized {
+            return Err(Error::Protocol("FCP not initialized".to_string()));
+        }
+        Ok(())
+    }
+
     /// Initialize the FCP protocol
     /// Must be called before sending any commands
     pub fn init(&mut self) -> Result<(Vec<u8>, Vec<u8>)> {
-        use crate::transport::{ControlTransfer, Direction};
+        if self.rebooted {
+            return Err(Error::Disconnected);
+        }
 
         tracing::info!("Initializing FCP protocol");
 
+        // Both init steps are sent with sequence number 1, like the kernel
+        // driver; this also resynchronises with the device
+
         // Step 0: Send INIT_1 command
+        self.seq_num = 0;
         let step0_resp = self.send_command(FcpOpcode::Init1, &[], 24)?;
         tracing::debug!("FCP Init Step 0 complete: {} bytes", step0_resp.len());
 
         // Step 2: Send INIT_2 command
+        self.seq_num = 0;
         let step2_resp = self.send_command(FcpOpcode::Init2, &[], 84)?;
         tracing::debug!("FCP Init Step 2 complete: {} bytes", step2_resp.len());
 
-        // Extract firmware version from step2_resp[8..12]
-        if step2_resp.len() >= 12 {
-            let firmware_version = u32::from_le_bytes([
-                step2_resp[8], step2_resp[9], step2_resp[10], step2_resp[11]
-            ]);
-            tracing::info!("Device firmware version: {}", firmware_version);
+        self.versions = DeviceVersions::from_init_response(&step2_resp);
+        match &self.versions {
+            Some(versions) => tracing::info!("Device firmware version: {}", versions.firmware),
+            None => tracing::warn!("INIT_2 response too short for firmware version"),
         }
 
         self.initialized = true;
         Ok((step0_resp, step2_resp))
     }
 
+    /// Get the firmware versions read during init
+    pub fn versions(&self) -> Option<DeviceVersions> {
+        self.versions
+    }
+
     /// Send an FCP command via USB class-specific control transfer
     ///
+    /// Commands are resent after a transient error as the retry policy
+    /// allows; flash and firmware writes never are.
+    fn send_command(&mut self, opcode: FcpOpcode, request_data: &[u8], response_size: usize) -> Result<Vec<u8>> {
+        let may_retry = !opcode.is_firmware_write()
+            && (opcode.is_read() || self.retry_policy.retry_writes);
+        let policy = if may_retry { self.retry_policy.clone() } else { RetryPolicy::none() };
+
+        policy.retry(|| self.send_command_synced(opcode as u32, request_data, response_size))
+    }
+
+    /// Send a command by raw opcode, including ones missing from `FcpOpcode`
+    ///
+    /// Sequence numbering, header building and response checks are the same
+    /// as for known commands, but the command is never retried since it may
+    /// not be safe to repeat. Meant for mapping undocumented commands.
+    #[cfg(feature = "debug-protocol")]
+    pub fn send_raw(&mut self, opcode: u32, data: &[u8], response_len: usize) -> Result<Vec<u8>> {
+        tracing::debug!("Sending raw FCP command {}", opcode_name(opcode));
+        self.send_command_synced(opcode, data, response_len)
+    }
+
+    /// Send an FCP command, resynchronising if the sequence numbers differ
+    ///
+    /// If the device answers with a different sequence number (e.g. another
+    /// application has been talking to it), the init handshake is re-run
+    /// and the command retried once.
+    fn send_command_synced(&mut self, opcode: u32, request_data: &[u8], response_size: usize) -> Result<Vec<u8>> {
+        if let Some(response) = self.transact(opcode, request_data, response_size)? {
+            return Ok(response);
+        }
+
+        let name = opcode_name(opcode);
+        if !self.initialized || opcode == FcpOpcode::Init1 as u32 || opcode == FcpOpcode::Init2 as u32 {
+            return Err(Error::Protocol(format!("FCP sequence mismatch on {}", name)));
+        }
+
+        tracing::warn!("FCP sequence mismatch on {}, re-initializing", name);
+        self.init()?;
+
+        self.transact(opcode, request_data, response_size)?.ok_or_else(|| {
+            Error::Protocol(format!("FCP sequence mismatch on {} after re-initializing", name))
+        })
+    }
+
+    /// Send one FCP command and read its response
+    ///
+    /// Returns `None` if the response's sequence number doesn't match.
     /// Based on Linux kernel mixer_scarlett2.c driver (scarlett2_usb_tx/rx functions).
     /// Uses class-specific control transfers, not vendor-specific.
-    fn send_command(&mut self, opcode: FcpOpcode, request_data: &[u8], response_size: usize) -> Result<Vec<u8>> {
-        use crate::transport::ControlTransfer;
+    fn transact(&mut self, opcode: u32, request_data: &[u8], response_size: usize) -> Result<Option<Vec<u8>>> {
+        let (transfer_out, request) = self.build_request(opcode, request_data, response_size);
+        self.transport.control_out(&transfer_out, &request)?;
+
+        // Only read response if we expect one
+        if response_size == 0 {
+            return Ok(Some(Vec::new()));
+        }
+
+        let transfer_in = self.response_transfer(transfer_out.timeout);
+        let mut response_buf = vec![0u8; FCP_HEADER_SIZE + response_size];
+        let actual = self.transport.control_in(&transfer_in, &mut response_buf)?;
+
+        self.check_response(opcode, &response_buf[..actual])
+    }
+
+    /// Send one FCP command without blocking, as `transact` does
+    async fn transact_async(
+        &mut self,
+        transport: &dyn AsyncUsbTransport,
+        opcode: u32,
+        request_data: &[u8],
+        response_size: usize,
+    ) -> Result<Option<Vec<u8>>> {
+        let (transfer_out, request) = self.build_request(opcode, request_data, response_size);
+        transport.control_out_async(&transfer_out, &request).await?;
+
+        if response_size == 0 {
+            return Ok(Some(Vec::new()));
+        }
+
+        let transfer_in = self.response_transfer(transfer_out.timeout);
+        let mut response_buf = vec![0u8; FCP_HEADER_SIZE + response_size];
+        let actual = transport.control_in_async(&transfer_in, &mut response_buf).await?;
+
+        self.check_response(opcode, &response_buf[..actual])
+    }
 
+    /// Number the next command and build its packet and OUT transfer
+    fn build_request(&mut self, opcode: u32, request_data: &[u8], response_size: usize) -> (ControlTransfer, Vec<u8>) {
         // Increment sequence number (kernel starts at 1 for init)
-        self.seq_num += 1;
+        self.seq_num = self.seq_num.wrapping_add(1);
 
-        tracing::trace!("FCP command: {:?}, seq={}, req_len={}, resp_len={}", opcode, self.seq_num, request_data.len(), response_size);
+        tracing::trace!("FCP command: {}, seq={}, req_len={}, resp_len={}", opcode_name(opcode), self.seq_num, request_data.len(), response_size);
 
         // Build Scarlett2 USB packet matching mixer_scarlett2.c
         // struct scarlett2_usb_packet:
@@ -490,7 +1028,7 @@ impl FcpProtocol {
         //   u8 data[];
 
         let mut request = Vec::new();
-        request.extend_from_slice(&(opcode as u32).to_le_bytes());  // cmd (4 bytes)
+        request.extend_from_slice(&opcode.to_le_bytes());  // cmd (4 bytes)
         request.extend_from_slice(&(request_data.len() as u16).to_le_bytes());  // size (2 bytes)
         request.extend_from_slice(&(self.seq_num).to_le_bytes());  // seq (2 bytes)
         request.extend_from_slice(&0u32.to_le_bytes());  // error (4 bytes)
@@ -503,83 +1041,197 @@ impl FcpProtocol {
         // From mixer_scarlett2.c:scarlett2_usb_tx()
         // USB_TYPE_CLASS | USB_RECIP_INTERFACE | USB_DIR_OUT = 0x21
         // Request = SCARLETT2_USB_CMD_REQ = 2
+        // Flash erase replies only once the erase is done; meters are
+        // polled, so a stuck read shouldn't hold up the next one
+        let timeout = match (opcode >> 12) as u16 {
+            FCP_OPCODE_CATEGORY_FLASH => self.timeout.max(FLASH_TIMEOUT),
+            FCP_OPCODE_CATEGORY_METER => self.timeout.min(METER_TIMEOUT),
+            _ => self.timeout,
+        };
+
         let transfer_out = ControlTransfer::class_out(
             2,  // SCARLETT2_USB_CMD_REQ
             0,  // value
             self.interface_num as u16,  // index = interface number!
-        );
+        ).with_timeout(timeout);
 
-        self.transport.control_out(&transfer_out, &request)?;
-
-        // Only read response if we expect one
-        if response_size == 0 {
-            return Ok(Vec::new());
-        }
+        (transfer_out, request)
+    }
 
-        // Read response via class-specific IN transfer
+    /// IN transfer for reading a command's response
+    fn response_transfer(&self, timeout: Duration) -> ControlTransfer {
         // From mixer_scarlett2.c:scarlett2_usb_rx()
         // USB_TYPE_CLASS | USB_RECIP_INTERFACE | USB_DIR_IN = 0xA1
         // Request = SCARLETT2_USB_CMD_RESP = 3
-        let transfer_in = ControlTransfer::class_in(
+        ControlTransfer::class_in(
             3,  // SCARLETT2_USB_CMD_RESP
             0,  // value
             self.interface_num as u16,  // index = interface number!
-        );
+        ).with_timeout(timeout)
+    }
 
+    /// Check a response's header and extract its data
+    ///
+    /// Returns `None` if the sequence number doesn't match.
+    fn check_response(&self, opcode: u32, response: &[u8]) -> Result<Option<Vec<u8>>> {
         // Response includes 16-byte Scarlett2 header + data
-        const HEADER_SIZE: usize = 16;
-        let total_size = HEADER_SIZE + response_size;
-        let mut response_buf = vec![0u8; total_size];
-        let actual = self.transport.control_in(&transfer_in, &mut response_buf)?;
-
-        if actual < HEADER_SIZE {
+        if response.len() < FCP_HEADER_SIZE {
             return Err(Error::Protocol(format!(
                 "Response too short: got {} bytes, need at least {} for header",
-                actual, HEADER_SIZE
+                response.len(), FCP_HEADER_SIZE
             )));
         }
 
         tracing::debug!("FCP response: {} bytes total ({} header + {} data)",
-                       actual, HEADER_SIZE, actual - HEADER_SIZE);
+                       response.len(), FCP_HEADER_SIZE, response.len() - FCP_HEADER_SIZE);
+
+        // The device answers init with sequence 0
+        let resp_seq = u16::from_le_bytes([response[6], response[7]]);
+        if resp_seq != self.seq_num && !(self.seq_num == 1 && resp_seq == 0) {
+            tracing::debug!("FCP sequence mismatch: sent {}, got {}", self.seq_num, resp_seq);
+            return Ok(None);
+        }
 
-        // TODO: Validate header (cmd, seq, size, error, pad) like kernel driver does
+        let error = u32::from_le_bytes([response[8], response[9], response[10], response[11]]);
+        if error != 0 {
+            return Err(device_error(error as i32, &opcode_name(opcode)));
+        }
 
         // Extract just the data portion (skip 16-byte header)
-        let data_len = actual - HEADER_SIZE;
-        let response = response_buf[HEADER_SIZE..HEADER_SIZE + data_len].to_vec();
-
-        Ok(response)
+        Ok(Some(response[FCP_HEADER_SIZE..].to_vec()))
     }
 
     /// Read meter levels
+    ///
+    /// `count` can't be more than the device's meter count, if that has
+    /// been read, or `MAX_METERS`.
     pub fn read_meters(&mut self, count: u16) -> Result<Vec<u32>> {
-        if !self.initialized {
-            return Err(Error::Protocol("FCP not initialized".to_string()));
+        self.ensure_initialized()?;
+        self.check_meter_count(count)?;
+
+        let response = self.send_command(FcpOpcode::MeterRead, &meter_request(count), count as usize * 4)?;
+        Ok(parse_meters(&response))
+    }
+
+    /// Check that a meter read asks for no more meters than there are
+    fn check_meter_count(&self, count: u16) -> Result<()> {
+        let max = self.meter_count.unwrap_or(MAX_METERS).min(MAX_METERS);
+        if count > max {
+            return Err(Error::InvalidParameter(format!(
+                "Can't read {} meters, the device has at most {}",
+                count, max
+            )));
+        }
+        Ok(())
+    }
+
+    /// Read meter levels without blocking the calling task
+    ///
+    /// Transient errors are retried as the retry policy allows, waiting
+    /// asynchronously in between. A sequence mismatch re-runs the init
+    /// handshake, which blocks. Transports without non-blocking transfers
+    /// fall back to `read_meters`.
+    pub async fn read_meters_async(&mut self, count: u16) -> Result<Vec<u32>> {
+        // Held apart from `self`, which each transfer borrows mutably
+        let transport = Arc::clone(&self.transport);
+        let Some(transport) = transport.as_async() else {
+            return self.read_meters(count);
+        };
+        self.ensure_initialized()?;
+        self.check_meter_count(count)?;
+
+        let opcode = FcpOpcode::MeterRead as u32;
+        let request = meter_request(count);
+        let policy = self.retry_policy.clone();
+        let mut delays = policy.backoff.iter();
+        let mut resynced = false;
+
+        loop {
+            match self.transact_async(transport, opcode, &request, count as usize * 4).await {
+                Ok(Some(response)) => return Ok(parse_meters(&response)),
+                Ok(None) if !resynced => {
+                    tracing::warn!("FCP sequence mismatch on {}, re-initializing", opcode_name(opcode));
+                    self.init()?;
+                    resynced = true;
+                }
+                Ok(None) => {
+                    return Err(Error::Protocol(format!(
+                        "FCP sequence mismatch on {} after re-initializing",
+                        opcode_name(opcode)
+                    )));
+                }
+                Err(e) if is_transient_error(&e) => {
+                    let Some(&delay) = delays.next() else {
+                        return Err(e);
+                    };
+                    tracing::debug!("Transient USB error ({}), retrying meter read", e);
+                    async_io::Timer::after(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
         }
+    }
 
-        // Build request: offset (u16), count (u16), pad (u32)
-        let mut request = Vec::new();
-        request.extend_from_slice(&0u16.to_le_bytes());  // offset = 0
-        request.extend_from_slice(&count.to_le_bytes());
-        request.extend_from_slice(&0u32.to_le_bytes());  // padding
+    /// Check whether the device supports an opcode category
+    pub fn cap_read(&mut self, category: u16) -> Result<bool> {
+        self.ensure_initialized()?;
+
+        let response = self.send_command(FcpOpcode::CapRead, &category.to_le_bytes(), 1)?;
+        Ok(response.first().is_some_and(|&supported| supported != 0))
+    }
+
+    /// Read the device capabilities
+    ///
+    /// Fails if the required INIT or DATA categories are missing.
+    pub fn read_capabilities(&mut self) -> Result<DeviceCapabilities> {
+        for (category, name) in [(FCP_OPCODE_CATEGORY_INIT, "INIT"), (FCP_OPCODE_CATEGORY_DATA, "DATA")] {
+            if !self.cap_read(category)? {
+                return Err(Error::NotSupported(format!("FCP {} category", name)));
+            }
+        }
+
+        // Controls and channel counts aren't part of CapRead
+        let base = self.model.map(DeviceCapabilities::for_model).unwrap_or_default();
+
+        let mut caps = DeviceCapabilities {
+            meter: self.cap_read(FCP_OPCODE_CATEGORY_METER)?,
+            mix: self.cap_read(FCP_OPCODE_CATEGORY_MIX)?,
+            mux: self.cap_read(FCP_OPCODE_CATEGORY_MUX)?,
+            flash: self.cap_read(FCP_OPCODE_CATEGORY_FLASH)?,
+            sync: self.cap_read(FCP_OPCODE_CATEGORY_SYNC)?,
+            esp_dfu: self.cap_read(FCP_OPCODE_CATEGORY_ESP_DFU)?,
+            num_meters: 0,
+            num_mixer_outputs: 0,
+            num_mixer_inputs: 0,
+            mux_sizes: [0; 3],
+            ..base
+        };
+
+        if let Some(devmap) = self.devmap.as_ref().filter(|devmap| devmap.num_outputs() > 0) {
+            caps.num_outputs = devmap.num_outputs() as u8;
+        }
+
+        if caps.meter {
+            caps.num_meters = self.read_meter_count()? as u8;
+        }
 
-        let response = self.send_command(FcpOpcode::MeterRead, &request, (count * 4) as usize)?;
+        if caps.mix {
+            let (outputs, inputs) = self.read_mix_info()?;
+            caps.num_mixer_outputs = outputs;
+            caps.num_mixer_inputs = inputs;
+        }
 
-        // Parse meter values (32-bit integers)
-        let mut meters = Vec::new();
-        for chunk in response.chunks_exact(4) {
-            let value = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
-            meters.push(value);
+        if caps.mux {
+            caps.mux_sizes = self.read_mux_sizes()?;
         }
 
-        Ok(meters)
+        tracing::debug!("FCP capabilities: {:?}", caps);
+        Ok(caps)
     }
 
     /// Read mixer info (number of outputs and inputs)
     pub fn read_mix_info(&mut self) -> Result<(u8, u8)> {
-        if !self.initialized {
-            return Err(Error::Protocol("FCP not initialized".to_string()));
-        }
+        self.ensure_initialized()?;
 
         let response = self.send_command(FcpOpcode::MixInfo, &[], 8)?;
 
@@ -590,19 +1242,374 @@ impl FcpProtocol {
         Ok((response[0], response[1]))  // (num_outputs, num_inputs)
     }
 
-    /// Read data value (1, 2, or 4 bytes)
-    pub fn read_data(&mut self, offset: u32, size: u32) -> Result<i32> {
-        if !self.initialized {
-            return Err(Error::Protocol("FCP not initialized".to_string()));
-        }
+    /// Get the gain of every mixer input to a mix (0 = Mix A)
+    pub fn get_mix(&mut self, mix: u16) -> Result<Vec<u16>> {
+        let (_, count) = self.read_mix_info()?;
 
+        // mix (u16), count (u16)
         let mut request = Vec::new();
-        request.extend_from_slice(&offset.to_le_bytes());
-        request.extend_from_slice(&size.to_le_bytes());
+        request.extend_from_slice(&mix.to_le_bytes());
+        request.extend_from_slice(&(count as u16).to_le_bytes());
 
-        let response = self.send_command(FcpOpcode::DataRead, &request, size as usize)?;
+        let response = self.send_command(FcpOpcode::MixRead, &request, count as usize * 2)?;
 
-        if response.len() < size as usize {
+        Ok(response
+            .chunks_exact(2)
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect())
+    }
+
+    /// Set the gain of every mixer input to a mix (0 = Mix A)
+    pub fn set_mix(&mut self, mix: u16, gains: &[u16]) -> Result<()> {
+        let (_, count) = self.read_mix_info()?;
+        if gains.len() != count as usize {
+            return Err(Error::InvalidParameter(format!(
+                "Mix needs {} gains, got {}",
+                count,
+                gains.len()
+            )));
+        }
+
+        // mix (u16), gains
+        let mut request = Vec::with_capacity(2 + gains.len() * 2);
+        request.extend_from_slice(&mix.to_le_bytes());
+        for gain in gains {
+            request.extend_from_slice(&gain.to_le_bytes());
+        }
+
+        self.send_command(FcpOpcode::MixWrite, &request, 0)?;
+        Ok(())
+    }
+
+    /// Set the gain of a mixer input to a mix
+    ///
+    /// The device only takes whole mixes, so the mix is read and written back.
+    pub fn set_mixer_volume(&mut self, mix: u16, input_index: u16, volume: u16) -> Result<()> {
+        let mut gains = self.get_mix(mix)?;
+        let gain = gains
+            .get_mut(input_index as usize)
+            .ok_or_else(|| Error::InvalidParameter(format!("No mixer input {}", input_index)))?;
+        *gain = volume;
+
+        self.set_mix(mix, &gains)
+    }
+
+    /// Pan a mixer input across a stereo pair of mixes at unity gain
+    ///
+    /// The hardware mixer is a gain matrix, so panning writes the input's
+    /// gain to both mixes of the pair (`stereo_bus` 0 = Mix A/B, 1 = Mix C/D,
+    /// ...) using the constant-power law in `mixer::pan_gains`: `pan = 0.0`
+    /// puts the input at -3 dB in each mix.
+    pub fn set_mix_pan(&mut self, source: u16, stereo_bus: u16, pan: f32) -> Result<()> {
+        if !(-1.0..=1.0).contains(&pan) {
+            return Err(Error::InvalidParameter(format!("Pan {} out of range (-1.0 to 1.0)", pan)));
+        }
+
+        let (num_mixes, _) = self.read_mix_info()?;
+        let left_mix = stereo_bus * 2;
+        if left_mix + 1 >= num_mixes as u16 {
+            return Err(Error::InvalidParameter(format!(
+                "No stereo mix {} ({} mixes)",
+                stereo_bus, num_mixes
+            )));
+        }
+
+        let (left, right) = pan_gains(pan);
+        let to_raw = |gain: f32| db_to_mixer_gain(linear_to_db(gain)) as u16;
+        tracing::info!("Panning mixer input {} on stereo mix {} to {}", source, stereo_bus, pan);
+        self.set_mixer_volume(left_mix, source, to_raw(left))?;
+        self.set_mixer_volume(left_mix + 1, source, to_raw(right))
+    }
+
+    /// Read the number of meter slots
+    ///
+    /// A device reporting no slots is an error, so a bad response can't
+    /// become the limit for later meter reads.
+    pub fn read_meter_count(&mut self) -> Result<u16> {
+        self.ensure_initialized()?;
+
+        let response = self.send_command(FcpOpcode::MeterInfo, &[], 4)?;
+        let count = match response.first() {
+            Some(&count) if count > 0 => count as u16,
+            _ => return Err(Error::Protocol(format!("Invalid meter info response: {:02x?}", response))),
+        };
+        self.meter_count = Some(count);
+        Ok(count)
+    }
+
+    /// Read which port each meter slot measures
+    ///
+    /// Slots are labelled from the device map, so read that first with
+    /// `read_devmap`; without it every slot is named "Meter N".
+    pub fn read_meter_info(&mut self) -> Result<Vec<MeterLabel>> {
+        let count = self.read_meter_count()?;
+        let layout = self.model.map(PortLayout::for_model).unwrap_or_default();
+
+        let labels = meter_labels(count, self.devmap.as_ref(), &layout);
+        self.meter_labels = Some(labels.clone());
+        Ok(labels)
+    }
+
+    /// Read every meter, in dB, with the name of what it measures
+    ///
+    /// The labels are read once and reused.
+    pub fn read_labeled_meters(&mut self) -> Result<Vec<(String, f32)>> {
+        let labels = match self.meter_labels.clone() {
+            Some(labels) => labels,
+            None => self.read_meter_info()?,
+        };
+        let levels = self.read_meters(labels.len() as u16)?;
+
+        Ok(labels.into_iter().zip(levels).map(|(label, raw)| (label.name, meter_to_db(raw))).collect())
+    }
+
+    /// Read the mux table size for each sample rate band (1x, 2x, 4x)
+    pub fn read_mux_sizes(&mut self) -> Result<[u16; 3]> {
+        self.ensure_initialized()?;
+
+        let response = self.send_command(FcpOpcode::MuxInfo, &[], 12)?;
+        if response.len() < 6 {
+            return Err(Error::Protocol("Mux info response too short".to_string()));
+        }
+
+        let mut sizes = [0; 3];
+        for (size, chunk) in sizes.iter_mut().zip(response.chunks_exact(2)) {
+            *size = u16::from_le_bytes([chunk[0], chunk[1]]);
+        }
+        Ok(sizes)
+    }
+
+    /// Read a mux table (0 = 1x rates, 1 = 2x, 2 = 4x)
+    ///
+    /// Each entry is `(source << 12) | destination`.
+    pub fn read_mux(&mut self, table: u8, count: u8) -> Result<Vec<u32>> {
+        self.ensure_initialized()?;
+
+        // offset, pad, count, table
+        let request = [0, 0, count, table];
+        let response = self.send_command(FcpOpcode::MuxRead, &request, count as usize * 4)?;
+
+        Ok(response
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect())
+    }
+
+    /// Write a whole mux table
+    pub fn write_mux(&mut self, table: u8, entries: &[u32]) -> Result<()> {
+        self.ensure_initialized()?;
+
+        // pad (u16), table (u16), entries
+        let mut request = Vec::with_capacity(4 + entries.len() * 4);
+        request.extend_from_slice(&0u16.to_le_bytes());
+        request.extend_from_slice(&(table as u16).to_le_bytes());
+        for entry in entries {
+            request.extend_from_slice(&entry.to_le_bytes());
+        }
+
+        self.send_command(FcpOpcode::MuxWrite, &request, 0)?;
+        Ok(())
+    }
+
+    /// Write a routing matrix to the device
+    ///
+    /// With a baseline (e.g. the matrix last written), only destinations
+    /// whose source changed are updated, and tables without any of them
+    /// aren't written at all. The device only takes whole tables, so each
+    /// affected table is read, patched and written back.
+    pub fn write_routing(&mut self, matrix: &RoutingMatrix, baseline: Option<&RoutingMatrix>) -> Result<()> {
+        matrix.validate()?;
+
+        let changes = match baseline {
+            Some(baseline) => baseline.diff(matrix),
+            // Without a baseline every destination is written, routed or not
+            None => (0..matrix.routes.len())
+                .map(|dest_idx| RouteChange { dest_idx, from: None, to: matrix.get_route(dest_idx) })
+                .collect(),
+        };
+        if changes.is_empty() {
+            tracing::debug!("Routing unchanged, nothing to write");
+            return Ok(());
+        }
+
+        // (destination id, source id) of each change
+        let mut updates = Vec::with_capacity(changes.len());
+        for change in &changes {
+            let dest = &matrix.destinations[change.dest_idx];
+            let dest_id = mux_port_id(dest)
+                .ok_or_else(|| Error::NotSupported(format!("Routing to {}", dest.name)))?;
+            let source_id = match change.to {
+                Some(source_idx) => {
+                    let source = &matrix.sources[source_idx];
+                    mux_port_id(source)
+                        .ok_or_else(|| Error::NotSupported(format!("Routing from {}", source.name)))?
+                }
+                None => 0,
+            };
+            updates.push((dest_id, source_id));
+        }
+
+        tracing::info!("Writing {} routing change(s)", updates.len());
+
+        for (table, size) in self.read_mux_sizes()?.into_iter().enumerate() {
+            if size == 0 {
+                continue;
+            }
+
+            let count = u8::try_from(size)
+                .map_err(|_| Error::Protocol(format!("Mux table {} too large: {} entries", table, size)))?;
+            let mut entries = self.read_mux(table as u8, count)?;
+            let mut touched = false;
+            for entry in entries.iter_mut() {
+                let dest_id = *entry & 0xfff;
+                if let Some(&(_, source_id)) = updates.iter().find(|&&(id, _)| id == dest_id) {
+                    *entry = (source_id << 12) | dest_id;
+                    touched = true;
+                }
+            }
+
+            if touched {
+                self.write_mux(table as u8, &entries)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read the routing matrix from the 1x rate mux table
+    ///
+    /// Fails with `NotSupported` if the model's port layout isn't known.
+    pub fn read_routing(&mut self) -> Result<RoutingMatrix> {
+        let model = self.model.ok_or_else(|| Error::NotSupported("Routing: device model unknown".to_string()))?;
+        let layout = model.port_layout();
+        if layout.destinations.is_empty() {
+            return Err(Error::NotSupported(format!("Routing on {}", model)));
+        }
+
+        let [size, ..] = self.read_mux_sizes()?;
+        let count = u8::try_from(size)
+            .map_err(|_| Error::Protocol(format!("Mux table 0 too large: {} entries", size)))?;
+        let entries = self.read_mux(0, count)?;
+
+        routing_from_mux(layout, &entries)
+    }
+
+    /// Read clock sync status
+    pub fn sync_status(&mut self) -> Result<SyncStatus> {
+        self.ensure_initialized()?;
+
+        let response = self.send_command(FcpOpcode::SyncRead, &[], 4)?;
+        SyncStatus::from_bytes(&response)
+    }
+
+    /// Read clock sync status along with the clock source
+    ///
+    /// The clock source is only known on devices whose device map has a
+    /// clock source member. An unlocked clock is reported as
+    /// `locked: false`, not as an error.
+    pub fn read_sync_status(&mut self) -> Result<SyncStatus> {
+        let status = self.sync_status()?;
+
+        let source = self
+            .devmap
+            .as_ref()
+            .and_then(|devmap| devmap.member(CLOCK_SOURCE_MEMBER))
+            .and_then(|member| Some((member.offset, member.width()?)));
+
+        let Some((offset, size)) = source else {
+            return Ok(status);
+        };
+
+        let raw = self.read_data(offset, size)?;
+        match ClockSource::from_raw(raw) {
+            Some(source) => Ok(status.with_clock_source(source)),
+            None => {
+                tracing::warn!("Unknown clock source value: {}", raw);
+                Ok(status)
+            }
+        }
+    }
+
+    /// Get the current sample rate
+    pub fn get_sample_rate(&mut self) -> Result<SampleRate> {
+        self.ensure_initialized()?;
+
+        crate::uac::read_sample_rate(&*self.transport, self.timeout, &self.retry_policy)
+    }
+
+    /// Change the sample rate
+    ///
+    /// Returns once the device has re-clocked and reports the new rate.
+    pub async fn set_sample_rate(&mut self, rate: SampleRate) -> Result<()> {
+        self.ensure_initialized()?;
+
+        crate::uac::change_sample_rate(&*self.transport, self.timeout, &self.retry_policy, self.model, rate).await
+    }
+
+    /// Read the device map and use it for later parameter lookups
+    pub fn read_devmap(&mut self) -> Result<DevMap> {
+        self.ensure_initialized()?;
+
+        let info = self.send_command(FcpOpcode::DevmapInfo, &[], 4)?;
+        if info.len() < 4 {
+            return Err(Error::Protocol("Device map info response too short".to_string()));
+        }
+        let size = u16::from_le_bytes([info[2], info[3]]) as usize;
+
+        // Blocks are requested by number; the last one may be short
+        let mut encoded = Vec::with_capacity(size);
+        for (block, start) in (0..size).step_by(DEVMAP_BLOCK_SIZE).enumerate() {
+            let len = DEVMAP_BLOCK_SIZE.min(size - start);
+            let data = self.send_command(FcpOpcode::DevmapRead, &(block as u32).to_le_bytes(), len)?;
+            if data.len() < len {
+                return Err(Error::Protocol("Device map block truncated".to_string()));
+            }
+            encoded.extend_from_slice(&data[..len]);
+        }
+
+        let devmap = DevMap::decode(&encoded)?;
+        tracing::debug!("Read device map: {} bytes, {} outputs", size, devmap.num_outputs());
+
+        self.devmap = Some(devmap.clone());
+        self.meter_labels = None;
+        Ok(devmap)
+    }
+
+    /// Get the device map, if it has been read
+    pub fn devmap(&self) -> Option<&DevMap> {
+        self.devmap.as_ref()
+    }
+
+    /// Locate a per-output parameter, preferring the device map
+    ///
+    /// Returns the offset and size in bytes.
+    pub(crate) fn output_location(&self, param: DevMapParam, output_index: u8) -> (u32, u32) {
+        let (base, size) = match param {
+            DevMapParam::LineOutVolume => (Self::LINE_OUT_VOLUME_OFFSET, 2),
+            DevMapParam::MuteSwitch => (Self::MUTE_SWITCH_OFFSET, 1),
+        };
+
+        self.devmap
+            .as_ref()
+            .and_then(|devmap| devmap.lookup(param, output_index))
+            .map(|loc| (loc.offset, loc.size))
+            .unwrap_or((base + output_index as u32 * size, size))
+    }
+
+    /// Read data value (1, 2, or 4 bytes)
+    pub fn read_data(&mut self, offset: u32, size: u32) -> Result<i32> {
+        self.ensure_initialized()?;
+        if !matches!(size, 1 | 2 | 4) {
+            return Err(Error::InvalidParameter(format!("Invalid data size: {}", size)));
+        }
+
+        let mut request = Vec::new();
+        request.extend_from_slice(&offset.to_le_bytes());
+        request.extend_from_slice(&size.to_le_bytes());
+
+        let response = self.send_command(FcpOpcode::DataRead, &request, size as usize)?;
+
+        if response.len() < size as usize {
             return Err(Error::Protocol("Data read response too short".to_string()));
         }
 
@@ -611,7 +1618,7 @@ impl FcpProtocol {
             1 => i8::from_le_bytes([response[0]]) as i32,
             2 => i16::from_le_bytes([response[0], response[1]]) as i32,
             4 => i32::from_le_bytes([response[0], response[1], response[2], response[3]]),
-            _ => return Err(Error::Protocol(format!("Invalid data size: {}", size))),
+            _ => unreachable!("size checked above"),
         };
 
         Ok(value)
@@ -619,9 +1626,7 @@ impl FcpProtocol {
 
     /// Write data value (1, 2, or 4 bytes)
     pub fn write_data(&mut self, offset: u32, size: u32, value: i32) -> Result<()> {
-        if !self.initialized {
-            return Err(Error::Protocol("FCP not initialized".to_string()));
-        }
+        self.ensure_initialized()?;
 
         let mut request = Vec::new();
         request.extend_from_slice(&offset.to_le_bytes());
@@ -636,127 +1641,2655 @@ impl FcpProtocol {
         }
 
         self.send_command(FcpOpcode::DataWrite, &request, 0)?;
+        self.writes += 1;
+
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate(offset..offset + size);
+        }
 
         Ok(())
     }
 
-    /// Volume control constants
-    /// Based on mixer_scarlett2.c
-    pub const VOLUME_BIAS: i32 = 127;  // 0 dB = 127
-    pub const VOLUME_MIN: i32 = 0;     // -127 dB
-    pub const VOLUME_MAX: i32 = 127;   // 0 dB
+    /// Read a block of raw bytes from the data space
+    ///
+    /// `len` can't be more than `MAX_PAYLOAD_LENGTH`.
+    pub fn read_data_block(&mut self, offset: u32, len: u32) -> Result<Vec<u8>> {
+        if len as usize > MAX_PAYLOAD_LENGTH {
+            return Err(Error::InvalidParameter(format!(
+                "Can't read {} bytes, at most {} fit in a response",
+                len, MAX_PAYLOAD_LENGTH
+            )));
+        }
+        self.ensure_initialized()?;
 
-    /// Configuration offsets (from mixer_scarlett2.c)
-    const LINE_OUT_VOLUME_OFFSET: u32 = 0x34;
-    const MUTE_SWITCH_OFFSET: u32 = 0x5c;
+        let mut request = Vec::new();
+        request.extend_from_slice(&offset.to_le_bytes());
+        request.extend_from_slice(&len.to_le_bytes());
 
-    /// Get volume for a specific output (0-based index)
-    /// Returns volume in dB (-127 to 0)
-    pub fn get_volume(&mut self, output_index: u8) -> Result<i32> {
-        if !self.initialized {
-            return Err(Error::Protocol("FCP not initialized".to_string()));
+        let response = self.send_command(FcpOpcode::DataRead, &request, len as usize)?;
+
+        if response.len() < len as usize {
+            return Err(Error::Protocol("Data read response too short".to_string()));
         }
 
-        // Read 16-bit volume value from device
-        let offset = Self::LINE_OUT_VOLUME_OFFSET + (output_index as u32 * 2);
-        let raw_value = self.read_data(offset, 2)?;
+        Ok(response[..len as usize].to_vec())
+    }
 
-        // Convert from device value to dB
-        // Device stores: 0 = -127dB, 127 = 0dB
-        let db = raw_value - Self::VOLUME_BIAS;
+    /// Write a block of raw bytes to the data space
+    pub fn write_data_block(&mut self, offset: u32, data: &[u8]) -> Result<()> {
+        self.ensure_initialized()?;
 
-        tracing::debug!("Output {} volume: {} dB (raw={})", output_index, db, raw_value);
-        Ok(db)
+        let len = data.len() as u32;
+        let mut request = Vec::new();
+        request.extend_from_slice(&offset.to_le_bytes());
+        request.extend_from_slice(&len.to_le_bytes());
+        request.extend_from_slice(data);
+
+        self.send_command(FcpOpcode::DataWrite, &request, 0)?;
+        self.writes += 1;
+
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate(offset..offset + len);
+        }
+
+        Ok(())
     }
 
-    /// Set volume for a specific output (0-based index)
-    /// volume_db: Volume in dB (-127 to 0)
-    pub fn set_volume(&mut self, output_index: u8, volume_db: i32) -> Result<()> {
-        if !self.initialized {
-            return Err(Error::Protocol("FCP not initialized".to_string()));
+    /// Number of data space writes made through this handler
+    ///
+    /// Used to tell when there are changes to save to flash.
+    pub fn config_writes(&self) -> u64 {
+        self.writes
+    }
+
+    /// Save the configuration to flash so it survives a power cycle
+    ///
+    /// Flash wears with every save, so avoid calling this per change; see
+    /// [`crate::autocommit::AutoCommit`].
+    pub fn commit_to_flash(&mut self) -> Result<()> {
+        tracing::info!("Saving configuration to flash");
+        config_items::commit_to_flash(self)
+    }
+
+    /// Read a data value, serving it from the cache if enabled
+    fn read_cached(&mut self, offset: u32, size: u32) -> Result<i32> {
+        if let Some(value) = self.cache.as_ref().and_then(|cache| cache.get(offset, size)) {
+            return Ok(value);
+        }
+
+        let value = self.read_data(offset, size)?;
+        if let Some(cache) = &mut self.cache {
+            cache.insert(offset, size, value);
+        }
+        Ok(value)
+    }
+
+    /// Drop cached values of the members matching `filter`
+    ///
+    /// Without a device map there is no way to tell which values are
+    /// affected, so everything is dropped.
+    fn invalidate_members(&mut self, filter: impl Fn(&crate::devmap::DevMapMember) -> bool) {
+        let Some(cache) = &mut self.cache else {
+            return;
+        };
+
+        match &self.devmap {
+            Some(devmap) => {
+                for range in devmap.member_ranges(filter) {
+                    cache.invalidate(range);
+                }
+            }
+            None => cache.clear(),
         }
+    }
 
-        // Clamp to valid range
-        let volume_db = volume_db.clamp(-Self::VOLUME_BIAS, 0);
+    /// Handle a notification from the device
+    ///
+    /// Drops the cached values of the members whose client notification
+    /// bits are in `mask`.
+    pub fn handle_notify(&mut self, mask: u32) {
+        self.invalidate_members(|member| member.notify_client.is_some_and(|bits| bits & mask != 0));
+
+        // Outputs assigned to the knob follow it
+        if mask & NOTIFY_MONITOR != 0 {
+            let mut ranges: Vec<(u32, u32)> = (0..self.num_line_outputs())
+                .map(|output| self.output_location(DevMapParam::LineOutVolume, output as u8))
+                .collect();
+            if let Ok((_, item)) = self.lookup_config(ConfigParam::MasterVolume) {
+                ranges.push((item.offset, item.size_bytes()));
+            }
 
-        // Convert dB to device value
-        let device_value = volume_db + Self::VOLUME_BIAS;
+            if let Some(cache) = &mut self.cache {
+                for (offset, size) in ranges {
+                    cache.invalidate(offset..offset + size);
+                }
+            }
+        }
+    }
 
-        tracing::info!("Setting output {} volume to {} dB (raw={})", output_index, volume_db, device_value);
+    /// Record a value read outside the cache, e.g. by a `ChangeWatcher`
+    pub fn update_cache(&mut self, offset: u32, size: u32, value: i32) {
+        if let Some(cache) = &mut self.cache {
+            cache.insert(offset, size, value);
+        }
+    }
 
-        // Write 16-bit volume value to device
-        let offset = Self::LINE_OUT_VOLUME_OFFSET + (output_index as u32 * 2);
-        self.write_data(offset, 2, device_value)?;
+    /// Re-read every cached value from the device
+    pub fn refresh(&mut self) -> Result<()> {
+        let Some(cache) = &self.cache else {
+            return Ok(());
+        };
 
+        for (offset, size) in cache.entries() {
+            let value = self.read_data(offset, size)?;
+            if let Some(cache) = &mut self.cache {
+                cache.insert(offset, size, value);
+            }
+        }
         Ok(())
     }
 
-    /// Adjust volume by delta (in dB)
-    pub fn adjust_volume(&mut self, output_index: u8, delta_db: i32) -> Result<i32> {
-        let current = self.get_volume(output_index)?;
-        let new_volume = (current + delta_db).clamp(-Self::VOLUME_BIAS, 0);
-        self.set_volume(output_index, new_volume)?;
-        Ok(new_volume)
+    /// Get preamp gain for an input (0-based index), in dB
+    pub fn get_input_gain(&mut self, input: u8) -> Result<u8> {
+        self.ensure_initialized()?;
+
+        let gain = config_items::get_input_gain(self, input)?;
+        tracing::debug!("Input {} gain: {} dB", input, gain);
+        Ok(gain)
     }
 
-    /// Get mute status for a specific output
-    pub fn get_mute(&mut self, output_index: u8) -> Result<bool> {
-        if !self.initialized {
-            return Err(Error::Protocol("FCP not initialized".to_string()));
-        }
+    /// Set preamp gain for an input (0-based index), in dB
+    ///
+    /// Values outside the device's gain range are clamped. A stereo-linked
+    /// partner input is set too.
+    pub fn set_input_gain(&mut self, input: u8, gain_db: u8) -> Result<()> {
+        self.ensure_initialized()?;
 
-        // Read 8-bit mute value from device
-        let offset = Self::MUTE_SWITCH_OFFSET + output_index as u32;
-        let muted = self.read_data(offset, 1)?;
+        tracing::info!("Setting input {} gain to {} dB", input, gain_db);
+        config_items::set_input_gain(self, input, gain_db)?;
+        Ok(())
+    }
 
-        Ok(muted != 0)
+    /// Get whether an input pair (0 = inputs 1+2) is stereo-linked
+    pub fn get_input_link(&mut self, pair: u8) -> Result<bool> {
+        self.ensure_initialized()?;
+
+        config_items::get_input_link(self, pair)
     }
 
-    /// Set mute status for a specific output
-    pub fn set_mute(&mut self, output_index: u8, muted: bool) -> Result<()> {
-        if !self.initialized {
-            return Err(Error::Protocol("FCP not initialized".to_string()));
+    /// Link or unlink an input pair (0 = inputs 1+2)
+    ///
+    /// Linked inputs share gain, Air and autogain.
+    pub fn set_input_link(&mut self, pair: u8, linked: bool) -> Result<()> {
+        self.ensure_initialized()?;
+
+        tracing::info!("Setting input pair {} link: {}", pair, linked);
+        config_items::set_input_link(self, pair, linked)
+    }
+
+    /// Get the configuration index for a phantom power switch
+    fn phantom_index(&self, channel_group: u8) -> Result<u8> {
+        self.model
+            .and_then(|m| config_items::phantom_config_index(m, channel_group))
+            .ok_or_else(|| Error::InvalidParameter(format!(
+                "No phantom power switch {}",
+                channel_group
+            )))
+    }
+
+    /// Get 48V phantom power state for a switch (0-based)
+    pub fn get_phantom(&mut self, channel_group: u8) -> Result<bool> {
+        self.ensure_initialized()?;
+
+        let index = self.phantom_index(channel_group)?;
+        Ok(self.get_config(ConfigParam::PhantomSwitch, index)? != 0)
+    }
+
+    /// Set 48V phantom power state for a switch (0-based)
+    ///
+    /// Gen 4 devices mute the affected inputs while the change is applied.
+    pub fn set_phantom(&mut self, channel_group: u8, enabled: bool) -> Result<()> {
+        self.ensure_initialized()?;
+
+        let index = self.phantom_index(channel_group)?;
+        tracing::info!("Setting phantom power switch {}: {}", channel_group, enabled);
+        self.set_config(ConfigParam::PhantomSwitch, index, enabled as i32)
+    }
+
+    /// Get the configuration index for an input's Air control
+    fn air_index(&self, input: u8) -> Result<u8> {
+        self.model
+            .and_then(|m| config_items::air_config_index(m, input))
+            .ok_or_else(|| Error::InvalidParameter(format!("Input {} has no Air control", input)))
+    }
+
+    /// Get Air mode for an input (0-based)
+    pub fn get_air(&mut self, input: u8) -> Result<AirMode> {
+        self.ensure_initialized()?;
+
+        let index = self.air_index(input)?;
+        let value = self.get_config(ConfigParam::AirSwitch, index)?;
+
+        AirMode::from_raw(value)
+            .ok_or_else(|| Error::Protocol(format!("Invalid Air mode value: {}", value)))
+    }
+
+    /// Set Air mode for an input (0-based)
+    ///
+    /// Presence + Drive is only available on 4th Gen devices.
+    pub fn set_air(&mut self, input: u8, mode: AirMode) -> Result<()> {
+        self.ensure_initialized()?;
+
+        let index = self.air_index(input)?;
+
+        if mode == AirMode::PresenceDrive && !self.model.is_some_and(|m| m.has_air_drive()) {
+            return Err(Error::NotSupported(
+                "Air Presence + Drive requires a 4th Gen device".to_string()
+            ));
         }
 
-        tracing::info!("Setting output {} mute: {}", output_index, muted);
+        tracing::info!("Setting input {} Air: {}", input, mode);
+        self.set_config(ConfigParam::AirSwitch, index, mode.to_raw())
+    }
 
-        // Write 8-bit mute value to device
-        let offset = Self::MUTE_SWITCH_OFFSET + output_index as u32;
-        self.write_data(offset, 1, if muted { 1 } else { 0 })?;
+    /// Get Line/Inst level for an input (0-based)
+    pub fn get_input_level(&mut self, input: u8) -> Result<InputLevel> {
+        self.ensure_initialized()?;
 
-        Ok(())
+        config_items::check_level_input(self.model, input)?;
+        Ok(InputLevel::from_raw(self.get_config(ConfigParam::LevelSwitch, input)?))
     }
 
-    /// Toggle mute for a specific output
-    pub fn toggle_mute(&mut self, output_index: u8) -> Result<bool> {
-        let current = self.get_mute(output_index)?;
-        let new_state = !current;
-        self.set_mute(output_index, new_state)?;
-        Ok(new_state)
+    /// Set Line/Inst level for an input (0-based)
+    pub fn set_input_level(&mut self, input: u8, level: InputLevel) -> Result<()> {
+        self.ensure_initialized()?;
+
+        config_items::check_level_input(self.model, input)?;
+
+        tracing::info!("Setting input {} level: {}", input, level);
+        self.set_config(ConfigParam::LevelSwitch, input, level.to_raw())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Get Direct Monitor mode
+    pub fn get_direct_monitor(&mut self) -> Result<DirectMonitorMode> {
+        self.ensure_initialized()?;
 
-    #[test]
-    fn test_header_serialization() {
-        let header = FcpMessageHeader::new_request(0x01, 100);
-        let bytes = header.to_bytes();
-        let decoded = FcpMessageHeader::from_bytes(&bytes).unwrap();
+        let raw = self.get_config(ConfigParam::DirectMonitor, 0)?;
+        config_items::direct_monitor_from_raw(self.model, raw)
+    }
+
+    /// Set Direct Monitor mode
+    ///
+    /// The Solo accepts Off/On and the 2i2 Off/Mono/Stereo.
+    pub fn set_direct_monitor(&mut self, mode: DirectMonitorMode) -> Result<()> {
+        self.ensure_initialized()?;
+
+        let raw = config_items::direct_monitor_to_raw(self.model, mode)?;
 
-        assert_eq!(decoded.magic, FCP_MAGIC_REQUEST);
-        assert_eq!(decoded.msg_type, 0x01);
-        assert_eq!(decoded.payload_length, 100);
+        tracing::info!("Setting Direct Monitor: {}", mode);
+        self.set_config(ConfigParam::DirectMonitor, 0, raw)
     }
 
-    #[test]
-    fn test_version_message() {
-        let msg = FcpVersionMessage::new(FCP_PROTOCOL_VERSION);
-        let bytes = msg.to_bytes();
-        let decoded = FcpVersionMessage::from_bytes(&bytes).unwrap();
+    /// Get monitor Dim state
+    ///
+    /// Not yet available on 4th Gen devices, which need the device map.
+    pub fn get_dim(&mut self) -> Result<bool> {
+        self.ensure_initialized()?;
 
-        assert_eq!(decoded.version, FCP_PROTOCOL_VERSION);
+        Ok(self.get_config(ConfigParam::DimMute, config_items::DIM_MUTE_INDEX_DIM)? != 0)
+    }
+
+    /// Set monitor Dim state
+    pub fn set_dim(&mut self, enabled: bool) -> Result<()> {
+        self.ensure_initialized()?;
+
+        tracing::info!("Setting monitor Dim: {}", enabled);
+        self.set_config(ConfigParam::DimMute, config_items::DIM_MUTE_INDEX_DIM, enabled as i32)
+    }
+
+    /// Get monitor Mute state
+    ///
+    /// Devices without a Mute button are muted when all of their monitor
+    /// outputs are.
+    pub fn get_monitor_mute(&mut self) -> Result<bool> {
+        self.ensure_initialized()?;
+
+        if self.lookup_config(ConfigParam::DimMute).is_ok() {
+            return Ok(self.get_config(ConfigParam::DimMute, config_items::DIM_MUTE_INDEX_MUTE)? != 0);
+        }
+
+        for output in self.monitor_outputs()? {
+            if !self.get_mute(output)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Set monitor Mute state
+    ///
+    /// Devices without a Mute button have each of their monitor outputs
+    /// muted instead.
+    pub fn set_monitor_mute(&mut self, muted: bool) -> Result<()> {
+        self.ensure_initialized()?;
+
+        tracing::info!("Setting monitor Mute: {}", muted);
+        if self.lookup_config(ConfigParam::DimMute).is_ok() {
+            return self.set_config(ConfigParam::DimMute, config_items::DIM_MUTE_INDEX_MUTE, muted as i32);
+        }
+
+        let values: Vec<(u8, i32)> = self
+            .monitor_outputs()?
+            .into_iter()
+            .map(|output| (output, muted as i32))
+            .collect();
+        self.write_output_values(DevMapParam::MuteSwitch, &values)
+    }
+
+    /// Get whether the monitor outputs are dimmed
+    pub fn get_monitor_dim(&mut self) -> Result<bool> {
+        self.ensure_initialized()?;
+
+        if self.lookup_config(ConfigParam::DimMute).is_ok() {
+            return self.get_dim();
+        }
+
+        self.monitor_outputs()?;
+        Ok(self.undimmed.is_some())
+    }
+
+    /// Dim or undim the monitor outputs
+    ///
+    /// Devices without a Dim button have their monitor outputs turned down
+    /// by [`MONITOR_DIM_DB`], and restored to their previous volumes when
+    /// undimmed.
+    pub fn set_monitor_dim(&mut self, dimmed: bool) -> Result<()> {
+        self.ensure_initialized()?;
+
+        if self.lookup_config(ConfigParam::DimMute).is_ok() {
+            return self.set_dim(dimmed);
+        }
+
+        let outputs = self.monitor_outputs()?;
+        if dimmed == self.undimmed.is_some() {
+            return Ok(());
+        }
+
+        tracing::info!("Setting monitor Dim: {}", dimmed);
+        if let Some(volumes) = self.undimmed.take() {
+            return self.set_volumes(&volumes);
+        }
+
+        let volumes = outputs
+            .into_iter()
+            .map(|output| Ok((output, self.get_volume(output)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let dimmed: Vec<(u8, f32)> = volumes
+            .iter()
+            .map(|&(output, db)| (output, db + MONITOR_DIM_DB))
+            .collect();
+        self.set_volumes(&dimmed)?;
+        self.undimmed = Some(volumes);
+        Ok(())
+    }
+
+    /// Get the monitor volume in dB, ignoring Dim
+    ///
+    /// This is the volume of the first monitor output.
+    pub fn get_master_volume(&mut self) -> Result<f32> {
+        let output = self.monitor_outputs()?[0];
+
+        match self.undimmed.as_ref().and_then(|volumes| volumes.first()) {
+            Some(&(_, db)) => Ok(db),
+            None => self.get_volume(output),
+        }
+    }
+
+    /// Set the volume of every monitor output, in dB
+    ///
+    /// While dimmed, the volume is restored when undimmed.
+    pub fn set_master_volume(&mut self, volume_db: f32) -> Result<()> {
+        let outputs = self.monitor_outputs()?;
+        let volume_db = self.volume_scale().clamp(volume_db);
+
+        tracing::info!("Setting monitor volume: {} dB", volume_db);
+        let offset = match &mut self.undimmed {
+            Some(volumes) => {
+                volumes.iter_mut().for_each(|(_, db)| *db = volume_db);
+                MONITOR_DIM_DB
+            }
+            None => 0.0,
+        };
+
+        let volumes: Vec<(u8, f32)> = outputs
+            .into_iter()
+            .map(|output| (output, volume_db + offset))
+            .collect();
+        self.set_volumes(&volumes)
+    }
+
+    /// Read the monitor volume, Mute and Dim into a mixer state
+    ///
+    /// Devices without monitor outputs leave the state as it is.
+    pub fn read_master(&mut self, state: &mut MixerState) -> Result<()> {
+        let volume_db = match self.get_master_volume() {
+            Err(Error::NotSupported(_)) => return Ok(()),
+            result => result?,
+        };
+
+        state.master_volume_db = volume_db;
+        state.master_muted = self.get_monitor_mute()?;
+        state.dim = self.get_monitor_dim()?;
+        Ok(())
+    }
+
+    /// Apply a mixer state's monitor volume, Mute and Dim to the device
+    pub fn apply_master(&mut self, state: &MixerState) -> Result<()> {
+        self.set_master_volume(state.master_volume_db)?;
+        self.set_monitor_dim(state.dim)?;
+        self.set_monitor_mute(state.master_muted)
+    }
+
+    /// Line outputs named as monitor outputs in the model's port layout
+    fn monitor_outputs(&self) -> Result<Vec<u8>> {
+        let model = self
+            .model
+            .ok_or_else(|| Error::NotSupported("Monitor outputs: device model unknown".to_string()))?;
+
+        let outputs: Vec<u8> = model
+            .port_layout()
+            .monitor_outputs()
+            .into_iter()
+            .map(|output| output as u8)
+            .collect();
+        if outputs.is_empty() {
+            return Err(Error::NotSupported(format!("Monitor outputs on {}", model)));
+        }
+
+        Ok(outputs)
+    }
+
+    /// Get whether the device is in MSD ("Easy Start") mode
+    pub fn get_msd_mode(&mut self) -> Result<bool> {
+        self.ensure_initialized()?;
+
+        Ok(self.get_config(ConfigParam::MsdSwitch, 0)? != 0)
+    }
+
+    /// Turn off MSD mode and reboot the device
+    ///
+    /// The device drops off the bus and re-enumerates with full
+    /// functionality; it must be reopened once hotplug reports it again.
+    pub fn disable_msd_mode(&mut self) -> Result<()> {
+        self.ensure_initialized()?;
+
+        tracing::info!("Disabling MSD mode");
+        self.set_config(ConfigParam::MsdSwitch, 0, 0)?;
+        self.reboot()
+    }
+
+    /// Reboot the device
+    ///
+    /// The device drops off the bus straight away, so a stall or disconnect
+    /// while sending is expected. Afterwards every call fails with
+    /// `Error::Disconnected`; reopen the device once it re-enumerates.
+    pub fn reboot(&mut self) -> Result<()> {
+        self.ensure_initialized()?;
+
+        tracing::info!("Rebooting device");
+        match self.send_command(FcpOpcode::Reboot, &[], 0) {
+            Ok(_) => {}
+            Err(Error::Disconnected | Error::Timeout(_)) => {}
+            Err(Error::Usb(msg)) if msg.contains("Stall") => {
+                tracing::debug!("Expected error during reboot: {}", msg);
+            }
+            Err(e) => return Err(e),
+        }
+
+        self.initialized = false;
+        self.rebooted = true;
+        Ok(())
+    }
+
+    /// Find the number of the flash segment called `name`
+    fn find_flash_segment(&mut self, name: &str) -> Result<u32> {
+        let info = self.send_command(FcpOpcode::FlashInfo, &[], 16)?;
+        if info.len() < 8 {
+            return Err(Error::Protocol("Flash info response too short".to_string()));
+        }
+        let count = u32::from_le_bytes([info[4], info[5], info[6], info[7]]);
+
+        for segment in 0..count {
+            let data = self.send_command(FcpOpcode::FlashSegmentInfo, &segment.to_le_bytes(), 24)?;
+            if data.len() < 24 {
+                return Err(Error::Protocol("Flash segment info response too short".to_string()));
+            }
+            let segment_name = data[8..24].split(|&b| b == 0).next().unwrap_or_default();
+            if segment_name == name.as_bytes() {
+                return Ok(segment);
+            }
+        }
+
+        Err(Error::NotSupported(format!("Flash segment {}", name)))
+    }
+
+    /// Erase the saved settings, restoring the factory defaults
+    ///
+    /// Only the settings segment is erased, never the firmware. Refused
+    /// unless `confirm` is set. The running settings are untouched until the
+    /// device restarts, so follow with [`Self::reboot`] to load the defaults
+    /// straight away; saving to flash before then writes them back.
+    pub async fn erase_config(&mut self, confirm: bool) -> Result<()> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+        if !confirm {
+            return Err(Error::InvalidParameter("Config erase not confirmed".to_string()));
+        }
+        self.ensure_initialized()?;
+
+        let segment = self.find_flash_segment(SETTINGS_SEGMENT_NAME)?;
+        tracing::warn!("Erasing saved configuration (flash segment {})", segment);
+
+        let mut request = segment.to_le_bytes().to_vec();
+        request.extend_from_slice(&0u32.to_le_bytes());
+        self.send_command(FcpOpcode::FlashErase, &request, 0)?;
+
+        let deadline = tokio::time::Instant::now() + FLASH_TIMEOUT;
+        loop {
+            let progress = self.send_command(FcpOpcode::FlashEraseProgress, &request, 1)?;
+            if progress.first() == Some(&FLASH_ERASE_DONE) {
+                break;
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::Protocol("Config erase did not finish".to_string()));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        tracing::info!("Saved configuration erased");
+        Ok(())
+    }
+
+    /// Start an ESP DFU transfer of `length` bytes with the given MD5 hash
+    pub fn esp_dfu_start(&mut self, length: u32, md5: &[u8; 16]) -> Result<()> {
+        self.ensure_initialized()?;
+
+        let mut request = Vec::with_capacity(24);
+        request.extend_from_slice(&0u32.to_le_bytes());  // offset
+        request.extend_from_slice(&length.to_le_bytes());
+        request.extend_from_slice(md5);
+
+        self.send_command(FcpOpcode::EspDfuStart, &request, 0)?;
+        Ok(())
+    }
+
+    /// Send one block of ESP firmware; an empty block ends the transfer
+    pub fn esp_dfu_write(&mut self, block: &[u8]) -> Result<()> {
+        self.ensure_initialized()?;
+
+        if block.len() > ESP_DFU_BLOCK_SIZE {
+            return Err(Error::InvalidParameter(format!(
+                "ESP DFU block too large: {} bytes (max {})",
+                block.len(),
+                ESP_DFU_BLOCK_SIZE
+            )));
+        }
+
+        self.send_command(FcpOpcode::EspDfuWrite, block, 0)?;
+        Ok(())
+    }
+
+    /// Flash a new ESP (Wi-Fi module) firmware image
+    ///
+    /// Requires the device map (see `read_devmap`). The ESP is turned off,
+    /// sent the image in `ESP_DFU_BLOCK_SIZE` blocks, and turned back on.
+    /// `progress` is called with the percentage written. The device only
+    /// signals DFU changes on the interrupt endpoint, so the ESP state and
+    /// DFU notification values are polled instead.
+    pub async fn esp_dfu_update(&mut self, data: &[u8], mut progress: impl FnMut(u8)) -> Result<()> {
+        self.ensure_initialized()?;
+
+        let layout = match &self.devmap {
+            Some(devmap) => EspDfuLayout::from_devmap(devmap)?,
+            None => return Err(Error::NotSupported("ESP DFU requires the device map".to_string())),
+        };
+
+        if data.is_empty() {
+            return Err(Error::InvalidParameter("ESP firmware image is empty".to_string()));
+        }
+
+        progress(0);
+
+        // State 0 isn't an eSuperState value: not running leapfrog firmware
+        let state = self.read_data(layout.state_offset, 1)?;
+        if state == 0 {
+            return Err(Error::NotSupported("ESP is not running leapfrog firmware".to_string()));
+        } else if state == layout.state_normal {
+            self.set_esp_state(&layout, layout.state_off).await?;
+        } else if state != layout.state_off {
+            return Err(Error::Protocol(format!("ESP is busy (state {}), cannot update firmware", state)));
+        }
+
+        tracing::info!("Starting ESP DFU: {} bytes", data.len());
+        self.esp_dfu_start(data.len() as u32, &compute_md5(data))?;
+        self.wait_for_esp_state(&layout, layout.state_dfu).await?;
+        self.wait_for_esp_dfu_notify(&layout, layout.notify_next_block).await?;
+
+        let mut last_percent = 0;
+        let mut written = 0;
+        for block in data.chunks(ESP_DFU_BLOCK_SIZE) {
+            self.esp_dfu_write(block)?;
+            self.wait_for_esp_dfu_notify(&layout, layout.notify_next_block).await?;
+
+            written += block.len();
+            let percent = (written * 100 / data.len()) as u8;
+            if percent != last_percent {
+                last_percent = percent;
+                progress(percent);
+            }
+        }
+
+        // The empty write asks the ESP to verify the MD5 and finish
+        self.esp_dfu_write(&[])?;
+        self.wait_for_esp_dfu_notify(&layout, layout.notify_finish).await?;
+
+        // Restart the ESP on the new firmware
+        self.set_esp_state(&layout, layout.state_off).await?;
+        self.set_esp_state(&layout, layout.state_normal).await?;
+
+        tracing::info!("ESP firmware update complete");
+        Ok(())
+    }
+
+    /// Request an ESP state and wait for the ESP to reach it
+    async fn set_esp_state(&mut self, layout: &EspDfuLayout, state: i32) -> Result<()> {
+        tracing::debug!("Setting ESP state to {}", state);
+
+        self.write_data(layout.boot_mode_offset, 1, state)?;
+        self.send_command(FcpOpcode::DataNotify, &layout.boot_mode_notify.to_le_bytes(), 0)?;
+
+        self.wait_for_esp_state(layout, state).await
+    }
+
+    /// Poll until the ESP reports `expected` or `ESP_DFU_TIMEOUT` elapses
+    async fn wait_for_esp_state(&mut self, layout: &EspDfuLayout, expected: i32) -> Result<()> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+        let deadline = tokio::time::Instant::now() + ESP_DFU_TIMEOUT;
+        loop {
+            let state = self.read_data(layout.state_offset, 1)?;
+            if state == expected {
+                return Ok(());
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::Protocol(format!(
+                    "ESP state change timeout: expected {}, got {}",
+                    expected, state
+                )));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Poll for a DFU notification from the ESP, clearing each one read
+    async fn wait_for_esp_dfu_notify(&mut self, layout: &EspDfuLayout, expected: i32) -> Result<()> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+        let deadline = tokio::time::Instant::now() + ESP_DFU_TIMEOUT;
+        loop {
+            let notify = self.read_data(layout.notify_offset, 1)?;
+            if notify != layout.notify_clear {
+                self.write_data(layout.notify_offset, 1, layout.notify_clear)?;
+
+                if notify == expected {
+                    return Ok(());
+                }
+                if notify == layout.notify_error {
+                    return Err(Error::Protocol("ESP reported a DFU error".to_string()));
+                }
+                tracing::debug!("Ignoring ESP DFU notification {}", notify);
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::Protocol(format!(
+                    "Timeout waiting for ESP DFU notification {}",
+                    expected
+                )));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Get whether standalone mode is enabled
+    pub fn get_standalone(&mut self) -> Result<bool> {
+        self.ensure_initialized()?;
+
+        Ok(self.get_config(ConfigParam::StandaloneSwitch, 0)? != 0)
+    }
+
+    /// Enable or disable standalone mode
+    pub fn set_standalone(&mut self, enabled: bool) -> Result<()> {
+        self.ensure_initialized()?;
+
+        tracing::info!("Setting standalone mode: {}", enabled);
+        self.set_config(ConfigParam::StandaloneSwitch, 0, enabled as i32)
+    }
+
+    /// Get whether talkback is enabled and active (18i20)
+    pub fn get_talkback(&mut self) -> Result<bool> {
+        self.ensure_initialized()?;
+
+        config_items::get_talkback(self)
+    }
+
+    /// Get where the device is drawing its power from
+    ///
+    /// Only bus-powerable devices report this. Insufficient power silently
+    /// disables phantom power.
+    pub fn power_status(&mut self) -> Result<PowerStatus> {
+        self.ensure_initialized()?;
+
+        config_items::get_power_status(self)
+    }
+
+    /// Get the monitor knob position in dB
+    pub fn get_knob_position(&mut self) -> Result<f32> {
+        self.ensure_initialized()?;
+
+        Ok(config_items::get_knob_position(self)? as f32)
+    }
+
+    /// Get the bitmap of outputs controlled by the monitor knob
+    /// (bit 0 = output 1)
+    pub fn get_knob_assignment(&mut self) -> Result<u32> {
+        self.ensure_initialized()?;
+
+        config_items::get_knob_assignment(self)
+    }
+
+    /// Choose which outputs the monitor knob controls (bit 0 = output 1)
+    ///
+    /// The other outputs are controlled in software.
+    pub fn set_knob_assignment(&mut self, mask: u32) -> Result<()> {
+        self.ensure_initialized()?;
+
+        tracing::info!("Setting monitor knob assignment: {:#x}", mask);
+        config_items::set_knob_assignment(self, mask)
+    }
+
+    /// Enable or disable talkback (18i20)
+    pub fn set_talkback(&mut self, enabled: bool) -> Result<()> {
+        self.ensure_initialized()?;
+
+        tracing::info!("Setting talkback: {}", enabled);
+        config_items::set_talkback(self, enabled)
+    }
+
+    /// Get whether talkback is routed to a mix (0 = Mix A)
+    pub fn get_talkback_mix(&mut self, mix_index: u8) -> Result<bool> {
+        self.ensure_initialized()?;
+
+        let bitmap = config_items::get_talkback_map(self)?;
+        Ok(mix_index < 16 && bitmap & (1 << mix_index) != 0)
+    }
+
+    /// Route talkback to a mix (0 = Mix A), or remove it
+    pub fn set_talkback_mix(&mut self, mix_index: u8, enabled: bool) -> Result<()> {
+        self.ensure_initialized()?;
+
+        tracing::info!("Setting talkback to mix {}: {}", mix_index, enabled);
+        config_items::set_talkback_mix(self, mix_index, enabled)
+    }
+
+    /// Start hardware autogain on an input (0-based)
+    pub fn start_autogain(&mut self, input: u8) -> Result<()> {
+        self.ensure_initialized()?;
+
+        tracing::info!("Starting autogain on input {}", input);
+        config_items::start_autogain(self, input)
+    }
+
+    /// Get the autogain status of an input (0-based)
+    pub fn autogain_status(&mut self, input: u8) -> Result<AutogainStatus> {
+        self.ensure_initialized()?;
+
+        config_items::get_autogain_status(self, input)
+    }
+
+    /// Start autogain and poll until it finishes or `timeout` elapses
+    pub async fn run_autogain(&mut self, input: u8, timeout: Duration) -> Result<AutogainStatus> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+        self.start_autogain(input)?;
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            let status = self.autogain_status(input)?;
+            if status.is_finished() {
+                tracing::info!("Autogain on input {} finished: {}", input, status);
+                return Ok(status);
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::Protocol(format!(
+                    "Autogain on input {} did not finish within {:?}",
+                    input, timeout
+                )));
+            }
+        }
+    }
+
+    /// Configuration offsets (from mixer_scarlett2.c), used when no
+    /// device map is available
+    const LINE_OUT_VOLUME_OFFSET: u32 = 0x34;
+    const MUTE_SWITCH_OFFSET: u32 = 0x5c;
+
+    /// Get the line output volume scale of the device
+    pub fn volume_scale(&self) -> VolumeScale {
+        self.model.map(VolumeScale::for_model).unwrap_or_default()
+    }
+
+    /// Get volume for a specific output (0-based index), in dB
+    pub fn get_volume(&mut self, output_index: u8) -> Result<f32> {
+        self.ensure_initialized()?;
+
+        let (offset, size) = self.output_location(DevMapParam::LineOutVolume, output_index);
+        let raw_value = self.read_cached(offset, size)?;
+        let db = self.volume_scale().to_db(raw_value);
+
+        tracing::debug!("Output {} volume: {} dB (raw={})", output_index, db, raw_value);
+        Ok(db)
+    }
+
+    /// Set volume for a specific output (0-based index), in dB
+    ///
+    /// The volume is clamped to the device's range and rounded to its step.
+    pub fn set_volume(&mut self, output_index: u8, volume_db: f32) -> Result<()> {
+        self.ensure_initialized()?;
+
+        let scale = self.volume_scale();
+        let device_value = scale.to_raw(volume_db);
+
+        tracing::info!(
+            "Setting output {} volume to {} dB (raw={})",
+            output_index,
+            scale.to_db(device_value),
+            device_value
+        );
+
+        let (offset, size) = self.output_location(DevMapParam::LineOutVolume, output_index);
+        self.write_data(offset, size, device_value)?;
+
+        Ok(())
+    }
+
+    /// Set the volume of several outputs, in dB
+    ///
+    /// Outputs whose volume registers are adjacent are written with a single
+    /// DataWrite; the rest are written one at a time.
+    pub fn set_volumes(&mut self, volumes: &[(u8, f32)]) -> Result<()> {
+        let scale = self.volume_scale();
+        let values: Vec<(u8, i32)> = volumes
+            .iter()
+            .map(|&(output, db)| (output, scale.to_raw(db)))
+            .collect();
+        self.write_output_values(DevMapParam::LineOutVolume, &values)
+    }
+
+    /// Write one parameter for several outputs, batching adjacent registers
+    fn write_output_values(&mut self, param: DevMapParam, values: &[(u8, i32)]) -> Result<()> {
+        self.ensure_initialized()?;
+
+        let mut writes: Vec<(u32, u32, i32)> = values
+            .iter()
+            .map(|&(output, value)| {
+                let (offset, size) = self.output_location(param, output);
+                (offset, size, value)
+            })
+            .collect();
+        writes.sort_by_key(|&(offset, _, _)| offset);
+        writes.dedup_by_key(|&mut (offset, _, _)| offset);
+
+        let mut start = 0;
+        while start < writes.len() {
+            let (offset, size, _) = writes[start];
+            let mut end = start + 1;
+            while end < writes.len()
+                && writes[end].1 == size
+                && writes[end].0 == writes[end - 1].0 + size
+            {
+                end += 1;
+            }
+
+            if end - start == 1 {
+                self.write_data(offset, size, writes[start].2)?;
+            } else {
+                let data: Vec<u8> = writes[start..end]
+                    .iter()
+                    .flat_map(|&(_, size, value)| value.to_le_bytes().into_iter().take(size as usize))
+                    .collect();
+                tracing::debug!("Writing {} {:?} values at offset {:#x}", end - start, param, offset);
+                self.write_data_block(offset, &data)?;
+            }
+
+            start = end;
+        }
+
+        Ok(())
+    }
+
+    /// Number of line outputs with a volume control
+    fn num_line_outputs(&self) -> usize {
+        match (&self.devmap, self.model) {
+            (Some(devmap), _) if devmap.num_outputs() > 0 => devmap.num_outputs(),
+            (_, Some(model)) => DeviceCapabilities::for_model(model).num_outputs as usize,
+            _ => 0,
+        }
+    }
+
+    /// Get the volume of every line output, in dB
+    ///
+    /// Reads the whole volume block in one transfer when the registers are
+    /// adjacent.
+    pub fn get_all_volumes(&mut self) -> Result<Vec<f32>> {
+        self.ensure_initialized()?;
+
+        let count = self.num_line_outputs();
+        if count == 0 {
+            return Err(Error::NotSupported("Line output volume".to_string()));
+        }
+
+        let locations: Vec<(u32, u32)> = (0..count)
+            .map(|output| self.output_location(DevMapParam::LineOutVolume, output as u8))
+            .collect();
+        let (first, size) = locations[0];
+        let contiguous = locations
+            .iter()
+            .enumerate()
+            .all(|(i, &(offset, slot_size))| slot_size == 2 && offset == first + i as u32 * size);
+
+        let raw: Vec<i32> = if contiguous {
+            let block = self.read_data_block(first, count as u32 * size)?;
+            block
+                .chunks_exact(2)
+                .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]) as i32)
+                .collect()
+        } else {
+            locations
+                .iter()
+                .map(|&(offset, size)| self.read_data(offset, size))
+                .collect::<Result<_>>()?
+        };
+
+        if let Some(cache) = &mut self.cache {
+            for (&(offset, size), &value) in locations.iter().zip(&raw) {
+                cache.insert(offset, size, value);
+            }
+        }
+
+        let scale = self.volume_scale();
+        Ok(raw.into_iter().map(|value| scale.to_db(value)).collect())
+    }
+
+    /// Adjust volume by delta (in dB), returning the new volume
+    ///
+    /// A linked partner is set to the same volume, which also pulls the
+    /// pair back together if they had drifted apart.
+    pub fn adjust_volume(&mut self, output_index: u8, delta_db: f32) -> Result<f32> {
+        let scale = self.volume_scale();
+        let current = self.get_volume(output_index)?;
+        let new_volume = scale.to_db(scale.to_raw(current + delta_db));
+        self.set_linked_volume(output_index, new_volume)?;
+        Ok(new_volume)
+    }
+
+    /// Link two outputs as a stereo pair
+    pub fn link_outputs(&mut self, left: u8, right: u8) -> Result<()> {
+        if left == right {
+            return Err(Error::InvalidParameter(format!("Cannot link output {} to itself", left)));
+        }
+        for output in [left, right] {
+            if let Some(partner) = self.linked_output(output) {
+                return Err(Error::InvalidParameter(format!(
+                    "Output {} is already linked to output {}",
+                    output, partner
+                )));
+            }
+        }
+
+        self.links.push((left, right));
+        Ok(())
+    }
+
+    /// Unlink the pair containing an output, returning its former partner
+    pub fn unlink_outputs(&mut self, output_index: u8) -> Option<u8> {
+        let partner = self.linked_output(output_index)?;
+        self.links.retain(|&(a, b)| a != output_index && b != output_index);
+        Some(partner)
+    }
+
+    /// Replace the link table, e.g. with the pairs saved in a device config
+    pub fn set_output_links(&mut self, links: &[(u8, u8)]) -> Result<()> {
+        self.links.clear();
+        for &(left, right) in links {
+            self.link_outputs(left, right)?;
+        }
+        Ok(())
+    }
+
+    /// Stereo-linked output pairs
+    pub fn output_links(&self) -> &[(u8, u8)] {
+        &self.links
+    }
+
+    /// The output linked to `output_index`, if any
+    pub fn linked_output(&self, output_index: u8) -> Option<u8> {
+        self.links.iter().find_map(|&(a, b)| match output_index {
+            i if i == a => Some(b),
+            i if i == b => Some(a),
+            _ => None,
+        })
+    }
+
+    /// Outputs controlled together with `output_index`
+    fn linked_group(&self, output_index: u8) -> Vec<u8> {
+        std::iter::once(output_index)
+            .chain(self.linked_output(output_index))
+            .collect()
+    }
+
+    /// Set the volume of an output and its linked partner in one write
+    pub fn set_linked_volume(&mut self, output_index: u8, volume_db: f32) -> Result<()> {
+        let volumes: Vec<(u8, f32)> = self
+            .linked_group(output_index)
+            .into_iter()
+            .map(|output| (output, volume_db))
+            .collect();
+        self.set_volumes(&volumes)
+    }
+
+    /// Set the mute of an output and its linked partner in one write
+    pub fn set_linked_mute(&mut self, output_index: u8, muted: bool) -> Result<()> {
+        tracing::info!("Setting output {} mute (linked): {}", output_index, muted);
+
+        let values: Vec<(u8, i32)> = self
+            .linked_group(output_index)
+            .into_iter()
+            .map(|output| (output, muted as i32))
+            .collect();
+        self.write_output_values(DevMapParam::MuteSwitch, &values)
+    }
+
+    /// Get mute status for a specific output
+    pub fn get_mute(&mut self, output_index: u8) -> Result<bool> {
+        self.ensure_initialized()?;
+
+        let (offset, size) = self.output_location(DevMapParam::MuteSwitch, output_index);
+        let muted = self.read_cached(offset, size)?;
+
+        Ok(muted != 0)
+    }
+
+    /// Set mute status for a specific output
+    pub fn set_mute(&mut self, output_index: u8, muted: bool) -> Result<()> {
+        self.ensure_initialized()?;
+
+        tracing::info!("Setting output {} mute: {}", output_index, muted);
+
+        let (offset, size) = self.output_location(DevMapParam::MuteSwitch, output_index);
+        self.write_data(offset, size, if muted { 1 } else { 0 })?;
+
+        Ok(())
+    }
+
+    /// Toggle mute for a specific output and its linked partner
+    pub fn toggle_mute(&mut self, output_index: u8) -> Result<bool> {
+        let current = self.get_mute(output_index)?;
+        let new_state = !current;
+        self.set_linked_mute(output_index, new_state)?;
+        Ok(new_state)
+    }
+}
+
+impl Protocol for FcpProtocol {
+    fn get_routing(&mut self) -> Result<RoutingMatrix> {
+        self.read_routing()
+    }
+
+    fn set_routing(&mut self, matrix: &RoutingMatrix) -> Result<()> {
+        self.write_routing(matrix, None)
+    }
+
+    fn get_mixer_state(&mut self) -> Result<MixerState> {
+        let gains = self.get_mix(MIXER_STATE_MIX)?;
+        let mut state = mixer_state_from_gains(&gains);
+        self.read_master(&mut state)?;
+        Ok(state)
+    }
+
+    fn set_channel_volume(&mut self, channel: usize, volume_db: f32) -> Result<()> {
+        let input = u16::try_from(channel)
+            .map_err(|_| Error::InvalidParameter(format!("No mixer input {}", channel)))?;
+        self.set_mixer_volume(MIXER_STATE_MIX, input, db_to_mixer_gain(volume_db) as u16)
+    }
+
+    /// Pans the mixer input across Mix A/B
+    fn set_channel_pan(&mut self, channel: usize, pan: f32) -> Result<()> {
+        let input = u16::try_from(channel)
+            .map_err(|_| Error::InvalidParameter(format!("No mixer input {}", channel)))?;
+        self.set_mix_pan(input, MIXER_STATE_MIX / 2, pan)
+    }
+
+    fn get_level_meters(&mut self) -> Result<Vec<LevelMeter>> {
+        let count = self.read_meter_count()?;
+        let levels = self.read_meters(count)?;
+        Ok(level_meters(levels.into_iter().map(meter_to_db)))
+    }
+
+    fn get_input_gain(&mut self, input: u8) -> Result<u8> {
+        FcpProtocol::get_input_gain(self, input)
+    }
+
+    fn set_input_gain(&mut self, input: u8, gain_db: u8) -> Result<()> {
+        FcpProtocol::set_input_gain(self, input, gain_db)
+    }
+
+    fn get_phantom(&mut self, channel_group: u8) -> Result<bool> {
+        FcpProtocol::get_phantom(self, channel_group)
+    }
+
+    fn set_phantom(&mut self, channel_group: u8, enabled: bool) -> Result<()> {
+        FcpProtocol::set_phantom(self, channel_group, enabled)
+    }
+
+    fn get_air(&mut self, input: u8) -> Result<AirMode> {
+        FcpProtocol::get_air(self, input)
+    }
+
+    fn set_air(&mut self, input: u8, mode: AirMode) -> Result<()> {
+        FcpProtocol::set_air(self, input, mode)
+    }
+
+    fn get_input_level(&mut self, input: u8) -> Result<InputLevel> {
+        FcpProtocol::get_input_level(self, input)
+    }
+
+    fn set_input_level(&mut self, input: u8, level: InputLevel) -> Result<()> {
+        FcpProtocol::set_input_level(self, input, level)
+    }
+
+    fn get_direct_monitor(&mut self) -> Result<DirectMonitorMode> {
+        FcpProtocol::get_direct_monitor(self)
+    }
+
+    fn set_direct_monitor(&mut self, mode: DirectMonitorMode) -> Result<()> {
+        FcpProtocol::set_direct_monitor(self, mode)
+    }
+
+    fn get_dim(&mut self) -> Result<bool> {
+        FcpProtocol::get_monitor_dim(self)
+    }
+
+    fn set_dim(&mut self, enabled: bool) -> Result<()> {
+        FcpProtocol::set_monitor_dim(self, enabled)
+    }
+
+    fn get_monitor_mute(&mut self) -> Result<bool> {
+        FcpProtocol::get_monitor_mute(self)
+    }
+
+    fn set_monitor_mute(&mut self, muted: bool) -> Result<()> {
+        FcpProtocol::set_monitor_mute(self, muted)
+    }
+
+    fn get_talkback(&mut self) -> Result<bool> {
+        FcpProtocol::get_talkback(self)
+    }
+
+    fn set_talkback(&mut self, enabled: bool) -> Result<()> {
+        FcpProtocol::set_talkback(self, enabled)
+    }
+
+    fn get_talkback_mix(&mut self, mix_index: u8) -> Result<bool> {
+        FcpProtocol::get_talkback_mix(self, mix_index)
+    }
+
+    fn set_talkback_mix(&mut self, mix_index: u8, enabled: bool) -> Result<()> {
+        FcpProtocol::set_talkback_mix(self, mix_index, enabled)
+    }
+
+    fn get_msd_mode(&mut self) -> Result<bool> {
+        FcpProtocol::get_msd_mode(self)
+    }
+
+    fn disable_msd_mode(&mut self) -> Result<()> {
+        FcpProtocol::disable_msd_mode(self)
+    }
+
+    fn get_standalone(&mut self) -> Result<bool> {
+        FcpProtocol::get_standalone(self)
+    }
+
+    fn set_standalone(&mut self, enabled: bool) -> Result<()> {
+        FcpProtocol::set_standalone(self, enabled)
+    }
+
+    fn sync_status(&mut self) -> Result<SyncStatus> {
+        FcpProtocol::sync_status(self)
+    }
+
+    fn power_status(&mut self) -> Result<PowerStatus> {
+        FcpProtocol::power_status(self)
+    }
+}
+
+impl ConfigAccess for FcpProtocol {
+    fn config_model(&self) -> Option<DeviceModel> {
+        self.model
+    }
+
+    /// Once the device map has been read it says where every control is;
+    /// a control it doesn't describe isn't supported
+    fn lookup_config(&self, param: ConfigParam) -> Result<(DeviceModel, ConfigItem)> {
+        let model = self.model.ok_or_else(|| {
+            Error::NotSupported(format!("{:?}: device model unknown", param))
+        })?;
+
+        let item = match &self.devmap {
+            Some(devmap) => devmap.input_config_item(param),
+            None => config_items::config_item(model, param),
+        };
+        item.map(|item| (model, item))
+            .ok_or_else(|| Error::NotSupported(format!("{:?} on {}", param, model)))
+    }
+
+    fn read_data(&mut self, offset: u32, size: u32) -> Result<i32> {
+        FcpProtocol::read_data(self, offset, size)
+    }
+
+    fn write_data(&mut self, offset: u32, size: u32, value: i32) -> Result<()> {
+        FcpProtocol::write_data(self, offset, size, value)
+    }
+
+    fn activate_config(&mut self, activate: u32) -> Result<()> {
+        self.send_command(FcpOpcode::DataNotify, &activate.to_le_bytes(), 0)?;
+        self.invalidate_members(|member| member.notify_device == Some(activate));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_transport::{packet, MockTransport, ThreadedTransport};
+    use std::sync::Arc;
+
+    /// Protocol ready to send commands, talking to a scripted transport
+    fn scripted_protocol(model: DeviceModel) -> (FcpProtocol, MockTransport) {
+        let mock = MockTransport::new();
+        let mut fcp = FcpProtocol::new(Box::new(mock.clone())).with_model(model);
+        fcp.initialized = true;
+        (fcp, mock)
+    }
+
+    /// Expect `opcode` as command `seq`, and answer it if it reads a response
+    fn expect(mock: &MockTransport, seq: u16, opcode: FcpOpcode, request: &[u8], response: Option<&[u8]>) {
+        mock.expect_command(0, opcode as u32, seq, request, response);
+    }
+
+    /// Expect a data space read at `offset`, answered with `value`
+    fn expect_read(mock: &MockTransport, seq: u16, offset: u32, value: &[u8]) {
+        let request = [offset.to_le_bytes(), (value.len() as u32).to_le_bytes()].concat();
+        expect(mock, seq, FcpOpcode::DataRead, &request, Some(value));
+    }
+
+    /// Expect a data space write of `value` at `offset`
+    fn expect_write(mock: &MockTransport, seq: u16, offset: u32, value: &[u8]) {
+        let request = [&offset.to_le_bytes()[..], &(value.len() as u32).to_le_bytes(), value].concat();
+        expect(mock, seq, FcpOpcode::DataWrite, &request, None);
+    }
+
+    /// Expect an activation notify for config item `activate`
+    fn expect_notify(mock: &MockTransport, seq: u16, activate: u32) {
+        expect(mock, seq, FcpOpcode::DataNotify, &activate.to_le_bytes(), None);
+    }
+
+    /// Expect a write of `value` to control `index` through the parameter
+    /// buffer at `pbuf`, and its activation
+    fn expect_pbuf_write(mock: &MockTransport, seq: u16, pbuf: u32, index: u8, value: u8, activate: u32) {
+        expect_write(mock, seq, pbuf + 1, &[index]);
+        expect_write(mock, seq + 1, pbuf, &[value]);
+        expect_notify(mock, seq + 2, activate);
+    }
+
+    /// Response to command `seq` carrying device error `error`
+    fn error_response(opcode: u32, seq: u16, error: u32) -> Vec<u8> {
+        let mut response = packet(opcode, seq, &[]);
+        response[8..12].copy_from_slice(&error.to_le_bytes());
+        response
+    }
+
+    /// INIT_2 response from firmware 2403, with only the parsed bytes filled in
+    const INIT_2_RESPONSE: [u8; 84] = {
+        let mut response = [0u8; 84];
+        response[0] = 0x01;
+        response[8] = 0x63;
+        response[9] = 0x09;
+        response
+    };
+
+    #[test]
+    fn test_versions_from_init() {
+        let versions = DeviceVersions::from_init_response(&INIT_2_RESPONSE).unwrap();
+        assert_eq!(versions.firmware, 2403);
+        assert_eq!(versions.esp, None);
+        assert!(DeviceVersions::from_init_response(&INIT_2_RESPONSE[..10]).is_none());
+
+        // Both init steps go out with sequence 1; the device may answer with 0
+        let mock = MockTransport::new();
+        let mut fcp = FcpProtocol::new(Box::new(mock.clone()));
+        let request = || ControlTransfer::class_out(2, 0, 0);
+        mock.expect_out(request(), &packet(FcpOpcode::Init1 as u32, 1, &[]));
+        mock.expect_response(0, &packet(FcpOpcode::Init1 as u32, 0, &[0; 24]));
+        mock.expect_out(request(), &packet(FcpOpcode::Init2 as u32, 1, &[]));
+        mock.expect_response(0, &packet(FcpOpcode::Init2 as u32, 0, &INIT_2_RESPONSE));
+
+        assert!(fcp.versions().is_none());
+        fcp.init().unwrap();
+        assert_eq!(fcp.versions().unwrap().firmware, 2403);
+        mock.assert_done();
+
+        // Commands carry on from there
+        expect_read(&mock, 2, 0x10, &[0]);
+        fcp.read_data(0x10, 1).unwrap();
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_input_gain_read() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett2i2Gen4);
+        expect_read(&mock, 1, 0x4b + 1, &[42]);
+
+        assert_eq!(fcp.get_input_gain(1).unwrap(), 42);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_seq_num_wraparound() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett2i2Gen4);
+        fcp.seq_num = u16::MAX;
+
+        expect_read(&mock, 0, 0x4b, &[0]);
+        expect_read(&mock, 1, 0x4b, &[0]);
+        for _ in 0..2 {
+            fcp.get_input_gain(0).unwrap();
+        }
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_seq_mismatch_recovery() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett2i2Gen4);
+        fcp.seq_num = 41;
+        let request = || ControlTransfer::class_out(2, 0, 0);
+        let read = [0x4c, 0, 0, 0, 1, 0, 0, 0];
+
+        // Stale answer, then INIT_1, INIT_2 and the retried read
+        mock.expect_out(request(), &packet(FcpOpcode::DataRead as u32, 42, &read));
+        mock.expect_response(0, &packet(FcpOpcode::DataRead as u32, 7, &[7]));
+        expect(&mock, 1, FcpOpcode::Init1, &[], Some(&[]));
+        expect(&mock, 1, FcpOpcode::Init2, &[], Some(&[]));
+        expect_read(&mock, 2, 0x4c, &[42]);
+
+        assert_eq!(fcp.get_input_gain(1).unwrap(), 42);
+        mock.assert_done();
+
+        // A device that never answers in sequence is an error
+        mock.expect_out(request(), &packet(FcpOpcode::DataRead as u32, 3, &read));
+        mock.expect_response(0, &packet(FcpOpcode::DataRead as u32, 103, &[7]));
+        mock.expect_out(request(), &packet(FcpOpcode::Init1 as u32, 1, &[]));
+        mock.expect_response(0, &packet(FcpOpcode::Init1 as u32, 101, &[]));
+        assert!(fcp.get_input_gain(1).is_err());
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett2i2Gen4);
+        let stall = || Error::Usb("Control OUT failed: Stall".to_string());
+        let request = || ControlTransfer::class_out(2, 0, 0);
+        let read = [0x4b, 0, 0, 0, 1, 0, 0, 0];
+        let write = [0x10, 0, 0, 0, 1, 0, 0, 0, 1];
+
+        // Reads are retried by default, as a new command
+        mock.expect_error(request(), &packet(FcpOpcode::DataRead as u32, 1, &read), stall());
+        expect_read(&mock, 2, 0x4b, &[42]);
+        assert_eq!(fcp.get_input_gain(0).unwrap(), 42);
+
+        // Writes aren't, unless the policy allows it
+        mock.expect_error(request(), &packet(FcpOpcode::DataWrite as u32, 3, &write), stall());
+        assert!(fcp.write_data(0x10, 1, 1).is_err());
+
+        fcp.set_retry_policy(RetryPolicy::default().with_retry_writes(true));
+        mock.expect_error(request(), &packet(FcpOpcode::DataWrite as u32, 4, &write), stall());
+        expect_write(&mock, 5, 0x10, &[1]);
+        fcp.write_data(0x10, 1, 1).unwrap();
+
+        // Flash writes never are
+        mock.expect_error(request(), &packet(FcpOpcode::FlashWrite as u32, 6, &[0; 8]), stall());
+        assert!(fcp.send_command(FcpOpcode::FlashWrite, &[0; 8], 0).is_err());
+
+        // Firmware updaters can opt out entirely
+        let mut fcp = fcp.with_retry_policy(RetryPolicy::none());
+        mock.expect_error(request(), &packet(FcpOpcode::DataRead as u32, 7, &read), stall());
+        assert!(fcp.get_input_gain(0).is_err());
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_meter_labels() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+
+        // Without a device map the slots are only numbered
+        expect(&mock, 1, FcpOpcode::MeterInfo, &[], Some(&[2, 0, 0, 0]));
+        let labels = fcp.read_meter_info().unwrap();
+        assert_eq!(labels.iter().map(|l| l.name.as_str()).collect::<Vec<_>>(), ["Meter 1", "Meter 2"]);
+        assert!(labels.iter().all(|l| l.port.is_none()));
+
+        fcp.devmap = Some(DevMap::from_json(crate::devmap::tests::TEST_JSON.as_bytes()).unwrap());
+        expect(&mock, 2, FcpOpcode::MeterInfo, &[], Some(&[5, 0, 0, 0]));
+        let labels = fcp.read_meter_info().unwrap();
+        let names: Vec<&str> = labels.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["Analogue 1", "Monitor 1", "PCM 1", "PCM 1", "Meter 5"]);
+        assert_eq!(labels[2].port.as_ref().unwrap().port_type, PortType::PcmOut);
+        assert_eq!(labels[3].port.as_ref().unwrap().port_type, PortType::PcmIn);
+
+        // The labels are reused for later reads
+        let levels: Vec<u8> = [4095u32, 0, 0, 0, 0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        expect(&mock, 3, FcpOpcode::MeterRead, &meter_request(5), Some(&levels));
+        let labeled = fcp.read_labeled_meters().unwrap();
+        assert_eq!(labeled[0], ("Analogue 1".to_string(), 0.0));
+        assert_eq!(labeled[4], ("Meter 5".to_string(), -127.0));
+        mock.assert_done();
+
+        assert_eq!(devmap_port_type("Mixer 34", false), Some(PortType::MixerIn));
+        assert_eq!(devmap_port_type("Microphone", true), Some(PortType::AnalogIn));
+        assert_eq!(devmap_port_type("Talkback", true), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_meters_async() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+        fcp.seq_num = 9;
+        let request = || ControlTransfer::class_out(2, 0, 0);
+        let stall = || Error::Usb("Control OUT failed: Stall".to_string());
+        let read = meter_request(2);
+
+        let levels: Vec<u8> = [0u32, 4095].iter().flat_map(|v| v.to_le_bytes()).collect();
+        expect(&mock, 10, FcpOpcode::MeterRead, &read, Some(&levels));
+        assert_eq!(fcp.read_meters_async(2).await.unwrap(), vec![0, 4095]);
+        assert_eq!(mock.transcript().last().unwrap().timeout, METER_TIMEOUT);
+
+        // Transient errors are retried
+        mock.expect_error(request(), &packet(FcpOpcode::MeterRead as u32, 11, &read), stall());
+        expect(&mock, 12, FcpOpcode::MeterRead, &read, Some(&levels));
+        assert_eq!(fcp.read_meters_async(2).await.unwrap(), vec![0, 4095]);
+
+        // A stale answer re-runs init, then the read is resent
+        mock.expect_out(request(), &packet(FcpOpcode::MeterRead as u32, 13, &read));
+        mock.expect_response(0, &packet(FcpOpcode::MeterRead as u32, 113, &levels));
+        expect(&mock, 1, FcpOpcode::Init1, &[], Some(&[]));
+        expect(&mock, 1, FcpOpcode::Init2, &[], Some(&[]));
+        expect(&mock, 2, FcpOpcode::MeterRead, &read, Some(&levels));
+        assert_eq!(fcp.read_meters_async(2).await.unwrap(), vec![0, 4095]);
+        mock.assert_done();
+    }
+
+    /// Protocol on a transport whose transfers finish on another thread
+    fn threaded_protocol(model: DeviceModel) -> (FcpProtocol, MockTransport) {
+        let mock = MockTransport::new();
+        let mut fcp = FcpProtocol::new(Box::new(ThreadedTransport(mock.clone()))).with_model(model);
+        fcp.initialized = true;
+        (fcp, mock)
+    }
+
+    #[tokio::test]
+    async fn test_transfers_in_current_thread_runtime() {
+        let (mut fcp, mock) = threaded_protocol(DeviceModel::Scarlett2i2Gen4);
+        let levels: Vec<u8> = [7u32, 4095].iter().flat_map(|v| v.to_le_bytes()).collect();
+
+        expect(&mock, 1, FcpOpcode::MeterRead, &meter_request(2), Some(&levels));
+        assert_eq!(fcp.read_meters(2).unwrap(), vec![7, 4095]);
+
+        // Without an async path the read falls back to blocking transfers
+        expect(&mock, 2, FcpOpcode::MeterRead, &meter_request(2), Some(&levels));
+        assert_eq!(fcp.read_meters_async(2).await.unwrap(), vec![7, 4095]);
+
+        expect_write(&mock, 3, 0xfd, &[0]);
+        expect_write(&mock, 4, 0xfc, &[2]);
+        expect_notify(&mock, 5, 11);
+        fcp.set_phantom(0, true).unwrap();
+        mock.assert_done();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_transfers_in_multi_thread_runtime() {
+        // Meters read on the sole worker must not stop its other tasks
+        let (mut fcp, mock) = threaded_protocol(DeviceModel::Scarlett2i2Gen4);
+        expect(&mock, 1, FcpOpcode::MeterRead, &meter_request(1), Some(&[0; 4]));
+
+        let ticker = tokio::spawn(tokio::time::sleep(Duration::from_millis(5)));
+        let task = tokio::spawn(async move { fcp.read_meters_async(1).await });
+        let result = tokio::time::timeout(Duration::from_secs(5), task).await;
+        assert_eq!(result.expect("meter read stalled the worker").unwrap().unwrap(), vec![0]);
+        ticker.await.unwrap();
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_transfers_on_blocking_thread() {
+        // How the GUI keeps device I/O off its event loop
+        let (mut fcp, mock) = threaded_protocol(DeviceModel::Scarlett2i2Gen4);
+        expect_read(&mock, 1, 0x48, &[1]);
+
+        let task = tokio::task::spawn_blocking(move || fcp.get_phantom(0));
+        let result = tokio::time::timeout(Duration::from_secs(5), task).await;
+        assert!(result.expect("phantom read never finished").unwrap().unwrap());
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett2i2Gen4);
+        let last_timeout = || mock.transcript().last().unwrap().timeout;
+
+        fcp.set_timeout(Duration::from_secs(5));
+        expect_write(&mock, 1, 0xfd, &[0]);
+        expect_write(&mock, 2, 0xfc, &[2]);
+        expect_notify(&mock, 3, 11);
+        fcp.set_phantom(0, true).unwrap();
+        assert_eq!(last_timeout(), Duration::from_secs(5));
+
+        // Flash commands never use less than the flash timeout
+        expect(&mock, 4, FcpOpcode::FlashErase, &[], None);
+        fcp.send_command(FcpOpcode::FlashErase, &[], 0).unwrap();
+        assert_eq!(last_timeout(), FLASH_TIMEOUT);
+
+        // Meter reads never wait longer than the meter timeout
+        expect(&mock, 5, FcpOpcode::MeterRead, &meter_request(2), Some(&[0; 8]));
+        fcp.read_meters(2).unwrap();
+        assert_eq!(last_timeout(), METER_TIMEOUT);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_read_limits() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+
+        // Nothing is sent for reads that can't be valid
+        assert!(matches!(fcp.read_meters(0xffff), Err(Error::InvalidParameter(_))));
+        assert!(matches!(fcp.read_data(0x34, 3), Err(Error::InvalidParameter(_))));
+        assert!(matches!(fcp.read_data(0x34, 0x10000), Err(Error::InvalidParameter(_))));
+        assert!(matches!(fcp.read_data_block(0, u32::MAX), Err(Error::InvalidParameter(_))));
+        assert!(mock.transcript().is_empty());
+
+        // Once the meter count is known, it is the limit
+        expect(&mock, 1, FcpOpcode::MeterInfo, &[], Some(&[2, 0, 0, 0]));
+        assert_eq!(fcp.read_meter_count().unwrap(), 2);
+        assert!(matches!(fcp.read_meters(3), Err(Error::InvalidParameter(_))));
+        assert!(matches!(
+            futures::executor::block_on(fcp.read_meters_async(3)),
+            Err(Error::InvalidParameter(_))
+        ));
+
+        expect(&mock, 2, FcpOpcode::MeterRead, &meter_request(2), Some(&[0; 8]));
+        assert_eq!(fcp.read_meters(2).unwrap(), vec![0, 0]);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_invalid_meter_count() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+
+        // Neither an empty response nor no slots becomes the limit
+        expect(&mock, 1, FcpOpcode::MeterInfo, &[], Some(&[]));
+        assert!(matches!(fcp.read_meter_count(), Err(Error::Protocol(_))));
+        expect(&mock, 2, FcpOpcode::MeterInfo, &[], Some(&[0, 0, 0, 0]));
+        assert!(matches!(fcp.read_meter_count(), Err(Error::Protocol(_))));
+        assert_eq!(fcp.meter_count, None);
+
+        expect(&mock, 3, FcpOpcode::MeterRead, &meter_request(2), Some(&[0; 8]));
+        assert_eq!(fcp.read_meters(2).unwrap(), vec![0, 0]);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_slow_device_timeouts() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+        fcp.set_retry_policy(RetryPolicy::none());
+        let slow = Duration::from_millis(500);
+
+        // A device taking 500 ms to answer misses the meter timeout...
+        let timeout = Error::Timeout("Control OUT".to_string());
+        let read = packet(FcpOpcode::MeterRead as u32, 1, &meter_request(4));
+        mock.expect_error(ControlTransfer::class_out(2, 0, 0), &read, timeout);
+        assert!(matches!(fcp.read_meters(4), Err(Error::Timeout(_))));
+        assert!(mock.transcript()[0].timeout < slow);
+
+        // ...but flash commands wait long enough
+        expect(&mock, 2, FcpOpcode::FlashInfo, &[], Some(&[0; 8]));
+        fcp.send_command(FcpOpcode::FlashInfo, &[], 8).unwrap();
+        expect(&mock, 3, FcpOpcode::FlashErase, &[0; 8], None);
+        fcp.send_command(FcpOpcode::FlashErase, &[0; 8], 0).unwrap();
+        assert!(mock.transcript()[1..].iter().all(|exchange| exchange.timeout > slow));
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_sync_status() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett2i2Gen4);
+
+        expect(&mock, 1, FcpOpcode::SyncRead, &[], Some(&1u32.to_le_bytes()));
+        assert!(fcp.sync_status().unwrap().locked);
+
+        expect(&mock, 2, FcpOpcode::SyncRead, &[], Some(&0u32.to_le_bytes()));
+        assert!(!fcp.sync_status().unwrap().locked);
+
+        // Without a device map the clock source is unknown
+        expect(&mock, 3, FcpOpcode::SyncRead, &[], Some(&1u32.to_le_bytes()));
+        assert_eq!(fcp.read_sync_status().unwrap().clock_source, None);
+
+        fcp.devmap = Some(DevMap::from_json(crate::devmap::tests::TEST_JSON.as_bytes()).unwrap());
+        expect(&mock, 4, FcpOpcode::SyncRead, &[], Some(&0u32.to_le_bytes()));
+        expect_read(&mock, 5, 16, &[2]);
+        let status = fcp.read_sync_status().unwrap();
+        assert!(!status.locked);
+        assert_eq!(status.clock_source, Some(ClockSource::Adat));
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_read_capabilities() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+
+        // INIT, DATA, then METER, MIX, MUX, FLASH, SYNC, ESP_DFU
+        let categories = [0x000u16, 0x800, 0x001, 0x002, 0x003, 0x004, 0x006, 0x009];
+        for (seq, (category, supported)) in (1..).zip(categories.into_iter().zip([1, 1, 1, 1, 1, 1, 1, 0])) {
+            expect(&mock, seq, FcpOpcode::CapRead, &category.to_le_bytes(), Some(&[supported]));
+        }
+        expect(&mock, 9, FcpOpcode::MeterInfo, &[], Some(&[64, 0, 0, 0]));
+        expect(&mock, 10, FcpOpcode::MixInfo, &[], Some(&[12, 25, 0, 0, 0, 0, 0, 0]));
+        expect(&mock, 11, FcpOpcode::MuxInfo, &[], Some(&[77, 0, 77, 0, 45, 0, 0, 0, 0, 0, 0, 0]));
+
+        let caps = fcp.read_capabilities().unwrap();
+        assert!(caps.mix && caps.sync && !caps.esp_dfu);
+        assert_eq!(caps.num_meters, 64);
+        assert_eq!((caps.num_mixer_outputs, caps.num_mixer_inputs), (12, 25));
+        assert_eq!(caps.mux_sizes, [77, 77, 45]);
+        // From the model table
+        assert!(caps.talkback);
+        assert_eq!(caps.num_inputs, 18);
+        mock.assert_done();
+
+        // DATA is required
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+        expect(&mock, 1, FcpOpcode::CapRead, &0x000u16.to_le_bytes(), Some(&[1]));
+        expect(&mock, 2, FcpOpcode::CapRead, &0x800u16.to_le_bytes(), Some(&[0]));
+        assert!(matches!(fcp.read_capabilities(), Err(Error::NotSupported(_))));
+        mock.assert_done();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sample_rate() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        // SAM_FREQ control of clock source 0x29
+        let sam_freq_in = || ControlTransfer::class_in(1, 0x100, 0x2900);
+
+        mock.expect_in(sam_freq_in(), &48000u32.to_le_bytes());
+        assert_eq!(fcp.get_sample_rate().unwrap(), SampleRate::Hz48000);
+
+        // Still at the old rate on the first read after the change
+        mock.expect_out(ControlTransfer::class_out(1, 0x100, 0x2900), &96000u32.to_le_bytes());
+        mock.expect_in(sam_freq_in(), &48000u32.to_le_bytes());
+        mock.expect_in(sam_freq_in(), &96000u32.to_le_bytes());
+        fcp.set_sample_rate(SampleRate::Hz96000).await.unwrap();
+        mock.assert_done();
+
+        let (mut fcp, _) = scripted_protocol(DeviceModel::VocasterOne);
+        assert!(matches!(
+            fcp.set_sample_rate(SampleRate::Hz96000).await,
+            Err(Error::NotSupported(_))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_meter_stream() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        fcp.set_retry_policy(RetryPolicy::none());
+        let read = |seq| expect(&mock, seq, FcpOpcode::MeterRead, &meter_request(1), Some(&4095u32.to_le_bytes()));
+        for seq in 1..=3 {
+            read(seq);
+        }
+
+        let fcp = Arc::new(tokio::sync::Mutex::new(fcp));
+        let interval = Duration::from_millis(20);
+        let mut stream = crate::meters::MeterStream::spawn(fcp.clone(), 1, interval);
+        let mut subscriber = stream.subscribe();
+
+        // The first read is immediate, then one per interval
+        let start = tokio::time::Instant::now();
+        assert!(stream.changed().await);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        for _ in 0..2 {
+            tokio::time::advance(interval).await;
+            assert!(stream.changed().await);
+        }
+        mock.assert_done();
+        assert_eq!(stream.latest().unwrap(), vec![0.0]);
+        assert!(subscriber.has_changed().unwrap());
+        subscriber.borrow_and_update();
+
+        // Read errors are delivered to subscribers without stopping the task
+        expect(&mock, 4, FcpOpcode::MeterRead, &meter_request(1), None);
+        mock.expect_response(0, &error_response(FcpOpcode::MeterRead as u32, 4, FcpErrorCode::InvalidState as u32));
+        read(5);
+        tokio::time::advance(interval).await;
+        subscriber.changed().await.unwrap();
+        let error = subscriber.borrow_and_update().clone().unwrap_err();
+        assert_eq!(error.device_code(), Some(FcpErrorCode::InvalidState));
+        tokio::time::advance(interval).await;
+        subscriber.changed().await.unwrap();
+        assert!(subscriber.borrow_and_update().is_ok());
+        mock.assert_done();
+
+        // Dropping every receiver stops the task, releasing the protocol
+        read(6);
+        drop(stream);
+        drop(subscriber);
+        tokio::time::sleep(interval).await;
+        assert_eq!(Arc::strong_count(&fcp), 1);
+        tokio::time::advance(interval * 3).await;
+        assert_eq!(mock.pending(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_meter_broadcast() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        fcp.set_retry_policy(RetryPolicy::none());
+        let levels = [4095u32.to_le_bytes(), 0u32.to_le_bytes()].concat();
+        let read = |seq| expect(&mock, seq, FcpOpcode::MeterRead, &meter_request(2), Some(&levels));
+
+        // The meter count is read once, then every subscriber sees each reading
+        expect(&mock, 1, FcpOpcode::MeterInfo, &[], Some(&[2, 0, 0, 0]));
+        for seq in 2..=4 {
+            read(seq);
+        }
+
+        let fcp = Arc::new(tokio::sync::Mutex::new(fcp));
+        let interval = Duration::from_millis(20);
+        let broadcast = crate::meters::MeterBroadcast::spawn(Arc::downgrade(&fcp), interval);
+        let mut first = broadcast.subscribe();
+        let mut second = broadcast.subscribe();
+
+        assert_eq!(first.recv().await.unwrap(), vec![0.0, -127.0]);
+        for _ in 0..2 {
+            tokio::time::advance(interval).await;
+            assert_eq!(first.recv().await.unwrap(), vec![0.0, -127.0]);
+        }
+        assert_eq!(second.recv().await.unwrap(), vec![0.0, -127.0]);
+        mock.assert_done();
+
+        // A slow subscriber skips to the newest readings
+        for seq in 5..=14 {
+            read(seq);
+            tokio::time::advance(interval).await;
+            first.recv().await.unwrap();
+        }
+        assert!(matches!(second.recv().await, Err(tokio::sync::broadcast::error::RecvError::Lagged(_))));
+        assert!(second.recv().await.is_ok());
+        mock.assert_done();
+
+        // Dropping the device stops the task at its next read
+        read(15);
+        drop(fcp);
+        tokio::time::sleep(interval * 2).await;
+        assert!(broadcast.is_finished());
+        tokio::time::advance(interval * 3).await;
+        assert_eq!(mock.pending(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_esp_dfu_update() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett16i16Gen4);
+
+        // Without a device map there is nothing to locate the ESP state
+        assert!(matches!(
+            fcp.esp_dfu_update(&[0; 10], |_| {}).await,
+            Err(Error::NotSupported(_))
+        ));
+
+        fcp.devmap = Some(DevMap::from_json(crate::devmap::tests::TEST_JSON.as_bytes()).unwrap());
+
+        let image: Vec<u8> = (0..1500).map(|i| i as u8).collect();
+
+        // ESP states (1 = off, 2 = DFU, 3 = normal), DFU notifications
+        // (1 = next block, 2 = finish) and the ESPBootMode requests
+        let (state, notify, boot_mode) = (0x204, 0x209, 0xc8);
+
+        expect_read(&mock, 1, state, &[1]);
+        let start = [&0u32.to_le_bytes()[..], &1500u32.to_le_bytes(), &compute_md5(&image)].concat();
+        expect(&mock, 2, FcpOpcode::EspDfuStart, &start, None);
+        expect_read(&mock, 3, state, &[2]);
+
+        // Each block waits for the device to ask for it; the empty write
+        // finishes the update
+        let mut seq = 4;
+        for block in image.chunks(ESP_DFU_BLOCK_SIZE).chain([&[][..]]) {
+            expect_read(&mock, seq, notify, &[1]);
+            expect_write(&mock, seq + 1, notify, &[0]);
+            expect(&mock, seq + 2, FcpOpcode::EspDfuWrite, block, None);
+            seq += 3;
+        }
+        expect_read(&mock, 13, notify, &[2]);
+        expect_write(&mock, 14, notify, &[0]);
+
+        // Turned off and back on by writing ESPBootMode and notifying the device
+        expect_write(&mock, 15, boot_mode, &[1]);
+        expect_notify(&mock, 16, 24);
+        expect_read(&mock, 17, state, &[1]);
+        expect_write(&mock, 18, boot_mode, &[3]);
+        expect_notify(&mock, 19, 24);
+        expect_read(&mock, 20, state, &[3]);
+
+        let mut percentages = Vec::new();
+        fcp.esp_dfu_update(&image, |p| percentages.push(p)).await.unwrap();
+        assert_eq!(percentages, vec![0, 68, 100]);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_config_cache() {
+        let (fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+        let mut fcp = fcp.with_cache(true);
+        fcp.devmap = Some(DevMap::from_json(crate::devmap::tests::TEST_JSON.as_bytes()).unwrap());
+        let volumes = [80, 82, 56, 58, 60, 62, 64, 66, 68, 70];
+        let mute = 120;
+
+        // Only the first pass over 10 outputs reaches the device
+        for (seq, offset) in (1..).zip(volumes) {
+            expect_read(&mock, seq, offset, &[0, 0]);
+        }
+        expect_read(&mock, 11, mute, &[0]);
+        for _ in 0..3 {
+            for output in 0..10 {
+                assert_eq!(fcp.get_volume(output).unwrap(), 0.0);
+            }
+            assert!(!fcp.get_mute(0).unwrap());
+        }
+        mock.assert_done();
+
+        // A mute notification leaves the volumes cached
+        fcp.handle_notify(8);
+        expect_read(&mock, 12, mute, &[1]);
+        assert!(fcp.get_mute(0).unwrap());
+        fcp.get_volume(0).unwrap();
+        mock.assert_done();
+
+        // Writes drop the value written
+        expect_write(&mock, 13, 82, &(-10i16).to_le_bytes());
+        fcp.set_volume(1, -10.0).unwrap();
+        expect_read(&mock, 14, 82, &(-10i16).to_le_bytes());
+        assert_eq!(fcp.get_volume(1).unwrap(), -10.0);
+        mock.assert_done();
+
+        // A refresh reads every cached item again, in offset order
+        let mut cached = volumes.to_vec();
+        cached.sort();
+        cached.push(mute);
+        assert_eq!(fcp.cache().unwrap().len(), cached.len());
+        for (seq, offset) in (15..).zip(cached) {
+            expect_read(&mock, seq, offset, if offset == mute { &[0] } else { &[0, 0] });
+        }
+        fcp.refresh().unwrap();
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_devmap_offsets() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+
+        // Without a device map the fixed offsets are used
+        expect_read(&mock, 1, 0x34 + 2, &[127, 0]);
+        fcp.get_volume(1).unwrap();
+
+        let encoded = crate::devmap::tests::encode(crate::devmap::tests::TEST_JSON);
+        let mut info = vec![0, 0];
+        info.extend_from_slice(&(encoded.len() as u16).to_le_bytes());
+        expect(&mock, 2, FcpOpcode::DevmapInfo, &[], Some(&info));
+        let mut seq = 3;
+        for (block, data) in encoded.chunks(DEVMAP_BLOCK_SIZE).enumerate() {
+            expect(&mock, seq, FcpOpcode::DevmapRead, &(block as u32).to_le_bytes(), Some(data));
+            seq += 1;
+        }
+        fcp.read_devmap().unwrap();
+
+        expect_read(&mock, seq, 82, &[127, 0]);
+        fcp.get_volume(1).unwrap();
+        expect_read(&mock, seq + 1, 120, &[1]);
+        assert!(fcp.get_mute(0).unwrap());
+
+        // Outputs missing from the device map fall back to the fixed offsets
+        expect_read(&mock, seq + 2, 0x5c + 1, &[0]);
+        fcp.get_mute(1).unwrap();
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_change_watcher() {
+        use crate::notify::{ChangeWatcher, DeviceChange};
+
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+        let mut watcher = ChangeWatcher::new();
+        watcher.watch_outputs(&fcp, 1);
+
+        // First poll only records volume and mute
+        expect_read(&mock, 1, 0x34, &[100, 0]);
+        expect_read(&mock, 2, 0x5c, &[0]);
+        assert!(watcher.poll(&mut fcp).unwrap().is_empty());
+
+        // Knob turned
+        expect_read(&mock, 3, 0x34, &[90, 0]);
+        expect_read(&mock, 4, 0x5c, &[0]);
+        assert_eq!(
+            watcher.poll(&mut fcp).unwrap(),
+            vec![DeviceChange { offset: 0x34, new_value: 90 }]
+        );
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_volume_scale() {
+        // Signed dB in 1 dB steps up to 0 dB
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+        expect_read(&mock, 1, 0x34, &(-10i16).to_le_bytes());
+        assert_eq!(fcp.get_volume(0).unwrap(), -10.0);
+
+        expect_write(&mock, 2, 0x34, &0i16.to_le_bytes());
+        expect_write(&mock, 3, 0x34, &(-127i16).to_le_bytes());
+        expect_write(&mock, 4, 0x34, &(-10i16).to_le_bytes());
+        fcp.set_volume(0, 3.0).unwrap();
+        fcp.set_volume(0, -200.0).unwrap();
+        fcp.set_volume(0, -10.4).unwrap();
+
+        // Clamped at 0 dB
+        expect_read(&mock, 5, 0x34, &(-1i16).to_le_bytes());
+        expect_write(&mock, 6, 0x34, &0i16.to_le_bytes());
+        assert_eq!(fcp.adjust_volume(0, 2.0).unwrap(), 0.0);
+        mock.assert_done();
+
+        let scale = VolumeScale::for_model(DeviceModel::Scarlett18i20Gen4);
+        assert_eq!(scale, VolumeScale::default());
+        assert_eq!(scale.to_raw(-127.5), -127);
+        assert_eq!(scale.to_db(-128), -127.0);
+        assert_eq!(scale.to_db(3), 0.0);
+    }
+
+    #[test]
+    fn test_monitor_dim_mute() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+        let volumes = |db: i16| db.to_le_bytes().repeat(10);
+
+        // No Mute button, so Monitor 1-10 are muted together
+        expect_write(&mock, 1, 0x5c, &[1; 10]);
+        fcp.set_monitor_mute(true).unwrap();
+
+        for output in 0..10 {
+            expect_read(&mock, 2 + output as u16, 0x5c + output, &[1]);
+        }
+        assert!(fcp.get_monitor_mute().unwrap());
+
+        // Dimmed from -10 dB to -28 dB
+        for output in 0..10 {
+            expect_read(&mock, 12 + output as u16, 0x34 + 2 * output, &(-10i16).to_le_bytes());
+        }
+        expect_write(&mock, 22, 0x34, &volumes(-28));
+        fcp.set_monitor_dim(true).unwrap();
+        assert!(fcp.get_monitor_dim().unwrap());
+        assert_eq!(fcp.get_master_volume().unwrap(), -10.0);
+
+        // Volume changes while dimmed stay dimmed, and are kept on undim
+        expect_write(&mock, 23, 0x34, &volumes(-24));
+        fcp.set_master_volume(-6.0).unwrap();
+        expect_write(&mock, 24, 0x34, &volumes(-6));
+        fcp.set_monitor_dim(false).unwrap();
+        assert!(!fcp.get_monitor_dim().unwrap());
+        mock.assert_done();
+
+        let (mut fcp, _mock) = scripted_protocol(DeviceModel::Scarlett2i2Gen4);
+        assert!(matches!(fcp.set_monitor_mute(true), Err(Error::NotSupported(_))));
+        assert!(matches!(fcp.set_monitor_dim(true), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_batched_volumes() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+
+        let volumes = |db: &[i16]| db.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>();
+        expect_write(&mock, 1, 0x34, &volumes(&[0, -1, -3, -127]));
+        fcp.set_volumes(&[(2, -3.0), (0, 0.0), (1, -1.0), (3, -127.0)]).unwrap();
+
+        // Non-adjacent outputs are written separately
+        expect_write(&mock, 2, 0x34, &volumes(&[0]));
+        expect_write(&mock, 3, 0x38, &volumes(&[0]));
+        fcp.set_volumes(&[(0, 0.0), (2, 0.0)]).unwrap();
+
+        expect_read(&mock, 4, 0x34, &volumes(&[0, -1, -3, -127]));
+        assert_eq!(fcp.get_all_volumes().unwrap(), vec![0.0, -1.0, -3.0, -127.0]);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_linked_outputs() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+
+        fcp.link_outputs(0, 1).unwrap();
+        assert_eq!(fcp.linked_output(1), Some(0));
+        assert!(fcp.link_outputs(1, 2).is_err());
+        assert!(fcp.link_outputs(2, 2).is_err());
+
+        // Both sides of the pair go out in one write
+        expect_write(&mock, 1, 0x34, &(-1i16).to_le_bytes().repeat(2));
+        fcp.set_linked_volume(1, -1.0).unwrap();
+        expect_write(&mock, 2, 0x5c, &[1, 1]);
+        fcp.set_linked_mute(0, true).unwrap();
+
+        // Unlinked outputs are written on their own
+        assert_eq!(fcp.unlink_outputs(0), Some(1));
+        assert_eq!(fcp.linked_output(1), None);
+        expect_write(&mock, 3, 0x36, &0i16.to_le_bytes());
+        fcp.set_linked_volume(1, 0.0).unwrap();
+        mock.assert_done();
+
+        fcp.set_output_links(&[(2, 3)]).unwrap();
+        assert_eq!(fcp.output_links(), &[(2, 3)]);
+    }
+
+    #[test]
+    fn test_linked_volume_drift() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        fcp.link_outputs(0, 1).unwrap();
+
+        // Output 0 at -10 dB while its partner drifted to -11 dB
+        expect_read(&mock, 1, 0x34, &(-10i16).to_le_bytes());
+        expect_write(&mock, 2, 0x34, &(-9i16).to_le_bytes().repeat(2));
+        assert_eq!(fcp.adjust_volume(0, 1.0).unwrap(), -9.0);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_write_routing_changes_only() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+
+        let baseline = RoutingMatrix::for_model(DeviceModel::Scarlett4i4Gen4);
+        fcp.write_routing(&baseline, Some(&baseline)).unwrap();
+        assert!(mock.transcript().is_empty());
+
+        // Analogue 1 to the first analogue output
+        let mut matrix = baseline.clone();
+        matrix.set_route(0, Some(0)).unwrap();
+
+        // Both tables are read; only the one holding the output is written
+        let entries = |ids: &[u32]| ids.iter().flat_map(|id| id.to_le_bytes()).collect::<Vec<u8>>();
+        expect(&mock, 1, FcpOpcode::MuxInfo, &[], Some(&[2, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0]));
+        expect(&mock, 2, FcpOpcode::MuxRead, &[0, 0, 2, 0], Some(&entries(&[0x080, 0x081])));
+        let write = [&[0, 0, 0, 0][..], &entries(&[0x80080, 0x081])].concat();
+        expect(&mock, 3, FcpOpcode::MuxWrite, &write, None);
+        expect(&mock, 4, FcpOpcode::MuxRead, &[0, 0, 2, 1], Some(&entries(&[0x081, 0x082])));
+        fcp.write_routing(&matrix, Some(&baseline)).unwrap();
+        mock.assert_done();
+
+        let mut invalid = matrix.clone();
+        invalid.routes[0] = Some(invalid.sources.len());
+        assert!(fcp.write_routing(&invalid, None).is_err());
+    }
+
+    #[test]
+    fn test_protocol_trait() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        let protocol: &mut dyn Protocol = &mut fcp;
+
+        // Mix A with unity gain on input 2
+        let mix_info = [2, 3, 0, 0, 0, 0, 0, 0];
+        let mix_a = [0, 0, 0, 0x20, 0, 0];
+        expect(&mock, 1, FcpOpcode::MixInfo, &[], Some(&mix_info));
+        expect(&mock, 2, FcpOpcode::MixRead, &[0, 0, 3, 0], Some(&mix_a));
+        let state = protocol.get_mixer_state().unwrap();
+        assert_eq!(state.channels.len(), 3);
+        assert_eq!(state.channels[1].volume_db, 0.0);
+        assert_eq!(state.channels[0].volume_db, scarlett_core::mixer::MIXER_MIN_DB);
+
+        expect(&mock, 3, FcpOpcode::MixInfo, &[], Some(&mix_info));
+        expect(&mock, 4, FcpOpcode::MixRead, &[0, 0, 3, 0], Some(&mix_a));
+        expect(&mock, 5, FcpOpcode::MixInfo, &[], Some(&mix_info));
+        expect(&mock, 6, FcpOpcode::MixWrite, &[0, 0, 0, 0, 0, 0x20, 0, 0x20], None);
+        protocol.set_channel_volume(2, 0.0).unwrap();
+        mock.assert_done();
+
+        // Centre pan writes -3 dB to both Mix A and Mix B
+        expect(&mock, 7, FcpOpcode::MixInfo, &[], Some(&mix_info));
+        for (seq, mix) in [(8, 0), (12, 1)] {
+            expect(&mock, seq, FcpOpcode::MixInfo, &[], Some(&mix_info));
+            expect(&mock, seq + 1, FcpOpcode::MixRead, &[mix, 0, 3, 0], Some(&[0; 6]));
+            expect(&mock, seq + 2, FcpOpcode::MixInfo, &[], Some(&mix_info));
+            expect(&mock, seq + 3, FcpOpcode::MixWrite, &[mix, 0, 0, 0, 0xa1, 0x16, 0, 0], None);
+        }
+        protocol.set_channel_pan(1, 0.0).unwrap();
+        mock.assert_done();
+
+        assert!(matches!(protocol.set_channel_pan(1, 1.5), Err(Error::InvalidParameter(_))));
+
+        // Routing comes from the 1x table
+        let layout = DeviceModel::Scarlett4i4Gen4.port_layout();
+        let destinations = layout.destinations.len() as u8;
+        let mut entries = vec![0u32; layout.destinations.len()];
+        entries[0] = (0x600 << 12) | 0x080;
+        let entries: Vec<u8> = entries.iter().flat_map(|entry| entry.to_le_bytes()).collect();
+        expect(&mock, 16, FcpOpcode::MuxInfo, &[], Some(&[destinations, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]));
+        expect(&mock, 17, FcpOpcode::MuxRead, &[0, 0, destinations, 0], Some(&entries));
+        let matrix = protocol.get_routing().unwrap();
+        let source = matrix.get_route(0).map(|idx| matrix.sources[idx].name.as_str());
+        assert_eq!(source, Some("PCM 1"));
+        assert_eq!(matrix.get_route(1), None);
+
+        expect(&mock, 18, FcpOpcode::MeterInfo, &[], Some(&[2, 0, 0, 0]));
+        expect(&mock, 19, FcpOpcode::MeterRead, &meter_request(2), Some(&[0xff, 0x0f, 0, 0, 0, 0, 0, 0]));
+        let meters = protocol.get_level_meters().unwrap();
+        assert_eq!(meters.len(), 2);
+        assert_eq!(meters[0].level_db, 0.0);
+
+        // Mix C/D doesn't exist with two mixes
+        expect(&mock, 20, FcpOpcode::MixInfo, &[], Some(&mix_info));
+        assert!(matches!(fcp.set_mix_pan(0, 1, 0.0), Err(Error::InvalidParameter(_))));
+        mock.assert_done();
+
+        let mut fcp = FcpProtocol::new(Box::new(MockTransport::new()));
+        fcp.initialized = true;
+        assert!(matches!(fcp.read_routing(), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_opcode_name() {
+        assert_eq!(opcode_name(FcpOpcode::MuxWrite as u32), "MuxWrite");
+        assert_eq!(opcode_name(0x6005), "0x6005");
+    }
+
+    #[cfg(feature = "debug-protocol")]
+    #[test]
+    fn test_send_raw() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+
+        mock.expect_command(0, 0x6005, 1, &[9], Some(&[1, 2, 3, 4]));
+        assert_eq!(fcp.send_raw(0x6005, &[9], 4).unwrap(), vec![1, 2, 3, 4]);
+
+        mock.expect_command(0, 0x6005, 2, &[], None);
+        mock.expect_response(0, &error_response(0x6005, 2, FcpErrorCode::InvalidCommand as u32));
+        assert!(matches!(
+            fcp.send_raw(0x6005, &[], 1),
+            Err(Error::Device { context, .. }) if context == "0x6005"
+        ));
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_device_error_codes() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        fcp.set_retry_policy(RetryPolicy::none());
+        let read = [0, 0, 0, 0, 1, 0, 0, 0];
+        let fail_read = |seq, error| {
+            expect(&mock, seq, FcpOpcode::DataRead, &read, None);
+            mock.expect_response(0, &error_response(FcpOpcode::DataRead as u32, seq, error));
+        };
+
+        fail_read(1, FcpErrorCode::InvalidState as u32);
+        let error = fcp.read_data(0, 1).unwrap_err();
+        assert!(matches!(
+            &error,
+            Error::Device { code: FcpErrorCode::InvalidState, context } if context == "DataRead"
+        ));
+        assert_eq!(error.hint(), Some("Reboot the device and try again"));
+
+        fail_read(2, 99);
+        assert!(matches!(fcp.read_data(0, 1), Err(Error::Protocol(_))));
+
+        // Device timeouts are retried like USB ones
+        fcp.set_retry_policy(RetryPolicy::exponential(1));
+        fail_read(3, FcpErrorCode::Timeout as u32);
+        expect_read(&mock, 4, 0, &[5]);
+        assert_eq!(fcp.read_data(0, 1).unwrap(), 5);
+        mock.assert_done();
+
+        let mut bytes = FcpMessageHeader::new_response(FcpResponseType::Error as u8, 2).to_bytes().to_vec();
+        bytes.extend_from_slice(&(FcpErrorCode::InvalidState as i16).to_le_bytes());
+        let message = FcpErrorMessage::from_bytes(&bytes).unwrap();
+        assert_eq!(message.to_error("test").device_code(), Some(FcpErrorCode::InvalidState));
+    }
+
+    #[test]
+    fn test_monitor_knob() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen3);
+
+        expect_read(&mock, 1, 0x76, &(-10i16).to_le_bytes());
+        assert_eq!(fcp.get_knob_position().unwrap(), -10.0);
+
+        // Outputs 1-2 and 7-8 on the knob
+        for (seq, (offset, assigned)) in (2..).zip((0x66..).zip([1, 1, 0, 0, 0, 0, 1, 1, 0, 0])) {
+            expect_read(&mock, seq, offset, &[assigned]);
+        }
+        assert_eq!(fcp.get_knob_assignment().unwrap(), 0b1100_0011);
+
+        assert!(fcp.set_knob_assignment(1 << 10).is_err());
+
+        // Each output's switch is written and activated in turn
+        for (seq, (offset, assigned)) in (12..).step_by(2).zip((0x66..0x70).zip([1, 1, 0, 0, 0, 0, 0, 0, 0, 0])) {
+            expect_write(&mock, seq, offset, &[assigned]);
+            expect_notify(&mock, seq + 1, 3);
+        }
+        fcp.set_knob_assignment(0b11).unwrap();
+        mock.assert_done();
+
+        let (mut fcp, _) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        assert!(matches!(fcp.get_knob_assignment(), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_power_status() {
+        // The bus-powerable 4i4 reports where its power comes from
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        for (seq, (external, low, status)) in [
+            (1, 0, PowerStatus::External),
+            (0, 0, PowerStatus::BusFull),
+            (0, 1, PowerStatus::BusInsufficient),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let seq = seq as u16 * 2 + 1;
+            expect_read(&mock, seq, 0x168, &[external]);
+            expect_read(&mock, seq + 1, 0x16d, &[low]);
+            assert_eq!(Protocol::power_status(&mut fcp).unwrap(), status);
+        }
+        mock.assert_done();
+
+        // The 18i20 has no power controls, with or without its device map
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+        assert!(matches!(fcp.power_status(), Err(Error::NotSupported(_))));
+        fcp.devmap = Some(DevMap::from_json(crate::devmap::tests::TEST_JSON.as_bytes()).unwrap());
+        assert!(matches!(fcp.power_status(), Err(Error::NotSupported(_))));
+        assert!(mock.transcript().is_empty());
+    }
+
+    #[test]
+    fn test_monitor_notify_invalidates_volumes() {
+        let (fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        let mut fcp = fcp.with_cache(true);
+
+        expect_read(&mock, 1, 0x34, &(-10i16).to_le_bytes());
+        fcp.get_volume(0).unwrap();
+        fcp.handle_notify(NOTIFY_MONITOR);
+        expect_read(&mock, 2, 0x34, &(-20i16).to_le_bytes());
+        assert_eq!(fcp.get_volume(0).unwrap(), -20.0);
+        mock.assert_done();
+
+        // Values seen by a change watcher refresh the cache
+        fcp.update_cache(0x34, 2, -30);
+        assert_eq!(fcp.get_volume(0).unwrap(), -30.0);
+    }
+
+    #[test]
+    fn test_reboot() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+
+        expect(&mock, 1, FcpOpcode::Reboot, &[], None);
+        fcp.reboot().unwrap();
+        mock.assert_done();
+
+        // Nothing more is sent
+        assert!(!fcp.is_connected());
+        assert!(matches!(fcp.get_volume(0), Err(Error::Disconnected)));
+        assert!(matches!(fcp.reboot(), Err(Error::Disconnected)));
+        assert_eq!(mock.transcript().len(), 1);
+    }
+
+    #[test]
+    fn test_disconnect_error() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        expect_read(&mock, 1, 0x34, &(-10i16).to_le_bytes());
+        assert_eq!(fcp.get_volume(0).unwrap(), -10.0);
+        assert!(fcp.is_connected());
+
+        // Unplugged while sending the next command
+        let request = [0x34u32.to_le_bytes(), 2u32.to_le_bytes()].concat();
+        mock.expect_error(
+            ControlTransfer::class_out(2, 0, 0),
+            &packet(FcpOpcode::DataRead as u32, 2, &request),
+            Error::Disconnected,
+        );
+        assert!(matches!(fcp.get_volume(0), Err(Error::Disconnected)));
+        assert!(!fcp.is_connected());
+        mock.assert_done();
+
+        // Not retried, and later commands fail the same way
+        assert!(matches!(fcp.get_volume(0), Err(Error::Disconnected)));
+        assert_eq!(mock.transcript().len(), 3);
+    }
+
+    #[test]
+    fn test_set_transport_after_reboot() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        expect(&mock, 1, FcpOpcode::Reboot, &[], None);
+        fcp.reboot().unwrap();
+
+        // The re-enumerated device needs the init handshake again
+        let mock = MockTransport::new();
+        fcp.set_transport(Box::new(mock.clone()));
+        assert!(fcp.is_connected());
+        assert!(matches!(fcp.get_volume(0), Err(Error::Protocol(_))));
+
+        let request = || ControlTransfer::class_out(2, 0, 0);
+        mock.expect_out(request(), &packet(FcpOpcode::Init1 as u32, 1, &[]));
+        mock.expect_response(0, &packet(FcpOpcode::Init1 as u32, 0, &[0; 24]));
+        mock.expect_out(request(), &packet(FcpOpcode::Init2 as u32, 1, &[]));
+        mock.expect_response(0, &packet(FcpOpcode::Init2 as u32, 0, &INIT_2_RESPONSE));
+        fcp.init().unwrap();
+
+        expect_read(&mock, 2, 0x34, &(-10i16).to_le_bytes());
+        assert_eq!(fcp.get_volume(0).unwrap(), -10.0);
+        mock.assert_done();
+    }
+
+    /// FlashSegmentInfo response for a segment called `name`
+    fn segment_info(name: &str) -> Vec<u8> {
+        let mut info = vec![0u8; 24];
+        info[8..8 + name.len()].copy_from_slice(name.as_bytes());
+        info
+    }
+
+    #[tokio::test]
+    async fn test_erase_config() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+
+        assert!(matches!(fcp.erase_config(false).await, Err(Error::InvalidParameter(_))));
+        assert!(mock.transcript().is_empty());
+
+        // The settings segment is found by name, erased, and polled until done
+        let mut flash_info = vec![0u8; 16];
+        flash_info[4..8].copy_from_slice(&3u32.to_le_bytes());
+        expect(&mock, 1, FcpOpcode::FlashInfo, &[], Some(&flash_info));
+        for (segment, name) in ["App_Gold", "App_Upgrade", "App_Settings"].iter().enumerate() {
+            let segment = segment as u32;
+            expect(&mock, 2 + segment as u16, FcpOpcode::FlashSegmentInfo, &segment.to_le_bytes(), Some(&segment_info(name)));
+        }
+        let erase = [2, 0, 0, 0, 0, 0, 0, 0];
+        expect(&mock, 5, FcpOpcode::FlashErase, &erase, None);
+        expect(&mock, 6, FcpOpcode::FlashEraseProgress, &erase, Some(&[0x40]));
+        expect(&mock, 7, FcpOpcode::FlashEraseProgress, &erase, Some(&[FLASH_ERASE_DONE]));
+        fcp.erase_config(true).await.unwrap();
+        mock.assert_done();
+
+        // Nothing is erased if there is no settings segment
+        flash_info[4..8].copy_from_slice(&1u32.to_le_bytes());
+        expect(&mock, 8, FcpOpcode::FlashInfo, &[], Some(&flash_info));
+        expect(&mock, 9, FcpOpcode::FlashSegmentInfo, &[0; 4], Some(&segment_info("App_Gold")));
+        assert!(matches!(fcp.erase_config(true).await, Err(Error::NotSupported(_))));
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_commit_to_flash() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        assert_eq!(fcp.config_writes(), 0);
+
+        expect_write(&mock, 1, 0x5c, &[1]);
+        expect_write(&mock, 2, 0x100, &[1, 2]);
+        fcp.set_mute(0, true).unwrap();
+        fcp.write_data_block(0x100, &[1, 2]).unwrap();
+        assert_eq!(fcp.config_writes(), 2);
+
+        expect_notify(&mock, 3, config_items::CONFIG_SAVE);
+        fcp.commit_to_flash().unwrap();
+        mock.assert_done();
+
+        // Saving isn't a write of its own
+        assert_eq!(fcp.config_writes(), 2);
+    }
+
+    #[test]
+    fn test_input_gain_write_clamps() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+
+        // Channel and value go through the parameter buffer, then activate,
+        // then the pair's link switch is checked
+        expect_write(&mock, 1, 0x131, &[0]);
+        expect_write(&mock, 2, 0x130, &[69]);
+        expect_notify(&mock, 3, 12);
+        expect_read(&mock, 4, 0x156, &[0]);
+        fcp.set_input_gain(0, 90).unwrap();
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_input_link() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+
+        expect_write(&mock, 1, 0x131, &[0]);
+        expect_write(&mock, 2, 0x130, &[1]);
+        expect_notify(&mock, 3, 17);
+        fcp.set_input_link(0, true).unwrap();
+
+        expect_read(&mock, 4, 0x156, &[1]);
+        assert!(fcp.get_input_link(0).unwrap());
+        assert!(matches!(fcp.get_input_link(1), Err(Error::InvalidParameter(_))));
+
+        // A linked pair takes the gain on both inputs
+        expect_write(&mock, 5, 0x131, &[1]);
+        expect_write(&mock, 6, 0x130, &[30]);
+        expect_notify(&mock, 7, 12);
+        expect_read(&mock, 8, 0x156, &[1]);
+        expect_write(&mock, 9, 0x131, &[0]);
+        expect_write(&mock, 10, 0x130, &[30]);
+        expect_notify(&mock, 11, 12);
+        fcp.set_input_gain(1, 30).unwrap();
+        mock.assert_done();
+
+        let (mut fcp, _) = scripted_protocol(DeviceModel::ScarlettSoloGen4);
+        assert!(matches!(fcp.get_input_link(0), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_devmap_input_controls() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett18i20Gen4);
+        fcp.devmap = Some(DevMap::from_json(crate::devmap::tests::TEST_JSON.as_bytes()).unwrap());
+
+        // The device map locates the preamp controls
+        expect_read(&mock, 1, 301, &[20]);
+        assert_eq!(fcp.get_input_gain(1).unwrap(), 20);
+
+        expect_write(&mock, 2, 300, &[30]);
+        expect_notify(&mock, 3, 30);
+        fcp.set_input_gain(0, 30).unwrap();
+
+        // Controls it doesn't describe aren't supported
+        assert!(matches!(fcp.get_air(0), Err(Error::NotSupported(_))));
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_input_gain_invalid_input() {
+        let (mut fcp, _mock) = scripted_protocol(DeviceModel::Scarlett2i2Gen4);
+        assert!(fcp.set_input_gain(2, 10).is_err());
+
+        let (mut fcp, _mock) = scripted_protocol(DeviceModel::ScarlettSoloGen4);
+        assert!(fcp.get_input_gain(0).is_err());
+    }
+
+    #[test]
+    fn test_phantom_write_muted_value() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::ScarlettSoloGen4);
+
+        // Solo Gen 4 switch index is 1; "on" is written as 0x02 while muted
+        expect_write(&mock, 1, 0xd9, &[1]);
+        expect_write(&mock, 2, 0xd8, &[0x02]);
+        expect_notify(&mock, 3, 9);
+        fcp.set_phantom(0, true).unwrap();
+
+        expect_read(&mock, 4, 0x47, &[0x03]);
+        assert!(!fcp.get_phantom(0).unwrap());
+        assert!(fcp.set_phantom(1, true).is_err());
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_air_roundtrip() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+
+        let modes = [(AirMode::Off, 0), (AirMode::Presence, 1), (AirMode::PresenceDrive, 2)];
+        for (seq, (mode, value)) in (1..).step_by(4).zip(modes) {
+            // Value written through the parameter buffer is read back
+            expect_pbuf_write(&mock, seq, 0x130, 1, value, 15);
+            fcp.set_air(1, mode).unwrap();
+
+            expect_read(&mock, seq + 3, 0x51, &[value]);
+            assert_eq!(fcp.get_air(1).unwrap(), mode);
+        }
+        mock.assert_done();
+
+        assert!(fcp.set_air(2, AirMode::Presence).is_err());
+    }
+
+    #[test]
+    fn test_input_level() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett2i2Gen4);
+
+        // Muteable control: Inst is written as 0x02
+        expect_pbuf_write(&mock, 1, 0xfc, 1, 0x02, 13);
+        fcp.set_input_level(1, InputLevel::Inst).unwrap();
+
+        expect_read(&mock, 4, 0x3d, &[0x02]);
+        assert_eq!(fcp.get_input_level(1).unwrap(), InputLevel::Inst);
+        mock.assert_done();
+
+        assert!(fcp.set_input_level(2, InputLevel::Inst).is_err());
+    }
+
+    #[test]
+    fn test_direct_monitor_encoding() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett2i2Gen4);
+
+        expect_pbuf_write(&mock, 1, 0xfc, 0, 2, 16);
+        fcp.set_direct_monitor(DirectMonitorMode::Stereo).unwrap();
+
+        expect_read(&mock, 4, 0x14a, &[1]);
+        assert_eq!(fcp.get_direct_monitor().unwrap(), DirectMonitorMode::Mono);
+        assert!(fcp.set_direct_monitor(DirectMonitorMode::On).is_err());
+        mock.assert_done();
+
+        // Solo only has on/off
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::ScarlettSoloGen4);
+        expect_pbuf_write(&mock, 1, 0xd8, 0, 1, 12);
+        fcp.set_direct_monitor(DirectMonitorMode::On).unwrap();
+        assert!(fcp.set_direct_monitor(DirectMonitorMode::Stereo).is_err());
+        mock.assert_done();
+
+        let (mut fcp, _mock) = scripted_protocol(DeviceModel::Scarlett4i4Gen4);
+        assert!(matches!(fcp.get_direct_monitor(), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_dim_not_supported() {
+        let (mut fcp, _mock) = scripted_protocol(DeviceModel::Scarlett2i2Gen4);
+        assert!(matches!(fcp.set_dim(true), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_autogain() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::Scarlett2i2Gen4);
+
+        expect_pbuf_write(&mock, 1, 0xfc, 0, 1, 10);
+        fcp.start_autogain(0).unwrap();
+
+        // Switch cleared, status 5 = clipped
+        expect_read(&mock, 4, 0x135, &[0]);
+        expect_read(&mock, 5, 0x137, &[5]);
+        assert_eq!(fcp.autogain_status(0).unwrap(), AutogainStatus::FailClipped);
+        mock.assert_done();
+
+        let (mut fcp, _mock) = scripted_protocol(DeviceModel::ScarlettSoloGen4);
+        assert!(matches!(fcp.start_autogain(0), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_air_solo_index() {
+        let (mut fcp, mock) = scripted_protocol(DeviceModel::ScarlettSoloGen4);
+
+        // Only input 2 has Air, and it is Air control 0
+        assert!(fcp.get_air(0).is_err());
+        expect_pbuf_write(&mock, 1, 0xd8, 0, 1, 11);
+        fcp.set_air(1, AirMode::Presence).unwrap();
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_header_serialization() {
+        let header = FcpMessageHeader::new_request(0x01, 100);
+        let bytes = header.to_bytes();
+        let decoded = FcpMessageHeader::from_bytes(&bytes).unwrap();
+
+        // Copy the fields out, as the packed header can't be borrowed
+        assert_eq!({ decoded.magic }, FCP_MAGIC_REQUEST);
+        assert_eq!({ decoded.msg_type }, 0x01);
+        assert_eq!({ decoded.payload_length }, 100);
+    }
+
+    #[test]
+    fn test_version_message() {
+        let msg = FcpVersionMessage::new(FCP_PROTOCOL_VERSION);
+        let bytes = msg.to_bytes();
+        let decoded = FcpVersionMessage::from_bytes(&bytes).unwrap();
+
+        assert_eq!(decoded.version, FCP_PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn test_message_payload_length() {
+        let message = |msg_type: FcpResponseType, payload_length: u32, payload: &[u8]| {
+            let mut bytes = FcpMessageHeader::new_response(msg_type as u8, payload_length).to_bytes().to_vec();
+            bytes.extend_from_slice(payload);
+            bytes
+        };
+
+        // Declared payload longer than what was received
+        let truncated = message(FcpResponseType::Error, 2, &[8]);
+        assert!(matches!(FcpErrorMessage::from_bytes(&truncated), Err(Error::Protocol(_))));
+        assert!(matches!(FcpResponse::from_bytes(&truncated), Err(Error::Protocol(_))));
+
+        // Declared length doesn't fit the message type
+        let progress = message(FcpResponseType::Progress, 4, &[50, 0, 0, 0]);
+        assert!(matches!(FcpProgressMessage::from_bytes(&progress), Err(Error::Protocol(_))));
+        let success = message(FcpResponseType::Success, 1, &[0]);
+        assert!(matches!(FcpSuccessMessage::from_bytes(&success), Err(Error::Protocol(_))));
+        let huge = message(FcpResponseType::Version, u32::MAX, &[1]);
+        assert!(matches!(FcpVersionMessage::from_bytes(&huge), Err(Error::Protocol(_))));
+
+        // Trailing bytes after the payload are ignored
+        let padded = message(FcpResponseType::Progress, 1, &[50, 0xff, 0xff]);
+        assert_eq!(FcpProgressMessage::from_bytes(&padded).unwrap().percent, 50);
+        match FcpResponse::from_bytes(&message(FcpResponseType::Success, 0, &[])).unwrap() {
+            FcpResponse::Success(_) => {}
+            other => panic!("Expected success, got {:?}", other),
+        }
     }
 }
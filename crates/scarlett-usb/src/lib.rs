@@ -7,19 +7,44 @@ pub mod detection;
 pub mod protocol;
 pub mod device_impl;
 pub mod gen3_protocol;
+pub mod mux;
 pub mod gen4_fcp;
 pub mod transport;
 pub mod direct_usb_transport;
+pub mod usbip_transport;
 pub mod firmware;
+pub mod config_items;
+pub mod devmap;
+pub mod notify;
+pub mod uac;
+pub mod cache;
+pub mod meters;
+pub mod autocommit;
+pub mod virtual_device;
+pub mod recorder;
+#[cfg(any(test, feature = "testing"))]
+pub mod mock_transport;
 
 pub use detection::{DeviceDetector, HotplugEvent};
-pub use device_impl::UsbDevice;
-pub use transport::{UsbTransport, TransportType, ControlTransfer, Direction};
-pub use direct_usb_transport::DirectUsbTransport;
-pub use gen4_fcp::{FcpProtocol, FcpOpcode};
-pub use firmware::{FirmwareFile, FirmwareHeader};
+pub use device_impl::{OpenOptions, UsbDevice};
+pub use transport::{AsyncUsbTransport, UsbTransport, TransportType, ControlTransfer, Direction, RetryPolicy};
+pub use direct_usb_transport::{DirectUsbTransport, KernelDriverPolicy};
+pub use usbip_transport::UsbIpTransport;
+pub use gen4_fcp::{DeviceVersions, FcpProtocol, FcpOpcode, VolumeScale};
+pub use scarlett_core::DeviceCapabilities;
+pub use firmware::{EspFirmware, FirmwareFile, FirmwareHeader, FirmwareUpdateOptions};
+pub use config_items::{ConfigAccess, ConfigItem, ConfigParam};
+pub use devmap::{DevMap, DevMapParam};
+pub use notify::{ChangeWatcher, DeviceChange, Notification};
+pub use cache::ConfigCache;
+pub use meters::{MeterBroadcast, MeterReading, MeterSource, MeterStream};
+pub use autocommit::{AutoCommit, DEFAULT_COMMIT_DELAY};
+pub use virtual_device::VirtualDevice;
+pub use recorder::{RecordedTransfer, ReplayTransport, SessionRecorder};
+#[cfg(any(test, feature = "testing"))]
+pub use mock_transport::MockTransport;
 
-use scarlett_core::{Error, Result};
+use scarlett_core::Result;
 
 /// Initialize USB subsystem
 pub fn init() -> Result<()> {
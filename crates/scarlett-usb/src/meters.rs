@@ -0,0 +1,256 @@
+//! Shared level meter streaming
+//!
+//! One background task reads the meters and every subscriber sees the same
+//! levels, so several windows showing meters don't each poll the device.
+
+use crate::gen3_protocol::Scarlett2Protocol;
+use crate::gen4_fcp::FcpProtocol;
+use futures::future::BoxFuture;
+use scarlett_core::mixer::linear_to_db;
+use scarlett_core::{Error, Result};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::sync::{broadcast, watch, Mutex};
+use tokio::task::JoinHandle;
+
+/// Raw meter value of a full-scale signal
+///
+/// Meter levels are linear 12-bit values on every generation; the kernel
+/// driver exposes them unchanged with a 0-4095 range.
+pub const METER_FULL_SCALE: u32 = 4095;
+
+/// Latest meter levels in dB, or the error from the last read
+pub type MeterReading = std::result::Result<Vec<f32>, Arc<Error>>;
+
+/// Readings a `MeterBroadcast` subscriber can fall behind by before it
+/// skips to the newest
+const BROADCAST_CAPACITY: usize = 4;
+
+/// Convert a raw meter value to dB (0 dB = full scale)
+///
+/// Matches the level meters in alsa-scarlett-gui (window-levels.c). The
+/// smallest non-zero level is about -72 dB; 0 means silence and gives
+/// -127 dB.
+pub fn meter_to_db(raw: u32) -> f32 {
+    linear_to_db(raw as f32 / METER_FULL_SCALE as f32).min(0.0)
+}
+
+/// Meter levels read by a background task and shared between subscribers
+///
+/// The task stops once the stream and every subscriber have been dropped,
+/// or after reporting that the device is gone.
+pub struct MeterStream {
+    meter_rx: watch::Receiver<MeterReading>,
+}
+
+impl MeterStream {
+    /// Start reading `count` meters every `interval`
+    pub fn spawn(fcp: Arc<Mutex<FcpProtocol>>, count: u16, interval: Duration) -> Self {
+        Self::start(fcp, Some(count), interval)
+    }
+
+    /// Start reading every meter of `source` every `interval`
+    ///
+    /// Only a weak reference is kept, so the task doesn't keep the device
+    /// open; it stops once the source has been dropped.
+    pub fn spawn_weak<S: MeterSource>(source: Weak<Mutex<S>>, interval: Duration) -> Self {
+        Self::start(source, None, interval)
+    }
+
+    /// Start the polling task, reading the meter count first if not given
+    fn start<S: MeterSource>(source: impl SourceRef<S>, mut count: Option<u16>, interval: Duration) -> Self {
+        let (meter_tx, meter_rx) = watch::channel(Ok(Vec::new()));
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = meter_tx.closed() => {
+                        tracing::debug!("No meter subscribers left, stopping");
+                        return;
+                    }
+                }
+
+                let Some(source) = source.get() else {
+                    tracing::debug!("Meter source dropped, stopping");
+                    return;
+                };
+                let reading = read_levels(&mut *source.lock().await, &mut count).await;
+
+                let gone = matches!(reading, Err(Error::Disconnected | Error::DeviceNotFound));
+                if let Err(e) = &reading {
+                    tracing::debug!("Meter read failed: {}", e);
+                }
+
+                if meter_tx.send(reading.map_err(Arc::new)).is_err() || gone {
+                    return;
+                }
+            }
+        });
+
+        Self { meter_rx }
+    }
+
+    /// Get another receiver of the meter levels
+    pub fn subscribe(&self) -> watch::Receiver<MeterReading> {
+        self.meter_rx.clone()
+    }
+
+    /// Latest levels, or the error from the last read
+    pub fn latest(&self) -> MeterReading {
+        self.meter_rx.borrow().clone()
+    }
+
+    /// Wait for the next reading, returning false once the task has stopped
+    pub async fn changed(&mut self) -> bool {
+        self.meter_rx.changed().await.is_ok()
+    }
+}
+
+/// How the polling task reaches its meter source
+trait SourceRef<S>: Send + 'static {
+    /// The source, or `None` once it has been dropped
+    fn get(&self) -> Option<Arc<Mutex<S>>>;
+}
+
+impl<S: MeterSource> SourceRef<S> for Arc<Mutex<S>> {
+    fn get(&self) -> Option<Arc<Mutex<S>>> {
+        Some(self.clone())
+    }
+}
+
+impl<S: MeterSource> SourceRef<S> for Weak<Mutex<S>> {
+    fn get(&self) -> Option<Arc<Mutex<S>>> {
+        self.upgrade()
+    }
+}
+
+/// Read `count` meters, asking the source how many it has the first time
+async fn read_levels<S: MeterSource>(source: &mut S, count: &mut Option<u16>) -> Result<Vec<f32>> {
+    let count = match *count {
+        Some(count) => count,
+        None => *count.insert(source.meter_count()?),
+    };
+    source.read_levels(count).await
+}
+
+/// A device or protocol handler that level meters can be read from
+pub trait MeterSource: Send + 'static {
+    /// Number of level meters
+    fn meter_count(&mut self) -> Result<u16>;
+
+    /// Read the first `count` level meters, in dB
+    fn read_levels(&mut self, count: u16) -> BoxFuture<'_, Result<Vec<f32>>>;
+}
+
+impl MeterSource for FcpProtocol {
+    fn meter_count(&mut self) -> Result<u16> {
+        self.read_meter_count()
+    }
+
+    fn read_levels(&mut self, count: u16) -> BoxFuture<'_, Result<Vec<f32>>> {
+        Box::pin(async move {
+            let raw = self.read_meters_async(count).await?;
+            Ok(raw.into_iter().map(meter_to_db).collect())
+        })
+    }
+}
+
+impl MeterSource for Scarlett2Protocol {
+    fn meter_count(&mut self) -> Result<u16> {
+        Scarlett2Protocol::meter_count(self)
+    }
+
+    fn read_levels(&mut self, count: u16) -> BoxFuture<'_, Result<Vec<f32>>> {
+        Box::pin(async move {
+            let raw = self.get_meter_levels_async(count).await?;
+            Ok(raw.into_iter().map(meter_to_db).collect())
+        })
+    }
+}
+
+/// No device open; reads fail as if it had been unplugged
+impl<S: MeterSource> MeterSource for Option<S> {
+    fn meter_count(&mut self) -> Result<u16> {
+        self.as_mut().ok_or(Error::DeviceNotFound)?.meter_count()
+    }
+
+    fn read_levels(&mut self, count: u16) -> BoxFuture<'_, Result<Vec<f32>>> {
+        match self {
+            Some(source) => source.read_levels(count),
+            None => Box::pin(std::future::ready(Err(Error::DeviceNotFound))),
+        }
+    }
+}
+
+/// Meter levels read by a [`MeterStream`] and broadcast to subscribers
+///
+/// Only successful reads are sent. A read that overruns the interval delays
+/// the next one rather than letting reads queue up. The task stops when the
+/// broadcast or the source is dropped, or once the device is gone.
+pub struct MeterBroadcast {
+    meter_tx: broadcast::Sender<Vec<f32>>,
+    task: JoinHandle<()>,
+}
+
+impl MeterBroadcast {
+    /// Start reading every meter of `source` every `interval`
+    ///
+    /// Only a weak reference is kept, so the task doesn't keep the device
+    /// open.
+    pub fn spawn<S: MeterSource>(source: Weak<Mutex<S>>, interval: Duration) -> Self {
+        let (meter_tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+        let task_tx = meter_tx.clone();
+        let mut stream = MeterStream::spawn_weak(source, interval);
+
+        // Dropping the stream when this task ends stops the polling task too
+        let task = tokio::spawn(async move {
+            while stream.changed().await {
+                // No subscribers is fine; one may subscribe later
+                if let Ok(levels) = stream.latest() {
+                    let _ = task_tx.send(levels);
+                }
+            }
+        });
+
+        Self { meter_tx, task }
+    }
+
+    /// Get a receiver of the meter levels
+    ///
+    /// A receiver that falls behind gets `RecvError::Lagged` and then the
+    /// newest readings.
+    pub fn subscribe(&self) -> broadcast::Receiver<Vec<f32>> {
+        self.meter_tx.subscribe()
+    }
+
+    /// Whether the polling task has stopped
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+impl Drop for MeterBroadcast {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter_to_db() {
+        assert_eq!(meter_to_db(METER_FULL_SCALE), 0.0);
+        assert_eq!(meter_to_db(0), -127.0);
+        assert!((meter_to_db(METER_FULL_SCALE / 2) + 6.02).abs() < 0.01);
+        assert!((meter_to_db(1) + 72.24).abs() < 0.01);
+
+        // Out-of-range levels clip at full scale
+        assert_eq!(meter_to_db(u32::MAX), 0.0);
+    }
+}
@@ -0,0 +1,404 @@
+//! Scripted transport for protocol tests
+//!
+//! Tests list the control transfers they expect, with exact payloads, along
+//! with the device's answers. Any other transfer, or a payload that differs,
+//! fails the test, so command sequences can be checked byte for byte
+//! without hardware. Enable the `testing` feature to use it outside this
+//! crate.
+
+use crate::transport::{AsyncUsbTransport, BulkTransfer, ControlTransfer, Direction, UsbTransport};
+use futures::future::BoxFuture;
+use scarlett_core::{Error, Result};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Class request carrying a command packet (SCARLETT2_USB_CMD_REQ)
+const CMD_REQ: u8 = 2;
+
+/// Class request reading back a response packet (SCARLETT2_USB_CMD_RESP)
+const CMD_RESP: u8 = 3;
+
+/// Build a Scarlett2/FCP packet: 16 byte header followed by `data`
+///
+/// The header holds the command, payload size and sequence number; the
+/// error and padding fields are zero.
+pub fn packet(cmd: u32, seq: u16, data: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(16 + data.len());
+    packet.extend_from_slice(&cmd.to_le_bytes());
+    packet.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    packet.extend_from_slice(&seq.to_le_bytes());
+    packet.extend_from_slice(&[0; 8]);
+    packet.extend_from_slice(data);
+    packet
+}
+
+/// A control transfer made through the mock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    /// Request type (vendor, class, standard)
+    pub request_type: u8,
+    /// Specific request
+    pub request: u8,
+    /// Request value
+    pub value: u16,
+    /// Request index (often interface number)
+    pub index: u16,
+    /// Direction (In or Out)
+    pub direction: Direction,
+    /// Timeout the transfer was made with
+    pub timeout: Duration,
+    /// Payload sent (OUT) or returned (IN)
+    pub data: Vec<u8>,
+}
+
+/// A transfer the test expects next, and how to answer it
+struct Expectation {
+    transfer: ControlTransfer,
+    /// Exact payload for OUT transfers, the response for IN transfers
+    data: Vec<u8>,
+    /// Fail the transfer with this error instead
+    error: Option<Error>,
+}
+
+#[derive(Default)]
+struct Script {
+    expected: VecDeque<Expectation>,
+    interrupts: VecDeque<(u8, Vec<u8>)>,
+    transcript: Vec<Exchange>,
+    disconnected: bool,
+}
+
+/// Transport that answers from a script of expected transfers
+///
+/// Clones share the script, so a test can keep one handle while the
+/// protocol under test owns another.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    script: Arc<Mutex<Script>>,
+}
+
+impl MockTransport {
+    /// Create a mock that expects no transfers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn script(&self) -> MutexGuard<'_, Script> {
+        // A failed expectation panics; keep later checks working
+        self.script.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, transfer: ControlTransfer, data: &[u8], error: Option<Error>) -> &Self {
+        self.script().expected.push_back(Expectation { transfer, data: data.to_vec(), error });
+        self
+    }
+
+    /// Expect an OUT transfer carrying exactly `data`
+    pub fn expect_out(&self, transfer: ControlTransfer, data: &[u8]) -> &Self {
+        assert_eq!(transfer.direction, Direction::Out, "expect_out needs an OUT transfer");
+        self.push(transfer, data, None)
+    }
+
+    /// Expect an IN transfer and answer it with `response`
+    pub fn expect_in(&self, transfer: ControlTransfer, response: &[u8]) -> &Self {
+        assert_eq!(transfer.direction, Direction::In, "expect_in needs an IN transfer");
+        self.push(transfer, response, None)
+    }
+
+    /// Expect a transfer and fail it with `error`
+    ///
+    /// For OUT transfers `data` is the expected payload; it is ignored for
+    /// IN transfers. `Error::Disconnected` also unplugs the mock, as
+    /// `DirectUsbTransport` notes the device is gone.
+    pub fn expect_error(&self, transfer: ControlTransfer, data: &[u8], error: Error) -> &Self {
+        self.push(transfer, data, Some(error))
+    }
+
+    /// Expect a command packet on `interface`, and its response if given
+    ///
+    /// The response echoes the command and sequence number with no error.
+    /// FCP commands that return nothing are not read back, so pass `None`
+    /// for them; Scarlett2 commands always are.
+    pub fn expect_command(&self, interface: u8, cmd: u32, seq: u16, request: &[u8], response: Option<&[u8]>) -> &Self {
+        self.expect_out(ControlTransfer::class_out(CMD_REQ, 0, interface as u16), &packet(cmd, seq, request));
+        if let Some(response) = response {
+            self.expect_response(interface, &packet(cmd, seq, response));
+        }
+        self
+    }
+
+    /// Expect a response read on `interface` and answer it with a raw packet
+    pub fn expect_response(&self, interface: u8, packet: &[u8]) -> &Self {
+        self.expect_in(ControlTransfer::class_in(CMD_RESP, 0, interface as u16), packet)
+    }
+
+    /// Send `data` on the next interrupt read from `endpoint`
+    ///
+    /// Interrupt reads with nothing queued see no data, as if they had
+    /// timed out.
+    pub fn queue_interrupt(&self, endpoint: u8, data: &[u8]) -> &Self {
+        self.script().interrupts.push_back((endpoint, data.to_vec()));
+        self
+    }
+
+    /// Fail every later transfer as if the device had been unplugged
+    pub fn disconnect(&self) {
+        self.script().disconnected = true;
+    }
+
+    /// Every control transfer made so far, including failed ones
+    pub fn transcript(&self) -> Vec<Exchange> {
+        self.script().transcript.clone()
+    }
+
+    /// Payloads of the OUT transfers made so far
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        self.script()
+            .transcript
+            .iter()
+            .filter(|exchange| exchange.direction == Direction::Out)
+            .map(|exchange| exchange.data.clone())
+            .collect()
+    }
+
+    /// Number of expected transfers not made yet
+    pub fn pending(&self) -> usize {
+        self.script().expected.len()
+    }
+
+    /// Check that every expected transfer has been made
+    pub fn assert_done(&self) {
+        let script = self.script();
+        let pending: Vec<String> = script.expected.iter().map(|e| describe(&e.transfer)).collect();
+        assert!(pending.is_empty(), "expected transfers not made: {}", pending.join(", "));
+    }
+
+    /// Take the next expectation, failing the test unless it matches
+    fn next(&self, transfer: &ControlTransfer) -> Result<Expectation> {
+        let mut script = self.script();
+        if script.disconnected {
+            return Err(Error::Disconnected);
+        }
+
+        let Some(expected) = script.expected.pop_front() else {
+            drop(script);
+            panic!("unexpected {}", describe(transfer));
+        };
+        let e = &expected.transfer;
+        if (e.request_type, e.request, e.value, e.index, e.direction)
+            != (transfer.request_type, transfer.request, transfer.value, transfer.index, transfer.direction)
+        {
+            drop(script);
+            panic!("expected {}, got {}", describe(e), describe(transfer));
+        }
+
+        if matches!(expected.error, Some(Error::Disconnected)) {
+            script.disconnected = true;
+        }
+        Ok(expected)
+    }
+
+    fn record(&self, transfer: &ControlTransfer, data: &[u8]) {
+        self.script().transcript.push(Exchange {
+            request_type: transfer.request_type,
+            request: transfer.request,
+            value: transfer.value,
+            index: transfer.index,
+            direction: transfer.direction,
+            timeout: transfer.timeout,
+            data: data.to_vec(),
+        });
+    }
+}
+
+/// Short description of a transfer for failure messages
+fn describe(transfer: &ControlTransfer) -> String {
+    format!(
+        "control {:?} (type 0x{:02x}, request {}, value {}, index {})",
+        transfer.direction, transfer.request_type, transfer.request, transfer.value, transfer.index
+    )
+}
+
+impl UsbTransport for MockTransport {
+    fn control_out(&self, transfer: &ControlTransfer, data: &[u8]) -> Result<usize> {
+        let expected = self.next(transfer)?;
+        self.record(transfer, data);
+        assert_eq!(data, expected.data, "payload of {}", describe(transfer));
+
+        match expected.error {
+            Some(error) => Err(error),
+            None => Ok(data.len()),
+        }
+    }
+
+    fn control_in(&self, transfer: &ControlTransfer, buffer: &mut [u8]) -> Result<usize> {
+        let expected = self.next(transfer)?;
+        if let Some(error) = expected.error {
+            self.record(transfer, &[]);
+            return Err(error);
+        }
+
+        let len = expected.data.len();
+        assert!(
+            len <= buffer.len(),
+            "{} byte response doesn't fit the {} byte buffer of {}",
+            len, buffer.len(), describe(transfer)
+        );
+        buffer[..len].copy_from_slice(&expected.data);
+        self.record(transfer, &expected.data);
+        Ok(len)
+    }
+
+    fn bulk_out(&self, _transfer: &BulkTransfer, _data: &[u8]) -> Result<usize> {
+        Err(Error::NotSupported("Bulk transfers".to_string()))
+    }
+
+    fn bulk_in(&self, _transfer: &BulkTransfer, _buffer: &mut [u8]) -> Result<usize> {
+        Err(Error::NotSupported("Bulk transfers".to_string()))
+    }
+
+    fn interrupt_in(&self, transfer: &BulkTransfer, buffer: &mut [u8]) -> Result<usize> {
+        let Some((endpoint, data)) = self.script().interrupts.pop_front() else {
+            return Ok(0);
+        };
+        assert_eq!(endpoint, transfer.endpoint, "interrupt read from the wrong endpoint");
+
+        let len = data.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+
+    fn is_connected(&self) -> bool {
+        !self.script().disconnected
+    }
+
+    fn transport_name(&self) -> &'static str {
+        "Mock"
+    }
+
+    fn as_async(&self) -> Option<&dyn AsyncUsbTransport> {
+        Some(self)
+    }
+}
+
+impl AsyncUsbTransport for MockTransport {
+    fn control_out_async<'a>(&'a self, transfer: &'a ControlTransfer, data: &'a [u8]) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(async move { self.control_out(transfer, data) })
+    }
+
+    fn control_in_async<'a>(&'a self, transfer: &'a ControlTransfer, buffer: &'a mut [u8]) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(async move { self.control_in(transfer, buffer) })
+    }
+}
+
+/// Mock that completes each transfer on another thread
+///
+/// This is how the kernel finishes a blocking control transfer, so tests
+/// can check protocol code doesn't rely on the calling thread to make
+/// progress, as a nested executor would. It has no async path.
+#[derive(Clone)]
+pub struct ThreadedTransport(pub MockTransport);
+
+impl ThreadedTransport {
+    fn complete<T: Send>(&self, transfer: impl FnOnce(&MockTransport) -> T + Send) -> T {
+        std::thread::scope(|scope| {
+            let thread = scope.spawn(|| transfer(&self.0));
+            thread.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+}
+
+impl UsbTransport for ThreadedTransport {
+    fn control_out(&self, transfer: &ControlTransfer, data: &[u8]) -> Result<usize> {
+        self.complete(|mock| mock.control_out(transfer, data))
+    }
+
+    fn control_in(&self, transfer: &ControlTransfer, buffer: &mut [u8]) -> Result<usize> {
+        self.complete(|mock| mock.control_in(transfer, buffer))
+    }
+
+    fn bulk_out(&self, transfer: &BulkTransfer, data: &[u8]) -> Result<usize> {
+        self.0.bulk_out(transfer, data)
+    }
+
+    fn bulk_in(&self, transfer: &BulkTransfer, buffer: &mut [u8]) -> Result<usize> {
+        self.0.bulk_in(transfer, buffer)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.0.is_connected()
+    }
+
+    fn transport_name(&self) -> &'static str {
+        "Threaded mock"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripted_exchange() {
+        let mock = MockTransport::new();
+        mock.expect_command(3, 0x1001, 7, &[1, 2], Some(&[0xaa]));
+        assert_eq!(mock.pending(), 2);
+
+        let out = ControlTransfer::class_out(2, 0, 3);
+        mock.control_out(&out, &packet(0x1001, 7, &[1, 2])).unwrap();
+
+        let mut buffer = [0u8; 32];
+        let len = mock.control_in(&ControlTransfer::class_in(3, 0, 3), &mut buffer).unwrap();
+        assert_eq!(buffer[..len], packet(0x1001, 7, &[0xaa]));
+        mock.assert_done();
+
+        let transcript = mock.transcript();
+        assert_eq!(transcript.len(), 2);
+        assert_eq!(transcript[0].direction, Direction::Out);
+        assert_eq!(transcript[0].data[4..6], 2u16.to_le_bytes());
+        assert_eq!(mock.sent(), vec![packet(0x1001, 7, &[1, 2])]);
+    }
+
+    #[test]
+    fn test_scripted_errors() {
+        let mock = MockTransport::new();
+        let transfer = ControlTransfer::vendor_in(1, 0, 0);
+        mock.expect_error(transfer.clone(), &[], Error::Timeout("Control IN".to_string()));
+
+        let mut buffer = [0u8; 4];
+        assert!(matches!(mock.control_in(&transfer, &mut buffer), Err(Error::Timeout(_))));
+
+        mock.disconnect();
+        assert!(!mock.is_connected());
+        assert!(matches!(mock.control_in(&transfer, &mut buffer), Err(Error::Disconnected)));
+    }
+
+    #[test]
+    fn test_disconnected_error_unplugs() {
+        let mock = MockTransport::new();
+        let transfer = ControlTransfer::vendor_in(1, 0, 0);
+        mock.expect_error(transfer.clone(), &[], Error::Disconnected);
+        assert!(mock.is_connected());
+
+        let mut buffer = [0u8; 4];
+        assert!(matches!(mock.control_in(&transfer, &mut buffer), Err(Error::Disconnected)));
+        assert!(!mock.is_connected());
+        assert!(matches!(mock.control_in(&transfer, &mut buffer), Err(Error::Disconnected)));
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected control Out")]
+    fn test_unexpected_transfer() {
+        let mock = MockTransport::new();
+        let _ = mock.control_out(&ControlTransfer::vendor_out(1, 0, 0), &[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "payload of control Out")]
+    fn test_wrong_payload() {
+        let mock = MockTransport::new();
+        mock.expect_out(ControlTransfer::vendor_out(1, 0, 0), &[1]);
+        let _ = mock.control_out(&ControlTransfer::vendor_out(1, 0, 0), &[2]);
+    }
+}
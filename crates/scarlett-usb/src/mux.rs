@@ -0,0 +1,259 @@
+//! Scarlett2 mux table layouts
+//!
+//! The device keeps one mux table per sample rate band (1x = 44.1/48 kHz,
+//! 2x = 88.2/96 kHz, 4x = 176.4/192 kHz). Each table is written whole, with
+//! its destinations in a fixed per-model order taken from the
+//! `mux_assignment` tables in mixer_scarlett2.c.
+
+use crate::gen4_fcp::mux_port_id;
+use scarlett_core::routing::{Port, PortType, RoutingMatrix};
+use scarlett_core::DeviceModel;
+
+/// Number of mux tables, one per sample rate band
+pub const MUX_TABLES: usize = 3;
+
+/// A run of consecutive destinations in a mux table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MuxRun {
+    /// Destination port type, or `None` for empty slots
+    pub port_type: Option<PortType>,
+    /// Index of the first destination
+    pub start: usize,
+    /// Number of slots
+    pub count: usize,
+}
+
+/// The destination order of each mux table
+pub type MuxTables = [&'static [MuxRun]; MUX_TABLES];
+
+const fn run(port_type: PortType, start: usize, count: usize) -> MuxRun {
+    MuxRun { port_type: Some(port_type), start, count }
+}
+
+const fn pcm(start: usize, count: usize) -> MuxRun {
+    run(PortType::PcmOut, start, count)
+}
+
+const fn analogue(start: usize, count: usize) -> MuxRun {
+    run(PortType::AnalogOut, start, count)
+}
+
+const fn spdif(start: usize, count: usize) -> MuxRun {
+    run(PortType::SpdifOut, start, count)
+}
+
+const fn adat(start: usize, count: usize) -> MuxRun {
+    run(PortType::AdatOut, start, count)
+}
+
+const fn mix(start: usize, count: usize) -> MuxRun {
+    run(PortType::MixerIn, start, count)
+}
+
+const fn empty(count: usize) -> MuxRun {
+    MuxRun { port_type: None, start: 0, count }
+}
+
+const S6I6_GEN2_MUX: MuxTables = [
+    &[pcm(0, 6), analogue(0, 4), spdif(0, 2), mix(0, 18), empty(8)],
+    &[pcm(0, 6), analogue(0, 4), spdif(0, 2), mix(0, 18), empty(8)],
+    &[pcm(0, 6), analogue(0, 4), spdif(0, 2), mix(0, 18), empty(8)],
+];
+
+const S18I8_GEN2_MUX: MuxTables = [
+    &[pcm(0, 18), analogue(0, 6), spdif(0, 2), mix(0, 18), empty(8)],
+    &[pcm(0, 14), analogue(0, 6), spdif(0, 2), mix(0, 18), empty(8)],
+    &[pcm(0, 10), analogue(0, 6), spdif(0, 2), mix(0, 18), empty(4)],
+];
+
+const S18I20_GEN2_MUX: MuxTables = [
+    &[pcm(0, 18), analogue(0, 10), spdif(0, 2), adat(0, 8), mix(0, 18), empty(8)],
+    &[pcm(0, 14), analogue(0, 10), spdif(0, 2), adat(0, 4), mix(0, 18), empty(8)],
+    &[pcm(0, 10), analogue(0, 10), spdif(0, 2), mix(0, 18), empty(6)],
+];
+
+const S4I4_GEN3_MUX: MuxTables = [
+    &[pcm(0, 6), analogue(0, 4), mix(0, 8), empty(16)],
+    &[pcm(0, 6), analogue(0, 4), mix(0, 8), empty(16)],
+    &[pcm(0, 6), analogue(0, 4), mix(0, 8), empty(16)],
+];
+
+const S8I6_GEN3_MUX: MuxTables = [
+    &[pcm(0, 8), analogue(0, 4), spdif(0, 2), pcm(8, 2), mix(0, 8), empty(18)],
+    &[pcm(0, 8), analogue(0, 4), spdif(0, 2), pcm(8, 2), mix(0, 8), empty(18)],
+    &[pcm(0, 8), analogue(0, 4), spdif(0, 2), pcm(8, 2), mix(0, 8), empty(18)],
+];
+
+const S18I8_GEN3_MUX: MuxTables = [
+    &[pcm(0, 10), pcm(12, 8), analogue(0, 2), analogue(6, 2), analogue(2, 4), spdif(0, 2), pcm(10, 2), mix(0, 20), empty(10)],
+    &[pcm(0, 10), pcm(12, 4), analogue(0, 2), analogue(6, 2), analogue(2, 4), spdif(0, 2), pcm(10, 2), mix(0, 20), empty(10)],
+    &[pcm(0, 10), analogue(0, 2), analogue(6, 2), analogue(2, 4), spdif(0, 2), mix(0, 20), empty(10)],
+];
+
+const S18I20_GEN3_MUX: MuxTables = [
+    &[pcm(0, 8), pcm(10, 10), analogue(0, 10), spdif(0, 2), adat(0, 8), pcm(8, 2), mix(0, 25), empty(12)],
+    &[pcm(0, 8), pcm(10, 8), analogue(0, 10), spdif(0, 2), adat(0, 8), pcm(8, 2), mix(0, 25), empty(10)],
+    &[pcm(0, 10), analogue(0, 10), spdif(0, 2), empty(24)],
+];
+
+const VOCASTER_ONE_MUX: MuxTables = [
+    &[mix(8, 1), pcm(5, 5), mix(6, 2), pcm(0, 5), mix(0, 6), analogue(0, 4)],
+    &[],
+    &[],
+];
+
+const VOCASTER_TWO_MUX: MuxTables = [
+    &[mix(12, 2), pcm(6, 8), mix(10, 2), pcm(0, 6), mix(0, 10), analogue(0, 6)],
+    &[],
+    &[],
+];
+
+const SOLO_GEN4_MUX: MuxTables = [
+    &[mix(4, 2), mix(2, 2), pcm(0, 4), mix(0, 2), analogue(0, 2)],
+    &[mix(4, 2), mix(2, 2), pcm(0, 4), mix(0, 2), analogue(0, 2)],
+    &[mix(4, 2), mix(2, 2), pcm(0, 4), mix(0, 2), analogue(0, 2)],
+];
+
+const S2I2_GEN4_MUX: MuxTables = [
+    &[mix(4, 2), mix(2, 2), pcm(0, 4), mix(0, 2), analogue(0, 2)],
+    &[mix(4, 2), mix(2, 2), pcm(0, 4), mix(0, 2), analogue(0, 2)],
+    &[mix(4, 2), mix(2, 2), pcm(0, 4), mix(0, 2), analogue(0, 2)],
+];
+
+const S4I4_GEN4_MUX: MuxTables = [
+    &[mix(10, 2), pcm(0, 6), mix(0, 10), analogue(0, 6)],
+    &[mix(10, 2), pcm(0, 6), mix(0, 10), analogue(0, 6)],
+    &[mix(10, 2), pcm(0, 6), mix(0, 10), analogue(0, 6)],
+];
+
+const CLARETT_2PRE_MUX: MuxTables = [
+    &[pcm(0, 12), analogue(0, 4), mix(0, 18), empty(8)],
+    &[pcm(0, 8), analogue(0, 4), mix(0, 18), empty(8)],
+    &[pcm(0, 2), analogue(0, 4), empty(26)],
+];
+
+const CLARETT_4PRE_MUX: MuxTables = [
+    &[pcm(0, 18), analogue(0, 6), spdif(0, 2), mix(0, 18), empty(8)],
+    &[pcm(0, 14), analogue(0, 6), spdif(0, 2), mix(0, 18), empty(8)],
+    &[pcm(0, 12), analogue(0, 6), spdif(0, 2), empty(24)],
+];
+
+const CLARETT_8PRE_MUX: MuxTables = [
+    &[pcm(0, 18), analogue(0, 10), spdif(0, 2), adat(0, 8), mix(0, 18), empty(8)],
+    &[pcm(0, 14), analogue(0, 10), spdif(0, 2), adat(0, 4), mix(0, 18), empty(8)],
+    &[pcm(0, 12), analogue(0, 10), spdif(0, 2), empty(22)],
+];
+
+/// Destinations of a mux table in order, leaving out empty slots
+///
+/// The device reports its level meters in this order for the 1x table (the
+/// `meter_map` tables in mixer_scarlett2.c list the same runs).
+pub fn table_ports(runs: &[MuxRun]) -> Vec<(PortType, usize)> {
+    runs.iter()
+        .filter_map(|run| run.port_type.map(|port_type| (port_type, run)))
+        .flat_map(|(port_type, run)| (run.start..run.start + run.count).map(move |i| (port_type, i)))
+        .collect()
+}
+
+/// Mux table layouts of a model
+///
+/// `None` for models without a mux (Solo and 2i2 Gen 3) and for models
+/// not covered by the Scarlett2 protocol.
+pub fn mux_tables(model: DeviceModel) -> Option<MuxTables> {
+    use DeviceModel::*;
+
+    let tables = match model {
+        Scarlett6i6Gen2 => S6I6_GEN2_MUX,
+        Scarlett18i8Gen2 => S18I8_GEN2_MUX,
+        Scarlett18i20Gen2 => S18I20_GEN2_MUX,
+        Scarlett4i4Gen3 => S4I4_GEN3_MUX,
+        Scarlett8i6Gen3 => S8I6_GEN3_MUX,
+        Scarlett18i8Gen3 => S18I8_GEN3_MUX,
+        Scarlett18i20Gen3 => S18I20_GEN3_MUX,
+        ScarlettSoloGen4 => SOLO_GEN4_MUX,
+        Scarlett2i2Gen4 => S2I2_GEN4_MUX,
+        Scarlett4i4Gen4 => S4I4_GEN4_MUX,
+        Clarett2PreUsb | Clarett2PrePlus => CLARETT_2PRE_MUX,
+        Clarett4PreUsb | Clarett4PrePlus => CLARETT_4PRE_MUX,
+        Clarett8PreUsb | Clarett8PrePlus => CLARETT_8PRE_MUX,
+        VocasterOne => VOCASTER_ONE_MUX,
+        VocasterTwo => VOCASTER_TWO_MUX,
+        _ => return None,
+    };
+    Some(tables)
+}
+
+/// Build the entries of one mux table from a routing matrix
+///
+/// Each entry is `dest_id | source_id << 12`. Empty slots are 0, and
+/// destinations without a route (or missing from the matrix) get source 0.
+pub fn table_entries(runs: &[MuxRun], matrix: &RoutingMatrix) -> Vec<u32> {
+    let mut entries = Vec::new();
+
+    for run in runs {
+        let Some(port_type) = run.port_type else {
+            entries.extend(std::iter::repeat_n(0, run.count));
+            continue;
+        };
+
+        for index in run.start..run.start + run.count {
+            let dest = Port::new(port_type, index, "");
+            let Some(dest_id) = mux_port_id(&dest) else {
+                entries.push(0);
+                continue;
+            };
+
+            let source_id = matrix
+                .destinations
+                .iter()
+                .position(|port| port.port_type == port_type && port.index == index)
+                .and_then(|dest_idx| matrix.get_route(dest_idx))
+                .and_then(|source_idx| mux_port_id(&matrix.sources[source_idx]))
+                .unwrap_or(0);
+            entries.push(dest_id | source_id << 12);
+        }
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tables_cover_layout() {
+        // The 1x table names every destination of the model exactly once
+        for model in DeviceModel::ALL {
+            let Some(tables) = mux_tables(model) else { continue };
+            let layout = model.port_layout();
+
+            let slots = table_ports(tables[0]);
+
+            assert_eq!(slots.len(), layout.destinations.len(), "{}", model);
+            for port in &layout.destinations {
+                assert!(slots.contains(&(port.port_type, port.index)), "{}: {}", model, port.name);
+            }
+        }
+
+        assert!(mux_tables(DeviceModel::Scarlett2i2Gen3).is_none());
+        assert!(mux_tables(DeviceModel::Scarlett18i20Gen4).is_none());
+    }
+
+    #[test]
+    fn test_table_entries() {
+        let mut matrix = RoutingMatrix::for_model(DeviceModel::Scarlett4i4Gen3);
+        // Monitor R from PCM 1
+        let pcm_1 = matrix.sources.iter().position(|port| port.name == "PCM 1").unwrap();
+        matrix.set_route(1, Some(pcm_1)).unwrap();
+        assert_eq!(matrix.destinations[1].name, "Monitor R");
+
+        let entries = table_entries(S4I4_GEN3_MUX[0], &matrix);
+        assert_eq!(entries.len(), 6 + 4 + 8 + 16);
+        assert_eq!(entries[0], 0x600);
+        assert_eq!(entries[6], 0x080);
+        assert_eq!(entries[7], 0x081 | 0x600 << 12);
+        assert_eq!(entries[10], 0x300);
+        assert!(entries[18..].iter().all(|&entry| entry == 0));
+    }
+}
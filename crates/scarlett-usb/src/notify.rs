@@ -0,0 +1,200 @@
+//! Device-side change tracking
+//!
+//! Front-panel controls (e.g. the monitor knob) change values on the device
+//! without the host asking. Gen 4 devices only signal which group of
+//! controls changed, so this watches a set of data offsets and reports
+//! values that differ from the last read. Gen 2/3 devices send the changed
+//! groups as [`Notification`]s on their interrupt endpoint.
+
+use crate::config_items::{self, ConfigAccess, ConfigParam};
+use crate::devmap::DevMapParam;
+use crate::gen3_protocol::Scarlett2Protocol;
+use crate::gen4_fcp::FcpProtocol;
+use scarlett_core::{DeviceModel, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+
+/// A value changed on the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceChange {
+    /// Offset into the device's data space
+    pub offset: u32,
+    /// Value now read from the device
+    pub new_value: i32,
+}
+
+/// A group of controls changed on a Scarlett2 device
+///
+/// The new values have to be read back from the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Notification {
+    /// Clock sync status
+    Sync,
+    /// Monitor Dim and Mute buttons
+    DimMute,
+    /// Monitor knob, and the line output volumes it controls
+    Monitor,
+    /// Input level, pad, Air, phantom power and link switches
+    InputOther,
+    /// Speaker switching and talkback
+    MonitorOther,
+    /// Direct Monitor switch (Solo, 2i2)
+    DirectMonitor,
+}
+
+/// Decode the bitmask of a Scarlett2 notification
+///
+/// Unknown bits are logged and skipped.
+pub fn decode_notifications(model: DeviceModel, mask: u32) -> Vec<Notification> {
+    // Bit 0 acknowledges a command and carries no state
+    let mut remaining = mask & !1;
+
+    let notifications = config_items::notifications(model)
+        .iter()
+        .filter(|&&(bit, _)| mask & bit != 0)
+        .map(|&(bit, notification)| {
+            remaining &= !bit;
+            notification
+        })
+        .collect();
+
+    if remaining != 0 {
+        tracing::warn!("Unhandled notification from {}: 0x{:08x}", model, remaining);
+    }
+
+    notifications
+}
+
+/// Tracks a set of data values and reports the ones that change
+#[derive(Debug, Clone, Default)]
+pub struct ChangeWatcher {
+    /// Offset and size in bytes of each watched value
+    watched: Vec<(u32, u32)>,
+    /// Last value read at each offset
+    values: HashMap<u32, i32>,
+}
+
+impl ChangeWatcher {
+    /// Create an empty watcher
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watch a value of `size` bytes at `offset`
+    pub fn watch(&mut self, offset: u32, size: u32) {
+        if !self.watched.iter().any(|&(o, _)| o == offset) {
+            self.watched.push((offset, size));
+        }
+    }
+
+    /// Watch the volume and mute of the first `count` outputs
+    pub fn watch_outputs(&mut self, fcp: &FcpProtocol, count: u8) {
+        for output in 0..count {
+            for param in [DevMapParam::LineOutVolume, DevMapParam::MuteSwitch] {
+                let (offset, size) = fcp.output_location(param, output);
+                self.watch(offset, size);
+            }
+        }
+    }
+
+    /// Watch the monitor knob position, if the device reports it
+    pub fn watch_knob(&mut self, fcp: &FcpProtocol) {
+        if let Ok((_, item)) = fcp.lookup_config(ConfigParam::MasterVolume) {
+            self.watch(item.offset, item.size_bytes());
+        }
+    }
+
+    /// Read every watched value and return the ones that changed
+    ///
+    /// The first read of a value only records it.
+    pub fn poll(&mut self, fcp: &mut FcpProtocol) -> Result<Vec<DeviceChange>> {
+        let mut changes = Vec::new();
+
+        for &(offset, size) in &self.watched {
+            let value = fcp.read_data(offset, size)?;
+            fcp.update_cache(offset, size, value);
+            if let Some(previous) = self.values.insert(offset, value) {
+                if previous != value {
+                    changes.push(DeviceChange { offset, new_value: value });
+                }
+            }
+        }
+
+        Ok(changes)
+    }
+}
+
+/// Poll a device in the background and stream the changes
+///
+/// The task stops when the receiver is dropped or a read fails (e.g. the
+/// device was unplugged).
+pub fn spawn_change_stream(
+    fcp: Arc<Mutex<FcpProtocol>>,
+    mut watcher: ChangeWatcher,
+    interval: Duration,
+) -> mpsc::UnboundedReceiver<DeviceChange> {
+    let (change_tx, change_rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+
+            let changes = match watcher.poll(&mut *fcp.lock().await) {
+                Ok(changes) => changes,
+                Err(e) => {
+                    tracing::warn!("Stopping change polling: {}", e);
+                    return;
+                }
+            };
+
+            for change in changes {
+                tracing::debug!("Device change at 0x{:x}: {}", change.offset, change.new_value);
+                if change_tx.send(change).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    change_rx
+}
+
+/// Read Scarlett2 notifications in the background and stream them
+///
+/// The task stops when the receiver is dropped or a read fails (e.g. the
+/// device was unplugged).
+pub fn spawn_notification_stream(
+    protocol: Arc<Mutex<Scarlett2Protocol>>,
+    interval: Duration,
+) -> mpsc::UnboundedReceiver<Notification> {
+    let (notify_tx, notify_rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+
+            let notifications = match protocol.lock().await.read_notifications() {
+                Ok(notifications) => notifications,
+                Err(e) => {
+                    tracing::warn!("Stopping notification reading: {}", e);
+                    return;
+                }
+            };
+
+            for notification in notifications {
+                tracing::debug!("Device notification: {:?}", notification);
+                if notify_tx.send(notification).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    notify_rx
+}
@@ -1,85 +1,254 @@
 //! Protocol implementation for different device generations
 
-use scarlett_core::{DeviceGeneration, Error, Result};
+use crate::transport::UsbTransport;
+use scarlett_core::mixer::{LevelMeter, MixerChannel, MixerState};
+use scarlett_core::routing::RoutingMatrix;
+use scarlett_core::{
+    AirMode, DeviceGeneration, DeviceModel, DirectMonitorMode, Error, InputLevel, PowerStatus, Result, SyncStatus,
+};
 
 /// Protocol trait for device-specific communication
 pub trait Protocol: Send + Sync {
     /// Get routing matrix
-    fn get_routing(&mut self) -> Result<scarlett_core::routing::RoutingMatrix>;
+    fn get_routing(&mut self) -> Result<RoutingMatrix>;
 
     /// Set routing
-    fn set_routing(&mut self, matrix: &scarlett_core::routing::RoutingMatrix) -> Result<()>;
+    ///
+    /// Implementations check the matrix with `RoutingMatrix::validate` first.
+    fn set_routing(&mut self, matrix: &RoutingMatrix) -> Result<()>;
 
     /// Get mixer state
-    fn get_mixer_state(&mut self) -> Result<scarlett_core::mixer::MixerState>;
+    ///
+    /// Holds the gains of [`MIXER_STATE_MIX`], with a channel per mixer input.
+    fn get_mixer_state(&mut self) -> Result<MixerState>;
 
-    /// Set mixer channel volume
+    /// Set the gain of a mixer input to [`MIXER_STATE_MIX`], in dB
     fn set_channel_volume(&mut self, channel: usize, volume_db: f32) -> Result<()>;
 
     /// Set mixer channel pan
     fn set_channel_pan(&mut self, channel: usize, pan: f32) -> Result<()>;
 
     /// Get level meters
-    fn get_level_meters(&mut self) -> Result<Vec<scarlett_core::mixer::LevelMeter>>;
-}
+    fn get_level_meters(&mut self) -> Result<Vec<LevelMeter>>;
 
-/// Create protocol handler for a device generation
-pub fn create_protocol(generation: DeviceGeneration) -> Box<dyn Protocol> {
-    match generation {
-        DeviceGeneration::Gen1 => Box::new(Gen1Protocol::new()),
-        DeviceGeneration::Gen2 => Box::new(Gen2Protocol::new()),
-        DeviceGeneration::Gen3 => Box::new(Gen3Protocol::new()),
-        DeviceGeneration::Gen4 => Box::new(Gen4Protocol::new()),
-        DeviceGeneration::Clarett => Box::new(ClarettProtocol::new()),
-        DeviceGeneration::ClarettPlus => Box::new(ClarettPlusProtocol::new()),
-        DeviceGeneration::Vocaster => Box::new(VocasterProtocol::new()),
+    /// Get preamp gain for an input, in dB
+    fn get_input_gain(&mut self, _input: u8) -> Result<u8> {
+        Err(Error::NotSupported("Input gain control".to_string()))
     }
-}
 
-/// Gen 1 protocol implementation
-pub struct Gen1Protocol;
+    /// Set preamp gain for an input, in dB
+    fn set_input_gain(&mut self, _input: u8, _gain_db: u8) -> Result<()> {
+        Err(Error::NotSupported("Input gain control".to_string()))
+    }
 
-impl Gen1Protocol {
-    pub fn new() -> Self {
-        Self
+    /// Get 48V phantom power state for a switch
+    fn get_phantom(&mut self, _channel_group: u8) -> Result<bool> {
+        Err(Error::NotSupported("Phantom power control".to_string()))
+    }
+
+    /// Set 48V phantom power state for a switch
+    fn set_phantom(&mut self, _channel_group: u8, _enabled: bool) -> Result<()> {
+        Err(Error::NotSupported("Phantom power control".to_string()))
+    }
+
+    /// Get Air mode for an input
+    fn get_air(&mut self, _input: u8) -> Result<AirMode> {
+        Err(Error::NotSupported("Air control".to_string()))
+    }
+
+    /// Set Air mode for an input
+    fn set_air(&mut self, _input: u8, _mode: AirMode) -> Result<()> {
+        Err(Error::NotSupported("Air control".to_string()))
+    }
+
+    /// Get -10 dB pad state for an input
+    fn get_pad(&mut self, _input: u8) -> Result<bool> {
+        Err(Error::NotSupported("Pad control".to_string()))
+    }
+
+    /// Set -10 dB pad state for an input
+    fn set_pad(&mut self, _input: u8, _enabled: bool) -> Result<()> {
+        Err(Error::NotSupported("Pad control".to_string()))
+    }
+
+    /// Get Line/Inst level for an input
+    fn get_input_level(&mut self, _input: u8) -> Result<InputLevel> {
+        Err(Error::NotSupported("Input level switching".to_string()))
+    }
+
+    /// Set Line/Inst level for an input
+    fn set_input_level(&mut self, _input: u8, _level: InputLevel) -> Result<()> {
+        Err(Error::NotSupported("Input level switching".to_string()))
+    }
+
+    /// Get Direct Monitor mode
+    fn get_direct_monitor(&mut self) -> Result<DirectMonitorMode> {
+        Err(Error::NotSupported("Direct Monitor".to_string()))
+    }
+
+    /// Set Direct Monitor mode
+    fn set_direct_monitor(&mut self, _mode: DirectMonitorMode) -> Result<()> {
+        Err(Error::NotSupported("Direct Monitor".to_string()))
+    }
+
+    /// Get monitor Dim state
+    fn get_dim(&mut self) -> Result<bool> {
+        Err(Error::NotSupported("Monitor Dim".to_string()))
+    }
+
+    /// Set monitor Dim state
+    fn set_dim(&mut self, _enabled: bool) -> Result<()> {
+        Err(Error::NotSupported("Monitor Dim".to_string()))
+    }
+
+    /// Get monitor Mute state
+    fn get_monitor_mute(&mut self) -> Result<bool> {
+        Err(Error::NotSupported("Monitor Mute".to_string()))
+    }
+
+    /// Set monitor Mute state
+    fn set_monitor_mute(&mut self, _muted: bool) -> Result<()> {
+        Err(Error::NotSupported("Monitor Mute".to_string()))
+    }
+
+    /// Get whether talkback is active
+    fn get_talkback(&mut self) -> Result<bool> {
+        Err(Error::NotSupported("Talkback".to_string()))
+    }
+
+    /// Enable or disable talkback
+    fn set_talkback(&mut self, _enabled: bool) -> Result<()> {
+        Err(Error::NotSupported("Talkback".to_string()))
+    }
+
+    /// Get whether talkback is routed to a mix
+    fn get_talkback_mix(&mut self, _mix_index: u8) -> Result<bool> {
+        Err(Error::NotSupported("Talkback".to_string()))
+    }
+
+    /// Route talkback to a mix, or remove it
+    fn set_talkback_mix(&mut self, _mix_index: u8, _enabled: bool) -> Result<()> {
+        Err(Error::NotSupported("Talkback".to_string()))
     }
-}
 
-impl Protocol for Gen1Protocol {
-    fn get_routing(&mut self) -> Result<scarlett_core::routing::RoutingMatrix> {
-        // TODO: Implement Gen 1 routing
-        Ok(scarlett_core::routing::RoutingMatrix::new())
+    /// Get whether the device is in MSD ("Easy Start") mode
+    fn get_msd_mode(&mut self) -> Result<bool> {
+        Err(Error::NotSupported("MSD mode".to_string()))
     }
 
-    fn set_routing(&mut self, _matrix: &scarlett_core::routing::RoutingMatrix) -> Result<()> {
-        // TODO: Implement Gen 1 routing
-        Ok(())
+    /// Turn off MSD mode and reboot the device
+    fn disable_msd_mode(&mut self) -> Result<()> {
+        Err(Error::NotSupported("MSD mode".to_string()))
     }
 
-    fn get_mixer_state(&mut self) -> Result<scarlett_core::mixer::MixerState> {
-        // TODO: Implement Gen 1 mixer
-        Ok(scarlett_core::mixer::MixerState::new())
+    /// Get whether standalone mode is enabled
+    fn get_standalone(&mut self) -> Result<bool> {
+        Err(Error::NotSupported("Standalone mode".to_string()))
     }
 
-    fn set_channel_volume(&mut self, _channel: usize, _volume_db: f32) -> Result<()> {
-        // TODO: Implement Gen 1 volume control
-        Ok(())
+    /// Enable or disable standalone mode
+    fn set_standalone(&mut self, _enabled: bool) -> Result<()> {
+        Err(Error::NotSupported("Standalone mode".to_string()))
     }
 
-    fn set_channel_pan(&mut self, _channel: usize, _pan: f32) -> Result<()> {
-        // TODO: Implement Gen 1 pan control
-        Ok(())
+    /// Get whether phantom power state is restored at power-on
+    fn get_phantom_persistence(&mut self) -> Result<bool> {
+        Err(Error::NotSupported("Phantom power persistence".to_string()))
     }
 
-    fn get_level_meters(&mut self) -> Result<Vec<scarlett_core::mixer::LevelMeter>> {
-        // TODO: Implement Gen 1 level meters
-        Ok(Vec::new())
+    /// Set whether phantom power state is restored at power-on
+    fn set_phantom_persistence(&mut self, _enabled: bool) -> Result<()> {
+        Err(Error::NotSupported("Phantom power persistence".to_string()))
+    }
+
+    /// Get clock sync status
+    fn sync_status(&mut self) -> Result<SyncStatus> {
+        Err(Error::NotSupported("Sync status".to_string()))
+    }
+
+    /// Get the power source
+    fn power_status(&mut self) -> Result<PowerStatus> {
+        Err(Error::NotSupported("Power status".to_string()))
     }
 }
 
-// Placeholder implementations for other generations
-macro_rules! impl_protocol_placeholder {
-    ($name:ident) => {
+/// Create and initialize a protocol handler for a device
+///
+/// `interface_num` is the vendor-specific interface the transport talks to
+/// (see `DirectUsbTransport::interface_number`). Generations without a
+/// protocol implementation get a handler whose calls all fail with
+/// `NotSupported`.
+pub fn create_protocol(
+    model: DeviceModel,
+    transport: Box<dyn UsbTransport>,
+    interface_num: u8,
+) -> Result<Box<dyn Protocol>> {
+    let protocol: Box<dyn Protocol> = match model.generation() {
+        DeviceGeneration::Gen4 if model.uses_fcp() => {
+            let mut protocol = Gen4Protocol::new_with_interface(transport, interface_num).with_model(model);
+            protocol.init()?;
+            Box::new(protocol)
+        }
+        // Gen 2/3 and the small Gen 4 devices use Scarlett2
+        DeviceGeneration::Gen2 | DeviceGeneration::Gen3 | DeviceGeneration::Gen4 => {
+            let mut protocol = Gen3Protocol::new(transport)
+                .with_interface(interface_num)
+                .with_model(model);
+            protocol.init()?;
+            Box::new(protocol)
+        }
+        DeviceGeneration::Gen1 => Box::new(Gen1Protocol::new()),
+        DeviceGeneration::Clarett => Box::new(ClarettProtocol::new()),
+        DeviceGeneration::ClarettPlus => Box::new(ClarettPlusProtocol::new()),
+        DeviceGeneration::Vocaster => Box::new(VocasterProtocol::new()),
+    };
+    Ok(protocol)
+}
+
+/// Gen 2 protocol implementation
+pub type Gen2Protocol = crate::gen3_protocol::Scarlett2Protocol;
+
+/// Gen 3 protocol implementation
+pub type Gen3Protocol = crate::gen3_protocol::Scarlett2Protocol;
+
+/// Gen 4 protocol implementation (16i16, 18i16 and 18i20; the smaller
+/// Gen 4 devices use [`Gen3Protocol`])
+pub type Gen4Protocol = crate::gen4_fcp::FcpProtocol;
+
+/// Mix whose gains make up the generic mixer state (Mix A)
+pub const MIXER_STATE_MIX: u16 = 0;
+
+/// Mixer state of one mix, with a channel per mixer input
+pub(crate) fn mixer_state_from_gains(gains: &[u16]) -> MixerState {
+    let mut state = MixerState::new();
+    state.channels = gains
+        .iter()
+        .enumerate()
+        .map(|(index, &gain)| {
+            let mut channel = MixerChannel::new(index, format!("Mixer In {}", index + 1));
+            channel.set_mixer_gain(gain as i16);
+            channel
+        })
+        .collect();
+    state
+}
+
+/// Level meters holding one reading each
+pub(crate) fn level_meters(levels_db: impl IntoIterator<Item = f32>) -> Vec<LevelMeter> {
+    levels_db
+        .into_iter()
+        .map(|level_db| {
+            let mut meter = LevelMeter::new();
+            meter.update(level_db);
+            meter
+        })
+        .collect()
+}
+
+// Generations without a protocol implementation; every call fails rather
+// than pretending to succeed
+macro_rules! impl_protocol_unsupported {
+    ($name:ident, $generation:literal) => {
         pub struct $name;
 
         impl $name {
@@ -88,37 +257,41 @@ macro_rules! impl_protocol_placeholder {
             }
         }
 
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
         impl Protocol for $name {
-            fn get_routing(&mut self) -> Result<scarlett_core::routing::RoutingMatrix> {
-                Ok(scarlett_core::routing::RoutingMatrix::new())
+            fn get_routing(&mut self) -> Result<RoutingMatrix> {
+                Err(Error::NotSupported(format!("Routing on {} devices", $generation)))
             }
 
-            fn set_routing(&mut self, _matrix: &scarlett_core::routing::RoutingMatrix) -> Result<()> {
-                Ok(())
+            fn set_routing(&mut self, _matrix: &RoutingMatrix) -> Result<()> {
+                Err(Error::NotSupported(format!("Routing on {} devices", $generation)))
             }
 
-            fn get_mixer_state(&mut self) -> Result<scarlett_core::mixer::MixerState> {
-                Ok(scarlett_core::mixer::MixerState::new())
+            fn get_mixer_state(&mut self) -> Result<MixerState> {
+                Err(Error::NotSupported(format!("Mixer on {} devices", $generation)))
             }
 
             fn set_channel_volume(&mut self, _channel: usize, _volume_db: f32) -> Result<()> {
-                Ok(())
+                Err(Error::NotSupported(format!("Mixer on {} devices", $generation)))
             }
 
             fn set_channel_pan(&mut self, _channel: usize, _pan: f32) -> Result<()> {
-                Ok(())
+                Err(Error::NotSupported(format!("Mixer on {} devices", $generation)))
             }
 
-            fn get_level_meters(&mut self) -> Result<Vec<scarlett_core::mixer::LevelMeter>> {
-                Ok(Vec::new())
+            fn get_level_meters(&mut self) -> Result<Vec<LevelMeter>> {
+                Err(Error::NotSupported(format!("Level meters on {} devices", $generation)))
             }
         }
     };
 }
 
-impl_protocol_placeholder!(Gen2Protocol);
-impl_protocol_placeholder!(Gen3Protocol);
-impl_protocol_placeholder!(Gen4Protocol);
-impl_protocol_placeholder!(ClarettProtocol);
-impl_protocol_placeholder!(ClarettPlusProtocol);
-impl_protocol_placeholder!(VocasterProtocol);
+impl_protocol_unsupported!(Gen1Protocol, "Gen 1");
+impl_protocol_unsupported!(ClarettProtocol, "Clarett");
+impl_protocol_unsupported!(ClarettPlusProtocol, "Clarett+");
+impl_protocol_unsupported!(VocasterProtocol, "Vocaster");
@@ -0,0 +1,523 @@
+//! Session recording and replay
+//!
+//! [`SessionRecorder`] wraps a transport and logs every control transfer
+//! as a line of JSON, with a timestamp and the bytes sent or received, so a
+//! session can be diffed against captures of Focusrite Control. A
+//! [`ReplayTransport`] feeds a recording back, answering IN transfers with
+//! the recorded data, to reproduce a session without hardware.
+
+use crate::transport::{AsyncUsbTransport, BulkTransfer, ControlTransfer, Direction, UsbTransport};
+use futures::future::BoxFuture;
+use scarlett_core::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// One control transfer of a recorded session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedTransfer {
+    /// Microseconds from the start of the recording to the transfer
+    pub time_us: u64,
+    /// Microseconds the transfer took
+    #[serde(default)]
+    pub latency_us: u64,
+    pub direction: Direction,
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    /// Bytes requested, as in the setup packet
+    #[serde(default)]
+    pub length: u16,
+    /// Bytes sent (OUT) or received (IN), as hex
+    #[serde(with = "hex_bytes")]
+    pub data: Vec<u8>,
+    /// Error the transfer failed with, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RecordedTransfer {
+    /// Check if this is a recording of `transfer`, ignoring the data
+    fn matches(&self, transfer: &ControlTransfer) -> bool {
+        (self.direction, self.request_type, self.request, self.value, self.index)
+            == (transfer.direction, transfer.request_type, transfer.request, transfer.value, transfer.index)
+    }
+}
+
+/// Transport wrapper that logs every control transfer
+///
+/// Each transfer is written as one line of JSON (see [`RecordedTransfer`])
+/// as soon as it completes, so the log survives a crash. Failing to write
+/// the log is reported but doesn't fail the transfer.
+pub struct SessionRecorder<T> {
+    inner: T,
+    start: Instant,
+    log: Mutex<Box<dyn Write + Send>>,
+}
+
+impl<T: UsbTransport> SessionRecorder<T> {
+    /// Record the transfers made through `inner` to `log`
+    pub fn new(inner: T, log: impl Write + Send + 'static) -> Self {
+        Self {
+            inner,
+            start: Instant::now(),
+            log: Mutex::new(Box::new(log)),
+        }
+    }
+
+    /// Record the transfers made through `inner` to a new file at `path`
+    pub fn create(inner: T, path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(inner, File::create(path)?))
+    }
+
+    /// Record the transfers made through `inner` to the end of the file at
+    /// `path`, e.g. to carry on a session after the device reconnects
+    ///
+    /// Times count from when this recorder was created.
+    pub fn append(inner: T, path: impl AsRef<Path>) -> Result<Self> {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(inner, file))
+    }
+
+    /// Stop recording and get the wrapped transport back
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn record(&self, transfer: &ControlTransfer, started: Instant, length: usize, data: &[u8], error: Option<&Error>) {
+        let entry = RecordedTransfer {
+            time_us: started.duration_since(self.start).as_micros() as u64,
+            latency_us: started.elapsed().as_micros() as u64,
+            direction: transfer.direction,
+            request_type: transfer.request_type,
+            request: transfer.request,
+            value: transfer.value,
+            index: transfer.index,
+            length: u16::try_from(length).unwrap_or(u16::MAX),
+            data: data.to_vec(),
+            error: error.map(|e| e.to_string()),
+        };
+
+        let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        let written = serde_json::to_writer(&mut *log, &entry)
+            .map_err(std::io::Error::from)
+            .and_then(|_| writeln!(log))
+            .and_then(|_| log.flush());
+        if let Err(e) = written {
+            tracing::warn!("Failed to record transfer: {}", e);
+        }
+    }
+
+    fn record_out(&self, transfer: &ControlTransfer, started: Instant, data: &[u8], result: &Result<usize>) {
+        self.record(transfer, started, data.len(), data, result.as_ref().err());
+    }
+
+    fn record_in(&self, transfer: &ControlTransfer, started: Instant, buffer: &[u8], result: &Result<usize>) {
+        match result {
+            Ok(len) => self.record(transfer, started, buffer.len(), &buffer[..*len], None),
+            Err(e) => self.record(transfer, started, buffer.len(), &[], Some(e)),
+        }
+    }
+}
+
+impl<T: UsbTransport> UsbTransport for SessionRecorder<T> {
+    fn control_out(&self, transfer: &ControlTransfer, data: &[u8]) -> Result<usize> {
+        let started = Instant::now();
+        let result = self.inner.control_out(transfer, data);
+        self.record_out(transfer, started, data, &result);
+        result
+    }
+
+    fn control_in(&self, transfer: &ControlTransfer, buffer: &mut [u8]) -> Result<usize> {
+        let started = Instant::now();
+        let result = self.inner.control_in(transfer, buffer);
+        self.record_in(transfer, started, buffer, &result);
+        result
+    }
+
+    fn bulk_out(&self, transfer: &BulkTransfer, data: &[u8]) -> Result<usize> {
+        self.inner.bulk_out(transfer, data)
+    }
+
+    fn bulk_in(&self, transfer: &BulkTransfer, buffer: &mut [u8]) -> Result<usize> {
+        self.inner.bulk_in(transfer, buffer)
+    }
+
+    fn interrupt_in(&self, transfer: &BulkTransfer, buffer: &mut [u8]) -> Result<usize> {
+        self.inner.interrupt_in(transfer, buffer)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn transport_name(&self) -> &'static str {
+        self.inner.transport_name()
+    }
+
+    fn as_async(&self) -> Option<&dyn AsyncUsbTransport> {
+        Some(self)
+    }
+}
+
+/// Non-blocking transfers go through the wrapped transport's own, if it
+/// has them
+impl<T: UsbTransport> AsyncUsbTransport for SessionRecorder<T> {
+    fn control_out_async<'a>(&'a self, transfer: &'a ControlTransfer, data: &'a [u8]) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let result = match self.inner.as_async() {
+                Some(inner) => inner.control_out_async(transfer, data).await,
+                None => self.inner.control_out(transfer, data),
+            };
+            self.record_out(transfer, started, data, &result);
+            result
+        })
+    }
+
+    fn control_in_async<'a>(&'a self, transfer: &'a ControlTransfer, buffer: &'a mut [u8]) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let result = match self.inner.as_async() {
+                Some(inner) => inner.control_in_async(transfer, buffer).await,
+                None => self.inner.control_in(transfer, buffer),
+            };
+            self.record_in(transfer, started, buffer, &result);
+            result
+        })
+    }
+}
+
+/// Transport that plays back a recorded session
+///
+/// Transfers must come in the recorded order with the same parameters,
+/// and OUT transfers with the same data; anything else fails with a
+/// `Protocol` error saying where the session diverged, unless mismatches
+/// are ignored. Recorded failures are replayed as `Usb` errors, and
+/// interrupt reads see no data.
+pub struct ReplayTransport {
+    playback: Mutex<Playback>,
+    ignore_mismatches: bool,
+}
+
+/// Transfers still to replay, and how many have been
+struct Playback {
+    transfers: VecDeque<RecordedTransfer>,
+    replayed: usize,
+}
+
+impl ReplayTransport {
+    /// Play back `transfers` in order
+    pub fn new(transfers: Vec<RecordedTransfer>) -> Self {
+        Self {
+            playback: Mutex::new(Playback {
+                transfers: transfers.into(),
+                replayed: 0,
+            }),
+            ignore_mismatches: false,
+        }
+    }
+
+    /// Replay recorded transfers in order even if the ones made differ
+    ///
+    /// Useful when only the responses matter, e.g. if the code under test
+    /// numbers its commands differently from the recorded session.
+    pub fn with_ignore_mismatches(mut self, ignore: bool) -> Self {
+        self.ignore_mismatches = ignore;
+        self
+    }
+
+    /// Play back a session written by [`SessionRecorder`]
+    pub fn from_reader(reader: impl BufRead) -> Result<Self> {
+        let mut transfers = Vec::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let transfer = serde_json::from_str(&line)
+                .map_err(|e| Error::Protocol(format!("Invalid recording on line {}: {}", number + 1, e)))?;
+            transfers.push(transfer);
+        }
+        Ok(Self::new(transfers))
+    }
+
+    /// Play back a session recorded to the file at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Number of recorded transfers not replayed yet
+    pub fn remaining(&self) -> usize {
+        self.playback.lock().unwrap_or_else(|e| e.into_inner()).transfers.len()
+    }
+
+    /// Take the next recorded transfer, if it matches `transfer` and `data`
+    fn next(&self, transfer: &ControlTransfer, data: Option<&[u8]>) -> Result<RecordedTransfer> {
+        let mut playback = self.playback.lock().unwrap_or_else(|e| e.into_inner());
+        let number = playback.replayed + 1;
+
+        let Some(recorded) = playback.transfers.front() else {
+            return Err(Error::Protocol(format!(
+                "Recording ended before transfer {} (control {:?}, request {})",
+                number, transfer.direction, transfer.request
+            )));
+        };
+        if !recorded.matches(transfer) || data.is_some_and(|data| data != recorded.data) {
+            let message = format!(
+                "Replay diverged at transfer {}: recorded control {:?} request {} value {} index {}, got control {:?} request {} value {} index {}",
+                number,
+                recorded.direction, recorded.request, recorded.value, recorded.index,
+                transfer.direction, transfer.request, transfer.value, transfer.index
+            );
+            if !self.ignore_mismatches {
+                return Err(Error::Protocol(message));
+            }
+            tracing::debug!("{}", message);
+        }
+
+        playback.replayed = number;
+        let recorded = playback.transfers.pop_front().expect("checked above");
+        match recorded.error {
+            Some(error) => Err(Error::Usb(format!("Recorded failure: {}", error))),
+            None => Ok(recorded),
+        }
+    }
+}
+
+impl UsbTransport for ReplayTransport {
+    fn control_out(&self, transfer: &ControlTransfer, data: &[u8]) -> Result<usize> {
+        self.next(transfer, Some(data))?;
+        Ok(data.len())
+    }
+
+    fn control_in(&self, transfer: &ControlTransfer, buffer: &mut [u8]) -> Result<usize> {
+        let recorded = self.next(transfer, None)?;
+        let len = recorded.data.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&recorded.data[..len]);
+        Ok(len)
+    }
+
+    fn bulk_out(&self, _transfer: &BulkTransfer, _data: &[u8]) -> Result<usize> {
+        Err(Error::NotSupported("Bulk transfers".to_string()))
+    }
+
+    fn bulk_in(&self, _transfer: &BulkTransfer, _buffer: &mut [u8]) -> Result<usize> {
+        Err(Error::NotSupported("Bulk transfers".to_string()))
+    }
+
+    fn interrupt_in(&self, _transfer: &BulkTransfer, _buffer: &mut [u8]) -> Result<usize> {
+        Ok(0)
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    fn transport_name(&self) -> &'static str {
+        "Replay"
+    }
+
+    fn as_async(&self) -> Option<&dyn AsyncUsbTransport> {
+        Some(self)
+    }
+}
+
+impl AsyncUsbTransport for ReplayTransport {
+    fn control_out_async<'a>(&'a self, transfer: &'a ControlTransfer, data: &'a [u8]) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(async move { self.control_out(transfer, data) })
+    }
+
+    fn control_in_async<'a>(&'a self, transfer: &'a ControlTransfer, buffer: &'a mut [u8]) -> BoxFuture<'a, Result<usize>> {
+        Box::pin(async move { self.control_in(transfer, buffer) })
+    }
+}
+
+/// Byte buffers as lowercase hex strings, for readable and diffable logs
+mod hex_bytes {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let hex: String = data.iter().map(|byte| format!("{:02x}", byte)).collect();
+        serializer.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        if hex.len() % 2 != 0 {
+            return Err(D::Error::custom("odd number of hex digits"));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .ok_or_else(|| D::Error::custom(format!("invalid hex at {}", i)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen4_fcp::{FcpOpcode, FcpProtocol};
+    use scarlett_core::DeviceModel;
+    use crate::mock_transport::{packet, MockTransport};
+    use std::sync::Arc;
+
+    /// Log writer whose contents the test can read back
+    #[derive(Clone, Default)]
+    struct SharedLog(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedLog {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    /// Read of 2 bytes at 0x34 (line output 1 volume) as FCP command `seq`
+    fn volume_read(seq: u16) -> Vec<u8> {
+        packet(FcpOpcode::DataRead as u32, seq, &[0x34, 0, 0, 0, 2, 0, 0, 0])
+    }
+
+    /// A successful FCP control transfer
+    fn recorded(direction: Direction, data: Vec<u8>) -> RecordedTransfer {
+        let (request_type, request) = match direction {
+            Direction::Out => (0x21, 2),
+            Direction::In => (0xa1, 3),
+        };
+        RecordedTransfer {
+            time_us: 0,
+            latency_us: 0,
+            direction,
+            request_type,
+            request,
+            value: 0,
+            index: 0,
+            length: data.len() as u16,
+            data,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_record_session() {
+        let mock = MockTransport::new();
+        mock.expect_command(0, FcpOpcode::DataRead as u32, 1, &[0x34, 0, 0, 0, 2, 0, 0, 0], Some(&[117, 0]));
+        mock.expect_error(ControlTransfer::class_out(2, 0, 0), &volume_read(2), Error::Timeout("Control OUT".to_string()));
+
+        let log = SharedLog::default();
+        let recorder = SessionRecorder::new(mock.clone(), log.clone());
+        recorder.control_out(&ControlTransfer::class_out(2, 0, 0), &volume_read(1)).unwrap();
+        let mut buffer = [0u8; 64];
+        let len = recorder.control_in(&ControlTransfer::class_in(3, 0, 0), &mut buffer).unwrap();
+        assert_eq!(len, 18);
+        assert!(recorder.control_out(&ControlTransfer::class_out(2, 0, 0), &volume_read(2)).is_err());
+        mock.assert_done();
+
+        let contents = log.contents();
+        let lines: Vec<RecordedTransfer> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!((lines[0].direction, lines[0].request_type, lines[0].request), (Direction::Out, 0x21, 2));
+        assert_eq!(lines[0].data, volume_read(1));
+        assert_eq!((lines[1].direction, lines[1].request_type, lines[1].request), (Direction::In, 0xa1, 3));
+        assert_eq!(lines[1].data, packet(FcpOpcode::DataRead as u32, 1, &[117, 0]));
+        assert_eq!((lines[0].length, lines[1].length), (24, 64));
+        assert_eq!(lines[2].error.as_deref(), Some("Control OUT timed out"));
+        assert!(lines.windows(2).all(|pair| pair[0].time_us <= pair[1].time_us));
+
+        // Data is logged as hex, and errors only when there is one
+        let hex: String = volume_read(1).iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert!(contents.lines().next().unwrap().contains(&format!("\"data\":\"{}\"", hex)));
+        assert!(!contents.lines().next().unwrap().contains("error"));
+    }
+
+    #[test]
+    fn test_replay_session() {
+        let mock = MockTransport::new();
+        mock.expect_command(0, FcpOpcode::DataRead as u32, 1, &[0x34, 0, 0, 0, 2, 0, 0, 0], Some(&[117, 0]));
+
+        let log = SharedLog::default();
+        let recorder = SessionRecorder::new(mock, log.clone());
+        let out = ControlTransfer::class_out(2, 0, 0);
+        let response = ControlTransfer::class_in(3, 0, 0);
+        let mut recorded = [0u8; 64];
+        recorder.control_out(&out, &volume_read(1)).unwrap();
+        let recorded_len = recorder.control_in(&response, &mut recorded).unwrap();
+
+        // The same transfers get the recorded answers back
+        let replay = ReplayTransport::from_reader(log.contents().as_bytes()).unwrap();
+        assert_eq!(replay.remaining(), 2);
+        assert_eq!(replay.control_out(&out, &volume_read(1)).unwrap(), 24);
+        let mut replayed = [0u8; 64];
+        let len = replay.control_in(&response, &mut replayed).unwrap();
+        assert_eq!(replayed[..len], recorded[..recorded_len]);
+        assert_eq!(replay.remaining(), 0);
+
+        // Nothing more was recorded
+        assert!(matches!(replay.control_out(&out, &volume_read(2)), Err(Error::Protocol(_))));
+    }
+
+    #[test]
+    fn test_replay_divergence() {
+        let replay = ReplayTransport::new(vec![
+            recorded(Direction::Out, volume_read(1)),
+            recorded(Direction::Out, volume_read(1)),
+        ]);
+
+        let transfer = ControlTransfer::class_out(2, 0, 0);
+        assert!(matches!(replay.control_out(&transfer, &volume_read(2)), Err(Error::Protocol(_))));
+        assert!(matches!(replay.control_in(&ControlTransfer::class_in(3, 0, 0), &mut [0; 4]), Err(Error::Protocol(_))));
+        assert_eq!(replay.remaining(), 2);
+        assert_eq!(replay.control_out(&transfer, &volume_read(1)).unwrap(), 24);
+        assert_eq!(replay.remaining(), 1);
+
+        // Ignoring mismatches, the recording plays on regardless
+        let replay = ReplayTransport::new(vec![recorded(Direction::Out, volume_read(1))]).with_ignore_mismatches(true);
+        assert_eq!(replay.control_out(&transfer, &volume_read(2)).unwrap(), 24);
+        assert_eq!(replay.remaining(), 0);
+        assert!(matches!(replay.control_out(&transfer, &volume_read(3)), Err(Error::Protocol(_))));
+    }
+
+    #[test]
+    fn test_replay_18i20_init() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/18i20g4-init.jsonl");
+        let replay = ReplayTransport::open(path).unwrap();
+        let mut fcp = FcpProtocol::new(Box::new(replay)).with_model(DeviceModel::Scarlett18i20Gen4);
+
+        fcp.init().unwrap();
+        assert_eq!(fcp.versions().unwrap().firmware, 2417);
+        assert_eq!(fcp.read_data(0x34, 2).unwrap(), 0x75);
+    }
+
+    #[test]
+    fn test_invalid_recording() {
+        assert!(ReplayTransport::from_reader("\n{\"time_us\":0}\n".as_bytes()).is_err());
+
+        let line = r#"{"time_us":5,"direction":"in","request_type":161,"request":3,"value":0,"index":0,"data":"0aff","error":"Control IN timed out"}"#;
+        let replay = ReplayTransport::from_reader(line.as_bytes()).unwrap();
+        let mut buffer = [0u8; 2];
+        assert!(matches!(replay.control_in(&ControlTransfer::class_in(3, 0, 0), &mut buffer), Err(Error::Usb(_))));
+
+        let odd = line.replace("0aff", "0af");
+        assert!(ReplayTransport::from_reader(odd.as_bytes()).is_err());
+    }
+}
@@ -3,14 +3,20 @@
 //! This module provides a transport-agnostic interface for USB communication,
 //! allowing for multiple backends:
 //! - Direct local USB (via nusb)
-//! - USB/IP network transport (future)
+//! - USB/IP network transport
 //! - Mock transport for testing
 
-use scarlett_core::{Error, Result};
+use futures::future::BoxFuture;
+use scarlett_core::{Error, FcpErrorCode, Result};
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+/// Default timeout for control transfers
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// USB Control Transfer Direction
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Direction {
     /// Host to Device
     Out,
@@ -50,7 +56,7 @@ impl ControlTransfer {
             value,
             index,
             direction,
-            timeout: Duration::from_secs(1),
+            timeout: DEFAULT_TIMEOUT,
         }
     }
 
@@ -81,7 +87,7 @@ impl ControlTransfer {
     }
 }
 
-/// USB Bulk Transfer Request
+/// USB Bulk or Interrupt Transfer Request
 #[derive(Debug, Clone)]
 pub struct BulkTransfer {
     /// Endpoint address
@@ -106,11 +112,35 @@ pub trait UsbTransport: Send + Sync {
     /// Perform a bulk transfer IN
     fn bulk_in(&self, transfer: &BulkTransfer, buffer: &mut [u8]) -> Result<usize>;
 
+    /// Perform an interrupt transfer IN
+    ///
+    /// Returns 0 if the device sends nothing before the timeout.
+    fn interrupt_in(&self, _transfer: &BulkTransfer, _buffer: &mut [u8]) -> Result<usize> {
+        Err(Error::NotSupported(format!("Interrupt transfers over {}", self.transport_name())))
+    }
+
     /// Check if transport is connected
     fn is_connected(&self) -> bool;
 
     /// Get transport type name (for debugging/display)
     fn transport_name(&self) -> &'static str;
+
+    /// Get the non-blocking interface of this transport, if it has one
+    fn as_async(&self) -> Option<&dyn AsyncUsbTransport> {
+        None
+    }
+}
+
+/// Non-blocking control transfers, for polling from an async task
+///
+/// The futures resolve once the transfer completes or times out, without
+/// tying up the executor thread in between.
+pub trait AsyncUsbTransport: Send + Sync {
+    /// Perform a control transfer OUT (host to device)
+    fn control_out_async<'a>(&'a self, transfer: &'a ControlTransfer, data: &'a [u8]) -> BoxFuture<'a, Result<usize>>;
+
+    /// Perform a control transfer IN (device to host)
+    fn control_in_async<'a>(&'a self, transfer: &'a ControlTransfer, buffer: &'a mut [u8]) -> BoxFuture<'a, Result<usize>>;
 }
 
 /// Transport type selector
@@ -119,7 +149,6 @@ pub enum TransportType {
     /// Direct local USB via nusb
     DirectUsb,
     /// USB/IP network transport
-    #[allow(dead_code)]
     UsbIp,
     /// Mock transport for testing
     #[allow(dead_code)]
@@ -136,6 +165,141 @@ impl TransportType {
     }
 }
 
+/// Default number of retries for transient transfer errors
+pub const DEFAULT_MAX_RETRIES: usize = 3;
+
+/// Delay before the first retry; doubled for each further attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(10);
+
+/// Check if a transfer error is worth retrying
+///
+/// Timeouts, stalls and bus faults are often transient under heavy bus
+/// load; disconnects and protocol errors are not.
+pub fn is_transient_error(error: &Error) -> bool {
+    match error {
+        Error::Timeout(_) => true,
+        Error::Usb(msg) => ["Stall", "Fault", "Unknown"].iter().any(|kind| msg.contains(kind)),
+        Error::Io(e) => matches!(
+            e.kind(),
+            std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+        ),
+        Error::Device { code, .. } => *code == FcpErrorCode::Timeout,
+        _ => false,
+    }
+}
+
+/// How often, and for which commands, to retry after a transient error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Delay before each retry; the number of retries is its length
+    pub backoff: Vec<Duration>,
+    /// Also retry commands that change device state
+    ///
+    /// Flash and firmware writes are never retried.
+    pub retry_writes: bool,
+}
+
+impl Default for RetryPolicy {
+    /// Three retries of reads only, after 10, 50 and 200 ms
+    fn default() -> Self {
+        Self {
+            backoff: vec![
+                Duration::from_millis(10),
+                Duration::from_millis(50),
+                Duration::from_millis(200),
+            ],
+            retry_writes: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> Self {
+        Self {
+            backoff: Vec::new(),
+            retry_writes: false,
+        }
+    }
+
+    /// Retry up to `max_retries` times, doubling the delay each time
+    pub fn exponential(max_retries: usize) -> Self {
+        let backoff = (0..max_retries)
+            .map(|attempt| RETRY_BASE_DELAY * 2u32.pow(attempt as u32))
+            .collect();
+
+        Self {
+            backoff,
+            retry_writes: false,
+        }
+    }
+
+    /// Enable or disable retrying commands that change device state
+    pub fn with_retry_writes(mut self, retry_writes: bool) -> Self {
+        self.retry_writes = retry_writes;
+        self
+    }
+
+    /// Maximum number of retries
+    pub fn max_retries(&self) -> usize {
+        self.backoff.len()
+    }
+
+    /// Run an operation, retrying transient errors
+    pub fn retry<T>(&self, mut operation: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut delays = self.backoff.iter();
+
+        loop {
+            match operation() {
+                Err(e) if is_transient_error(&e) => {
+                    let Some(&delay) = delays.next() else {
+                        return Err(e);
+                    };
+                    tracing::debug!(
+                        "Transient USB error ({}), retry {}/{}",
+                        e,
+                        self.max_retries() - delays.len(),
+                        self.max_retries()
+                    );
+                    std::thread::sleep(delay);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Run an async operation, retrying transient errors
+    ///
+    /// Waits between attempts without blocking the calling task.
+    pub async fn retry_async<T, F>(&self, mut operation: impl FnMut() -> F) -> Result<T>
+    where
+        F: std::future::Future<Output = Result<T>>,
+    {
+        let mut delays = self.backoff.iter();
+
+        loop {
+            match operation().await {
+                Err(e) if is_transient_error(&e) => {
+                    let Some(&delay) = delays.next() else {
+                        return Err(e);
+                    };
+                    tracing::debug!("Transient USB error ({}), retrying", e);
+                    async_io::Timer::after(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Run a transfer, retrying transient errors with exponential backoff
+///
+/// Only wrap operations that are safe to repeat, i.e. before the device has
+/// accepted a command.
+pub fn retry_transient<T>(max_retries: usize, transfer: impl FnMut() -> Result<T>) -> Result<T> {
+    RetryPolicy::exponential(max_retries).retry(transfer)
+}
+
 /// Helper functions for common transfer patterns
 pub mod helpers {
     use super::*;
@@ -197,44 +361,53 @@ pub mod helpers {
     }
 }
 
+/// Bytes shown per hex dump line
+const HEX_DUMP_WIDTH: usize = 16;
+
+/// Format bytes as a hex dump, one line per 16 bytes
+///
+/// Each line is `offset: xx xx ... ascii`, with non-printable bytes shown
+/// as `.` in the ASCII column.
+pub fn hex_dump(data: &[u8]) -> String {
+    data.chunks(HEX_DUMP_WIDTH)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                .collect();
+            format!("{:04x}: {:<width$}  {}", line * HEX_DUMP_WIDTH, hex.join(" "), ascii, width = HEX_DUMP_WIDTH * 3 - 1)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Log the bytes of a control transfer at trace level
+///
+/// Only built with the `packet-dump` feature, so transfers pay nothing for
+/// it otherwise. Filter on the `scarlett_usb::packet` target to see just
+/// the dumps.
+#[cfg(feature = "packet-dump")]
+pub(crate) fn dump_packet(transport: &str, transfer: &ControlTransfer, data: &[u8]) {
+    tracing::trace!(
+        target: "scarlett_usb::packet",
+        "{} control {:?}: type=0x{:02x}, req=0x{:02x}, val=0x{:04x}, idx=0x{:04x}, {} bytes\n{}",
+        transport,
+        transfer.direction,
+        transfer.request_type,
+        transfer.request,
+        transfer.value,
+        transfer.index,
+        data.len(),
+        hex_dump(data)
+    );
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    // Mock transport for testing
-    struct MockTransport {
-        connected: bool,
-    }
-
-    impl UsbTransport for MockTransport {
-        fn control_out(&self, _transfer: &ControlTransfer, data: &[u8]) -> Result<usize> {
-            Ok(data.len())
-        }
-
-        fn control_in(&self, _transfer: &ControlTransfer, buffer: &mut [u8]) -> Result<usize> {
-            // Fill with test data
-            for (i, byte) in buffer.iter_mut().enumerate() {
-                *byte = (i % 256) as u8;
-            }
-            Ok(buffer.len())
-        }
-
-        fn bulk_out(&self, _transfer: &BulkTransfer, data: &[u8]) -> Result<usize> {
-            Ok(data.len())
-        }
-
-        fn bulk_in(&self, _transfer: &BulkTransfer, buffer: &mut [u8]) -> Result<usize> {
-            Ok(buffer.len())
-        }
-
-        fn is_connected(&self) -> bool {
-            self.connected
-        }
-
-        fn transport_name(&self) -> &'static str {
-            "Mock"
-        }
-    }
+    use crate::mock_transport::MockTransport;
 
     #[test]
     fn test_control_transfer_builder() {
@@ -247,37 +420,86 @@ mod tests {
     }
 
     #[test]
-    fn test_mock_transport() {
-        let transport = MockTransport { connected: true };
-
-        // Test control OUT
-        let transfer = ControlTransfer::vendor_out(0x01, 0x00, 0x00);
-        let result = transport.control_out(&transfer, &[1, 2, 3, 4]);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), 4);
-
-        // Test control IN
-        let transfer = ControlTransfer::vendor_in(0x02, 0x00, 0x00);
-        let mut buffer = vec![0u8; 16];
-        let result = transport.control_in(&transfer, &mut buffer);
-        assert!(result.is_ok());
-
-        // Test connection
-        assert!(transport.is_connected());
-        assert_eq!(transport.transport_name(), "Mock");
+    fn test_retry_transient() {
+        let mut calls = 0;
+        let result = retry_transient(3, || {
+            calls += 1;
+            if calls < 3 {
+                Err(Error::Usb("Control OUT failed: Stall".to_string()))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // Timeouts are retried; a device that has gone isn't
+        assert!(is_transient_error(&Error::Timeout("Control IN".to_string())));
+        assert!(!is_transient_error(&Error::Disconnected));
+        assert_eq!(Error::Timeout("Control IN".to_string()).to_string(), "Control IN timed out");
+        assert!(Error::Timeout("Control IN".to_string()).hint().is_some());
+
+        // Protocol errors are returned immediately
+        let mut calls = 0;
+        let result: Result<()> = retry_transient(3, || {
+            calls += 1;
+            Err(Error::Protocol("bad response".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+
+        // Retries disabled
+        let mut calls = 0;
+        let _: Result<()> = retry_transient(0, || {
+            calls += 1;
+            Err(Error::Usb("Control OUT failed: Stall".to_string()))
+        });
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.max_retries(), 3);
+        assert!(!policy.retry_writes);
+
+        assert_eq!(
+            RetryPolicy::exponential(3).backoff,
+            vec![Duration::from_millis(10), Duration::from_millis(20), Duration::from_millis(40)]
+        );
+
+        let mut calls = 0;
+        let result: Result<()> = RetryPolicy::exponential(2).retry(|| {
+            calls += 1;
+            Err(Error::Timeout("Control IN".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
     }
 
     #[test]
     fn test_helpers() {
-        let transport = MockTransport { connected: true };
+        let transport = MockTransport::new();
+        transport.expect_out(ControlTransfer::vendor_out(0x01, 0, 0), &[1, 2, 3]);
+        transport.expect_in(ControlTransfer::vendor_in(0x02, 0, 0), &[4, 5]);
+        transport.expect_in(ControlTransfer::class_in(0x03, 0x0100, 2), &[6]);
+
+        helpers::vendor_write(&transport, 0x01, 0x00, 0x00, &[1, 2, 3]).unwrap();
 
-        // Test vendor write
-        let result = helpers::vendor_write(&transport, 0x01, 0x00, 0x00, &[1, 2, 3]);
-        assert!(result.is_ok());
+        // Reads return only what the device sent
+        assert_eq!(helpers::vendor_read(&transport, 0x02, 0x00, 0x00, 10).unwrap(), [4, 5]);
+        assert_eq!(helpers::class_read(&transport, 0x03, 0x0100, 2, 4).unwrap(), [6]);
+        transport.assert_done();
+    }
 
-        // Test vendor read
-        let result = helpers::vendor_read(&transport, 0x02, 0x00, 0x00, 10);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().len(), 10);
+    #[test]
+    fn test_hex_dump() {
+        assert_eq!(hex_dump(&[]), "");
+        assert_eq!(hex_dump(b"FCP\x00\x01"), "0000: 46 43 50 00 01                                   FCP..");
+
+        let data: Vec<u8> = (0x30..0x30 + 18).collect();
+        let lines: Vec<String> = hex_dump(&data).lines().map(str::to_string).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "0000: 30 31 32 33 34 35 36 37 38 39 3a 3b 3c 3d 3e 3f  0123456789:;<=>?");
+        assert_eq!(lines[1], format!("0010: 40 41{}  @A", " ".repeat(42)));
     }
 }
@@ -0,0 +1,96 @@
+//! USB Audio Class 2 requests
+//!
+//! The sample rate is not part of the vendor protocol: it is the SAM_FREQ
+//! control of the interface's UAC2 clock source entity, as set by the host
+//! audio driver.
+
+use crate::transport::{ControlTransfer, RetryPolicy, UsbTransport};
+use scarlett_core::{DeviceModel, Error, Result, SampleRate};
+use std::time::Duration;
+
+/// UAC2 CUR request
+const UAC2_CS_CUR: u8 = 0x01;
+
+/// UAC2 clock source sampling frequency control selector
+const UAC2_CS_SAM_FREQ_CONTROL: u8 = 0x01;
+
+/// Audio Control interface number
+const AUDIO_CONTROL_INTERFACE: u8 = 0;
+
+/// Clock source entity ID on Focusrite interfaces
+const CLOCK_SOURCE_ID: u8 = 0x29;
+
+/// How long the device may take to re-clock after a rate change
+const RECLOCK_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn sam_freq_transfer(out: bool, timeout: Duration) -> ControlTransfer {
+    let value = (UAC2_CS_SAM_FREQ_CONTROL as u16) << 8;
+    let index = ((CLOCK_SOURCE_ID as u16) << 8) | AUDIO_CONTROL_INTERFACE as u16;
+
+    let transfer = if out {
+        ControlTransfer::class_out(UAC2_CS_CUR, value, index)
+    } else {
+        ControlTransfer::class_in(UAC2_CS_CUR, value, index)
+    };
+    transfer.with_timeout(timeout)
+}
+
+/// Read the current sample rate
+pub(crate) fn read_sample_rate(
+    transport: &dyn UsbTransport,
+    timeout: Duration,
+    retry: &RetryPolicy,
+) -> Result<SampleRate> {
+    let transfer = sam_freq_transfer(false, timeout);
+    let mut buffer = [0u8; 4];
+
+    let len = retry.retry(|| transport.control_in(&transfer, &mut buffer))?;
+    if len < 4 {
+        return Err(Error::Protocol("Sample rate response too short".to_string()));
+    }
+
+    let hz = u32::from_le_bytes(buffer);
+    SampleRate::from_hz(hz).ok_or_else(|| Error::Protocol(format!("Unknown sample rate: {} Hz", hz)))
+}
+
+/// Change the sample rate and wait until the device reports it
+///
+/// Rates the model doesn't support are refused with `Error::NotSupported`.
+pub(crate) async fn change_sample_rate(
+    transport: &dyn UsbTransport,
+    timeout: Duration,
+    retry: &RetryPolicy,
+    model: Option<DeviceModel>,
+    rate: SampleRate,
+) -> Result<()> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    if let Some(model) = model {
+        if !model.sample_rates().contains(&rate) {
+            return Err(Error::NotSupported(format!("{} on the {}", rate, model.name())));
+        }
+    }
+
+    tracing::info!("Setting sample rate: {}", rate);
+    let transfer = sam_freq_transfer(true, timeout);
+    // Setting the same rate twice is harmless
+    retry.retry(|| transport.control_out(&transfer, &rate.hz().to_le_bytes()))?;
+
+    // Reads fail or report the old rate while the device re-clocks
+    let deadline = tokio::time::Instant::now() + RECLOCK_TIMEOUT;
+    loop {
+        match read_sample_rate(transport, timeout, &RetryPolicy::none()) {
+            Ok(current) if current == rate => return Ok(()),
+            Ok(current) => tracing::debug!("Sample rate still {}", current),
+            Err(e) => tracing::debug!("Sample rate read failed while re-clocking: {}", e),
+        }
+
+        if tokio::time::Instant::now() >= deadline {
+            return Err(Error::Protocol(format!(
+                "Device did not switch to {} within {:?}",
+                rate, RECLOCK_TIMEOUT
+            )));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,493 @@
+//! USB/IP Network Transport
+//!
+//! Controls a device exported by a remote `usbipd` server (e.g. a Scarlett
+//! attached to a headless Raspberry Pi). Only control transfers are
+//! implemented, as they are all the Scarlett protocols use.
+
+use crate::transport::{BulkTransfer, ControlTransfer, Direction, UsbTransport};
+use scarlett_core::{Error, Result};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tracing::{debug, trace};
+
+/// Default usbipd TCP port
+pub const USBIP_PORT: u16 = 3240;
+
+/// USB/IP protocol version
+const USBIP_VERSION: u16 = 0x0111;
+
+const OP_REQ_IMPORT: u16 = 0x8003;
+const OP_REP_IMPORT: u16 = 0x0003;
+
+const USBIP_CMD_SUBMIT: u32 = 0x0000_0001;
+const USBIP_RET_SUBMIT: u32 = 0x0000_0003;
+
+const USBIP_DIR_OUT: u32 = 0;
+const USBIP_DIR_IN: u32 = 1;
+
+/// Size of the bus ID field in OP_REQ_IMPORT
+const BUSID_SIZE: usize = 32;
+
+/// Size of the exported device description in OP_REP_IMPORT
+const DEVICE_INFO_SIZE: usize = 0x138;
+
+/// Size of the CMD_SUBMIT / RET_SUBMIT headers
+const SUBMIT_HEADER_SIZE: usize = 48;
+
+/// State of an imported device connection
+struct Connection {
+    stream: TcpStream,
+    seqnum: u32,
+    /// Timed-out requests whose replies may still arrive: the sequence
+    /// number, and for IN transfers the length asked for
+    abandoned: Vec<(u32, Option<usize>)>,
+}
+
+/// USB/IP transport implementation
+pub struct UsbIpTransport {
+    connection: Mutex<Connection>,
+    devid: u32,
+    vendor_id: u16,
+    product_id: u16,
+    connected: AtomicBool,
+}
+
+impl UsbIpTransport {
+    /// Connect to a usbip server and import the device with the given bus ID
+    ///
+    /// The bus ID is the one shown by `usbip list -r <host>`, e.g. "1-1.2".
+    pub fn connect(addr: impl ToSocketAddrs, busid: &str) -> Result<Self> {
+        if busid.len() >= BUSID_SIZE {
+            return Err(Error::InvalidParameter(format!("Bus ID too long: {}", busid)));
+        }
+
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+
+        debug!("Importing USB/IP device {}", busid);
+
+        let mut request = Vec::with_capacity(8 + BUSID_SIZE);
+        request.extend_from_slice(&USBIP_VERSION.to_be_bytes());
+        request.extend_from_slice(&OP_REQ_IMPORT.to_be_bytes());
+        request.extend_from_slice(&0u32.to_be_bytes());
+        let mut busid_field = [0u8; BUSID_SIZE];
+        busid_field[..busid.len()].copy_from_slice(busid.as_bytes());
+        request.extend_from_slice(&busid_field);
+        stream.write_all(&request)?;
+
+        let mut reply = [0u8; 8];
+        stream.read_exact(&mut reply)?;
+
+        let code = u16::from_be_bytes([reply[2], reply[3]]);
+        let status = u32::from_be_bytes([reply[4], reply[5], reply[6], reply[7]]);
+        if code != OP_REP_IMPORT {
+            return Err(Error::Usb(format!("Unexpected USB/IP reply code 0x{:04x}", code)));
+        }
+        if status != 0 {
+            return Err(Error::Usb(format!(
+                "USB/IP server refused to export {} (status {})",
+                busid, status
+            )));
+        }
+
+        let mut info = [0u8; DEVICE_INFO_SIZE];
+        stream.read_exact(&mut info)?;
+
+        // path[256], busid[32], then big-endian busnum, devnum, speed, VID, PID
+        let field = |offset: usize| u32::from_be_bytes([
+            info[offset], info[offset + 1], info[offset + 2], info[offset + 3],
+        ]);
+        let busnum = field(288);
+        let devnum = field(292);
+        let vendor_id = u16::from_be_bytes([info[300], info[301]]);
+        let product_id = u16::from_be_bytes([info[302], info[303]]);
+
+        debug!(
+            "Imported {} ({:04x}:{:04x}, bus {} dev {})",
+            busid, vendor_id, product_id, busnum, devnum
+        );
+
+        Ok(Self {
+            connection: Mutex::new(Connection { stream, seqnum: 0, abandoned: Vec::new() }),
+            devid: (busnum << 16) | devnum,
+            vendor_id,
+            product_id,
+            connected: AtomicBool::new(true),
+        })
+    }
+
+    /// USB Vendor ID of the imported device
+    pub fn vendor_id(&self) -> u16 {
+        self.vendor_id
+    }
+
+    /// USB Product ID of the imported device
+    pub fn product_id(&self) -> u16 {
+        self.product_id
+    }
+
+    /// Submit a control transfer and wait for its completion
+    ///
+    /// Returns the data received for IN transfers.
+    fn submit_control(
+        &self,
+        transfer: &ControlTransfer,
+        out_data: &[u8],
+        in_length: usize,
+    ) -> Result<Vec<u8>> {
+        if !self.is_connected() {
+            return Err(Error::Disconnected);
+        }
+
+        let mut connection = self
+            .connection
+            .lock()
+            .map_err(|_| Error::Usb("USB/IP connection poisoned".to_string()))?;
+
+        connection.seqnum = connection.seqnum.wrapping_add(1);
+        let seqnum = connection.seqnum;
+
+        let result = connection.exchange(self.devid, seqnum, transfer, out_data, in_length);
+        match &result {
+            Err(Error::Timeout(_)) => {
+                // The reply may still arrive; the next exchange skips it
+                let in_length = (transfer.direction == Direction::In).then_some(in_length);
+                connection.abandoned.push((seqnum, in_length));
+            }
+            Err(Error::Io(_) | Error::Protocol(_) | Error::Disconnected) => {
+                // The stream is closed or out of sync; don't reuse it
+                self.connected.store(false, Ordering::SeqCst);
+            }
+            _ => {}
+        }
+        result
+    }
+}
+
+impl Connection {
+    fn exchange(
+        &mut self,
+        devid: u32,
+        seqnum: u32,
+        transfer: &ControlTransfer,
+        out_data: &[u8],
+        in_length: usize,
+    ) -> Result<Vec<u8>> {
+        self.stream.set_read_timeout(Some(transfer.timeout))?;
+
+        let length = match transfer.direction {
+            Direction::Out => out_data.len(),
+            Direction::In => in_length,
+        };
+        let length = u16::try_from(length)
+            .map_err(|_| Error::InvalidParameter(format!("Control transfer too long: {}", length)))?;
+
+        let mut packet = encode_cmd_submit(devid, seqnum, transfer, length);
+        if transfer.direction == Direction::Out {
+            packet.extend_from_slice(out_data);
+        }
+        self.stream.write_all(&packet).map_err(socket_error)?;
+
+        let (status, actual_length) = loop {
+            let mut header = [0u8; SUBMIT_HEADER_SIZE];
+            self.stream.read_exact(&mut header).map_err(socket_error)?;
+            let (reply_seqnum, status, actual_length) = decode_ret_submit(&header)?;
+            if reply_seqnum == seqnum {
+                break (status, actual_length);
+            }
+
+            let Some(index) = self.abandoned.iter().position(|&(abandoned, _)| abandoned == reply_seqnum) else {
+                return Err(Error::Protocol(format!(
+                    "USB/IP sequence mismatch: expected {}, got {}",
+                    seqnum, reply_seqnum
+                )));
+            };
+            let (_, abandoned_length) = self.abandoned.swap_remove(index);
+            debug!("Discarding late USB/IP reply {}", reply_seqnum);
+            if let Some(abandoned_length) = abandoned_length {
+                self.read_data(actual_length, abandoned_length)?;
+            }
+        };
+
+        let data = match transfer.direction {
+            Direction::In => self.read_data(actual_length, in_length)?,
+            Direction::Out => Vec::new(),
+        };
+
+        if status != 0 {
+            return Err(Error::Usb(format!("USB/IP control transfer failed: status {}", status)));
+        }
+
+        Ok(data)
+    }
+
+    /// Read the data of an IN reply, which can't be longer than asked for
+    fn read_data(&mut self, actual_length: usize, in_length: usize) -> Result<Vec<u8>> {
+        if actual_length > in_length {
+            return Err(Error::Protocol(format!(
+                "USB/IP reply of {} bytes is longer than the {} asked for",
+                actual_length, in_length
+            )));
+        }
+
+        let mut data = vec![0; actual_length];
+        self.stream.read_exact(&mut data).map_err(|e| match socket_error(e) {
+            // Part of the data may have been read, so the stream is out of sync
+            Error::Timeout(_) => Error::Protocol("USB/IP reply data timed out".to_string()),
+            error => error,
+        })?;
+        Ok(data)
+    }
+}
+
+/// Map a socket error to the error a local device would report
+fn socket_error(error: std::io::Error) -> Error {
+    match error.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+            Error::Timeout("USB/IP control transfer".to_string())
+        }
+        ErrorKind::UnexpectedEof
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::BrokenPipe => Error::Disconnected,
+        _ => Error::Io(error),
+    }
+}
+
+/// Encode a USBIP_CMD_SUBMIT header for a control transfer on endpoint 0
+fn encode_cmd_submit(devid: u32, seqnum: u32, transfer: &ControlTransfer, length: u16) -> Vec<u8> {
+    let direction = match transfer.direction {
+        Direction::Out => USBIP_DIR_OUT,
+        Direction::In => USBIP_DIR_IN,
+    };
+
+    let mut packet = Vec::with_capacity(SUBMIT_HEADER_SIZE);
+    packet.extend_from_slice(&USBIP_CMD_SUBMIT.to_be_bytes());
+    packet.extend_from_slice(&seqnum.to_be_bytes());
+    packet.extend_from_slice(&devid.to_be_bytes());
+    packet.extend_from_slice(&direction.to_be_bytes());
+    packet.extend_from_slice(&0u32.to_be_bytes()); // endpoint
+    packet.extend_from_slice(&0u32.to_be_bytes()); // transfer_flags
+    packet.extend_from_slice(&(length as u32).to_be_bytes());
+    packet.extend_from_slice(&0u32.to_be_bytes()); // start_frame
+    packet.extend_from_slice(&0xffff_ffffu32.to_be_bytes()); // number_of_packets (not ISO)
+    packet.extend_from_slice(&0u32.to_be_bytes()); // interval
+
+    // Setup packet fields are little-endian, as on the wire
+    packet.push(transfer.request_type);
+    packet.push(transfer.request);
+    packet.extend_from_slice(&transfer.value.to_le_bytes());
+    packet.extend_from_slice(&transfer.index.to_le_bytes());
+    packet.extend_from_slice(&length.to_le_bytes());
+
+    packet
+}
+
+/// Decode a USBIP_RET_SUBMIT header, returning the sequence number, status
+/// and actual length
+fn decode_ret_submit(header: &[u8; SUBMIT_HEADER_SIZE]) -> Result<(u32, i32, usize)> {
+    let field = |offset: usize| u32::from_be_bytes([
+        header[offset], header[offset + 1], header[offset + 2], header[offset + 3],
+    ]);
+
+    if field(0) != USBIP_RET_SUBMIT {
+        return Err(Error::Protocol(format!("Unexpected USB/IP command 0x{:08x}", field(0))));
+    }
+
+    Ok((field(4), field(20) as i32, field(24) as usize))
+}
+
+impl UsbTransport for UsbIpTransport {
+    fn control_out(&self, transfer: &ControlTransfer, data: &[u8]) -> Result<usize> {
+        trace!(
+            "USB/IP control OUT: type=0x{:02x}, req=0x{:02x}, val=0x{:04x}, idx=0x{:04x}, len={}",
+            transfer.request_type,
+            transfer.request,
+            transfer.value,
+            transfer.index,
+            data.len()
+        );
+
+        #[cfg(feature = "packet-dump")]
+        crate::transport::dump_packet(self.transport_name(), transfer, data);
+
+        self.submit_control(transfer, data, 0)?;
+        Ok(data.len())
+    }
+
+    fn control_in(&self, transfer: &ControlTransfer, buffer: &mut [u8]) -> Result<usize> {
+        trace!(
+            "USB/IP control IN: type=0x{:02x}, req=0x{:02x}, val=0x{:04x}, idx=0x{:04x}, len={}",
+            transfer.request_type,
+            transfer.request,
+            transfer.value,
+            transfer.index,
+            buffer.len()
+        );
+
+        let data = self.submit_control(transfer, &[], buffer.len())?;
+        buffer[..data.len()].copy_from_slice(&data);
+        #[cfg(feature = "packet-dump")]
+        crate::transport::dump_packet(self.transport_name(), transfer, &data);
+        Ok(data.len())
+    }
+
+    fn bulk_out(&self, _transfer: &BulkTransfer, _data: &[u8]) -> Result<usize> {
+        Err(Error::NotSupported("Bulk transfers over USB/IP".to_string()))
+    }
+
+    fn bulk_in(&self, _transfer: &BulkTransfer, _buffer: &mut [u8]) -> Result<usize> {
+        Err(Error::NotSupported("Bulk transfers over USB/IP".to_string()))
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    fn transport_name(&self) -> &'static str {
+        "USB/IP"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    #[test]
+    fn test_cmd_submit_encoding() {
+        let transfer = ControlTransfer::class_in(3, 0, 5);
+        let packet = encode_cmd_submit(0x0001_0002, 7, &transfer, 64);
+
+        assert_eq!(packet.len(), SUBMIT_HEADER_SIZE);
+        assert_eq!(packet[0..4], USBIP_CMD_SUBMIT.to_be_bytes());
+        assert_eq!(packet[4..8], 7u32.to_be_bytes());
+        assert_eq!(packet[12..16], USBIP_DIR_IN.to_be_bytes());
+        assert_eq!(packet[24..28], 64u32.to_be_bytes());
+        assert_eq!(packet[40..48], [0xA1, 3, 0, 0, 5, 0, 64, 0]);
+    }
+
+    /// Serve one imported device, handing the stream to `serve` after the
+    /// import handshake
+    fn import_server(
+        serve: impl FnOnce(TcpStream) + Send + 'static,
+    ) -> (UsbIpTransport, std::thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let mut request = [0u8; 8 + BUSID_SIZE];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(&request[8..11], b"1-1");
+
+            let mut reply = vec![0u8; 8 + DEVICE_INFO_SIZE];
+            reply[0..2].copy_from_slice(&USBIP_VERSION.to_be_bytes());
+            reply[2..4].copy_from_slice(&OP_REP_IMPORT.to_be_bytes());
+            reply[8 + 300..8 + 302].copy_from_slice(&0x1235u16.to_be_bytes());
+            stream.write_all(&reply).unwrap();
+
+            serve(stream);
+        });
+
+        (UsbIpTransport::connect(addr, "1-1").unwrap(), server)
+    }
+
+    /// Read a CMD_SUBMIT, returning its sequence number
+    fn read_cmd(stream: &mut TcpStream) -> u32 {
+        let mut cmd = [0u8; SUBMIT_HEADER_SIZE];
+        stream.read_exact(&mut cmd).unwrap();
+        u32::from_be_bytes([cmd[4], cmd[5], cmd[6], cmd[7]])
+    }
+
+    /// Send a successful RET_SUBMIT carrying `data`
+    fn write_ret(stream: &mut TcpStream, seqnum: u32, actual_length: u32, data: &[u8]) {
+        let mut ret = [0u8; SUBMIT_HEADER_SIZE];
+        ret[0..4].copy_from_slice(&USBIP_RET_SUBMIT.to_be_bytes());
+        ret[4..8].copy_from_slice(&seqnum.to_be_bytes());
+        ret[24..28].copy_from_slice(&actual_length.to_be_bytes());
+        stream.write_all(&ret).unwrap();
+        stream.write_all(data).unwrap();
+    }
+
+    #[test]
+    fn test_control_in_roundtrip() {
+        let (transport, server) = import_server(|mut stream| {
+            let seqnum = read_cmd(&mut stream);
+            write_ret(&mut stream, seqnum, 2, &[0xab, 0xcd]);
+        });
+        assert_eq!(transport.vendor_id(), 0x1235);
+
+        let mut buffer = [0u8; 8];
+        let len = transport.control_in(&ControlTransfer::vendor_in(0, 0, 0), &mut buffer).unwrap();
+        assert_eq!(&buffer[..len], &[0xab, 0xcd]);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_reply_longer_than_requested() {
+        let (transport, server) = import_server(|mut stream| {
+            let seqnum = read_cmd(&mut stream);
+            write_ret(&mut stream, seqnum, 0x1000_0000, &[]);
+        });
+
+        let mut buffer = [0u8; 8];
+        let transfer = ControlTransfer::vendor_in(0, 0, 0);
+        assert!(matches!(transport.control_in(&transfer, &mut buffer), Err(Error::Protocol(_))));
+        assert!(!transport.is_connected());
+        assert!(matches!(transport.control_in(&transfer, &mut buffer), Err(Error::Disconnected)));
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_sequence_mismatch_closes() {
+        let (transport, server) = import_server(|mut stream| {
+            let seqnum = read_cmd(&mut stream);
+            write_ret(&mut stream, seqnum + 5, 0, &[]);
+        });
+
+        let transfer = ControlTransfer::vendor_out(0, 0, 0);
+        assert!(matches!(transport.control_out(&transfer, &[1]), Err(Error::Protocol(_))));
+        assert!(!transport.is_connected());
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_timeout_skips_late_reply() {
+        let (transport, server) = import_server(|mut stream| {
+            // Answer the first request only once the second arrives
+            let first = read_cmd(&mut stream);
+            let second = read_cmd(&mut stream);
+            write_ret(&mut stream, first, 2, &[1, 1]);
+            write_ret(&mut stream, second, 2, &[2, 2]);
+        });
+
+        let mut buffer = [0u8; 8];
+        let transfer = ControlTransfer::vendor_in(0, 0, 0).with_timeout(Duration::from_millis(50));
+        assert!(matches!(transport.control_in(&transfer, &mut buffer), Err(Error::Timeout(_))));
+        assert!(transport.is_connected());
+
+        let transfer = transfer.with_timeout(Duration::from_secs(5));
+        let len = transport.control_in(&transfer, &mut buffer).unwrap();
+        assert_eq!(&buffer[..len], &[2, 2]);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_closed_connection() {
+        let (transport, server) = import_server(drop);
+        server.join().unwrap();
+
+        let transfer = ControlTransfer::vendor_out(0, 0, 0);
+        assert!(matches!(transport.control_out(&transfer, &[1]), Err(Error::Disconnected)));
+        assert!(!transport.is_connected());
+    }
+}
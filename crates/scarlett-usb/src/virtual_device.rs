@@ -0,0 +1,662 @@
+//! Simulated devices for working without hardware
+//!
+//! Setting `SCARLETT_VIRTUAL` to a comma-separated list of models (e.g.
+//! `SCARLETT_VIRTUAL=18i20g4,2i2g3`) makes scans report a virtual device
+//! for each one. Opening it gives a [`VirtualDevice`] behind the usual
+//! [`UsbDevice`](crate::UsbDevice) whose settings live in memory: writes
+//! are checked the way the hardware checks them and read back until the
+//! program exits.
+
+use crate::gen4_fcp::VolumeScale;
+use crate::meters::{meter_to_db, MeterSource};
+use crate::protocol::{level_meters, mixer_state_from_gains, Protocol};
+use futures::future::BoxFuture;
+use scarlett_core::mixer::{db_to_mixer_gain, LevelMeter, MixerState};
+use scarlett_core::routing::{Port, PortType, RoutingMatrix};
+use scarlett_core::{
+    AirMode, Device, DeviceCapabilities, DeviceInfo, DeviceModel, DirectMonitorMode, Error, InputLevel, Result,
+};
+
+/// Environment variable listing the models to simulate
+pub const VIRTUAL_ENV: &str = "SCARLETT_VIRTUAL";
+
+/// Firmware version reported by virtual devices
+pub const VIRTUAL_FIRMWARE: &str = "virtual";
+
+/// USB path prefix marking a virtual device
+const VIRTUAL_PATH_PREFIX: &str = "virtual-";
+
+/// Short name of a Scarlett model, e.g. `18i20g4` for the 18i20 (4th Gen)
+pub fn short_name(model: DeviceModel) -> Option<String> {
+    let name = model.name().strip_prefix("Scarlett ")?;
+    let (size, generation) = name.split_once(" (")?;
+    Some(format!("{}g{}", size.to_lowercase(), generation.get(..1)?))
+}
+
+/// Find a Scarlett model by its short name, ignoring case
+pub fn model_from_short_name(name: &str) -> Option<DeviceModel> {
+    let name = name.trim().to_lowercase();
+    DeviceModel::all()
+        .iter()
+        .copied()
+        .find(|&model| short_name(model).is_some_and(|short| short == name))
+}
+
+/// Virtual devices for a `SCARLETT_VIRTUAL` value
+///
+/// Unknown model names are skipped with a warning.
+pub fn parse_virtual_devices(value: &str) -> Vec<DeviceInfo> {
+    value
+        .split(',')
+        .filter(|name| !name.trim().is_empty())
+        .filter_map(|name| {
+            let model = model_from_short_name(name);
+            if model.is_none() {
+                tracing::warn!("Ignoring unknown virtual device model {:?}", name.trim());
+            }
+            model
+        })
+        .enumerate()
+        .map(|(index, model)| {
+            DeviceInfo::new(
+                model,
+                format!("VIRTUAL{:04}", index + 1),
+                format!("{}{:03}", VIRTUAL_PATH_PREFIX, index + 1),
+            )
+        })
+        .collect()
+}
+
+/// Virtual devices requested through `SCARLETT_VIRTUAL`, if it is set
+pub fn virtual_devices() -> Vec<DeviceInfo> {
+    std::env::var(VIRTUAL_ENV)
+        .map(|value| parse_virtual_devices(&value))
+        .unwrap_or_default()
+}
+
+/// Check if a scan result is a virtual device
+pub fn is_virtual(info: &DeviceInfo) -> bool {
+    info.usb_path.starts_with(VIRTUAL_PATH_PREFIX)
+}
+
+/// A device simulated in memory
+///
+/// Follows the per-model tables for which controls exist and their ranges,
+/// so out-of-range writes fail as they would on the hardware. There is no
+/// audio, so the meters always read silence.
+pub struct VirtualDevice {
+    info: DeviceInfo,
+    capabilities: DeviceCapabilities,
+    volume_scale: VolumeScale,
+    volumes: Vec<f32>,
+    mutes: Vec<bool>,
+    routing: RoutingMatrix,
+    mix: Vec<u16>,
+    input_gains: Vec<u8>,
+    phantom: Vec<bool>,
+    air: Vec<AirMode>,
+    pad: Vec<bool>,
+    levels: Vec<InputLevel>,
+    direct_monitor: DirectMonitorMode,
+    dim: bool,
+    monitor_mute: bool,
+    talkback: bool,
+    talkback_mixes: Vec<bool>,
+    writes: u64,
+}
+
+impl VirtualDevice {
+    /// Create a virtual device with every control at its power-on default
+    pub fn new(mut info: DeviceInfo) -> Self {
+        let model = info.model;
+        let capabilities = DeviceCapabilities::for_model(model);
+        let volume_scale = VolumeScale::for_model(model);
+        let num_line_outputs = model
+            .port_layout()
+            .destinations
+            .iter()
+            .filter(|port| port.port_type == PortType::AnalogOut)
+            .count();
+        let num_mixes = model
+            .port_layout()
+            .sources
+            .iter()
+            .filter(|port| port.port_type == PortType::MixerOut)
+            .count();
+        info.firmware_version = Some(VIRTUAL_FIRMWARE.to_string());
+
+        Self {
+            info,
+            volume_scale,
+            volumes: vec![volume_scale.clamp(0.0); num_line_outputs],
+            mutes: vec![false; num_line_outputs],
+            routing: RoutingMatrix::for_model(model),
+            mix: vec![0; capabilities.num_mixer_inputs as usize],
+            input_gains: vec![0; model.gain_input_count() as usize],
+            phantom: vec![false; model.phantom_count() as usize],
+            air: vec![AirMode::Off; model.air_inputs().len()],
+            pad: vec![false; model.pad_inputs().len()],
+            levels: vec![InputLevel::Line; model.level_inputs().len()],
+            direct_monitor: DirectMonitorMode::Off,
+            dim: false,
+            monitor_mute: false,
+            talkback: false,
+            talkback_mixes: vec![false; num_mixes],
+            capabilities,
+            writes: 0,
+        }
+    }
+
+    /// Get the device capabilities, from the per-model tables
+    pub fn capabilities(&self) -> &DeviceCapabilities {
+        &self.capabilities
+    }
+
+    /// Number of configuration writes made so far
+    pub fn config_writes(&self) -> u64 {
+        self.writes
+    }
+
+    /// Pretend to save the configuration to flash
+    ///
+    /// The settings already last as long as the device does.
+    pub fn commit_to_flash(&mut self) -> Result<()> {
+        tracing::info!("Saving virtual {} configuration (no-op)", self.info.model.name());
+        Ok(())
+    }
+
+    /// Get the line output volume scale
+    pub fn volume_scale(&self) -> VolumeScale {
+        self.volume_scale
+    }
+
+    /// Get the volume of a line output, in dB
+    pub fn get_volume(&mut self, output_index: u8) -> Result<f32> {
+        let index = self.line_output(output_index)?;
+        Ok(self.volumes[index])
+    }
+
+    /// Set the volume of a line output, in dB
+    ///
+    /// Rounded to the device's volume steps, like a register write.
+    pub fn set_volume(&mut self, output_index: u8, volume_db: f32) -> Result<()> {
+        let index = self.line_output(output_index)?;
+        let scale = self.volume_scale;
+        self.volumes[index] = scale.to_db(scale.to_raw(volume_db));
+        self.writes += 1;
+        Ok(())
+    }
+
+    /// Adjust the volume of a line output, returning the new volume in dB
+    pub fn adjust_volume(&mut self, output_index: u8, delta_db: f32) -> Result<f32> {
+        let current = self.get_volume(output_index)?;
+        self.set_volume(output_index, current + delta_db)?;
+        self.get_volume(output_index)
+    }
+
+    /// Get the mute state of a line output
+    pub fn get_mute(&mut self, output_index: u8) -> Result<bool> {
+        let index = self.line_output(output_index)?;
+        Ok(self.mutes[index])
+    }
+
+    /// Set the mute state of a line output
+    pub fn set_mute(&mut self, output_index: u8, muted: bool) -> Result<()> {
+        let index = self.line_output(output_index)?;
+        self.mutes[index] = muted;
+        self.writes += 1;
+        Ok(())
+    }
+
+    /// Toggle the mute state of a line output, returning the new state
+    pub fn toggle_mute(&mut self, output_index: u8) -> Result<bool> {
+        let muted = !self.get_mute(output_index)?;
+        self.set_mute(output_index, muted)?;
+        Ok(muted)
+    }
+
+    fn model(&self) -> DeviceModel {
+        self.info.model
+    }
+
+    fn line_output(&self, output_index: u8) -> Result<usize> {
+        let index = output_index as usize;
+        if index >= self.volumes.len() {
+            return Err(Error::InvalidParameter(format!(
+                "No line output {} on {}",
+                output_index,
+                self.model()
+            )));
+        }
+        Ok(index)
+    }
+
+    fn gain_input(&self, input: u8) -> Result<usize> {
+        let count = self.input_gains.len();
+        if input as usize >= count {
+            return Err(Error::InvalidParameter(format!(
+                "Input {} has no gain control ({} gain inputs)",
+                input, count
+            )));
+        }
+        Ok(input as usize)
+    }
+
+    fn phantom_switch(&self, channel_group: u8) -> Result<usize> {
+        if channel_group as usize >= self.phantom.len() {
+            return Err(Error::InvalidParameter(format!("No phantom power switch {}", channel_group)));
+        }
+        Ok(channel_group as usize)
+    }
+
+    fn air_input(&self, input: u8) -> Result<usize> {
+        let inputs = self.model().air_inputs();
+        if !inputs.contains(&input) {
+            return Err(Error::InvalidParameter(format!("Input {} has no Air control", input)));
+        }
+        Ok((input - inputs.start) as usize)
+    }
+
+    fn pad_input(&self, input: u8) -> Result<usize> {
+        let inputs = self.model().pad_inputs();
+        if inputs.is_empty() {
+            return Err(Error::NotSupported(format!("Pad control on {}", self.model())));
+        }
+        if !inputs.contains(&input) {
+            return Err(Error::InvalidParameter(format!("Input {} has no pad", input)));
+        }
+        Ok((input - inputs.start) as usize)
+    }
+
+    fn level_input(&self, input: u8) -> Result<usize> {
+        let inputs = self.model().level_inputs();
+        if inputs.is_empty() {
+            return Err(Error::NotSupported(format!("Input level switching on {}", self.model())));
+        }
+        if !inputs.contains(&input) {
+            return Err(Error::InvalidParameter(format!("Input {} has no Inst mode", input)));
+        }
+        Ok((input - inputs.start) as usize)
+    }
+
+    fn check_routing(&self) -> Result<()> {
+        if !self.capabilities.mux {
+            return Err(Error::NotSupported(format!("Routing on {}", self.model())));
+        }
+        Ok(())
+    }
+
+    fn check_mixer(&self) -> Result<()> {
+        if !self.capabilities.mix {
+            return Err(Error::NotSupported(format!("Mixer on {}", self.model())));
+        }
+        Ok(())
+    }
+
+    fn check_talkback(&self) -> Result<()> {
+        if !self.model().has_talkback() {
+            return Err(Error::NotSupported(format!("Talkback on {}", self.model())));
+        }
+        Ok(())
+    }
+}
+
+/// Check if two port lists name the same ports in the same order
+fn same_ports(a: &[Port], b: &[Port]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(a, b)| a.port_type == b.port_type && a.index == b.index)
+}
+
+impl Device for VirtualDevice {
+    fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    fn num_inputs(&self) -> usize {
+        self.capabilities.num_inputs as usize
+    }
+
+    fn num_outputs(&self) -> usize {
+        self.capabilities.num_outputs as usize
+    }
+
+    fn num_mixer_inputs(&self) -> usize {
+        self.mix.len()
+    }
+
+    fn has_mixer(&self) -> bool {
+        self.capabilities.mix
+    }
+
+    fn has_routing(&self) -> bool {
+        self.capabilities.mux
+    }
+}
+
+impl Protocol for VirtualDevice {
+    fn get_routing(&mut self) -> Result<RoutingMatrix> {
+        self.check_routing()?;
+        Ok(self.routing.clone())
+    }
+
+    fn set_routing(&mut self, matrix: &RoutingMatrix) -> Result<()> {
+        self.check_routing()?;
+        matrix.validate()?;
+        if !same_ports(&matrix.sources, &self.routing.sources)
+            || !same_ports(&matrix.destinations, &self.routing.destinations)
+        {
+            return Err(Error::InvalidParameter(format!(
+                "Routing ports don't match the {}",
+                self.model()
+            )));
+        }
+
+        self.routing = matrix.clone();
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn get_mixer_state(&mut self) -> Result<MixerState> {
+        self.check_mixer()?;
+        Ok(mixer_state_from_gains(&self.mix))
+    }
+
+    fn set_channel_volume(&mut self, channel: usize, volume_db: f32) -> Result<()> {
+        self.check_mixer()?;
+        let gain = self
+            .mix
+            .get_mut(channel)
+            .ok_or_else(|| Error::InvalidParameter(format!("No mixer input {}", channel)))?;
+        *gain = db_to_mixer_gain(volume_db) as u16;
+        self.writes += 1;
+        Ok(())
+    }
+
+    /// The hardware mixer is a gain matrix with no pan control
+    fn set_channel_pan(&mut self, _channel: usize, _pan: f32) -> Result<()> {
+        Err(Error::NotSupported("Mixer pan".to_string()))
+    }
+
+    /// One silent meter per routing destination
+    fn get_level_meters(&mut self) -> Result<Vec<LevelMeter>> {
+        let count = self.routing.destinations.len();
+        Ok(level_meters(std::iter::repeat_n(meter_to_db(0), count)))
+    }
+
+    fn get_input_gain(&mut self, input: u8) -> Result<u8> {
+        let index = self.gain_input(input)?;
+        Ok(self.input_gains[index])
+    }
+
+    /// Values outside the device's gain range are clamped
+    fn set_input_gain(&mut self, input: u8, gain_db: u8) -> Result<()> {
+        let index = self.gain_input(input)?;
+        let (min, max) = self.model().input_gain_range().unwrap_or((0, 0));
+        self.input_gains[index] = gain_db.clamp(min, max);
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn get_phantom(&mut self, channel_group: u8) -> Result<bool> {
+        let index = self.phantom_switch(channel_group)?;
+        Ok(self.phantom[index])
+    }
+
+    fn set_phantom(&mut self, channel_group: u8, enabled: bool) -> Result<()> {
+        let index = self.phantom_switch(channel_group)?;
+        self.phantom[index] = enabled;
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn get_air(&mut self, input: u8) -> Result<AirMode> {
+        let index = self.air_input(input)?;
+        Ok(self.air[index])
+    }
+
+    /// Presence + Drive is only available on 4th Gen devices
+    fn set_air(&mut self, input: u8, mode: AirMode) -> Result<()> {
+        let index = self.air_input(input)?;
+        if mode == AirMode::PresenceDrive && !self.model().has_air_drive() {
+            return Err(Error::NotSupported(
+                "Air Presence + Drive requires a 4th Gen device".to_string()
+            ));
+        }
+
+        self.air[index] = mode;
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn get_pad(&mut self, input: u8) -> Result<bool> {
+        let index = self.pad_input(input)?;
+        Ok(self.pad[index])
+    }
+
+    fn set_pad(&mut self, input: u8, enabled: bool) -> Result<()> {
+        let index = self.pad_input(input)?;
+        self.pad[index] = enabled;
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn get_input_level(&mut self, input: u8) -> Result<InputLevel> {
+        let index = self.level_input(input)?;
+        Ok(self.levels[index])
+    }
+
+    fn set_input_level(&mut self, input: u8, level: InputLevel) -> Result<()> {
+        let index = self.level_input(input)?;
+        self.levels[index] = level;
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn get_direct_monitor(&mut self) -> Result<DirectMonitorMode> {
+        if self.model().direct_monitor_modes().is_empty() {
+            return Err(Error::NotSupported(format!("Direct Monitor on {}", self.model())));
+        }
+        Ok(self.direct_monitor)
+    }
+
+    fn set_direct_monitor(&mut self, mode: DirectMonitorMode) -> Result<()> {
+        let modes = self.model().direct_monitor_modes();
+        if modes.is_empty() {
+            return Err(Error::NotSupported(format!("Direct Monitor on {}", self.model())));
+        }
+        if !modes.contains(&mode) {
+            return Err(Error::InvalidParameter(format!(
+                "Direct Monitor {} not available on {}",
+                mode,
+                self.model()
+            )));
+        }
+
+        self.direct_monitor = mode;
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn get_dim(&mut self) -> Result<bool> {
+        Ok(self.dim)
+    }
+
+    fn set_dim(&mut self, enabled: bool) -> Result<()> {
+        self.dim = enabled;
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn get_monitor_mute(&mut self) -> Result<bool> {
+        Ok(self.monitor_mute)
+    }
+
+    fn set_monitor_mute(&mut self, muted: bool) -> Result<()> {
+        self.monitor_mute = muted;
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn get_talkback(&mut self) -> Result<bool> {
+        self.check_talkback()?;
+        Ok(self.talkback)
+    }
+
+    fn set_talkback(&mut self, enabled: bool) -> Result<()> {
+        self.check_talkback()?;
+        self.talkback = enabled;
+        self.writes += 1;
+        Ok(())
+    }
+
+    fn get_talkback_mix(&mut self, mix_index: u8) -> Result<bool> {
+        self.check_talkback()?;
+        self.talkback_mixes
+            .get(mix_index as usize)
+            .copied()
+            .ok_or_else(|| Error::InvalidParameter(format!("No mix {}", mix_index)))
+    }
+
+    fn set_talkback_mix(&mut self, mix_index: u8, enabled: bool) -> Result<()> {
+        self.check_talkback()?;
+        let routed = self
+            .talkback_mixes
+            .get_mut(mix_index as usize)
+            .ok_or_else(|| Error::InvalidParameter(format!("No mix {}", mix_index)))?;
+        *routed = enabled;
+        self.writes += 1;
+        Ok(())
+    }
+}
+
+impl MeterSource for VirtualDevice {
+    fn meter_count(&mut self) -> Result<u16> {
+        Ok(self.routing.destinations.len() as u16)
+    }
+
+    fn read_levels(&mut self, count: u16) -> BoxFuture<'_, Result<Vec<f32>>> {
+        Box::pin(async move { Ok(vec![meter_to_db(0); count as usize]) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(model: DeviceModel) -> VirtualDevice {
+        VirtualDevice::new(DeviceInfo::new(model, "VIRTUAL0001".to_string(), "virtual-001".to_string()))
+    }
+
+    #[test]
+    fn test_short_names() {
+        assert_eq!(short_name(DeviceModel::Scarlett18i20Gen4).as_deref(), Some("18i20g4"));
+        assert_eq!(short_name(DeviceModel::ScarlettSoloGen3).as_deref(), Some("solog3"));
+        assert_eq!(short_name(DeviceModel::VocasterOne), None);
+
+        for &model in DeviceModel::all() {
+            if let Some(short) = short_name(model) {
+                assert_eq!(model_from_short_name(&short), Some(model), "{}", short);
+            }
+        }
+        assert_eq!(model_from_short_name(" 2I2G3 "), Some(DeviceModel::Scarlett2i2Gen3));
+    }
+
+    #[test]
+    fn test_parse_virtual_devices() {
+        let devices = parse_virtual_devices("18i20g4, nonsense,2i2g3,");
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].model, DeviceModel::Scarlett18i20Gen4);
+        assert_eq!(devices[1].model, DeviceModel::Scarlett2i2Gen3);
+        assert_ne!(devices[0].serial_number, devices[1].serial_number);
+        assert!(devices.iter().all(is_virtual));
+
+        assert!(parse_virtual_devices("").is_empty());
+        assert!(!is_virtual(&DeviceInfo::new(
+            DeviceModel::Scarlett2i2Gen3,
+            "S123".to_string(),
+            "usb-001-004".to_string()
+        )));
+    }
+
+    #[test]
+    fn test_volume_and_mute_read_back() {
+        let mut device = device(DeviceModel::Scarlett18i20Gen4);
+        assert_eq!(device.info().firmware_version.as_deref(), Some(VIRTUAL_FIRMWARE));
+
+        device.set_volume(1, -20.3).unwrap();
+        assert_eq!(device.get_volume(1).unwrap(), -20.0);
+        assert_eq!(device.adjust_volume(1, 100.0).unwrap(), 0.0);
+        assert_eq!(device.get_volume(0).unwrap(), 0.0);
+
+        assert!(device.toggle_mute(3).unwrap());
+        assert!(device.get_mute(3).unwrap());
+        assert!(!device.get_mute(2).unwrap());
+
+        assert!(matches!(device.set_volume(20, 0.0), Err(Error::InvalidParameter(_))));
+        assert_eq!(device.config_writes(), 3);
+    }
+
+    #[test]
+    fn test_routing_and_mixer_read_back() {
+        let mut device = device(DeviceModel::Scarlett4i4Gen3);
+
+        let mut matrix = device.get_routing().unwrap();
+        matrix.set_route(0, Some(1)).unwrap();
+        device.set_routing(&matrix).unwrap();
+        assert_eq!(device.get_routing().unwrap().routes, matrix.routes);
+
+        let other = RoutingMatrix::for_model(DeviceModel::Scarlett18i8Gen3);
+        assert!(matches!(device.set_routing(&other), Err(Error::InvalidParameter(_))));
+
+        device.set_channel_volume(2, 0.0).unwrap();
+        let state = device.get_mixer_state().unwrap();
+        assert_eq!(state.channels.len(), 8);
+        assert_eq!(state.channels[2].mixer_gain(), db_to_mixer_gain(0.0));
+        assert!(device.set_channel_volume(8, 0.0).is_err());
+
+        let mut solo = self::device(DeviceModel::ScarlettSoloGen3);
+        assert!(matches!(solo.get_routing(), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_inputs_follow_model_tables() {
+        let mut device = device(DeviceModel::Scarlett2i2Gen4);
+
+        device.set_input_gain(1, 100).unwrap();
+        assert_eq!(device.get_input_gain(1).unwrap(), 69);
+        assert!(device.set_input_gain(2, 10).is_err());
+
+        device.set_phantom(0, true).unwrap();
+        assert!(device.get_phantom(0).unwrap());
+        device.set_air(1, AirMode::PresenceDrive).unwrap();
+        assert_eq!(device.get_air(1).unwrap(), AirMode::PresenceDrive);
+        device.set_direct_monitor(DirectMonitorMode::Stereo).unwrap();
+        assert_eq!(device.get_direct_monitor().unwrap(), DirectMonitorMode::Stereo);
+        assert!(device.set_direct_monitor(DirectMonitorMode::On).is_err());
+        assert!(matches!(device.get_pad(0), Err(Error::NotSupported(_))));
+        assert!(matches!(device.get_talkback(), Err(Error::NotSupported(_))));
+
+        let mut gen3 = self::device(DeviceModel::Scarlett2i2Gen3);
+        assert!(matches!(gen3.set_air(0, AirMode::PresenceDrive), Err(Error::NotSupported(_))));
+    }
+
+    #[test]
+    fn test_open_as_usb_device() {
+        let info = parse_virtual_devices("18i20g4").remove(0);
+        let mut device = crate::UsbDevice::open_virtual(info);
+        device.initialize().unwrap();
+
+        assert_eq!(device.info().firmware_version.as_deref(), Some(VIRTUAL_FIRMWARE));
+        assert!(device.is_connected() && device.has_routing());
+        assert!(device.fcp_protocol().is_none());
+
+        device.set_volume(0, -12.0).unwrap();
+        assert_eq!(device.get_volume(0).unwrap(), -12.0);
+        device.protocol().set_dim(true).unwrap();
+        assert!(device.protocol().get_dim().unwrap());
+    }
+}